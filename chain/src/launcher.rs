@@ -4,7 +4,7 @@ use crate::settings::{AppConfig, DbCacheSize, HttpSettings, IndexerSettings};
 use crate::{chain_config, combine};
 use futures::future::ready;
 use redbit::storage::init::{Storage, StorageOwner};
-use redbit::{error, info, AppError, OpenApiRouter, RequestState, WriteTxContext};
+use redbit::{error, info, AppError, DbLayout, MountPoint, OpenApiRouter, RequestState, SpillConfig, WriteTxContext, WriterConfig, SPILL_DIR};
 use std::env;
 use std::sync::Arc;
 use std::time::Duration;
@@ -27,7 +27,7 @@ pub async fn maybe_run_server(
             .allow_origin(cors::Any) // or use a specific origin: `AllowOrigin::exact("http://localhost:5173".parse().unwrap())`
             .allow_methods(cors::Any)
             .allow_headers(cors::Any));
-        redbit::rest::serve(RequestState { storage: Arc::clone(&storage) }, http_conf.bind_address, extras, Some(cors), shutdown).await
+        redbit::rest::serve(RequestState::new(Arc::clone(&storage)).with_read_limits(http_conf.max_concurrent_reads, http_conf.max_concurrent_streams), http_conf.bind_address, extras, Some(cors), shutdown).await
     } else {
         info!("HTTP server is disabled, skipping");
         ready(()).await
@@ -73,13 +73,14 @@ fn teardown<FB: SizeLike + 'static, TB: BlockLike + 'static, CTX: WriteTxContext
     chain: Arc<dyn BlockChainLike<TB, CTX>>,
     syncer: Arc<ChainSyncer<FB, TB, CTX>>,
     storage_owner: StorageOwner,
-) {
+) -> Result<(), ChainError> {
     drop(storage_view);
     drop(chain);
     drop(syncer);
     storage_owner.assert_last_refs();
-    drop(storage_owner);
+    storage_owner.close()?;
     info!("Shutdown complete");
+    Ok(())
 }
 
 pub async fn maybe_run_scheduling<FB: SizeLike + 'static, TB: BlockLike + 'static, CTX: WriteTxContext + 'static>(
@@ -107,11 +108,78 @@ fn maybe_console_init() {
     info!("Running production build without console subscriber");
 }
 
-pub async fn build_storage(config: &AppConfig) -> Result<(bool, StorageOwner, Arc<Storage>), AppError>  {
+fn db_layout(config: &AppConfig) -> DbLayout {
     let db_path: String = format!("{}/{}/{}", config.indexer.db_path, "main", config.indexer.name);
     let full_path = env::home_dir().unwrap().join(&db_path);
+    let mounts = config.indexer.db_mounts.iter().map(|m| MountPoint {
+        dir: m.dir.join("main").join(&config.indexer.name),
+        ..m.clone()
+    }).collect();
+    DbLayout::with_mounts(full_path, mounts)
+}
+
+pub async fn build_storage(config: &AppConfig) -> Result<(bool, StorageOwner, Arc<Storage>), AppError>  {
     let db_cache_size_gb: DbCacheSize = config.indexer.db_cache_size_gb;
-    StorageOwner::build_storage(full_path, db_cache_size_gb.0).await
+    let layout = db_layout(config);
+    let spill = match config.indexer.max_writer_buffer_mb_size {
+        0 => None,
+        mb => Some(SpillConfig::new(layout.root.join(SPILL_DIR), mb * 1024 * 1024)),
+    };
+    if let Some(spill) = &spill {
+        spill.remove_stale_runs()?;
+    }
+    let (created, owner, _) = StorageOwner::build_storage(layout, db_cache_size_gb.0).await?;
+    let owner = owner.with_writer_config(WriterConfig { queue_budget: config.indexer.max_writer_queue_mb_size * 1024 * 1024, spill });
+    let view = owner.view();
+    Ok((created, owner, view))
+}
+
+pub async fn build_read_only_storage(config: &AppConfig) -> Result<(StorageOwner, Arc<Storage>), AppError>  {
+    let db_cache_size_gb: DbCacheSize = config.indexer.db_cache_size_gb;
+    StorageOwner::open_read_only(db_layout(config), db_cache_size_gb.0).await
+}
+
+/// `<binary> fsck [--repair]` checks the storage instead of syncing, returns whether repair was requested.
+fn fsck_command() -> Option<bool> {
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        Some("fsck") => Some(args.any(|a| a == "--repair")),
+        _ => None,
+    }
+}
+
+async fn run_fsck(config: &AppConfig, repair: bool) -> Result<(), ChainError> {
+    let (_, storage_owner, storage_view) = build_storage(config).await?;
+    info!("Checking storage integrity{}", if repair { " with repair" } else { "" });
+    let report = redbit::fsck(&storage_view, repair)?;
+    drop(storage_view);
+    storage_owner.assert_last_refs();
+    storage_owner.close()?;
+    if report.is_clean() {
+        info!("{}", report);
+        Ok(())
+    } else {
+        error!("{}", report);
+        Err(ChainError::new(format!("storage has {} unrepaired issues", report.issues.iter().filter(|i| !i.repaired).count())))
+    }
+}
+
+/// `<binary> serve` runs just the http server on read-only storage, any number of them can share it while the indexer is stopped.
+/// redb cannot read a db another process writes, so next to a running indexer it has to serve a snapshot directory.
+fn serve_command() -> bool {
+    env::args().nth(1).as_deref() == Some("serve")
+}
+
+async fn run_read_only_server(config: AppConfig, extras: Option<OpenApiRouter<RequestState>>, cors: Option<CorsLayer>) -> Result<(), ChainError> {
+    let (storage_owner, storage_view) = build_read_only_storage(&config).await?;
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let server_f = maybe_run_server(config.http, Arc::clone(&storage_view), extras, cors, shutdown_rx);
+    let res = combine::futures(server_f, ready(()), shutdown_tx).await;
+    drop(storage_view);
+    storage_owner.assert_last_refs();
+    storage_owner.close()?;
+    info!("Shutdown complete");
+    Ok(res)
 }
 
 enum Flow { Continue, Stop }
@@ -147,15 +215,14 @@ async fn launch_with_provider<
 
     match run_initial_sync_phase(config.indexer.clone(), unlinked_headers.first().cloned(), Arc::clone(&syncer), shutdown_tx.clone(), shutdown_rx.clone()).await {
         Flow::Stop => {
-            teardown::<FB, TB, CTX>(storage_view, chain, syncer, storage_owner);
-            Ok(())
+            teardown::<FB, TB, CTX>(storage_view, chain, syncer, storage_owner)
         }
         Flow::Continue => {
             let indexing_f = maybe_run_scheduling(config.indexer, Scheduler::new(Arc::clone(&syncer)), shutdown_rx.clone());
             let server_f   = maybe_run_server(config.http, Arc::clone(&storage_view), extras, cors, shutdown_rx.clone());
             let res = combine::futures(indexing_f, server_f, shutdown_tx).await;
 
-            teardown::<FB, TB, CTX>(storage_view, chain, syncer, storage_owner);
+            teardown::<FB, TB, CTX>(storage_view, chain, syncer, storage_owner)?;
             Ok(res)
         }
     }
@@ -173,6 +240,14 @@ where
     PFN: FnOnce(AppConfig) -> Result<Arc<dyn BlockProvider<FB, TB>>, ChainError>,
 {
     let config: AppConfig = chain_config::load_config("config/settings", "REDBIT")?;
+    if let Some(repair) = fsck_command() {
+        maybe_console_init();
+        return run_fsck(&config, repair).await;
+    }
+    if serve_command() {
+        maybe_console_init();
+        return run_read_only_server(config, extras, cors).await;
+    }
     let provider = block_provider_factory(config)?;
     launch_with_provider::<FB, TB, CTX>(provider, build_chain, extras, cors).await
 }
@@ -190,6 +265,14 @@ where
     PFut: Future<Output = Arc<dyn BlockProvider<FB, TB>>> + Send,
 {
     let config: AppConfig = chain_config::load_config("config/settings", "REDBIT")?;
+    if let Some(repair) = fsck_command() {
+        maybe_console_init();
+        return run_fsck(&config, repair).await;
+    }
+    if serve_command() {
+        maybe_console_init();
+        return run_read_only_server(config, extras, cors).await;
+    }
     let provider = block_provider_factory(config).await;
     launch_with_provider::<FB, TB, CTX>(provider, build_chain, extras, cors).await
 }
\ No newline at end of file
@@ -11,6 +11,7 @@ pub mod stats;
 pub mod chain_config;
 pub mod size_batcher;
 pub mod block_stream;
+pub mod segwit;
 pub mod err;
 mod reorder_buffer;
 
@@ -0,0 +1,72 @@
+use bech32::{Fe32, segwit};
+use redbit::codec::BitPrefix;
+
+/// Program bits a segwit prefix may narrow, past them the chars of the shortest program of the version are followed by
+/// padding and the six checksum chars, which are not stored.
+fn program_bits(version: u8) -> usize {
+    if version == 0 { 20 * 8 } else { 32 * 8 }
+}
+
+/// Leading bits of the segwit addresses starting with `prefix` in their stored layout [tag, ver, program],
+/// the witness version takes a whole byte and each following bech32 char 5 bits of the program, base58 is not bit aligned.
+/// The caller checks the hrp, `None` means the prefix holds a char outside of the bech32 charset.
+pub fn segwit_prefix(prefix: &str, tag: u8) -> Option<BitPrefix> {
+    if let Ok((_, ver, program)) = segwit::decode(prefix) {
+        let mut bytes = vec![tag, ver.to_u8()];
+        bytes.extend_from_slice(&program);
+        return Some(BitPrefix::from_bytes(bytes));
+    }
+    // '1' is not in the bech32 charset so the last one separates the hrp
+    let mut data = prefix[prefix.rfind('1')? + 1..].chars().map(|c| Fe32::from_char(c.to_ascii_lowercase()).ok());
+    let mut bits = BitPrefix::from_bytes(vec![tag]);
+    let Some(version) = data.next() else { return Some(bits) };
+    let version = version?.to_u8();
+    bits.push_bits(version as u32, 8);
+    let mut left = program_bits(version);
+    for fe in data {
+        let fe = fe?.to_u8() as u32;
+        let width = left.min(5);
+        if width == 0 {
+            break;
+        }
+        bits.push_bits(fe >> (5 - width), width);
+        left -= width;
+    }
+    Some(bits)
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+    use bech32::hrp;
+
+    const TAG: u8 = 0xB0;
+
+    fn stored(ver: u8, program: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![TAG, ver];
+        bytes.extend_from_slice(program);
+        bytes
+    }
+
+    #[test]
+    fn segwit_prefix_bounds_the_addresses_it_starts() {
+        for (version, program) in [(segwit::VERSION_0, vec![0x75; 20]), (segwit::VERSION_1, vec![0x75; 32])] {
+            let address = segwit::encode(hrp::BC, version, &program).unwrap();
+            let stored = stored(version.to_u8(), &program);
+            assert_eq!(segwit_prefix(&address, TAG).unwrap().bounds().0, stored);
+            // the last prefixes run into the padding and the checksum
+            for len in "bc1".len()..address.len() {
+                let (from, until) = segwit_prefix(&address[..len], TAG).unwrap().bounds();
+                assert!(from <= stored && stored < until.unwrap(), "prefix {} must contain {}", &address[..len], address);
+            }
+        }
+    }
+
+    #[test]
+    fn segwit_prefix_excludes_other_programs() {
+        let address = segwit::encode(hrp::BC, segwit::VERSION_0, &[0x75; 20]).unwrap();
+        let (_, until) = segwit_prefix(&address[..20], TAG).unwrap().bounds();
+        assert!(stored(0, &[0x76; 20]) >= until.unwrap());
+        assert!(segwit_prefix("bc1qb", TAG).is_none(), "b is not a bech32 char");
+    }
+}
@@ -1,3 +1,4 @@
+use redbit::MountPoint;
 use serde::Deserialize;
 use std::fmt::Debug;
 use std::net::SocketAddr;
@@ -128,19 +129,41 @@ pub struct IndexerSettings {
     pub name: String,
     pub enable: bool,
     pub db_path: String,
+    #[serde(default)]
+    pub db_mounts: Vec<MountPoint>,
     #[serde(deserialize_with = "duration_from_secs")]
     pub node_sync_interval_s: Duration,
     pub fork_detection_heights: u8,
     pub min_entity_batch_size: usize,
     pub max_entity_buffer_kb_size: usize,
+    #[serde(default = "default_max_writer_queue_mb_size")]
+    pub max_writer_queue_mb_size: usize,
+    #[serde(default)]
+    pub max_writer_buffer_mb_size: usize,
     pub non_durable_batches: usize,
     pub db_cache_size_gb: DbCacheSize,
     pub processing_parallelism: Parallelism,
     pub validation_from_height: u32,
 }
 
+fn default_max_writer_queue_mb_size() -> usize {
+    redbit::DEFAULT_WRITER_QUEUE_BUDGET / (1024 * 1024)
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct HttpSettings {
     pub enable: bool,
     pub bind_address: SocketAddr,
+    #[serde(default = "default_max_concurrent_reads")]
+    pub max_concurrent_reads: usize,
+    #[serde(default = "default_max_concurrent_streams")]
+    pub max_concurrent_streams: usize,
+}
+
+fn default_max_concurrent_reads() -> usize {
+    redbit::DEFAULT_READ_CONCURRENCY
+}
+
+fn default_max_concurrent_streams() -> usize {
+    redbit::DEFAULT_STREAM_CONCURRENCY
 }
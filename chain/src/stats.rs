@@ -1,4 +1,4 @@
-use redbit::TaskResult;
+use redbit::{LruStats, QueueStats, TaskResult};
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 
@@ -9,6 +9,16 @@ pub struct ReportRow {
     pub sort: ReportData,
     pub write: ReportData,
     pub flush: ReportData,
+    pub lru: Option<ReportLru>,
+    /// One per shard, in shard order.
+    pub queue: Vec<QueueStats>,
+}
+
+/// LRU counters of the last batch and of all batches so far.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportLru {
+    pub last: LruStats,
+    pub total: LruStats,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -58,16 +68,46 @@ impl Report {
         let header_avg  = plus_eq_header("avg");
         let sum_col_w = header_last.len().max(header_avg.len());
 
+        fn hit_rate_fmt(lru: &LruStats) -> String {
+            lru.hit_rate().map_or("-".to_string(), |r| format!("{}", r.round() as u64))
+        }
+
+        fn lru_fmt(lru: &Option<ReportLru>) -> (String, String) {
+            match lru {
+                Some(l) => (
+                    format!("{:>3} / {:>3}", hit_rate_fmt(&l.last), hit_rate_fmt(&l.total)),
+                    format!("{} / {}", l.last.evictions, l.total.evictions),
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            }
+        }
+
+        fn queue_fmt(q: &QueueStats) -> String {
+            format!("{} / {} / {}", q.peak_depth, q.peak_bytes.div_ceil(1024 * 1024), q.blocked_ms)
+        }
+
+        // a sharded column gets a `column[shard]` row per shard below its own one
+        fn queue_cell(queue: &[QueueStats]) -> String {
+            match queue {
+                [] => "-".to_string(),
+                [q] => queue_fmt(q),
+                shards => format!("{} shards", shards.len()),
+            }
+        }
+
         let mut lines = Vec::with_capacity(self.0.len() + 1);
 
         // (2) single header line with both shaped columns, separated by |
         lines.push(format!(
-            "{:<name_w$}{sep}{:>sum_w$}{sep}{:>sum_w$}{sep}{:>8}{sep}{:>8}",
+            "{:<name_w$}{sep}{:>sum_w$}{sep}{:>sum_w$}{sep}{:>8}{sep}{:>8}{sep}{:>14}{sep}{:>14}{sep}{:>18}",
             "TASK (c)ollect,(s)ort,(w)rite,(f)lush ms",
             header_last,
             header_avg,
             "dev",
             "coefov %",
+            "lru hit % l/a",
+            "evicted l/a",
+            "queue d/MB/wait ms",
             name_w = NAME_WIDTH,
             sum_w = sum_col_w,
             sep = SEP
@@ -78,18 +118,33 @@ impl Report {
             let avgs  = [r.collect.avg,  r.sort.avg,  r.write.avg,  r.flush.avg];
             let devs  = [r.collect.dev,  r.sort.dev,  r.write.dev,  r.flush.dev];
             let cvs   = [r.collect.cv,   r.sort.cv,   r.write.cv,   r.flush.cv];
+            let (hit_rates, evictions) = lru_fmt(&r.lru);
 
             lines.push(format!(
-                "{:<name_w$}{sep}{:>sum_w$}{sep}{:>sum_w$}{sep}{:>8}{sep}{:>8}",
+                "{:<name_w$}{sep}{:>sum_w$}{sep}{:>sum_w$}{sep}{:>8}{sep}{:>8}{sep}{:>14}{sep}{:>14}{sep}{:>18}",
                 r.name,
                 plus_eq_fmt(&lasts),
                 plus_eq_fmt(&avgs),
                 devs.iter().copied().sum::<u128>(),
                 cvs.iter().map(|v| v.round() as u128).sum::<u128>(),
+                hit_rates,
+                evictions,
+                queue_cell(&r.queue),
                 name_w = NAME_WIDTH,
                 sum_w = sum_col_w,
                 sep = SEP
             ));
+            if r.queue.len() > 1 {
+                for (shard, q) in r.queue.iter().enumerate() {
+                    lines.push(format!(
+                        "{:<name_w$}{sep}{:>sum_w$}{sep}{:>sum_w$}{sep}{:>8}{sep}{:>8}{sep}{:>14}{sep}{:>14}{sep}{:>18}",
+                        format!("{}[{}]", r.name, shard), "", "", "", "", "", "", queue_fmt(q),
+                        name_w = NAME_WIDTH,
+                        sum_w = sum_col_w,
+                        sep = SEP
+                    ));
+                }
+            }
         }
         format!("Task performance :\n{}", lines.join("\n"))
     }
@@ -106,6 +161,7 @@ pub struct TaskAcc {
     collect_sumsqs: HashMap<String, f64>,
     sort_totals: HashMap<String, u128>,
     sort_sumsqs: HashMap<String, f64>,
+    lru_totals: HashMap<String, LruStats>,
     pub iters:  u64,
 }
 
@@ -130,6 +186,9 @@ impl TaskAcc {
             Self::update_phase_maps(&mut self.sort_totals,    &mut self.sort_sumsqs,    &tr.name, tr.stats.sort_took);
             Self::update_phase_maps(&mut self.write_totals,   &mut self.write_sumsqs,   &tr.name, tr.stats.write_took);
             Self::update_phase_maps(&mut self.flush_totals,   &mut self.flush_sumsqs,   &tr.name, tr.stats.flush_took);
+            if let Some(lru) = tr.stats.lru {
+                self.lru_totals.entry(tr.name.clone()).or_default().merge(&lru);
+            }
         }
     }
 
@@ -178,9 +237,15 @@ impl TaskAcc {
             let write   = self.build_data(tr.stats.write_took,   w_tot, w_sq);
             let flush   = self.build_data(tr.stats.flush_took,   f_tot, f_sq);
 
+            let lru = tr.stats.lru.map(|last| ReportLru {
+                last,
+                total: self.lru_totals.get(&tr.name).copied().unwrap_or(last),
+            });
+
             rows.push(ReportRow {
                 name: tr.name.clone(),
-                collect, sort, write, flush,
+                collect, sort, write, flush, lru,
+                queue: tr.stats.queue.clone(),
             });
         }
         Report(rows)
@@ -318,4 +383,64 @@ mod tests {
         assert!(!text.contains("c+s+w+f"), "legacy header artifact present:\n{}", text);
         assert!(!text.contains("(c)collect"), "legacy header artifact present:\n{}", text);
     }
+
+    #[test]
+    fn lru_counters_accumulate_and_render_hit_rates() {
+        let mut s = TaskAcc::default();
+        let with_lru = |hits, misses, evictions| {
+            let mut m = HashMap::new();
+            let stats = TaskStats::new(0, 0, 1, 0).with_lru(Some(LruStats { hits, misses, inserts: misses, evictions }));
+            m.insert("idx".to_string(), TaskResult::new("idx", stats));
+            m.insert("plain".to_string(), tr("plain", 0, 0, 1, 0));
+            m
+        };
+        s.update(&with_lru(1, 3, 0));
+        let b = with_lru(3, 1, 2);
+        s.update(&b);
+
+        let mut report = s.build_report(&b);
+        let idx = report.0.iter().find(|r| r.name == "idx").unwrap().lru.clone().unwrap();
+        assert_eq!(idx.last.hits, 3);
+        assert_eq!(idx.total, LruStats { hits: 4, misses: 4, inserts: 4, evictions: 2 });
+        assert!(report.0.iter().find(|r| r.name == "plain").unwrap().lru.is_none());
+
+        let text = report.printable();
+        assert!(text.contains("lru hit % l/a"), "missing lru header:\n{}", text);
+        assert!(text.contains(" 75 /  50"), "missing hit rates:\n{}", text);
+        assert!(text.contains("2 / 2"), "missing evictions:\n{}", text);
+    }
+
+    #[test]
+    fn queue_peaks_render_per_task() {
+        let mut s = TaskAcc::default();
+        let mut b = HashMap::new();
+        let stats = TaskStats::new(0, 0, 1, 0).with_queue(QueueStats { peak_depth: 7, peak_bytes: 3 * 1024 * 1024, blocked_sends: 1, blocked_ms: 12 });
+        b.insert("utxo_amount".to_string(), TaskResult::new("utxo_amount", stats));
+        b.insert("MASTER".to_string(), TaskResult::master(1));
+        s.update(&b);
+
+        let mut report = s.build_report(&b);
+        let text = report.printable();
+        assert!(text.contains("queue d/MB/wait ms"), "missing queue header:\n{}", text);
+        assert!(text.contains("7 / 3 / 12"), "missing queue peaks:\n{}", text);
+    }
+
+    #[test]
+    fn queue_peaks_render_per_shard() {
+        let mut s = TaskAcc::default();
+        let mut b = HashMap::new();
+        let stats = TaskStats::new(0, 0, 1, 0)
+            .with_queue(QueueStats { peak_depth: 7, peak_bytes: 3 * 1024 * 1024, blocked_sends: 2, blocked_ms: 12 })
+            .with_queue(QueueStats { peak_depth: 1, peak_bytes: 1024, blocked_sends: 0, blocked_ms: 0 });
+        b.insert("utxo_address_dict".to_string(), TaskResult::new("utxo_address_dict", stats));
+        s.update(&b);
+
+        let mut report = s.build_report(&b);
+        let text = report.printable();
+        assert!(text.contains("2 shards"), "missing shard count:\n{}", text);
+        let shard_0 = text.lines().find(|l| l.starts_with("utxo_address_dict[0]")).expect("row of shard 0");
+        assert!(shard_0.ends_with("7 / 3 / 12"), "{}", shard_0);
+        let shard_1 = text.lines().find(|l| l.starts_with("utxo_address_dict[1]")).expect("row of shard 1");
+        assert!(shard_1.ends_with("1 / 1 / 0"), "{}", shard_1);
+    }
 }
@@ -1,6 +1,7 @@
 use bech32::{Fe32, hrp, segwit};
 use bitcoin::WitnessVersion;
 use redbit::ByteVecColumnSerde;
+use redbit::codec::BitPrefix;
 use serde::{Deserialize, Deserializer, Serializer};
 use serde_with::{DeserializeAs, SerializeAs};
 
@@ -124,6 +125,10 @@ impl ByteVecColumnSerde for BaseOrBech {
     fn encoded_example() -> String {
         "1MNr16FTvjhTAw9GBNxhfirmPt9KzSvgMw".to_string()
     }
+
+    fn prefix(prefix: &str) -> Option<BitPrefix> {
+        bech32_prefix(prefix)
+    }
 }
 
 /// Tag for SegWit (Bech32) addresses. 0xB0 is chosen to distinguish SegWit payloads from legacy types.
@@ -150,6 +155,13 @@ fn looks_bech32_addr(s: &str) -> bool {
     has_prefix_icase(s, b"bc1") || has_prefix_icase(s, b"tb1") || has_prefix_icase(s, b"bcrt1")
 }
 
+fn bech32_prefix(prefix: &str) -> Option<BitPrefix> {
+    if !looks_bech32_addr(prefix) {
+        return None;
+    }
+    chain::segwit::segwit_prefix(prefix, TAG_SEGWIT)
+}
+
 fn encode_tagged_segwit<S: Serializer>(src: &[u8], ser: S) -> Result<S::Ok, S::Error> {
     // src = [TAG_SEGWIT, ver, program...]
     let ver = src[1];
@@ -36,7 +36,7 @@ async fn main() -> Result<()> {
 
         assert_eq!(storage_scripts, btc_scripts, "Output scripts in storage do not match those in the original block");
     }
-    drop(storage_owner);
+    storage_owner.close()?;
     info!("Validation successful");
     Ok(())
 }
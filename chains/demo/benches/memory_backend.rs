@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use redbit::redb::backends::InMemoryBackend;
+use redbit::redb::{Database, Durability, StorageBackend, TableDefinition};
+use redbit::MemoryBackend;
+
+const TABLE: TableDefinition<u32, u64> = TableDefinition::new("memory_backend_bench");
+
+/// What a test module does with each of its in-memory dbs: create it and run a few small commits.
+fn create_and_write<B: StorageBackend>(backend: B) {
+    let db = Database::builder().set_cache_size(1024 * 1024).create_with_backend(backend).expect("Failed to create db");
+    for tx_idx in 0..4u32 {
+        let mut tx = db.begin_write().expect("Failed to begin write");
+        tx.set_durability(Durability::None).expect("Failed to set durability");
+        {
+            let mut table = tx.open_table(TABLE).expect("Failed to open table");
+            for key in tx_idx * 100..(tx_idx + 1) * 100 {
+                table.insert(key, key as u64).expect("Failed to insert");
+            }
+        }
+        tx.commit().expect("Failed to commit");
+    }
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("memory_backend");
+    group.warm_up_time(Duration::from_millis(50));
+    group.measurement_time(Duration::from_millis(500));
+    group.sample_size(10);
+
+    group.bench_function(BenchmarkId::from_parameter("redb_in_memory"), |bencher| {
+        bencher.iter(|| create_and_write(InMemoryBackend::new()))
+    });
+    group.bench_function(BenchmarkId::from_parameter("redbit_memory"), |bencher| {
+        bencher.iter(|| create_and_write(MemoryBackend::new()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
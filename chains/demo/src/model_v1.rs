@@ -13,7 +13,7 @@ pub use chain::*;
 
 #[column("hex")] pub struct BlockHash(pub [u8; 32]);
 #[column("hex")] pub struct TxHash(pub [u8; 32]);
-#[column("base64")] pub struct Address(pub Vec<u8>);
+#[column("base64", compress = "zstd")] pub struct Address(pub Vec<u8>);
 #[column("utf-8")] pub struct AssetName(pub Vec<u8>); // String is supported but this is more efficient
 #[column] pub struct Duration(pub std::time::Duration);
 #[column] pub struct Weight(pub u32);
@@ -38,7 +38,7 @@ pub struct Block {
 pub struct Header {
     #[fk(one2one)]
     pub height: Height,
-    #[column(index)]
+    #[column(index, unique)]
     pub hash: BlockHash,
     #[column(index)]
     pub prev_hash: BlockHash,
@@ -56,7 +56,7 @@ pub struct Header {
 pub struct Transaction {
     #[fk(one2many)]
     pub id: BlockPointer,
-    #[column(index, used, shards = 3, db_cache = 4, lru_cache = 2)]
+    #[column(index, used, bloom, shards = 3, db_cache = 4, lru_cache = 2)]
     pub hash: TxHash,
     pub utxos: Vec<Utxo>,
     #[write_from_using(input_refs, hash)] // implement custom write_from_using function, see hook.rs
@@ -69,10 +69,11 @@ pub struct Transaction {
 }
 
 #[entity]
+#[index(columns(address, amount), shards = 2)]
 pub struct Utxo {
     #[fk(one2many, db_cache = 2)]
     pub id: TransactionPointer,
-    #[column(shards = 3)]
+    #[column(shards = 3, partition = "range")]
     pub amount: u64,
     #[column(dictionary, shards = 4, db_cache = 10, lru_cache = 2)]
     pub address: Address,
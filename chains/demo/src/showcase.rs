@@ -24,6 +24,7 @@ async fn main() -> Result<()> {
     Block::take(&block_read_ctx, 100)?;
     Block::get(&block_read_ctx, first_block.height)?;
     Block::range(&block_read_ctx, first_block.height, last_block.height, None)?;
+    Block::count(&block_read_ctx, first_block.height, last_block.height)?;
     Block::exists(&block_read_ctx, first_block.height)?;
     Block::first(&block_read_ctx)?;
     Block::last(&block_read_ctx)?;
@@ -66,6 +67,8 @@ async fn main() -> Result<()> {
 
     Utxo::get_by_address(utxo_read_ctx, &first_utxo.address)?;
     Utxo::get_ids_by_address(utxo_read_ctx, &first_utxo.address)?;
+    Utxo::count_by_address(utxo_read_ctx, &first_utxo.address)?;
+    Utxo::exists_by_address(utxo_read_ctx, &first_utxo.address)?;
     Utxo::take(utxo_read_ctx, 100)?;
     Utxo::get(utxo_read_ctx, first_utxo.id)?;
     Utxo::range(utxo_read_ctx, first_utxo.id, last_utxo.id, None)?;
@@ -84,23 +87,23 @@ async fn main() -> Result<()> {
     Asset::parent_key(first_asset.id)?;
 
     /* Streaming examples */
-    Block::stream_range(Block::begin_read_ctx(&storage)?, first_block.height, last_block.height, None)?.try_collect::<Vec<Block>>().await?;
-    Header::stream_by_hash(Header::begin_read_ctx(&storage)?, first_block_header.hash, None)?.try_collect::<Vec<Header>>().await?;
-    Header::stream_by_timestamp(Header::begin_read_ctx(&storage)?, first_block_header.timestamp, None)?.try_collect::<Vec<Header>>().await?;
-    Header::stream_range(Header::begin_read_ctx(&storage)?, first_block_header.height, last_block_header.height, None)?.try_collect::<Vec<Header>>().await?;
+    Block::stream_range(Block::begin_read_ctx(&storage)?, first_block.height, last_block.height, PageQuery::default(), Projection::default(), None)?.try_collect::<Vec<Block>>().await?;
+    Header::stream_by_hash(Header::begin_read_ctx(&storage)?, first_block_header.hash, PageQuery::default(), Projection::default(), None)?.try_collect::<Vec<Header>>().await?;
+    Header::stream_by_timestamp(Header::begin_read_ctx(&storage)?, first_block_header.timestamp, PageQuery::default(), Projection::default(), None)?.try_collect::<Vec<Header>>().await?;
+    Header::stream_range(Header::begin_read_ctx(&storage)?, first_block_header.height, last_block_header.height, PageQuery::default(), Projection::default(), None)?.try_collect::<Vec<Header>>().await?;
     Header::stream_range_by_timestamp(Header::begin_read_ctx(&storage)?, first_block_header.timestamp, last_block_header.timestamp, None)?.try_collect::<Vec<Header>>().await?;
-    Transaction::stream_ids_by_hash(Transaction::begin_read_ctx(&storage)?, first_transaction.hash)?.try_collect::<Vec<BlockPointer>>().await?;
-    Transaction::stream_by_hash(Transaction::begin_read_ctx(&storage)?, first_transaction.hash, None)?.try_collect::<Vec<Transaction>>().await?;
-    Transaction::stream_range(Transaction::begin_read_ctx(&storage)?, first_transaction.id, last_transaction.id, None)?.try_collect::<Vec<Transaction>>().await?;
-    Utxo::stream_ids_by_address(Utxo::begin_read_ctx(&storage)?, first_utxo.address.clone())?.try_collect::<Vec<TransactionPointer>>().await?;
-    Utxo::stream_range(Utxo::begin_read_ctx(&storage)?, first_utxo.id, last_utxo.id, None)?.try_collect::<Vec<Utxo>>().await?;
-    Utxo::stream_by_address(Utxo::begin_read_ctx(&storage)?, first_utxo.address.clone(), None)?.try_collect::<Vec<Utxo>>().await?;
+    Transaction::stream_ids_by_hash(Transaction::begin_read_ctx(&storage)?, first_transaction.hash, PageQuery::default())?.try_collect::<Vec<BlockPointer>>().await?;
+    Transaction::stream_by_hash(Transaction::begin_read_ctx(&storage)?, first_transaction.hash, PageQuery::default(), Projection::default(), None)?.try_collect::<Vec<Transaction>>().await?;
+    Transaction::stream_range(Transaction::begin_read_ctx(&storage)?, first_transaction.id, last_transaction.id, PageQuery::default(), Projection::default(), None)?.try_collect::<Vec<Transaction>>().await?;
+    Utxo::stream_ids_by_address(Utxo::begin_read_ctx(&storage)?, first_utxo.address.clone(), PageQuery::default())?.try_collect::<Vec<TransactionPointer>>().await?;
+    Utxo::stream_range(Utxo::begin_read_ctx(&storage)?, first_utxo.id, last_utxo.id, PageQuery::default(), Projection::default(), None)?.try_collect::<Vec<Utxo>>().await?;
+    Utxo::stream_by_address(Utxo::begin_read_ctx(&storage)?, first_utxo.address.clone(), PageQuery::default(), Projection::default(), None)?.try_collect::<Vec<Utxo>>().await?;
     // streaming parents
-    Utxo::stream_transactions_by_address(Transaction::begin_read_ctx(&storage)?, first_utxo.address, None)?.try_collect::<Vec<Transaction>>().await?;
-    Asset::stream_by_name(Asset::begin_read_ctx(&storage)?, first_asset.name.clone(), None)?.try_collect::<Vec<Asset>>().await?;
-    Asset::stream_range(Asset::begin_read_ctx(&storage)?, first_asset.id, last_asset.id, None)?.try_collect::<Vec<Asset>>().await?;
+    Utxo::stream_transactions_by_address(Transaction::begin_read_ctx(&storage)?, first_utxo.address, PageQuery::default(), Projection::default(), None)?.try_collect::<Vec<Transaction>>().await?;
+    Asset::stream_by_name(Asset::begin_read_ctx(&storage)?, first_asset.name.clone(), PageQuery::default(), Projection::default(), None)?.try_collect::<Vec<Asset>>().await?;
+    Asset::stream_range(Asset::begin_read_ctx(&storage)?, first_asset.id, last_asset.id, PageQuery::default(), Projection::default(), None)?.try_collect::<Vec<Asset>>().await?;
     // streaming parents
-    Asset::stream_utxos_by_name(Utxo::begin_read_ctx(&storage)?, first_asset.name, None)?.try_collect::<Vec<Utxo>>().await?;
+    Asset::stream_utxos_by_name(Utxo::begin_read_ctx(&storage)?, first_asset.name, PageQuery::default(), Projection::default(), None)?.try_collect::<Vec<Utxo>>().await?;
 
     println!("\nDeleting blocks:");
     for height in block_heights.into_iter() {
@@ -130,7 +130,7 @@ mod tests {
         let transaction_tx = Transaction::begin_read_ctx(&storage).unwrap();
         let transaction = blocks.first().unwrap().transactions.first().unwrap();
 
-        let found_by_hash = Transaction::stream_by_hash(transaction_tx, transaction.hash.clone(), None).unwrap().try_collect::<Vec<Transaction>>().await.unwrap();
+        let found_by_hash = Transaction::stream_by_hash(transaction_tx, transaction.hash.clone(), PageQuery::default(), Projection::default(), None).unwrap().try_collect::<Vec<Transaction>>().await.unwrap();
         assert_eq!(found_by_hash.len(), 1);
         assert!(found_by_hash.iter().any(|tx| tx.id == transaction.id));
         assert!(found_by_hash.iter().any(|tx| tx.id == transaction.id));
@@ -143,12 +143,69 @@ mod tests {
         let utxo_tx = Utxo::begin_read_ctx(&storage).unwrap();
         let utxo = blocks.first().unwrap().transactions.first().unwrap().utxos.first().unwrap();
 
-        let found_by_address = Utxo::stream_by_address(utxo_tx, utxo.address.clone(), None).unwrap().try_collect::<Vec<Utxo>>().await.unwrap();
+        let found_by_address = Utxo::stream_by_address(utxo_tx, utxo.address.clone(), PageQuery::default(), Projection::default(), None).unwrap().try_collect::<Vec<Utxo>>().await.unwrap();
         assert_eq!(found_by_address.len(), 1);
         assert!(found_by_address.iter().any(|tx| tx.id == utxo.id));
         assert!(found_by_address.iter().any(|tx| tx.id == utxo.id));
     }
 
+    #[tokio::test]
+    async fn it_should_page_entities_in_descending_order() {
+        let (blocks, _storage_owner, storage) = init_temp_storage("db_test", 0).await;
+        let from = blocks.first().unwrap().height;
+        let until = blocks.last().unwrap().height.next_index();
+
+        let first_page = Block::stream_range(Block::begin_read_ctx(&storage).unwrap(), from, until, PageQuery::desc(2), Projection::default(), None).unwrap();
+        let cursor = first_page.cursor.clone().expect("Expected a cursor as one block remains");
+        let first_heights: Vec<Height> = first_page.try_collect::<Vec<Block>>().await.unwrap().iter().map(|b| b.height).collect();
+        assert_eq!(first_heights, vec![blocks[2].height, blocks[1].height]);
+
+        let last_page = Block::stream_range(Block::begin_read_ctx(&storage).unwrap(), from, until, PageQuery::desc(2).after(cursor), Projection::default(), None).unwrap();
+        assert!(last_page.cursor.is_none());
+        let last_heights: Vec<Height> = last_page.try_collect::<Vec<Block>>().await.unwrap().iter().map(|b| b.height).collect();
+        assert_eq!(last_heights, vec![blocks[0].height]);
+    }
+
+    #[tokio::test]
+    async fn it_should_load_only_projected_fields() {
+        let (blocks, _storage_owner, storage) = init_temp_storage("db_test", 0).await;
+        let block = blocks.first().unwrap();
+        let block_tx = Block::begin_read_ctx(&storage).unwrap();
+
+        let query = ProjectionQuery { fields: Some("header.hash".to_string()), expand: Some("header".to_string()) };
+        let projection = Block::projection(&query.paths()).unwrap();
+        let projected = Block::get_projected(&block_tx, block.height, &projection).unwrap().unwrap();
+        assert_eq!(projected.height, block.height);
+        assert_eq!(projected.header.hash, block.header.hash);
+        assert_eq!(projected.header.timestamp, Timestamp::default(), "Unselected columns keep their default value");
+        assert!(projected.transactions.is_empty(), "Relationships not expanded are not loaded");
+
+        let query = ProjectionQuery { fields: None, expand: Some("headers".to_string()) };
+        assert!(matches!(Block::projection(&query.paths()), Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn it_should_count_entities_without_loading_keys() {
+        let (blocks, _storage_owner, storage) = init_temp_storage("db_test", 0).await;
+        let block_tx = Block::begin_read_ctx(&storage).unwrap();
+        let from = blocks.first().unwrap().height;
+        let until = blocks.last().unwrap().height.next_index();
+        assert_eq!(Block::count(&block_tx, from, until).unwrap(), blocks.len() as u64);
+
+        let utxo_tx = &block_tx.transactions.utxos;
+        let utxo = blocks.first().unwrap().transactions.first().unwrap().utxos.first().unwrap();
+        let expected = blocks.iter().flat_map(|b| b.transactions.iter().flat_map(|t| t.utxos.iter())).filter(|u| u.address == utxo.address).count();
+        assert_eq!(Utxo::count_by_address(utxo_tx, &utxo.address).unwrap(), expected as u64);
+        assert!(Utxo::exists_by_address(utxo_tx, &utxo.address).unwrap());
+
+        let tx_tx = &block_tx.transactions;
+        let transaction = blocks.first().unwrap().transactions.first().unwrap();
+        let expected = blocks.iter().flat_map(|b| b.transactions.iter()).filter(|t| t.hash == transaction.hash).count();
+        assert_eq!(Transaction::count_by_hash(tx_tx, &transaction.hash).unwrap(), expected as u64);
+        assert_eq!(Transaction::count_by_hash(tx_tx, &TxHash([u8::MAX; 32])).unwrap(), 0);
+        assert!(!Transaction::exists_by_hash(tx_tx, &TxHash([u8::MAX; 32])).unwrap());
+    }
+
     #[tokio::test]
     async fn store_many_utxos() {
         let (blocks, _storage_owner, storage) = init_temp_storage("db_test", 0).await;
@@ -178,6 +235,92 @@ mod tests {
         assert_eq!(expected_blocks, found_by_timestamp_range);
     }
 
+    #[tokio::test]
+    async fn it_should_get_and_range_entities_by_composite_index() {
+        let (blocks, _storage_owner, storage) = init_temp_storage("db_test", 0).await;
+        let block = blocks.first().unwrap();
+        let utxo = block.transactions.first().unwrap().utxos.first().unwrap();
+
+        let utxo_tx = Utxo::begin_read_ctx(&storage).unwrap();
+        let found_by_address_and_amount = Utxo::get_by_address_and_amount(&utxo_tx, &utxo.address, &utxo.amount).expect("Failed to query by composite index");
+        assert!(found_by_address_and_amount.iter().all(|u| u.address == utxo.address && u.amount == utxo.amount));
+        assert!(found_by_address_and_amount.iter().any(|u| u.id == utxo.id));
+
+        let found_by_range = Utxo::range_by_address_and_amount(&utxo_tx, &utxo.address, &utxo.amount, &(utxo.amount + 1)).expect("Failed to range by composite index");
+        assert_eq!(found_by_address_and_amount, found_by_range);
+        let found_below = Utxo::range_by_address_and_amount(&utxo_tx, &utxo.address, &0, &utxo.amount).expect("Failed to range by composite index");
+        assert!(found_below.iter().all(|u| u.address == utxo.address && u.amount < utxo.amount));
+
+        Block::remove(Arc::clone(&storage), block.height).expect("Failed to delete by ID");
+        let utxo_tx = Utxo::begin_read_ctx(&storage).unwrap();
+        let found_after_removal = Utxo::get_by_address_and_amount(&utxo_tx, &utxo.address, &utxo.amount).expect("Failed to query by composite index");
+        assert!(found_after_removal.iter().all(|u| u.id != utxo.id));
+    }
+
+    #[tokio::test]
+    async fn it_should_filter_entities_by_boolean_groups() {
+        let (blocks, _storage_owner, storage) = init_temp_storage("db_test", 0).await;
+        let all_utxos = blocks.iter().flat_map(|b| b.transactions.iter().flat_map(|t| t.utxos.clone())).collect::<Vec<Utxo>>();
+        let (address_a, address_b) = (all_utxos[0].address.clone(), all_utxos[4].address.clone());
+        let dust = all_utxos[0].amount.max(all_utxos[4].amount);
+
+        // address = A OR address = B, excluding amount < dust
+        let query = UtxoFilterQuery {
+            or: Some(vec![
+                UtxoFilterQuery { address: Some(FilterOp::Eq(address_a.clone())), ..Default::default() },
+                UtxoFilterQuery { address: Some(FilterOp::Eq(address_b.clone())), ..Default::default() },
+            ]),
+            not: Some(Box::new(UtxoFilterQuery { amount: Some(FilterOp::Lt(dust)), ..Default::default() })),
+            ..Default::default()
+        };
+        let expected: Vec<Utxo> =
+            all_utxos.iter().filter(|u| (u.address == address_a || u.address == address_b) && u.amount >= dust).cloned().collect();
+        assert!(!expected.is_empty());
+
+        let utxo_tx = Utxo::begin_read_ctx(&storage).unwrap();
+        let found: Vec<Utxo> = all_utxos.iter().filter_map(|u| Utxo::filter(&utxo_tx, u.id, &query).unwrap()).collect();
+        assert_eq!(expected, found);
+
+        let json = serde_json::to_value(&query).unwrap();
+        assert!(json.get("$or").is_some() && json.get("$not").is_some());
+        let parsed: UtxoFilterQuery = serde_json::from_value(json).unwrap();
+        assert!(found.iter().all(|u| parsed.matches(u)));
+    }
+
+    #[tokio::test]
+    async fn it_should_find_entities_driven_by_the_most_selective_column() {
+        let (blocks, _storage_owner, storage) = init_temp_storage("db_test", 0).await;
+        let all_utxos = blocks.iter().flat_map(|b| b.transactions.iter().flat_map(|t| t.utxos.clone())).collect::<Vec<Utxo>>();
+        let addresses = vec![all_utxos[0].address.clone(), all_utxos[4].address.clone()];
+        let min_amount = all_utxos[0].amount.min(all_utxos[4].amount);
+
+        // utxos where address in (X, Y) and amount > Z
+        let query = UtxoFilterQuery { address: Some(FilterOp::In(addresses.clone())), amount: Some(FilterOp::Gt(min_amount)), ..Default::default() };
+        let utxo_tx = Utxo::begin_read_ctx(&storage).unwrap();
+        let plan = Utxo::explain(&utxo_tx, &query).unwrap();
+        assert_eq!((plan.scan, plan.column.as_deref()), (ScanKind::Dictionary, Some("address")));
+        assert_eq!(plan.residual, vec!["amount"]);
+        assert_eq!(plan.estimated_keys, Some(2));
+        let expected: Vec<Utxo> = all_utxos.iter().filter(|u| addresses.contains(&u.address) && u.amount > min_amount).cloned().collect();
+        assert!(!expected.is_empty());
+        assert_eq!(Utxo::find(&utxo_tx, &query, 100).unwrap(), expected);
+
+        let headers: Vec<Header> = blocks.iter().map(|b| b.header.clone()).collect();
+        let header_tx = Header::begin_read_ctx(&storage).unwrap();
+        let by_range = HeaderFilterQuery { timestamp: Some(FilterOp::Ge(headers[1].timestamp)), ..Default::default() };
+        let range_plan = Header::explain(&header_tx, &by_range).unwrap();
+        assert_eq!((range_plan.scan, range_plan.estimated_keys), (ScanKind::Range, Some(2)));
+        assert_eq!(Header::find(&header_tx, &by_range, 100).unwrap(), headers[1..].to_vec());
+
+        // a single hash is more selective than the two timestamps of the range
+        let by_hash = HeaderFilterQuery { hash: Some(FilterOp::Eq(headers[2].hash)), ..by_range };
+        let hash_plan = Header::explain(&header_tx, &by_hash).unwrap();
+        assert_eq!((hash_plan.scan, hash_plan.column.as_deref(), hash_plan.candidates.len()), (ScanKind::Index, Some("hash"), 2));
+        assert_eq!(Header::find(&header_tx, &by_hash, 100).unwrap(), vec![headers[2].clone()]);
+        let streamed = Header::stream_find(header_tx, by_hash, 100).unwrap().try_collect::<Vec<Header>>().await.unwrap();
+        assert_eq!(streamed, vec![headers[2].clone()]);
+    }
+
     #[tokio::test]
     async fn it_should_get_entities_by_index() {
         let (blocks, _storage_owner, storage) = init_temp_storage("db_test", 0).await;
@@ -204,6 +347,37 @@ mod tests {
         assert!(found_by_address.iter().any(|tx| tx.id == utxo.id));
     }
 
+    #[tokio::test]
+    async fn it_should_store_compressed_addresses_and_find_them_by_value() {
+        let (_storage_owner, storage) = StorageOwner::temp("db_test_zstd", 0, true).await.unwrap();
+        let mut blocks = Block::sample_many(Default::default(), 3);
+        for (i, utxo) in blocks.iter_mut().flat_map(|b| b.transactions.iter_mut()).flat_map(|t| t.utxos.iter_mut()).enumerate() {
+            utxo.address = Address(format!("addr1q{}{}", "9x8y7z".repeat(20), i % 4).into_bytes());
+        }
+        let ctx = Block::begin_write_ctx(&storage, Durability::None).unwrap();
+        ctx.two_phase_commit_or_rollback_and_close_with(|tx_context| {
+            Block::store_many(&tx_context, blocks.clone(), true)?;
+            Ok(())
+        }).expect("Failed to persist sample blocks");
+
+        let utxo_tx = Utxo::begin_read_ctx(&storage).unwrap();
+        let utxo = blocks.last().unwrap().transactions.last().unwrap().utxos.last().unwrap();
+        assert_eq!(Utxo::get(&utxo_tx, utxo.id).unwrap(), Some(utxo.clone()));
+        let found_by_address = Utxo::get_by_address(&utxo_tx, &utxo.address).expect("Failed to query by address");
+        assert!(found_by_address.iter().all(|u| u.address == utxo.address));
+        assert!(found_by_address.iter().any(|u| u.id == utxo.id));
+
+        // the composite index is keyed by the raw address
+        let found_by_address_and_amount = Utxo::get_by_address_and_amount(&utxo_tx, &utxo.address, &utxo.amount).expect("Failed to query by composite index");
+        assert!(found_by_address_and_amount.iter().any(|u| u.id == utxo.id));
+        let found_by_range = Utxo::range_by_address_and_amount(&utxo_tx, &utxo.address, &utxo.amount, &(utxo.amount + 1)).expect("Failed to range by composite index");
+        assert_eq!(found_by_address_and_amount, found_by_range);
+
+        let address_info = &Utxo::table_info(&storage).unwrap().address[0];
+        assert_eq!(address_info.table_entries, 4);
+        assert!(address_info.stored_value_bytes * 2 < address_info.raw_value_bytes, "{:?}", address_info);
+    }
+
     #[tokio::test]
     async fn it_should_get_entities_by_range_on_pk() {
         let (blocks, _storage_owner, storage) = init_temp_storage("db_test", 0).await;
@@ -282,6 +456,129 @@ mod tests {
         assert_eq!(&block, &loaded_block2);
     }
 
+    #[tokio::test]
+    async fn it_should_find_and_repair_orphaned_children() {
+        let (_blocks, _storage_owner, storage) = init_temp_storage("db_test", 0).await;
+        let orphans = |report: &redbit::FsckReport| report.issues.iter().filter(|i| i.subject == "Utxo" && i.problem.contains("has no parent")).count();
+        assert_eq!(orphans(&redbit::fsck(&storage, false).expect("fsck")), 0);
+
+        let missing_tx = TransactionPointer::from_parent(BlockPointer::from_parent(Height(99), 0), 0);
+        Utxo::persist(Arc::clone(&storage), Utxo { id: missing_tx, ..Utxo::sample() }).expect("Failed to persist orphan");
+
+        let report = redbit::fsck(&storage, false).expect("fsck");
+        assert_eq!(orphans(&report), 1, "{report}");
+        assert!(!report.is_clean());
+
+        let repaired = redbit::fsck(&storage, true).expect("repair");
+        assert!(repaired.issues.iter().filter(|i| i.subject == "Utxo").all(|i| i.repaired), "{repaired}");
+        let utxo_tx = Utxo::begin_read_ctx(&storage).unwrap();
+        assert!(!Utxo::exists(&utxo_tx, missing_tx).unwrap());
+        assert_eq!(orphans(&redbit::fsck(&storage, false).expect("fsck")), 0);
+    }
+
+    #[tokio::test]
+    async fn it_should_read_but_not_write_read_only_storage() {
+        let db_dir = std::env::temp_dir().join(format!("redbit/db_test_read_only_{}", rand::random::<u64>()));
+        let blocks = Block::sample_many(Default::default(), 3);
+        {
+            let (_, storage_owner, storage) = StorageOwner::build_storage(redbit::DbLayout::new(db_dir.clone()), 0).await.unwrap();
+            let ctx = Block::begin_write_ctx(&storage, Durability::Immediate).unwrap();
+            ctx.two_phase_commit_or_rollback_and_close_with(|tx_context| {
+                Block::store_many(&tx_context, blocks.clone(), true)?;
+                Ok(())
+            }).expect("Failed to persist sample blocks");
+            let next_to_writer = StorageOwner::open_read_only(redbit::DbLayout::new(db_dir.clone()), 0).await;
+            assert!(matches!(next_to_writer, Err(AppError::ReadOnly(_))), "redb cannot read a db opened by a writer");
+            drop(storage);
+            storage_owner.assert_last_refs();
+        }
+
+        let (_owner, storage) = StorageOwner::open_read_only(redbit::DbLayout::new(db_dir.clone()), 0).await.unwrap();
+        let (_other_owner, other) = StorageOwner::open_read_only(redbit::DbLayout::new(db_dir.clone()), 0).await.unwrap();
+        for storage in [&storage, &other] {
+            let block_tx = Block::begin_read_ctx(storage).unwrap();
+            assert_eq!(Block::last(&block_tx).unwrap().unwrap().height, blocks.last().unwrap().height);
+        }
+        assert!(matches!(Block::begin_write_ctx(&storage, Durability::Immediate), Err(AppError::ReadOnly(_))));
+        assert!(matches!(Block::new_write_ctx(&storage), Err(AppError::ReadOnly(_))));
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_a_second_key_for_a_unique_index_value() {
+        let (blocks, _storage_owner, storage) = init_temp_storage("db_test_unique", 0).await;
+        let mut duplicate = Block::sample_many(Default::default(), 4).pop().unwrap();
+        duplicate.header.hash = blocks[0].header.hash;
+
+        let ctx = Block::begin_write_ctx(&storage, Durability::None).unwrap();
+        let result = ctx.two_phase_commit_or_rollback_and_close_with(|tx_context| {
+            Block::store_many(&tx_context, vec![duplicate.clone()], true)?;
+            Ok(())
+        });
+        assert!(matches!(result, Err(AppError::UniqueViolation(_))), "unexpected result {:?}", result.err());
+
+        let header_tx = Header::begin_read_ctx(&storage).unwrap();
+        assert_eq!(Header::get_by_hash(&header_tx, &blocks[0].header.hash).unwrap(), Some(blocks[0].header.clone()));
+        assert!(Header::get(&header_tx, duplicate.height).unwrap().is_none(), "the failed block must be rolled back");
+
+        duplicate.header.hash = BlockHash([0xAB; 32]);
+        let ctx = Block::begin_write_ctx(&storage, Durability::None).unwrap();
+        ctx.two_phase_commit_or_rollback_and_close_with(|tx_context| {
+            Block::store_many(&tx_context, vec![duplicate.clone()], true)?;
+            Ok(())
+        }).expect("Failed to persist the corrected block");
+        let header_tx = Header::begin_read_ctx(&storage).unwrap();
+        assert_eq!(Header::get_by_hash(&header_tx, &duplicate.header.hash).unwrap(), Some(duplicate.header.clone()));
+    }
+
+    #[tokio::test]
+    async fn it_should_answer_index_lookups_through_persisted_bloom_filters() {
+        let db_dir = std::env::temp_dir().join(format!("redbit/db_test_bloom_{}", rand::random::<u64>()));
+        let blocks = Block::sample_many(Default::default(), 3);
+        let known = blocks.last().unwrap().transactions.first().unwrap().clone();
+        let unknown = TxHash([0xEE; 32]);
+        for reopened in [false, true] {
+            let (_, storage_owner, storage) = StorageOwner::build_storage(redbit::DbLayout::new(db_dir.clone()), 0).await.unwrap();
+            let loaded: Vec<usize> = storage.index_dbs.keys().filter_map(|name| storage.bloom_filters.shard_filters(name).unwrap()).map(|f| f.len()).collect();
+            assert_eq!(loaded, vec![3], "bloom filters are loaded with the storage, not on first query");
+            if !reopened {
+                let ctx = Block::begin_write_ctx(&storage, Durability::Immediate).unwrap();
+                ctx.two_phase_commit_or_rollback_and_close_with(|tx_context| {
+                    Block::store_many(&tx_context, blocks.clone(), true)?;
+                    Ok(())
+                }).expect("Failed to persist sample blocks");
+            }
+            let tx_context = Transaction::begin_read_ctx(&storage).unwrap();
+            assert_eq!(Transaction::get_by_hash(&tx_context, &known.hash).unwrap(), vec![known.clone()]);
+            assert!(Transaction::get_by_hash(&tx_context, &unknown).unwrap().is_empty());
+            drop(tx_context);
+            drop(storage);
+            storage_owner.close().unwrap();
+        }
+        let bloom_files = std::fs::read_dir(&db_dir).unwrap()
+            .filter(|e| e.as_ref().unwrap().path().extension().is_some_and(|ext| ext == "bloom"))
+            .count();
+        assert_eq!(bloom_files, 3, "one bloom filter per shard of the transaction hash index");
+    }
+
+    #[tokio::test]
+    async fn it_should_pin_reads_to_the_last_committed_height() {
+        let (blocks, _storage_owner, storage) = init_temp_storage("db_test_pinned", 0).await;
+        let guard = storage.commit_fence.enter();
+        let reader = {
+            let storage = Arc::clone(&storage);
+            std::thread::spawn(move || Transaction::begin_pinned_read_ctx(&storage).map(|pinned| pinned.height))
+        };
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!reader.is_finished(), "pinned read must wait for the commit in flight");
+        drop(guard);
+        let last_height = blocks.last().unwrap().height.0 as u128;
+        assert_eq!(reader.join().unwrap().unwrap(), Some(last_height));
+
+        let pinned = Block::begin_pinned_read_ctx(&storage).unwrap();
+        let last_block = Block::last(&pinned.value).unwrap().unwrap();
+        assert_eq!(pinned.height, Some(last_block.height.0 as u128));
+    }
+
     #[tokio::test]
     async fn it_should_get_first_and_last_entity() {
         let (blocks, _storage_owner, storage) = init_temp_storage("db_test", 0).await;
@@ -1,5 +1,6 @@
 use bech32::{hrp, segwit};
 use redbit::ByteVecColumnSerde;
+use redbit::codec::BitPrefix;
 use serde::{Deserialize, Deserializer, Serializer};
 use serde_with::{DeserializeAs, SerializeAs};
 
@@ -135,6 +136,10 @@ impl ByteVecColumnSerde for BaseOrBech {
         payload.extend(std::iter::repeat(0x22u8).take(20));
         bs58::encode(payload).with_check().into_string()
     }
+
+    fn prefix(prefix: &str) -> Option<BitPrefix> {
+        bech32_prefix(prefix)
+    }
 }
 
 pub const TAG_SEGWIT: u8 = 0xB0;
@@ -157,6 +162,13 @@ fn looks_bech32_addr(s: &str) -> bool {
     has_prefix_icase(s, b"ltc1") || has_prefix_icase(s, b"tltc1")
 }
 
+fn bech32_prefix(prefix: &str) -> Option<BitPrefix> {
+    if !looks_bech32_addr(prefix) {
+        return None;
+    }
+    chain::segwit::segwit_prefix(prefix, TAG_SEGWIT)
+}
+
 fn encode_tagged_segwit<S: Serializer>(src: &[u8], ser: S) -> Result<S::Ok, S::Error> {
     // src = [TAG_SEGWIT, ver, program]
     let ver = src[1];
@@ -292,4 +304,23 @@ mod tests {
         assert_eq!(roundtrip_json(&ltc), ltc);
         assert_eq!(original.0, ltc.0);
     }
+
+    #[test]
+    fn bech32_prefix_bounds_the_addresses_it_starts() {
+        let program = vec![0x75; 20];
+        let address = segwit::encode(hrp::Hrp::parse_unchecked("ltc"), segwit::VERSION_0, &program).unwrap();
+        let mut stored = vec![TAG_SEGWIT, 0];
+        stored.extend_from_slice(&program);
+
+        assert_eq!(BaseOrBech::prefix(&address).unwrap().bounds().0, stored);
+        for len in "ltc1".len()..address.len() {
+            let (from, until) = BaseOrBech::prefix(&address[..len]).unwrap().bounds();
+            assert!(from <= stored && stored < until.unwrap(), "prefix {} must contain {}", &address[..len], address);
+        }
+        let mut other = vec![TAG_SEGWIT, 0];
+        other.extend_from_slice(&[0x76; 20]);
+        let (_, until) = BaseOrBech::prefix(&address[..20]).unwrap().bounds();
+        assert!(other >= until.unwrap());
+        assert!(BaseOrBech::prefix(&BaseOrBech::encoded_example()).is_none());
+    }
 }
@@ -2,16 +2,24 @@ use crate::column::column_codec::*;
 use crate::macro_utils;
 use crate::macro_utils::InnerKind;
 use proc_macro2::{Ident, Literal, TokenStream};
+use proc_macro_error::abort;
 use quote::quote;
 use syn::punctuated::Punctuated;
 use syn::token::Comma;
 use syn::{parse_str, Attribute, FieldsNamed, Path, Type};
 
+/// `compress = "zstd"` of a newtype column, `dict` is a path relative to the crate manifest embedded at compile time.
+pub struct ZstdAttr {
+    pub level: Option<i32>,
+    pub dict: Option<syn::LitStr>,
+}
+
 pub fn generate_column_impls(
     struct_ident: &Ident,
     new_type: &Type,
     inner_type: &Type,
     binary_encoding_opt: Option<String>,
+    mut compression: Option<ZstdAttr>,
 ) -> (TokenStream, Option<Attribute>, Punctuated<Path, Comma>) {
     let kind = macro_utils::classify_inner_type(inner_type);
 
@@ -25,9 +33,24 @@ pub fn generate_column_impls(
     let mut iterable_code = quote! { compile_error!("Sampleable::next is not supported for this type.") };
     let mut custom_db_codec = quote! {};
     let mut cache_key_codec = quote! {};
+    let mut prefix_range_code = quote! { redbit::codec::exact_range(prefix) };
 
     match kind {
         InnerKind::ByteArray(len) => {
+            let prefix_codec = match binary_encoding.as_ref() {
+                "hex" => Some(quote! { redbit::codec::Hex }),
+                "base64" => Some(quote! { redbit::codec::Base64 }),
+                "utf-8" => Some(quote! { redbit::codec::Utf8 }),
+                _ => None,
+            };
+            if let Some(codec) = prefix_codec {
+                prefix_range_code = quote! {
+                    match <#codec as ByteVecColumnSerde>::prefix(prefix) {
+                        Some(bits) => bits.array_range::<#len, _>(Self),
+                        None => redbit::codec::exact_range(prefix),
+                    }
+                };
+            }
             let(encoding, example) = match binary_encoding.as_ref() {
                 "hex" => ("serde_with::hex::Hex", quote! { Self([0u8; #len]) }),
                 "base64" => ("serde_with::base64::Base64", quote! { Self([0u8; #len]) }),
@@ -66,11 +89,30 @@ pub fn generate_column_impls(
             default_code = quote! { Self(<#ty as ByteVecColumnSerde>::decoded_example()) };
             struct_attr = Some(syn::parse_quote! { #[serde_as(as = #binary_encoding_literal)] });
             url_encoded_code = quote! { serde_json::to_string(&self).unwrap().trim_matches('"').to_string() };
-            custom_db_codec =  quote ! { impl_redb_newtype_vec!(#new_type); };
-            cache_key_codec = quote! { impl_cachekey_vec!(#new_type); };
+            match compression.take() {
+                Some(ZstdAttr { level, dict }) => {
+                    let level = level.map(|l| quote! { #l }).unwrap_or_else(|| quote! { redbit::compress::DEFAULT_ZSTD_LEVEL });
+                    let dict = match dict {
+                        Some(path) => quote! { include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/", #path)) },
+                        None => quote! { &[] },
+                    };
+                    custom_db_codec = quote! { impl_redb_newtype_vec_zstd!(#new_type, #level, #dict); };
+                    cache_key_codec = quote! { impl_cachekey_vec_zstd!(#new_type); };
+                }
+                None => {
+                    custom_db_codec = quote! { impl_redb_newtype_vec!(#new_type); };
+                    cache_key_codec = quote! { impl_cachekey_vec!(#new_type); };
+                }
+            }
             iterable_code = quote! {
                 Self(<#ty as ByteVecColumnSerde>::next_value(&self.0))
             };
+            prefix_range_code = quote! {
+                match <#ty as ByteVecColumnSerde>::prefix(prefix) {
+                    Some(bits) => Ok(bits.range(Self)),
+                    None => redbit::codec::exact_range(prefix),
+                }
+            };
         }
         InnerKind::Integer(int_type) => {
             schema_type = quote! { SchemaType::Type(Type::Integer) };
@@ -153,6 +195,9 @@ pub fn generate_column_impls(
             // leave defaults (compile error for next)
         }
     }
+    if compression.is_some() {
+        abort!(struct_ident, "`compress = \"zstd\"` applies to `Vec<u8>` columns only, fixed-width and integer values do not compress");
+    }
 
     let impls = quote! {
         #custom_db_codec
@@ -168,6 +213,12 @@ pub fn generate_column_impls(
             }
         }
 
+        impl PrefixEncoded for #struct_ident {
+            fn prefix_range(prefix: &str) -> Result<(Bound<Self>, Bound<Self>), AppError> {
+                #prefix_range_code
+            }
+        }
+
         impl Default for #struct_ident {
             fn default() -> Self {
                 #default_code
@@ -0,0 +1,50 @@
+use super::CompositeColumns;
+use crate::field_parser::EntityDef;
+use crate::rest::FunctionDef;
+use proc_macro2::Ident;
+use quote::{format_ident, quote};
+
+pub fn get_by_def(entity_def: &EntityDef, index_name: &Ident, columns: &CompositeColumns, index_table: &Ident) -> FunctionDef {
+    let fn_name = format_ident!("get_by_{}", index_name);
+    let EntityDef { entity_name, entity_type, read_ctx_type, .. } = &entity_def;
+    let CompositeColumns { names, types, .. } = columns;
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: &#read_ctx_type, #(#names: &#types),*) -> Result<Vec<#entity_type>, AppError> {
+            let iter = tx_context.#index_table.index_keys((#(#names.clone()),*))?.into_iter().flatten().map(|res| res.map(|kg| kg.value()));
+            Self::compose_many(&tx_context, iter, None)
+        }
+    };
+
+    let test_stream = Some(quote! {
+        #[test]
+        fn #fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            #(let #names = <#types as Default>::default();)*
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let entities = #entity_name::#fn_name(&tx_context, #(&#names),*)?;
+            let expected_entities = vec![#entity_type::sample()];
+            assert_eq!(expected_entities, entities, "Expected entities to be returned for the given composite index");
+            Ok(())
+        }
+    });
+
+    let bench_fn_name = format_ident!("_{}", fn_name);
+    let bench_stream = Some(quote! {
+        #[bench]
+        fn #bench_fn_name(b: &mut Bencher) {
+            let (storage_owner, storage) = &*STORAGE;
+            #(let #names = <#types as Default>::default();)*
+            let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
+            b.iter(|| {
+                #entity_name::#fn_name(&tx_context, #(&#names),*).expect("Failed to get entities by composite index");
+            });
+        }
+    });
+
+    FunctionDef {
+        fn_stream,
+        endpoint: None,
+        test_stream,
+        bench_stream
+    }
+}
@@ -0,0 +1,86 @@
+mod get_by;
+mod stream_by;
+mod range_by;
+mod stream_range_by;
+
+use crate::column::{delete, info};
+use crate::entity::context;
+use crate::entity::context::TxContextItem;
+use crate::entity::info::TableInfoItem;
+use crate::entity::query::RangeQuery;
+use crate::field_parser::{CompositeIndexDef, EntityDef};
+use crate::rest::FunctionDef;
+use crate::table::IndexTableDefs;
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::Type;
+
+/// Columns of a composite index, all but the last one are matched exactly by range queries.
+pub struct CompositeColumns {
+    pub names: Vec<Ident>,
+    pub types: Vec<Type>,
+    pub value_type: Type,
+}
+
+impl CompositeColumns {
+    fn new(index_def: &CompositeIndexDef) -> Self {
+        let names: Vec<Ident> = index_def.columns.iter().map(|c| c.name.clone()).collect();
+        let types: Vec<Type> = index_def.columns.iter().map(|c| c.tpe.clone()).collect();
+        let value_type = syn::parse_quote! { (#(#types),*) };
+        CompositeColumns { names, types, value_type }
+    }
+
+    pub fn leading(&self) -> (&[Ident], &[Type]) {
+        (&self.names[..self.names.len() - 1], &self.types[..self.types.len() - 1])
+    }
+
+    pub fn last(&self) -> (&Ident, &Type) {
+        (self.names.last().unwrap(), self.types.last().unwrap())
+    }
+}
+
+pub struct DbCompositeIndexMacros {
+    pub index_tables: IndexTableDefs,
+    pub range_query: RangeQuery,
+    pub tx_context_item: TxContextItem,
+    pub table_info_item: TableInfoItem,
+    pub store_statement: TokenStream,
+    pub delete_statement: TokenStream,
+    pub delete_many_statement: TokenStream,
+    pub function_defs: Vec<FunctionDef>,
+}
+
+impl DbCompositeIndexMacros {
+    pub fn new(entity_def: &EntityDef, index_def: &CompositeIndexDef) -> DbCompositeIndexMacros {
+        let index_name = &index_def.name;
+        let pk_name = &entity_def.key_def.field_def().name;
+        let columns = CompositeColumns::new(index_def);
+        let index_tables = IndexTableDefs::new(entity_def, index_name, &columns.value_type, index_def.column_props.clone());
+        let table = &index_tables.var_name;
+        let (last_name, last_type) = columns.last();
+        let range_query = crate::entity::query::col_range_query(&entity_def.entity_name, index_name, last_type);
+
+        let names = &columns.names;
+        let store_statement = quote! {
+            tx_context.#table.insert_on_flush(instance.#pk_name, (#(instance.#names.clone()),*))?;
+        };
+
+        let function_defs = vec![
+            get_by::get_by_def(entity_def, index_name, &columns, table),
+            stream_by::stream_by_def(entity_def, index_name, &columns, table),
+            range_by::range_by_def(entity_def, index_name, &columns, table),
+            stream_range_by::stream_range_by_def(entity_def, index_name, &columns, last_name, table, &range_query.ty),
+        ];
+
+        DbCompositeIndexMacros {
+            range_query,
+            tx_context_item: context::tx_context_index_item(&index_tables),
+            table_info_item: info::index_table_info(index_name, table),
+            store_statement,
+            delete_statement: delete::delete_index_statement(table),
+            delete_many_statement: delete::delete_many_index_statement(table),
+            function_defs,
+            index_tables,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use super::CompositeColumns;
+use crate::field_parser::EntityDef;
+use crate::rest::FunctionDef;
+use proc_macro2::Ident;
+use quote::{format_ident, quote};
+
+/// Leading columns are matched exactly and the last one is ranged, the composite values of all shards are merged in order.
+pub fn range_by_def(entity_def: &EntityDef, index_name: &Ident, columns: &CompositeColumns, index_table: &Ident) -> FunctionDef {
+    let fn_name = format_ident!("range_by_{}", index_name);
+    let EntityDef { entity_name, entity_type, read_ctx_type, .. } = &entity_def;
+    let (leading_names, leading_types) = columns.leading();
+    let (_, last_type) = columns.last();
+    let value_type = &columns.value_type;
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: &#read_ctx_type, #(#leading_names: &#leading_types,)* from: &#last_type, until: &#last_type) -> Result<Vec<#entity_type>, AppError> {
+            let range = (#(#leading_names.clone(),)* from.clone())..(#(#leading_names.clone(),)* until.clone());
+            let pk_iter =
+                tx_context.#index_table.index_range::<#value_type>(range)?
+                .flat_map(|r| match r {
+                    Ok((_k, value_iter)) => Either::Left(value_iter.map(|res| res.map(|kg| kg.value()))),
+                    Err(e) => Either::Right(std::iter::once(Err(e))),
+                });
+            Self::compose_many(&tx_context, pk_iter, None)
+        }
+    };
+
+    let test_stream = Some(quote! {
+        #[test]
+        fn #fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            #(let #leading_names = <#leading_types as Default>::default();)*
+            let from_value = <#last_type as Default>::default();
+            let until_value = from_value.nth_value(2);
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let entities = #entity_name::#fn_name(&tx_context, #(&#leading_names,)* &from_value, &until_value)?;
+            let expected_entities = vec![#entity_type::sample()];
+            assert_eq!(expected_entities, entities, "Expected only entities of the leading columns to be returned for the given range");
+            Ok(())
+        }
+    });
+
+    let bench_fn_name = format_ident!("_{}", fn_name);
+    let bench_stream = Some(quote! {
+        #[bench]
+        fn #bench_fn_name(b: &mut Bencher) {
+            let (storage_owner, storage) = &*STORAGE;
+            #(let #leading_names = <#leading_types as Default>::default();)*
+            let from_value = <#last_type as Default>::default();
+            let until_value = from_value.nth_value(2);
+            let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
+            b.iter(|| {
+                #entity_name::#fn_name(&tx_context, #(&#leading_names,)* &from_value, &until_value).expect("Failed to range entities by composite index");
+            });
+        }
+    });
+
+    FunctionDef {
+        fn_stream,
+        endpoint: None,
+        test_stream,
+        bench_stream
+    }
+}
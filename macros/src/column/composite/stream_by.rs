@@ -0,0 +1,111 @@
+use super::CompositeColumns;
+use crate::endpoint::EndpointDef;
+use crate::field_parser::EntityDef;
+use crate::rest::HttpParams::{Body, Path};
+use crate::rest::{BodyExpr, EndpointTag, FunctionDef, HttpMethod, PathExpr};
+use proc_macro2::Ident;
+use quote::{format_ident, quote};
+
+pub fn stream_by_def(entity_def: &EntityDef, index_name: &Ident, columns: &CompositeColumns, index_table: &Ident) -> FunctionDef {
+    let fn_name = format_ident!("stream_by_{}", index_name);
+    let EntityDef { key_def, entity_name, entity_type, query_type, read_ctx_type, ..} = &entity_def;
+    let pk_type = key_def.field_def().tpe;
+    let CompositeColumns { names, types, .. } = columns;
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: #read_ctx_type, #(#names: #types),*, query: Option<#query_type>) -> Result<impl futures::Stream<Item = Result<#entity_type, AppError>> + Send, AppError> {
+            let iter = tx_context.#index_table.index_keys((#(#names),*))?.into_iter().flatten().map(|res| res.map(|kg| kg.value()));
+            Self::compose_many_stream(tx_context, iter, query)
+        }
+    };
+
+    let test_with_filter_fn_name = format_ident!("{}_with_filter", fn_name);
+    let test_stream = Some(quote! {
+        #[tokio::test]
+        async fn #fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let entity_stream = #entity_name::#fn_name(tx_context, #(<#types as Default>::default()),*, None)?;
+            let entities = entity_stream.try_collect::<Vec<#entity_type>>().await?;
+            let expected_entities = vec![#entity_type::sample()];
+            assert_eq!(expected_entities, entities, "Expected entities to be returned for the given composite index");
+            Ok(())
+        }
+        #[tokio::test]
+        async fn #test_with_filter_fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let pk = #pk_type::default();
+            let query = #query_type::sample();
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let entity_stream = #entity_name::#fn_name(tx_context, #(<#types as Default>::default()),*, Some(query.clone()))?;
+            let entities = entity_stream.try_collect::<Vec<#entity_type>>().await?;
+            let expected_entity = #entity_type::sample_with_query(pk, &query).expect("Failed to create sample entity");
+            assert_eq!(entities.len(), 1, "Expected only one entity to be returned");
+            assert_eq!(entities[0], expected_entity, "Composite index result is not equal to sample, query: {:?}", query);
+            Ok(())
+        }
+    });
+
+    let bench_fn_name = format_ident!("_{}", fn_name);
+    let bench_stream = Some(quote! {
+        #[bench]
+        fn #bench_fn_name(b: &mut Bencher) {
+            let (storage_owner, storage) = &*STORAGE;
+            let query = #query_type::sample();
+            let rt = Runtime::new().unwrap();
+            b.iter(|| {
+                rt.block_on(async {
+                    let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
+                    let entity_stream = #entity_name::#fn_name(tx_context, #(<#types as Default>::default()),*, Some(query.clone())).expect("Failed to get entities by composite index");
+                    entity_stream.try_collect::<Vec<#entity_type>>().await.expect("Failed to collect entity stream");
+                })
+            });
+        }
+    });
+
+    let handler_fn_name = format!("{}_{}", entity_name.to_string().to_lowercase(), fn_name);
+    let path_exprs = names.iter().zip(types.iter()).map(|(name, ty)| PathExpr {
+        name: name.clone(),
+        ty: ty.clone(),
+        description: "Composite index column".to_string(),
+        sample: quote! { <#ty as Default>::default().url_encode() },
+    }).collect::<Vec<_>>();
+    let endpoint_path = names.iter().map(|name| format!("/{}/{{{}}}", name, name)).collect::<String>();
+
+    FunctionDef {
+        fn_stream,
+        endpoint: Some(EndpointDef {
+            return_type: Some(entity_type.clone()),
+            tag: EndpointTag::DataRead,
+            fn_name: fn_name.clone(),
+            params: vec![
+                Path(path_exprs),
+                Body(BodyExpr {
+                    ty: syn::parse_quote! { Option<#query_type> },
+                    extraction: quote! { MaybeJson(body): MaybeJson<#query_type> },
+                    samples: quote! { vec![#query_type::sample()] },
+                    required: false,
+                })
+            ],
+            method: HttpMethod::POST,
+            handler_name: format_ident!("{}", handler_fn_name),
+            handler_impl_stream: quote! {
+               impl IntoResponse {
+                   match state.read_stream(move |state| #entity_name::begin_pinned_read_ctx(&state.storage)
+                        .and_then(|pinned| pinned.try_map(|tx_context| #entity_name::#fn_name(tx_context, #(#names),*, body)))).await {
+                            Ok(pinned) => pinned.map(|stream| axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap())).into_response(),
+                            Err(err)   => err.into_response(),
+                    }
+                }
+            },
+            utoipa_responses: quote! {
+                responses(
+                    (status = OK, content_type = "application/x-ndjson", body = #entity_type),
+                    (status = 500, content_type = "application/json", body = ErrorResponse),
+                )
+            },
+            endpoint: format!("/{}{}", entity_name.to_string().to_lowercase(), endpoint_path),
+        }.to_endpoint()),
+        test_stream,
+        bench_stream
+    }
+}
@@ -0,0 +1,137 @@
+use super::CompositeColumns;
+use crate::endpoint::EndpointDef;
+use crate::field_parser::EntityDef;
+use crate::rest::HttpParams::{Body, Path, Query};
+use crate::rest::{BodyExpr, EndpointTag, FunctionDef, HttpMethod, PathExpr, QueryExpr};
+use proc_macro2::Ident;
+use quote::{format_ident, quote};
+use syn::Type;
+
+pub fn stream_range_by_def(entity_def: &EntityDef, index_name: &Ident, columns: &CompositeColumns, last_name: &Ident, index_table: &Ident, range_query_ty: &Type) -> FunctionDef {
+    let fn_name = format_ident!("stream_range_by_{}", index_name);
+    let EntityDef { key_def, entity_name, entity_type, query_type, read_ctx_type, ..} = &entity_def;
+    let pk_type = &key_def.field_def().tpe;
+    let (leading_names, leading_types) = columns.leading();
+    let (_, last_type) = columns.last();
+    let value_type = &columns.value_type;
+    let fn_stream = quote! {
+        pub fn #fn_name(
+            tx_context: #read_ctx_type,
+            #(#leading_names: #leading_types,)*
+            from: #last_type,
+            until: #last_type,
+            query: Option<#query_type>,
+        ) -> Result<impl futures::Stream<Item = Result<#entity_type, AppError>> + Send, AppError> {
+            let range = (#(#leading_names.clone(),)* from)..(#(#leading_names,)* until);
+            let pk_iter =
+                tx_context.#index_table.index_range::<#value_type>(range)?
+                .flat_map(|r| match r {
+                    Ok((_k, value_iter)) => Either::Left(value_iter.map(|res| res.map(|kg| kg.value()))),
+                    Err(e) => Either::Right(std::iter::once(Err(e))),
+                });
+            Self::compose_many_stream(tx_context, pk_iter, query)
+        }
+    };
+
+    let test_with_filter_fn_name = format_ident!("{}_with_filter", fn_name);
+    let test_stream = Some(quote! {
+        #[tokio::test]
+        async fn #fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let from_value = <#last_type as Default>::default();
+            let until_value = from_value.nth_value(2);
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let entity_stream = #entity_name::#fn_name(tx_context, #(<#leading_types as Default>::default(),)* from_value, until_value, None)?;
+            let entities = entity_stream.try_collect::<Vec<#entity_type>>().await?;
+            let expected_entities = vec![#entity_type::sample()];
+            assert_eq!(expected_entities, entities, "Expected only entities of the leading columns to be returned for the given range");
+            Ok(())
+        }
+        #[tokio::test]
+        async fn #test_with_filter_fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let pk = #pk_type::default();
+            let from_value = <#last_type as Default>::default();
+            let until_value = from_value.nth_value(3);
+            let query = #query_type::sample();
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let entity_stream = #entity_name::#fn_name(tx_context, #(<#leading_types as Default>::default(),)* from_value, until_value, Some(query.clone()))?;
+            let entities = entity_stream.try_collect::<Vec<#entity_type>>().await?;
+            let expected_entity = #entity_type::sample_with_query(pk, &query).expect("Failed to create sample entity with query");
+            assert_eq!(entities.len(), 1, "Expected only one entity to be returned for the given composite range with filter");
+            assert_eq!(entities[0], expected_entity, "Composite range result is not equal to sample because it is filtered, query: {:?}", query);
+            Ok(())
+        }
+    });
+
+    let bench_fn_name = format_ident!("_{}", fn_name);
+    let bench_stream = Some(quote! {
+        #[bench]
+        fn #bench_fn_name(b: &mut Bencher) {
+            let (storage_owner, storage) = &*STORAGE;
+            let query = #query_type::sample();
+            let rt = Runtime::new().unwrap();
+            b.iter(|| {
+                rt.block_on(async {
+                    let from_value = <#last_type as Default>::default();
+                    let until_value = from_value.nth_value(3);
+                    let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
+                    let entity_stream = #entity_name::#fn_name(tx_context, #(<#leading_types as Default>::default(),)* from_value, until_value, Some(query.clone())).expect("Failed to range entities by composite index");
+                    entity_stream.try_collect::<Vec<#entity_type>>().await.expect("Failed to collect entity stream");
+                })
+            });
+        }
+    });
+
+    let handler_fn_name = format!("{}_{}", entity_name.to_string().to_lowercase(), fn_name);
+    let path_exprs = leading_names.iter().zip(leading_types.iter()).map(|(name, ty)| PathExpr {
+        name: name.clone(),
+        ty: ty.clone(),
+        description: "Leading composite index column".to_string(),
+        sample: quote! { <#ty as Default>::default().url_encode() },
+    }).collect::<Vec<_>>();
+    let endpoint_path = leading_names.iter().map(|name| format!("/{}/{{{}}}", name, name)).collect::<String>();
+
+    FunctionDef {
+        fn_stream,
+        endpoint: Some(EndpointDef {
+            return_type: Some(entity_type.clone()),
+            tag: EndpointTag::DataRead,
+            fn_name: fn_name.clone(),
+            params: vec![
+                Path(path_exprs),
+                Query(QueryExpr {
+                    ty: range_query_ty.clone(),
+                    extraction: quote! { extract::Query(query): extract::Query<#range_query_ty> },
+                    samples: quote! { vec![#range_query_ty::sample()] },
+                }),
+                Body(BodyExpr {
+                    ty: syn::parse_quote! { Option<#query_type> },
+                    extraction: quote! { MaybeJson(body): MaybeJson<#query_type> },
+                    samples: quote! { vec![#query_type::sample()] },
+                    required: false,
+                })
+            ],
+            method: HttpMethod::POST,
+            handler_name: format_ident!("{}", handler_fn_name),
+            handler_impl_stream: quote! {
+               impl IntoResponse {
+                   match state.read_stream(move |state| #entity_name::begin_pinned_read_ctx(&state.storage)
+                        .and_then(|pinned| pinned.try_map(|tx_context| #entity_name::#fn_name(tx_context, #(#leading_names,)* query.from, query.until, body)))).await {
+                            Ok(pinned) => pinned.map(|stream| axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap())).into_response(),
+                            Err(err)   => err.into_response(),
+                    }
+                }
+            },
+            utoipa_responses: quote! {
+                responses(
+                    (status = OK, content_type = "application/x-ndjson", body = #entity_type),
+                    (status = 500, content_type = "application/json", body = ErrorResponse),
+                )
+            },
+            endpoint: format!("/{}{}/{}", entity_name.to_string().to_lowercase(), endpoint_path, last_name),
+        }.to_endpoint()),
+        test_stream,
+        bench_stream
+    }
+}
@@ -0,0 +1,98 @@
+use crate::endpoint::EndpointDef;
+use crate::field_parser::EntityDef;
+use crate::rest::HttpParams::Path;
+use crate::rest::{EndpointTag, FunctionDef, HttpMethod, PathExpr};
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use syn::Type;
+
+pub fn by_dict_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Type, dict_table_var: &Ident) -> FunctionDef {
+    let read_ctx_type = &entity_def.read_ctx_type;
+    let fn_name = format_ident!("count_by_{}", column_name);
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: &#read_ctx_type, val: &#column_type) -> Result<u64, AppError> {
+            Ok(tx_context.#dict_table_var.dict_keys(val)?.map_or(0, |pks| pks.len()))
+        }
+    };
+    count_fn_def(entity_def, column_name, column_type, fn_name, fn_stream, "Secondary index column (dict)")
+}
+
+pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Type, index_table: &Ident) -> FunctionDef {
+    let read_ctx_type = &entity_def.read_ctx_type;
+    let fn_name = format_ident!("count_by_{}", column_name);
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: &#read_ctx_type, val: &#column_type) -> Result<u64, AppError> {
+            Ok(tx_context.#index_table.index_keys(val)?.map_or(0, |pks| pks.len()))
+        }
+    };
+    count_fn_def(entity_def, column_name, column_type, fn_name, fn_stream, "Secondary index column")
+}
+
+fn count_fn_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Type, fn_name: Ident, fn_stream: TokenStream, description: &str) -> FunctionDef {
+    let entity_name = &entity_def.entity_name;
+    let keys_fn_name = format_ident!("get_{}s_by_{}", entity_def.key_def.field_def().name, column_name);
+
+    let test_stream = Some(quote! {
+        #[test]
+        fn #fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let val = #column_type::default();
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let count = #entity_name::#fn_name(&tx_context, &val)?;
+            let pks = #entity_name::#keys_fn_name(&tx_context, &val)?;
+            assert_eq!(count, pks.len() as u64, "Count should match the number of keys indexed by the value");
+            Ok(())
+        }
+    });
+
+    let bench_fn_name = format_ident!("_{}", fn_name);
+    let bench_stream = Some(quote! {
+        #[bench]
+        fn #bench_fn_name(b: &mut Bencher) {
+            let (storage_owner, storage) = &*STORAGE;
+            let val = #column_type::default();
+            let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
+            b.iter(|| {
+                #entity_name::#fn_name(&tx_context, &val).expect("Failed to count entities by index");
+            });
+        }
+    });
+
+    let handler_fn_name = format!("{}_{}", entity_name.to_string().to_lowercase(), fn_name);
+
+    FunctionDef {
+        fn_stream,
+        endpoint: Some(EndpointDef {
+            return_type: Some(syn::parse_quote!(u64)),
+            tag: EndpointTag::DataRead,
+            fn_name: fn_name.clone(),
+            params: vec![Path(vec![PathExpr {
+                name: column_name.clone(),
+                ty: column_type.clone(),
+                description: description.to_string(),
+                sample: quote! { #column_type::default().url_encode() },
+            }])],
+            method: HttpMethod::GET,
+            handler_name: format_ident!("{}", handler_fn_name),
+            handler_impl_stream: quote! {
+               Result<PinnedRead<AppJson<u64>>, AppError> {
+                    state.read(move |state| {
+                        #entity_name::begin_pinned_read_ctx(&state.storage)?.try_map(|tx_context| {
+                            let result = #entity_name::#fn_name(&tx_context, &#column_name)?;
+                            Ok(AppJson(result))
+                        })
+                    }).await
+                }
+            },
+            utoipa_responses: quote! {
+                responses(
+                    (status = OK, content_type = "application/json", body = u64),
+                    (status = 500, content_type = "application/json", body = ErrorResponse),
+                )
+            },
+            endpoint: format!("/{}/{}/{{{}}}/count", entity_name.to_string().to_lowercase(), column_name, column_name),
+        }.to_endpoint()),
+        test_stream,
+        bench_stream
+    }
+}
@@ -0,0 +1,35 @@
+use crate::field_parser::EntityDef;
+use crate::rest::FunctionDef;
+use crate::table::TableDef;
+use proc_macro2::Ident;
+use quote::{format_ident, quote};
+use syn::Type;
+
+/// Pointer columns reference another entity by its pk, this lists the rows whose pointer does not resolve.
+pub fn fn_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Type, table: &Ident) -> FunctionDef {
+    let read_ctx_type = &entity_def.read_ctx_type;
+    let pk_type = &entity_def.key_def.field_def().tpe;
+    let pk_table = TableDef::pk(entity_def).var_name;
+    let fn_name = format_ident!("dangling_{}", column_name);
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: &#read_ctx_type, resolves: impl Fn(&#column_type) -> Result<bool, AppError>) -> Result<Vec<(#pk_type, #column_type)>, AppError> {
+            let mut dangling = Vec::new();
+            for entry_res in tx_context.#pk_table.iter_keys()? {
+                let pk = entry_res?.0.value();
+                if let Some(guard) = tx_context.#table.get_value(pk)? {
+                    let pointer = guard.value();
+                    if !resolves(&pointer)? {
+                        dangling.push((pk, pointer));
+                    }
+                }
+            }
+            Ok(dangling)
+        }
+    };
+    FunctionDef {
+        fn_stream,
+        endpoint: None,
+        test_stream: None,
+        bench_stream: None,
+    }
+}
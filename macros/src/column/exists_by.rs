@@ -0,0 +1,63 @@
+use crate::rest::FunctionDef;
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use syn::Type;
+use crate::field_parser::EntityDef;
+
+pub fn by_dict_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Type, dict_table_var: &Ident) -> FunctionDef {
+    let read_ctx_type = &entity_def.read_ctx_type;
+    let fn_name = format_ident!("exists_by_{}", column_name);
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: &#read_ctx_type, val: &#column_type) -> Result<bool, AppError> {
+            Ok(tx_context.#dict_table_var.dict_keys(val)?.is_some_and(|pks| !pks.is_empty()))
+        }
+    };
+    exists_fn_def(entity_def, column_type, fn_name, fn_stream)
+}
+
+pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Type, index_table: &Ident) -> FunctionDef {
+    let read_ctx_type = &entity_def.read_ctx_type;
+    let fn_name = format_ident!("exists_by_{}", column_name);
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: &#read_ctx_type, val: &#column_type) -> Result<bool, AppError> {
+            Ok(tx_context.#index_table.index_keys(val)?.is_some_and(|pks| !pks.is_empty()))
+        }
+    };
+    exists_fn_def(entity_def, column_type, fn_name, fn_stream)
+}
+
+fn exists_fn_def(entity_def: &EntityDef, column_type: &Type, fn_name: Ident, fn_stream: TokenStream) -> FunctionDef {
+    let entity_name = &entity_def.entity_name;
+
+    let test_stream = Some(quote! {
+        #[test]
+        fn #fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let val = #column_type::default();
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let exists = #entity_name::#fn_name(&tx_context, &val)?;
+            assert!(exists, "Entity is supposed to exist for the given index value");
+            Ok(())
+        }
+    });
+
+    let bench_fn_name = format_ident!("_{}", fn_name);
+    let bench_stream = Some(quote! {
+        #[bench]
+        fn #bench_fn_name(b: &mut Bencher) {
+            let (storage_owner, storage) = &*STORAGE;
+            let val = #column_type::default();
+            let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
+            b.iter(|| {
+                #entity_name::#fn_name(&tx_context, &val).expect("Failed to check entity exists by index");
+            });
+        }
+    });
+
+    FunctionDef {
+        fn_stream,
+        endpoint: None,
+        test_stream,
+        bench_stream
+    }
+}
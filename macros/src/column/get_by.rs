@@ -55,17 +55,28 @@ pub fn get_by_dict_def(
     }
 }
 
-pub fn get_by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Type, index_table_var: &Ident) -> FunctionDef {
+/// Unique indexes hold at most one key per value, so their lookup returns an `Option`.
+pub fn get_by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Type, index_table_var: &Ident, unique: bool) -> FunctionDef {
     let fn_name = format_ident!("get_by_{}", column_name);
     let entity_name = &entity_def.entity_name;
     let entity_type = &entity_def.entity_type;
     let read_ctx_type = &entity_def.read_ctx_type;
-    let fn_stream = quote! {
-        pub fn #fn_name(tx_context: &#read_ctx_type, val: &#column_type) -> Result<Vec<#entity_type>, AppError> {
-            let iter = tx_context.#index_table_var.index_keys(val)?.map(|res| res.map(|kg| kg.value()));
-            Self::compose_many(&tx_context, iter, None)
+    let fn_stream = if unique {
+        quote! {
+            pub fn #fn_name(tx_context: &#read_ctx_type, val: &#column_type) -> Result<Option<#entity_type>, AppError> {
+                let iter = tx_context.#index_table_var.index_keys(val)?.into_iter().flatten().map(|res| res.map(|kg| kg.value()));
+                Ok(Self::compose_many(&tx_context, iter.take(1), None)?.into_iter().next())
+            }
+        }
+    } else {
+        quote! {
+            pub fn #fn_name(tx_context: &#read_ctx_type, val: &#column_type) -> Result<Vec<#entity_type>, AppError> {
+                let iter = tx_context.#index_table_var.index_keys(val)?.into_iter().flatten().map(|res| res.map(|kg| kg.value()));
+                Self::compose_many(&tx_context, iter, None)
+            }
         }
     };
+    let expected_entities = if unique { quote! { Some(#entity_type::sample()) } } else { quote! { vec![#entity_type::sample()] } };
 
     let test_stream = Some(quote! {
         #[test]
@@ -74,7 +85,7 @@ pub fn get_by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type
             let val = #column_type::default();
             let tx_context = #entity_name::begin_read_ctx(&storage)?;
             let entities = #entity_name::#fn_name(&tx_context, &val)?;
-            let expected_entities = vec![#entity_type::sample()];
+            let expected_entities = #expected_entities;
             assert_eq!(expected_entities, entities, "Expected entities to be returned for the given index");
             Ok(())
         }
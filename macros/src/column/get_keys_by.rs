@@ -60,7 +60,7 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
     let fn_name = format_ident!("get_{}s_by_{}", pk_name, column_name);
     let fn_stream = quote! {
         pub fn #fn_name(tx_context: &#read_ctx_type, val: &#column_type) -> Result<Vec<#pk_type>, AppError> {
-            redbit::utils::collect_multimap_value(tx_context.#index_table.index_keys(val)?)
+            tx_context.#index_table.index_keys(val)?.map_or(Ok(Vec::new()), redbit::utils::collect_multimap_value)
         }
     };
 
@@ -114,3 +114,17 @@ pub fn dict_init_with_query(column_name: &Ident, dict_table_var: &Ident) -> Toke
     }
 }
 
+
+pub fn projected_init(column_name: &Ident, init_expr: TokenStream) -> TokenStream {
+    quote! {
+        let #column_name = if projection.loads(stringify!(#column_name)) { #init_expr } else { Default::default() };
+    }
+}
+
+pub fn project_statement(column_name: &Ident) -> TokenStream {
+    quote! {
+        if !projection.loads(stringify!(#column_name)) {
+            entity.#column_name = Default::default();
+        }
+    }
+}
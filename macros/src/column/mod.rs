@@ -1,22 +1,28 @@
 mod delete;
+mod dangling;
 mod stream_by;
 mod stream_parents_by;
 mod init;
 mod stream_range_by;
+mod stream_prefix_by;
 mod store;
 mod stream_keys_by;
 mod query;
 mod range_by;
 mod get_by;
 mod get_keys_by;
+mod count_by;
+mod exists_by;
 pub mod column_impls;
 pub mod column_codec;
 pub mod info;
 pub mod transient;
+pub mod composite;
 
 use crate::{entity, pk};
 use crate::entity::context;
 use crate::entity::context::TxContextItem;
+use crate::entity::find::FindScan;
 use crate::entity::query::{FilterQueryItem, RangeQuery};
 use crate::field_parser::{ColumnProps, EntityDef, FieldDef, IndexingType, OneToManyParentDef, Used};
 use crate::rest::*;
@@ -31,7 +37,10 @@ pub struct DbColumnMacros {
     pub table_index_definition: Option<IndexTableDefs>,
     pub table_dict_definition: Option<DictTableDefs>,
     pub struct_init: TokenStream,
+    pub struct_init_projected: TokenStream,
+    pub project_statement: TokenStream,
     pub filter_query_init: FilterQueryItem,
+    pub find_scan: Option<FindScan>,
     pub tx_context_items: Vec<TxContextItem>,
     pub table_info_item: TableInfoItem,
     pub struct_init_with_query: TokenStream,
@@ -79,19 +88,22 @@ impl DbColumnMacros {
             field_def: col_def.clone(),
             range_query: None,
             filter_query_init: query::filter_query_init(column_name, column_type),
+            find_scan: None,
             tx_context_items: vec![context::tx_context_plain_item(&plain_table_def)],
             table_info_item: info::plain_table_info(column_name, &plain_table_def.var_name),
             table_plain_definitions: vec![plain_table_def.clone()],
             table_index_definition: None,
             table_dict_definition: None,
             struct_init: init::plain_init(column_name, &plain_table_def.var_name),
+            struct_init_projected: init::projected_init(column_name, init::plain_init_expr(&plain_table_def.var_name)),
+            project_statement: init::project_statement(column_name),
             struct_init_with_query: init::plain_init_with_query(column_name, &plain_table_def.var_name),
             struct_default_init: init::default_init(column_name, column_type, is_pointer),
             struct_default_init_with_query: init::default_init_with_query(column_name, column_type, is_pointer),
             store_statement: store::store_statement(pk_name, column_name, &plain_table_def.var_name, used),
             delete_statement: delete::delete_statement(&plain_table_def.var_name),
             delete_many_statement: pk::delete::delete_many_statement(&plain_table_def.var_name),
-            function_defs: vec![],
+            function_defs: if is_pointer { vec![dangling::fn_def(entity_def, column_name, column_type, &plain_table_def.var_name)] } else { vec![] },
         }
     }
 
@@ -111,13 +123,16 @@ impl DbColumnMacros {
         let index_tables = IndexTableDefs::new(entity_def, column_name, column_type, column_props);
 
         let mut function_defs: Vec<FunctionDef> = Vec::new();
-        function_defs.push(get_by::get_by_index_def(entity_def, column_name, column_type, &index_tables.var_name));
+        function_defs.push(get_by::get_by_index_def(entity_def, column_name, column_type, &index_tables.var_name, index_tables.column_props.unique));
         function_defs.push(stream_by::by_index_def(entity_def, column_name, column_type, &index_tables.var_name));
         if let Some(parent_def) = parent_def_opt {
             function_defs.push(stream_parents_by::by_index_def(entity_def, column_name, column_type, &index_tables.var_name, &parent_def));
         }
         function_defs.push(get_keys_by::by_index_def(entity_def, column_name, column_type, &index_tables.var_name));
         function_defs.push(stream_keys_by::by_index_def(entity_def, column_name, column_type, &index_tables.var_name));
+        function_defs.push(count_by::by_index_def(entity_def, column_name, column_type, &index_tables.var_name));
+        function_defs.push(exists_by::by_index_def(entity_def, column_name, column_type, &index_tables.var_name));
+        function_defs.push(stream_prefix_by::stream_prefix_by_def(entity_def, column_name, column_type, &index_tables.var_name));
         let mut range_query = None;
 
         if range {
@@ -131,12 +146,15 @@ impl DbColumnMacros {
             field_def: col_field_def.clone(),
             range_query,
             filter_query_init: query::filter_query_init(column_name, column_type),
+            find_scan: Some(query::find_scan(column_name, column_type, &index_tables.var_name, false, range)),
             tx_context_items: vec![context::tx_context_index_item(&index_tables)],
             table_info_item: info::index_table_info(column_name, &index_tables.var_name),
             table_plain_definitions: vec![],
             table_index_definition: Some(index_tables.clone()),
             table_dict_definition: None,
             struct_init: init::index_init(column_name, &index_tables.var_name),
+            struct_init_projected: init::projected_init(column_name, init::index_init_expr(&index_tables.var_name)),
+            project_statement: init::project_statement(column_name),
             struct_init_with_query: init::index_init_with_query(column_name, &index_tables.var_name),
             struct_default_init: init::default_init(column_name, column_type, is_pointer),
             struct_default_init_with_query: init::default_init_with_query(column_name, column_type, is_pointer),
@@ -171,18 +189,24 @@ impl DbColumnMacros {
 
         function_defs.push(get_keys_by::by_dict_def(entity_def, column_name, column_type, &dict_tables.var_name));
         function_defs.push(stream_keys_by::by_dict_def(entity_def, column_name, column_type, &dict_tables.var_name));
+        function_defs.push(count_by::by_dict_def(entity_def, column_name, column_type, &dict_tables.var_name));
+        function_defs.push(exists_by::by_dict_def(entity_def, column_name, column_type, &dict_tables.var_name));
+        function_defs.push(stream_prefix_by::stream_prefix_by_def(entity_def, column_name, column_type, &dict_tables.var_name));
 
         let store_statement = store::store_dict_def(column_name, pk_name, &dict_tables.var_name, used);
         DbColumnMacros {
             field_def: col_field_def.clone(),
             range_query: None,
             filter_query_init: query::filter_query_init(column_name, column_type),
+            find_scan: Some(query::find_scan(column_name, column_type, &dict_tables.var_name, true, false)),
             tx_context_items: vec![context::tx_context_dict_item(&dict_tables)],
             table_info_item: info::dict_table_info(column_name, &dict_tables.var_name),
             table_plain_definitions: Vec::new(),
             table_index_definition: None,
             table_dict_definition: Some(dict_tables.clone()),
             struct_init: init::dict_init(column_name, &dict_tables.var_name),
+            struct_init_projected: init::projected_init(column_name, init::dict_init_expr(&dict_tables.var_name)),
+            project_statement: init::project_statement(column_name),
             struct_init_with_query: init::dict_init_with_query(column_name, &dict_tables.var_name),
             struct_default_init_with_query: init::default_init_with_query(column_name, column_type, is_pointer),
             struct_default_init: init::default_init(column_name, column_type, is_pointer),
@@ -1,10 +1,74 @@
 use proc_macro2::Ident;
 use quote::quote;
 use syn::Type;
+use crate::entity::find::FindScan;
 use crate::entity::query::FilterQueryItem;
 
 pub fn filter_query_init(column_name: &Ident, column_type: &Type) -> FilterQueryItem {
     let definition = quote! { pub #column_name: Option<FilterOp<#column_type>> };
     let init = quote! { #column_name: Some(FilterOp::Eq(#column_type::default())) };
-    FilterQueryItem { definition, init }
-}
\ No newline at end of file
+    let matches = quote! { self.#column_name.as_ref().is_none_or(|op| op.matches(&entity.#column_name)) };
+    FilterQueryItem { field: column_name.clone(), definition, init, matches }
+}
+
+/// `Eq` / `In` conditions are looked up by value in index and dictionary columns, `Lt` / `Le` / `Gt` / `Ge` conditions
+/// are ranged over only in range columns whose values are ordered like the column type. A range is estimated after all
+/// lookups and its keys are counted only until it can no longer beat the best candidate so far.
+pub fn find_scan(column_name: &Ident, column_type: &Type, table: &Ident, dictionary: bool, range: bool) -> FindScan {
+    let name = column_name.to_string();
+    let (keys_fn, kind) =
+        if dictionary {
+            (quote! { dict_keys }, quote! { ScanKind::Dictionary })
+        } else {
+            (quote! { index_keys }, quote! { ScanKind::Index })
+        };
+    let (range_estimate, range_scan) =
+        if range {
+            (
+                quote! {
+                    if let Some(op) = query.#column_name.as_ref() && op.point_values().is_none() && let Some(bounds) = op.bounds() {
+                        let budget = candidates.iter().map(|c| c.estimated_keys).min().unwrap_or(u64::MAX).min(RANGE_ESTIMATE_BUDGET as u64);
+                        let mut values = tx_context.#table.index_range::<#column_type>(bounds)?;
+                        let mut estimated_keys = 0;
+                        while estimated_keys < budget && let Some(entry) = values.next() {
+                            estimated_keys += entry?.1.len();
+                        }
+                        candidates.push(ScanCandidate::new(#name, ScanKind::Range, estimated_keys.min(budget)));
+                    }
+                },
+                quote! {
+                    if plan.scan == ScanKind::Range && let Some(bounds) = op.bounds() {
+                        let keys = tx_context.#table.index_range::<#column_type>(bounds)?.flat_map(|res| match res {
+                            Ok((_value, pks)) => Either::Left(pks.map(|res| res.map(|kg| kg.value()))),
+                            Err(e) => Either::Right(std::iter::once(Err(e))),
+                        });
+                        return Ok(Box::new(keys));
+                    }
+                },
+            )
+        } else {
+            (quote! {}, quote! {})
+        };
+    let estimate = quote! {
+        if let Some(op) = query.#column_name.as_ref() && let Some(values) = op.point_values() {
+            let mut estimated_keys = 0;
+            for value in values {
+                estimated_keys += tx_context.#table.#keys_fn(value)?.map_or(0, |keys| keys.len());
+            }
+            candidates.push(ScanCandidate::new(#name, #kind, estimated_keys));
+        }
+    };
+    let scan = quote! {
+        if let Some(op) = query.#column_name.as_ref() && plan.column.as_deref() == Some(#name) {
+            #range_scan
+            if let Some(values) = op.point_values() {
+                let mut scans = Vec::new();
+                for value in values {
+                    scans.extend(tx_context.#table.#keys_fn(value)?);
+                }
+                return Ok(Box::new(scans.into_iter().flatten().map(|res| res.map(|kg| kg.value()))));
+            }
+        }
+    };
+    FindScan { estimate, range_estimate, scan }
+}
@@ -1,7 +1,7 @@
 use crate::endpoint::EndpointDef;
 use crate::field_parser::EntityDef;
-use crate::rest::HttpParams::{Body, Path};
-use crate::rest::{BodyExpr, EndpointTag, FunctionDef, HttpMethod, PathExpr};
+use crate::rest::HttpParams::{Body, Path, Query};
+use crate::rest::{BodyExpr, EndpointTag, FunctionDef, HttpMethod, PathExpr, QueryExpr};
 use proc_macro2::Ident;
 use quote::{format_ident, quote};
 use syn::Type;
@@ -16,9 +16,10 @@ pub fn by_dict_def(
     let EntityDef { key_def, entity_name, entity_type, query_type, read_ctx_type, ..} = &entity_def;
     let pk_type = key_def.field_def().tpe;
     let fn_stream = quote! {
-        pub fn #fn_name(tx_context: #read_ctx_type, val: #column_type, query: Option<#query_type>) -> Result<impl futures::Stream<Item = Result<#entity_type, AppError>> + Send, AppError> {
+        pub fn #fn_name(tx_context: #read_ctx_type, val: #column_type, page: PageQuery, projection: Projection, query: Option<#query_type>) -> Result<Paged<#entity_type>, AppError> {
             let iter = tx_context.#dict_table_var.dict_keys(val)?.into_iter().flatten().map(|res| res.map(|kg| kg.value()));
-            Self::compose_many_stream(tx_context, iter, query)
+            let (pks, cursor) = page.paginate(page.ordered(iter))?;
+            Ok(Paged::new(Self::compose_many_stream_projected(tx_context, pks, query, projection.clone())?, cursor).projected(projection))
         }
     };
 
@@ -29,7 +30,7 @@ pub fn by_dict_def(
             let (storage_owner, storage) = &*STORAGE;
             let val = #column_type::default();
             let tx_context = #entity_name::begin_read_ctx(&storage)?;
-            let entity_stream = #entity_name::#fn_name(tx_context, val, None)?;
+            let entity_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default(), Projection::default(), None)?;
             let entities = entity_stream.try_collect::<Vec<#entity_type>>().await?;
             let expected_entities = vec![#entity_type::sample()];
             assert_eq!(expected_entities, entities, "Expected entities to be returned for the given dictionary index");
@@ -42,7 +43,7 @@ pub fn by_dict_def(
             let pk = #pk_type::default();
             let query = #query_type::sample();
             let tx_context = #entity_name::begin_read_ctx(&storage)?;
-            let entity_stream = #entity_name::#fn_name(tx_context, val, Some(query.clone()))?;
+            let entity_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default(), Projection::default(), Some(query.clone()))?;
             let entities = entity_stream.try_collect::<Vec<#entity_type>>().await?;
             let expected_entity = #entity_type::sample_with_query(pk, &query).expect("Failed to create sample entity");
             assert_eq!(entities.len(), 1, "Expected only one entity to be returned for the given dictionary index with filter");
@@ -62,7 +63,7 @@ pub fn by_dict_def(
                 rt.block_on(async {
                     let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
                     let val = #column_type::default();
-                    let entity_stream = #entity_name::#fn_name(tx_context, val, Some(query.clone())).expect("Failed to get entities by index");
+                    let entity_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default(), Projection::default(), Some(query.clone())).expect("Failed to get entities by index");
                     entity_stream.try_collect::<Vec<#entity_type>>().await.expect("Failed to collect entity stream");
                 })
             });
@@ -82,7 +83,15 @@ pub fn by_dict_def(
                 ty: column_type.clone(),
                 description: "Secondary index column with dictionary".to_string(),
                 sample: quote! { #column_type::default().url_encode() },
-            }]), Body(BodyExpr {
+            }]), Query(QueryExpr {
+                ty: syn::parse_quote!(PageQuery),
+                extraction: quote! { extract::Query(page): extract::Query<PageQuery> },
+                samples: quote! { vec![PageQuery::sample()] },
+            }), Query(QueryExpr {
+                ty: syn::parse_quote!(ProjectionQuery),
+                extraction: quote! { extract::Query(projection): extract::Query<ProjectionQuery> },
+                samples: quote! { vec![ProjectionQuery::default(), ProjectionQuery::sample()] },
+            }), Body(BodyExpr {
                 ty: syn::parse_quote! { Option<#query_type> },
                 extraction: quote! { MaybeJson(body): MaybeJson<#query_type> },
                 samples: quote! { vec![#query_type::sample()] },
@@ -92,9 +101,9 @@ pub fn by_dict_def(
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
                impl IntoResponse {
-                   match #entity_name::begin_read_ctx(&state.storage)
-                        .and_then(|tx_context| #entity_name::#fn_name(tx_context, #column_name, body)) {
-                            Ok(stream) => axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap()).into_response(),
+                   match state.read_paged(move |state| #entity_name::begin_pinned_read_ctx(&state.storage)
+                        .and_then(|pinned| pinned.try_map(|tx_context| #entity_name::#fn_name(tx_context, #column_name, page, #entity_name::projection(&projection.paths())?, body)))).await {
+                            Ok(pinned) => pinned.map(|paged| paged.into_ndjson()).into_response(),
                             Err(err)   => err.into_response(),
                     }
                 }
@@ -102,6 +111,7 @@ pub fn by_dict_def(
             utoipa_responses: quote! {
                 responses(
                     (status = OK, content_type = "application/x-ndjson", body = #entity_type),
+                    (status = 400, content_type = "application/json", body = ErrorResponse),
                     (status = 500, content_type = "application/json", body = ErrorResponse),
                 )
             },
@@ -117,9 +127,10 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
     let EntityDef { key_def, entity_name, entity_type, query_type, read_ctx_type, ..} = &entity_def;
     let pk_type = key_def.field_def().tpe;
     let fn_stream = quote! {
-        pub fn #fn_name(tx_context: #read_ctx_type, val: #column_type, query: Option<#query_type>) -> Result<impl futures::Stream<Item = Result<#entity_type, AppError>> + Send, AppError> {
-            let iter = tx_context.#index_table.index_keys(val)?.map(|res| res.map(|kg| kg.value()));
-            Self::compose_many_stream(tx_context, iter, query)
+        pub fn #fn_name(tx_context: #read_ctx_type, val: #column_type, page: PageQuery, projection: Projection, query: Option<#query_type>) -> Result<Paged<#entity_type>, AppError> {
+            let iter = tx_context.#index_table.index_keys(val)?.into_iter().flatten().map(|res| res.map(|kg| kg.value()));
+            let (pks, cursor) = page.paginate(page.ordered(iter))?;
+            Ok(Paged::new(Self::compose_many_stream_projected(tx_context, pks, query, projection.clone())?, cursor).projected(projection))
         }
     };
 
@@ -130,7 +141,7 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
             let (storage_owner, storage) = &*STORAGE;
             let val = #column_type::default();
             let tx_context = #entity_name::begin_read_ctx(&storage)?;
-            let entity_stream = #entity_name::#fn_name(tx_context, val, None)?;
+            let entity_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default(), Projection::default(), None)?;
             let entities = entity_stream.try_collect::<Vec<#entity_type>>().await?;
             let expected_entities = vec![#entity_type::sample()];
             assert_eq!(expected_entities, entities, "Expected entities to be returned for the given index");
@@ -143,7 +154,7 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
             let pk = #pk_type::default();
             let query = #query_type::sample();
             let tx_context = #entity_name::begin_read_ctx(&storage)?;
-            let entity_stream = #entity_name::#fn_name(tx_context, val, Some(query.clone()))?;
+            let entity_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default(), Projection::default(), Some(query.clone()))?;
             let entities = entity_stream.try_collect::<Vec<#entity_type>>().await?;
             let expected_entity = #entity_type::sample_with_query(pk, &query).expect("Failed to create sample entity");
             assert_eq!(entities.len(), 1, "Expected only one entity to be returned");
@@ -162,7 +173,7 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
             b.iter(|| {
                 rt.block_on(async {
                     let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
-                    let entity_stream = #entity_name::#fn_name(tx_context, #column_type::default(), Some(query.clone())).expect("Failed to get entities by index");
+                    let entity_stream = #entity_name::#fn_name(tx_context, #column_type::default(), PageQuery::default(), Projection::default(), Some(query.clone())).expect("Failed to get entities by index");
                     entity_stream.try_collect::<Vec<#entity_type>>().await.expect("Failed to collect entity stream");
                 })
             });
@@ -183,7 +194,15 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
                     description: "Secondary index column".to_string(),
                     sample: quote! { #column_type::default().url_encode() },
                 }]
-                ), Body(BodyExpr {
+                ), Query(QueryExpr {
+                    ty: syn::parse_quote!(PageQuery),
+                    extraction: quote! { extract::Query(page): extract::Query<PageQuery> },
+                    samples: quote! { vec![PageQuery::sample()] },
+                }), Query(QueryExpr {
+                    ty: syn::parse_quote!(ProjectionQuery),
+                    extraction: quote! { extract::Query(projection): extract::Query<ProjectionQuery> },
+                    samples: quote! { vec![ProjectionQuery::default(), ProjectionQuery::sample()] },
+                }), Body(BodyExpr {
                     ty: syn::parse_quote! { Option<#query_type> },
                     extraction: quote! { MaybeJson(body): MaybeJson<#query_type> },
                     samples: quote! { vec![#query_type::sample()] },
@@ -194,9 +213,9 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
                impl IntoResponse {
-                   match #entity_name::begin_read_ctx(&state.storage)
-                        .and_then(|tx_context| #entity_name::#fn_name(tx_context, #column_name, body)) {
-                            Ok(stream) => axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap()).into_response(),
+                   match state.read_paged(move |state| #entity_name::begin_pinned_read_ctx(&state.storage)
+                        .and_then(|pinned| pinned.try_map(|tx_context| #entity_name::#fn_name(tx_context, #column_name, page, #entity_name::projection(&projection.paths())?, body)))).await {
+                            Ok(pinned) => pinned.map(|paged| paged.into_ndjson()).into_response(),
                             Err(err)   => err.into_response(),
                     }
                 }
@@ -204,6 +223,7 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
             utoipa_responses: quote! {
                 responses(
                     (status = OK, content_type = "application/x-ndjson", body = #entity_type),
+                    (status = 400, content_type = "application/json", body = ErrorResponse),
                     (status = 500, content_type = "application/json", body = ErrorResponse),
                 )
             },
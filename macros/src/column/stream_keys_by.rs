@@ -1,6 +1,6 @@
 use crate::endpoint::EndpointDef;
-use crate::rest::HttpParams::Path;
-use crate::rest::{EndpointTag, FunctionDef, HttpMethod, PathExpr};
+use crate::rest::HttpParams::{Path, Query};
+use crate::rest::{EndpointTag, FunctionDef, HttpMethod, PathExpr, QueryExpr};
 use proc_macro2::Ident;
 use quote::{format_ident, quote};
 use syn::Type;
@@ -15,9 +15,10 @@ pub fn by_dict_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Ty
     let entity_name = &entity_def.entity_name;
     let read_ctx_type = &entity_def.read_ctx_type;
     let fn_stream = quote! {
-        pub fn #fn_name(tx_context: #read_ctx_type, val: #column_type) -> Result<impl futures::Stream<Item = Result<#pk_type, AppError>> + Send, AppError> {
-            let iter = tx_context.#dict_table_var.dict_keys(val)?.into_iter().flatten().map(|res| res.map(|g| g.value().clone()).map_err(AppError::from));
-            Ok(stream::iter(iter))
+        pub fn #fn_name(tx_context: #read_ctx_type, val: #column_type, page: PageQuery) -> Result<Paged<#pk_type>, AppError> {
+            let iter = tx_context.#dict_table_var.dict_keys(val)?.into_iter().flatten().map(|res| res.map(|g| g.value()));
+            let (pks, cursor) = page.paginate(page.ordered(iter))?;
+            Ok(Paged::new(stream::iter(pks.map(|res| res.map_err(AppError::from))), cursor))
         }
     };
 
@@ -27,7 +28,7 @@ pub fn by_dict_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Ty
             let (storage_owner, storage) = &*STORAGE;
             let val = #column_type::default();
             let tx_context = #entity_name::begin_read_ctx(&storage)?;
-            let pk_stream = #entity_name::#fn_name(tx_context, val)?;
+            let pk_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default())?;
             let pks = pk_stream.try_collect::<Vec<#pk_type>>().await?;
             assert_eq!(vec![#pk_type::default()], pks);
             Ok(())
@@ -44,7 +45,7 @@ pub fn by_dict_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Ty
                 rt.block_on(async {
                     let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
                     let val = #column_type::default();
-                    let pk_stream = #entity_name::#fn_name(tx_context, val).expect("Stream creation failed");
+                    let pk_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default()).expect("Stream creation failed");
                     pk_stream.try_collect::<Vec<#pk_type>>().await.expect("Failed to collect stream");
                 })
             });
@@ -63,14 +64,18 @@ pub fn by_dict_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Ty
                 ty: column_type.clone(),
                 description: "Secondary index column (dict)".to_string(),
                 sample: quote! { #column_type::default().url_encode() },
-            }])],
+            }]), Query(QueryExpr {
+                ty: syn::parse_quote!(PageQuery),
+                extraction: quote! { extract::Query(page): extract::Query<PageQuery> },
+                samples: quote! { vec![PageQuery::sample()] },
+            })],
             method: HttpMethod::GET,
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
                impl IntoResponse {
-                   match #entity_name::begin_read_ctx(&state.storage)
-                        .and_then(|tx_context| #entity_name::#fn_name(tx_context, #column_name)) {
-                            Ok(stream) => axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap()).into_response(),
+                   match state.read_paged(move |state| #entity_name::begin_pinned_read_ctx(&state.storage)
+                        .and_then(|pinned| pinned.try_map(|tx_context| #entity_name::#fn_name(tx_context, #column_name, page)))).await {
+                            Ok(pinned) => pinned.map(|paged| paged.into_ndjson()).into_response(),
                             Err(err)   => err.into_response(),
                     }
                 }
@@ -78,6 +83,7 @@ pub fn by_dict_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Ty
             utoipa_responses: quote! {
                 responses(
                     (status = OK, content_type = "application/x-ndjson", body = #pk_type),
+                    (status = 400, content_type = "application/json", body = ErrorResponse),
                     (status = 500, content_type = "application/json", body = ErrorResponse),
                 )
             },
@@ -99,8 +105,10 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
     let pk_type = &key_def.tpe;
     let fn_name = format_ident!("stream_{}s_by_{}", pk_name, column_name);
     let fn_stream = quote! {
-        pub fn #fn_name(tx_context: #read_ctx_type, val: #column_type) -> Result<impl futures::Stream<Item = Result<#pk_type, AppError>> + Send, AppError> {
-            Ok(stream::iter(tx_context.#index_table.index_keys(&val)?).map(|res| res.map(|e| e.value().clone()).map_err(AppError::from)))
+        pub fn #fn_name(tx_context: #read_ctx_type, val: #column_type, page: PageQuery) -> Result<Paged<#pk_type>, AppError> {
+            let iter = tx_context.#index_table.index_keys(&val)?.into_iter().flatten().map(|res| res.map(|e| e.value()));
+            let (pks, cursor) = page.paginate(page.ordered(iter))?;
+            Ok(Paged::new(stream::iter(pks.map(|res| res.map_err(AppError::from))), cursor))
         }
     };
 
@@ -111,7 +119,7 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
             let (storage_owner, storage) = &*STORAGE;
             let val = #column_type::default();
             let tx_context = #entity_name::begin_read_ctx(&storage)?;
-            let pk_stream = #entity_name::#fn_name(tx_context, val)?;
+            let pk_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default())?;
             let pks = pk_stream.try_collect::<Vec<#pk_type>>().await?;
             assert_eq!(vec![#pk_type::default()], pks);
             Ok(())
@@ -128,7 +136,7 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
                 rt.block_on(async {
                     let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
                     let val = #column_type::default();
-                    let pk_stream = #entity_name::#fn_name(tx_context, val).expect("Stream creation failed");
+                    let pk_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default()).expect("Stream creation failed");
                     pk_stream.try_collect::<Vec<#pk_type>>().await.expect("Failed to collect stream");
                 })
             });
@@ -147,14 +155,18 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
                 ty: column_type.clone(),
                 description: "Secondary index column".to_string(),
                 sample: quote! { #column_type::default().url_encode() },
-            }])],
+            }]), Query(QueryExpr {
+                ty: syn::parse_quote!(PageQuery),
+                extraction: quote! { extract::Query(page): extract::Query<PageQuery> },
+                samples: quote! { vec![PageQuery::sample()] },
+            })],
             method: HttpMethod::GET,
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
                impl IntoResponse {
-                   match #entity_name::begin_read_ctx(&state.storage)
-                        .and_then(|tx_context| #entity_name::#fn_name(tx_context, #column_name)) {
-                            Ok(stream) => axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap()).into_response(),
+                   match state.read_paged(move |state| #entity_name::begin_pinned_read_ctx(&state.storage)
+                        .and_then(|pinned| pinned.try_map(|tx_context| #entity_name::#fn_name(tx_context, #column_name, page)))).await {
+                            Ok(pinned) => pinned.map(|paged| paged.into_ndjson()).into_response(),
                             Err(err)   => err.into_response(),
                     }
                 }
@@ -162,6 +174,7 @@ pub fn by_index_def(entity_def: &EntityDef, column_name: &Ident, column_type: &T
             utoipa_responses: quote! {
                 responses(
                     (status = OK, content_type = "application/x-ndjson", body = #pk_type),
+                    (status = 400, content_type = "application/json", body = ErrorResponse),
                     (status = 500, content_type = "application/json", body = ErrorResponse),
                 )
             },
@@ -1,7 +1,7 @@
 use crate::endpoint::EndpointDef;
 use crate::field_parser::{EntityDef, OneToManyParentDef};
-use crate::rest::HttpParams::{Body, Path};
-use crate::rest::{BodyExpr, EndpointTag, FunctionDef, HttpMethod, PathExpr};
+use crate::rest::HttpParams::{Body, Path, Query};
+use crate::rest::{BodyExpr, EndpointTag, FunctionDef, HttpMethod, PathExpr, QueryExpr};
 use proc_macro2::Ident;
 use quote::{format_ident, quote};
 use syn::Type;
@@ -23,18 +23,20 @@ pub fn by_dict_def(
 
     let fn_name = format_ident!("stream_{}s_by_{}", parent_ident.to_string().to_lowercase(), column_name);
     let fn_stream = quote! {
-        pub fn #fn_name(parent_tx_context: #parent_tx_context_type, val: #column_type, query: Option<#stream_parent_query_type>) -> Result<impl futures::Stream<Item = Result<#parent_type, AppError>> + Send, AppError> {
-            let unique_parent_pk_iter = parent_tx_context.#parent_one2many_field_name.#dict_table_var.dict_keys(val)?
+        pub fn #fn_name(parent_tx_context: #parent_tx_context_type, val: #column_type, page: PageQuery, projection: Projection, query: Option<#stream_parent_query_type>) -> Result<Paged<#parent_type>, AppError> {
+            let parent_pk_iter = parent_tx_context.#parent_one2many_field_name.#dict_table_var.dict_keys(val)?
                 .into_iter()
                 .flatten()
-                .map(|r| r.map(|g| g.value().parent))
+                .map(|r| r.map(|g| g.value().parent));
+            let unique_parent_pk_iter = page.ordered(parent_pk_iter)
                 .scan(HashSet::new(), |seen, r| {
                     Some(match r {
                         Ok(parent) => if seen.insert(parent) { Some(Ok(parent)) } else { None },
                         Err(e) => Some(Err(e)),
                     })
                 }).flatten();
-            #parent_type::compose_many_stream(parent_tx_context, unique_parent_pk_iter, query)
+            let (parent_pks, cursor) = page.paginate(unique_parent_pk_iter)?;
+            Ok(Paged::new(#parent_type::compose_many_stream_projected(parent_tx_context, parent_pks, query, projection.clone())?, cursor).projected(projection))
         }
     };
 
@@ -45,7 +47,7 @@ pub fn by_dict_def(
             let (storage_owner, storage) = &*STORAGE;
             let val = #column_type::default();
             let tx_context = #parent_type::begin_read_ctx(&storage)?;
-            let entity_stream = #entity_name::#fn_name(tx_context, val, None)?;
+            let entity_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default(), Projection::default(), None)?;
             let parent_entities = entity_stream.try_collect::<Vec<#parent_type>>().await?;
             let expected_entities = vec![#parent_type::sample()];
             assert_eq!(expected_entities, parent_entities, "Expected parent entities to be returned for the given dictionary index");
@@ -59,7 +61,7 @@ pub fn by_dict_def(
             let parent_pk = pk.parent();
             let query = #stream_parent_query_type::sample();
             let tx_context = #parent_type::begin_read_ctx(&storage)?;
-            let entity_stream = #entity_name::#fn_name(tx_context, val, Some(query.clone()))?;
+            let entity_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default(), Projection::default(), Some(query.clone()))?;
             let parent_entities = entity_stream.try_collect::<Vec<#parent_type>>().await?;
             let expected_entity = #parent_type::sample_with_query(parent_pk, &query).expect("Failed to create sample parent entity");
             assert_eq!(parent_entities.len(), 1, "Expected only one parent entity to be returned for the given dictionary index with filter");
@@ -79,7 +81,7 @@ pub fn by_dict_def(
                 rt.block_on(async {
                     let tx_context = #parent_type::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
                     let val = #column_type::default();
-                    let parent_entity_stream = #entity_name::#fn_name(tx_context, val, Some(query.clone())).expect("Failed to get parent entities by index");
+                    let parent_entity_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default(), Projection::default(), Some(query.clone())).expect("Failed to get parent entities by index");
                     parent_entity_stream.try_collect::<Vec<#parent_type>>().await.expect("Failed to collect parent entity stream");
                 })
             });
@@ -99,7 +101,15 @@ pub fn by_dict_def(
                 ty: column_type.clone(),
                 description: "Secondary index column with dictionary".to_string(),
                 sample: quote! { #column_type::default().url_encode() },
-            }]), Body(BodyExpr {
+            }]), Query(QueryExpr {
+                ty: syn::parse_quote!(PageQuery),
+                extraction: quote! { extract::Query(page): extract::Query<PageQuery> },
+                samples: quote! { vec![PageQuery::sample()] },
+            }), Query(QueryExpr {
+                ty: syn::parse_quote!(ProjectionQuery),
+                extraction: quote! { extract::Query(projection): extract::Query<ProjectionQuery> },
+                samples: quote! { vec![ProjectionQuery::default(), ProjectionQuery::sample()] },
+            }), Body(BodyExpr {
                 ty: syn::parse_quote! { Option<#stream_parent_query_type> },
                 extraction: quote! { MaybeJson(body): MaybeJson<#stream_parent_query_type> },
                 samples: quote! { vec![#stream_parent_query_type::sample()] },
@@ -109,9 +119,9 @@ pub fn by_dict_def(
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
                impl IntoResponse {
-                   match #parent_type::begin_read_ctx(&state.storage)
-                        .and_then(|tx_context| #entity_name::#fn_name(tx_context, #column_name, body)) {
-                            Ok(stream) => axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap()).into_response(),
+                   match state.read_paged(move |state| #parent_type::begin_pinned_read_ctx(&state.storage)
+                        .and_then(|pinned| pinned.try_map(|tx_context| #entity_name::#fn_name(tx_context, #column_name, page, #parent_type::projection(&projection.paths())?, body)))).await {
+                            Ok(pinned) => pinned.map(|paged| paged.into_ndjson()).into_response(),
                             Err(err)   => err.into_response(),
                     }
                 }
@@ -119,6 +129,7 @@ pub fn by_dict_def(
             utoipa_responses: quote! {
                 responses(
                     (status = OK, content_type = "application/x-ndjson", body = #parent_type),
+                    (status = 400, content_type = "application/json", body = ErrorResponse),
                     (status = 500, content_type = "application/json", body = ErrorResponse),
                 )
             },
@@ -146,16 +157,19 @@ pub fn by_index_def(
 
     let fn_name = format_ident!("stream_{}s_by_{}", parent_ident.to_string().to_lowercase(), column_name);
     let fn_stream = quote! {
-        pub fn #fn_name(parent_tx_context: #parent_tx_context_type, val: #column_type, query: Option<#stream_parent_query_type>) -> Result<impl futures::Stream<Item = Result<#parent_type, AppError>> + Send, AppError> {
-            let unique_parent_pk_iter = parent_tx_context.#parent_one2many_field_name.#index_table.index_keys(&val)?
-                .map(|r| r.map(|g| g.value().parent))
+        pub fn #fn_name(parent_tx_context: #parent_tx_context_type, val: #column_type, page: PageQuery, projection: Projection, query: Option<#stream_parent_query_type>) -> Result<Paged<#parent_type>, AppError> {
+            let parent_pk_iter = parent_tx_context.#parent_one2many_field_name.#index_table.index_keys(&val)?
+                .into_iter().flatten()
+                .map(|r| r.map(|g| g.value().parent));
+            let unique_parent_pk_iter = page.ordered(parent_pk_iter)
                 .scan(HashSet::new(), |seen, r| {
                     Some(match r {
                         Ok(parent) => if seen.insert(parent) { Some(Ok(parent)) } else { None },
                         Err(e) => Some(Err(e)),
                     })
                 }).flatten();
-            #parent_type::compose_many_stream(parent_tx_context, unique_parent_pk_iter, query)
+            let (parent_pks, cursor) = page.paginate(unique_parent_pk_iter)?;
+            Ok(Paged::new(#parent_type::compose_many_stream_projected(parent_tx_context, parent_pks, query, projection.clone())?, cursor).projected(projection))
         }
     };
 
@@ -166,7 +180,7 @@ pub fn by_index_def(
             let (storage_owner, storage) = &*STORAGE;
             let val = #column_type::default();
             let tx_context = #parent_type::begin_read_ctx(&storage)?;
-            let entity_stream = #entity_name::#fn_name(tx_context, val, None)?;
+            let entity_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default(), Projection::default(), None)?;
             let parent_entities = entity_stream.try_collect::<Vec<#parent_type>>().await?;
             let expected_entities = vec![#parent_type::sample()];
             assert_eq!(expected_entities, parent_entities, "Expected parent entities to be returned for the given index");
@@ -180,7 +194,7 @@ pub fn by_index_def(
             let parent_pk = pk.parent();
             let query = #stream_parent_query_type::sample();
             let tx_context = #parent_type::begin_read_ctx(&storage)?;
-            let entity_stream = #entity_name::#fn_name(tx_context, val, Some(query.clone()))?;
+            let entity_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default(), Projection::default(), Some(query.clone()))?;
             let parent_entities = entity_stream.try_collect::<Vec<#parent_type>>().await?;
             let expected_entity = #parent_type::sample_with_query(parent_pk, &query).expect("Failed to create sample entity");
             assert_eq!(parent_entities.len(), 1, "Expected only one parent entity to be returned for the given index with filter");
@@ -200,7 +214,7 @@ pub fn by_index_def(
                 rt.block_on(async {
                     let tx_context = #parent_type::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
                     let val = #column_type::default();
-                    let parent_entity_stream = #entity_name::#fn_name(tx_context, val, Some(query.clone())).expect("Failed to get parent entities by index");
+                    let parent_entity_stream = #entity_name::#fn_name(tx_context, val, PageQuery::default(), Projection::default(), Some(query.clone())).expect("Failed to get parent entities by index");
                     parent_entity_stream.try_collect::<Vec<#parent_type>>().await.expect("Failed to collect parent entity stream");
                 })
             });
@@ -221,7 +235,15 @@ pub fn by_index_def(
                     description: "Secondary index column".to_string(),
                     sample: quote! { #column_type::default().url_encode() },
                 }]
-                ), Body(BodyExpr {
+                ), Query(QueryExpr {
+                    ty: syn::parse_quote!(PageQuery),
+                    extraction: quote! { extract::Query(page): extract::Query<PageQuery> },
+                    samples: quote! { vec![PageQuery::sample()] },
+                }), Query(QueryExpr {
+                    ty: syn::parse_quote!(ProjectionQuery),
+                    extraction: quote! { extract::Query(projection): extract::Query<ProjectionQuery> },
+                    samples: quote! { vec![ProjectionQuery::default(), ProjectionQuery::sample()] },
+                }), Body(BodyExpr {
                     ty: syn::parse_quote! { Option<#stream_parent_query_type> },
                     extraction: quote! { MaybeJson(body): MaybeJson<#stream_parent_query_type> },
                     samples: quote! { vec![#stream_parent_query_type::sample()] },
@@ -232,9 +254,9 @@ pub fn by_index_def(
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
                impl IntoResponse {
-                   match #parent_type::begin_read_ctx(&state.storage)
-                        .and_then(|tx_context| #entity_name::#fn_name(tx_context, #column_name, body)) {
-                            Ok(stream) => axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap()).into_response(),
+                   match state.read_paged(move |state| #parent_type::begin_pinned_read_ctx(&state.storage)
+                        .and_then(|pinned| pinned.try_map(|tx_context| #entity_name::#fn_name(tx_context, #column_name, page, #parent_type::projection(&projection.paths())?, body)))).await {
+                            Ok(pinned) => pinned.map(|paged| paged.into_ndjson()).into_response(),
                             Err(err)   => err.into_response(),
                     }
                 }
@@ -242,6 +264,7 @@ pub fn by_index_def(
             utoipa_responses: quote! {
                 responses(
                     (status = OK, content_type = "application/x-ndjson", body = #parent_type),
+                    (status = 400, content_type = "application/json", body = ErrorResponse),
                     (status = 500, content_type = "application/json", body = ErrorResponse),
                 )
             },
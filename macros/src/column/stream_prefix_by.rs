@@ -0,0 +1,123 @@
+use crate::endpoint::EndpointDef;
+use crate::field_parser::EntityDef;
+use crate::rest::HttpParams::{Body, Path, Query};
+use crate::rest::{BodyExpr, EndpointTag, FunctionDef, HttpMethod, PathExpr, QueryExpr};
+use proc_macro2::Ident;
+use quote::{format_ident, quote};
+use syn::Type;
+
+/// Index and dictionary columns are ranged over the values starting with a prefix of their url encoding, `limit` caps
+/// the matching ids before the optional filter.
+pub fn stream_prefix_by_def(entity_def: &EntityDef, column_name: &Ident, column_type: &Type, table: &Ident) -> FunctionDef {
+    let fn_name = format_ident!("stream_prefix_by_{}", column_name);
+    let EntityDef { key_def, entity_name, entity_type, query_type, read_ctx_type, ..} = &entity_def;
+    let pk_type = key_def.field_def().tpe;
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: #read_ctx_type, prefix: String, limit: usize, query: Option<#query_type>) -> Result<impl futures::Stream<Item = Result<#entity_type, AppError>> + Send, AppError> {
+            if limit > MAX_PREFIX_LIMIT {
+                return Err(AppError::BadRequest(format!("Cannot search more than {} entities by prefix at once", MAX_PREFIX_LIMIT)));
+            }
+            let range = <#column_type as PrefixEncoded>::prefix_range(&prefix)?;
+            let pks = tx_context.#table.value_range_keys::<#column_type>(range, limit)?;
+            Self::compose_many_stream(tx_context, pks.into_iter().map(|kg| Ok(kg.value())), query)
+        }
+    };
+
+    let test_with_filter_fn_name = format_ident!("{}_with_filter", fn_name);
+    let test_stream = Some(quote! {
+        #[tokio::test]
+        async fn #fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let prefix = #column_type::default().url_encode();
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let entities = #entity_name::#fn_name(tx_context, prefix.clone(), 10, None)?.try_collect::<Vec<#entity_type>>().await?;
+            assert_eq!(entities, vec![#entity_type::sample()], "Expected the entity of the whole value used as a prefix");
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            assert!(#entity_name::#fn_name(tx_context, prefix, MAX_PREFIX_LIMIT + 1, None).is_err(), "Expected the limit to be capped");
+            Ok(())
+        }
+        #[tokio::test]
+        async fn #test_with_filter_fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let prefix = #column_type::default().url_encode();
+            let pk = #pk_type::default();
+            let query = #query_type::sample();
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let entity_stream = #entity_name::#fn_name(tx_context, prefix, 10, Some(query.clone()))?;
+            let entities = entity_stream.try_collect::<Vec<#entity_type>>().await?;
+            let expected_entity = #entity_type::sample_with_query(pk, &query).expect("Failed to create sample entity");
+            assert_eq!(entities, vec![expected_entity], "Prefix result is not equal to sample because it is filtered, query: {:?}", query);
+            Ok(())
+        }
+    });
+
+    let bench_fn_name = format_ident!("_{}", fn_name);
+    let bench_stream = Some(quote! {
+        #[bench]
+        fn #bench_fn_name(b: &mut Bencher) {
+            let (storage_owner, storage) = &*STORAGE;
+            let query = #query_type::sample();
+            let prefix = #column_type::default().url_encode();
+            let rt = Runtime::new().unwrap();
+            b.iter(|| {
+                rt.block_on(async {
+                    let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
+                    let entity_stream = #entity_name::#fn_name(tx_context, prefix.clone(), 10, Some(query.clone())).expect("Failed to get entities by prefix");
+                    entity_stream.try_collect::<Vec<#entity_type>>().await.expect("Failed to collect entity stream");
+                })
+            });
+        }
+    });
+
+    let handler_fn_name = format!("{}_{}", entity_name.to_string().to_lowercase(), fn_name);
+
+    FunctionDef {
+        fn_stream,
+        endpoint: Some(EndpointDef {
+            return_type: Some(entity_type.clone()),
+            tag: EndpointTag::DataRead,
+            fn_name: fn_name.clone(),
+            params: vec![
+                Path(vec![PathExpr {
+                    name: format_ident!("prefix"),
+                    ty: syn::parse_quote!(String),
+                    description: "Prefix of the url encoded column value, columns whose encoding is not bit aligned take whole values".to_string(),
+                    sample: quote! { #column_type::default().url_encode() },
+                }]),
+                Query(QueryExpr {
+                    ty: syn::parse_quote!(PrefixQuery),
+                    extraction: quote! { extract::Query(query): extract::Query<PrefixQuery> },
+                    samples: quote! { vec![PrefixQuery::sample()] },
+                }),
+                Body(BodyExpr {
+                    ty: syn::parse_quote! { Option<#query_type> },
+                    extraction: quote! { MaybeJson(body): MaybeJson<#query_type> },
+                    samples: quote! { vec![#query_type::sample()] },
+                    required: false,
+                })
+            ],
+            method: HttpMethod::POST,
+            handler_name: format_ident!("{}", handler_fn_name),
+            handler_impl_stream: quote! {
+               impl IntoResponse {
+                   let limit = query.limit.unwrap_or(DEFAULT_PREFIX_LIMIT);
+                   match state.read_stream(move |state| #entity_name::begin_pinned_read_ctx(&state.storage)
+                        .and_then(|pinned| pinned.try_map(|tx_context| #entity_name::#fn_name(tx_context, prefix, limit, body)))).await {
+                            Ok(pinned) => pinned.map(|stream| axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap())).into_response(),
+                            Err(err)   => err.into_response(),
+                    }
+                }
+            },
+            utoipa_responses: quote! {
+                responses(
+                    (status = OK, content_type = "application/x-ndjson", body = #entity_type),
+                    (status = 400, content_type = "application/json", body = ErrorResponse),
+                    (status = 500, content_type = "application/json", body = ErrorResponse),
+                )
+            },
+            endpoint: format!("/{}/{}/prefix/{{prefix}}", entity_name.to_string().to_lowercase(), column_name),
+        }.to_endpoint()),
+        test_stream,
+        bench_stream
+    }
+}
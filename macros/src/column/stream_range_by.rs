@@ -99,9 +99,9 @@ pub fn stream_range_by_index_def(entity_def: &EntityDef, column_name: &Ident, co
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
                impl IntoResponse {
-                   match #entity_name::begin_read_ctx(&state.storage)
-                        .and_then(|tx_context| #entity_name::#fn_name(tx_context, query.from, query.until, body)) {
-                            Ok(stream) => axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap()).into_response(),
+                   match state.read_stream(move |state| #entity_name::begin_pinned_read_ctx(&state.storage)
+                        .and_then(|pinned| pinned.try_map(|tx_context| #entity_name::#fn_name(tx_context, query.from, query.until, body)))).await {
+                            Ok(pinned) => pinned.map(|stream| axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap())).into_response(),
                             Err(err)   => err.into_response(),
                     }
                 }
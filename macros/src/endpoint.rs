@@ -76,7 +76,7 @@ impl EndpointDef {
         let endpoint_path = &self.endpoint;
         let return_type: Option<Type> = self.return_type.clone();
         let mut path_expr = quote! { #endpoint_path };
-        let mut query_param = None;
+        let mut query_params = Vec::new();
         let mut body_param = None;
 
         // Analyze and extract each param kind
@@ -86,7 +86,7 @@ impl EndpointDef {
                     path_expr = generate_path_expr(&self.endpoint, path_params);
                 }
                 HttpParams::Query(param) => {
-                    query_param = Some(param);
+                    query_params.push(param);
                 }
                 HttpParams::Body(param) => {
                     body_param = Some(param);
@@ -108,9 +108,27 @@ impl EndpointDef {
                 }
             };
 
+        // every combination of the samples of all query params
+        let query_strings = (!query_params.is_empty()).then(|| {
+            let samples: Vec<&TokenStream> = query_params.iter().map(|qp| &qp.samples).collect();
+            quote! {
+                {
+                    let mut query_strings = vec![String::new()];
+                    #(
+                        query_strings = query_strings.into_iter().flat_map(|prefix| {
+                            #samples.into_iter().map(move |sample| {
+                                let query_string = serde_urlencoded::to_string(sample).unwrap();
+                                if prefix.is_empty() || query_string.is_empty() { format!("{}{}", prefix, query_string) } else { format!("{}&{}", prefix, query_string) }
+                            })
+                        }).collect();
+                    )*
+                    query_strings
+                }
+            }
+        });
+
         let mut tests: Vec<TokenStream> = Vec::new();
-        if let (Some(qp), Some(bp)) = (query_param, body_param) {
-            let query_samples = qp.clone().samples;
+        if let (Some(query_strings), Some(bp)) = (&query_strings, body_param) {
             let body_param_clone = bp.clone();
             let body_samples = body_param_clone.samples;
             let body_required = body_param_clone.required;
@@ -119,8 +137,7 @@ impl EndpointDef {
                 #[tokio::test]
                 async fn #test_fn_name() {
                     let (storage_owner, server) = #server;
-                    for query_sample in #query_samples {
-                        let query_string = serde_urlencoded::to_string(query_sample.clone()).unwrap();
+                    for query_string in #query_strings {
                         let final_path = format!("{}?{}", #path_expr, query_string);
                         info!("Testing endpoint: {} : {} with body", #method_name, final_path);
                         for body_sample in #body_samples {
@@ -134,15 +151,13 @@ impl EndpointDef {
                     }
                 }
             });
-        } else if let Some(qp) = query_param {
-            let query_samples = qp.clone().samples;
+        } else if let Some(query_strings) = &query_strings {
             let test_fn_name = format_ident!("http_endpoint_with_query_{}", &self.fn_name);
                 tests.push(quote! {
                     #[tokio::test]
                     async fn #test_fn_name() {
                         let (storage_owner, server) = #server;
-                        for query_sample in #query_samples {
-                            let query_string = serde_urlencoded::to_string(query_sample).unwrap();
+                        for query_string in #query_strings {
                             let final_path = format!("{}?{}", #path_expr, query_string);
                             info!("Testing endpoint: {} : {}", #method_name, &final_path);
                             let response = server.method(#method_name, &final_path).await;
@@ -155,8 +155,8 @@ pub fn block_like(block_type: Type, pk_name: &Ident, pk_type: &Type, field_defs:
 
             fn get_header_by_hash(&self, hash: <<Block as BlockLike>::Header as BlockHeaderLike>::Hash) -> Result<Vec<#header_type>, ChainError> {
                 let tx_context = #header_type::begin_read_ctx(&self.storage)?;
-                let header = #header_type::get_by_hash(&tx_context, &hash)?;
-                Ok(header)
+                let headers = #header_type::get_by_hash(&tx_context, &hash)?.into_iter().collect();
+                Ok(headers)
             }
 
             fn store_blocks(&self, indexing_context: &#write_tx_context, blocks: Vec<#block_type>, durability: Durability) -> Result<HashMap<String, TaskResult>, ChainError> {
@@ -182,10 +182,10 @@ pub fn block_like(block_type: Type, pk_name: &Ident, pk_type: &Type, field_defs:
 
             async fn validate_chain(&self, validation_from_height: u32) -> Result<Vec<#header_type>, ChainError> {
                 use futures::StreamExt;
-                let tx_context = #header_type::begin_read_ctx(&self.storage)?; // kept as-is even if unused
+                let tx_context = #header_type::begin_read_ctx(&self.storage)?;
                 let mut affected_headers: Vec<#header_type> = Vec::new();
                 if let Some(tip_header) = #header_type::last(&tx_context)? {
-                    let mut stream = #header_type::stream_range(tx_context, #pk_type(validation_from_height), tip_header.#pk_name, None)?;
+                    let mut stream = #header_type::stream_range(tx_context, #pk_type(validation_from_height), tip_header.#pk_name, PageQuery::default(), Projection::default(), None)?;
 
                     // get the first header (nothing to validate yet)
                     let mut prev = match stream.next().await {
@@ -4,6 +4,12 @@ use syn::Type;
 use crate::field_parser::EntityDef;
 use crate::rest::FunctionDef;
 
+/// Field an entity `projection` can select, either a column or a relationship with the entity type it loads.
+pub enum ProjectionItem {
+    Column(Ident),
+    Relationship(Ident, Box<Type>),
+}
+
 pub fn compose_token_stream(entity_def: &EntityDef, field_names: &[Ident], struct_inits: &[TokenStream]) -> FunctionDef {
     let entity_name = &entity_def.entity_name;
     let entity_type = &entity_def.entity_type;
@@ -40,6 +46,129 @@ pub fn compose_token_stream(entity_def: &EntityDef, field_names: &[Ident], struc
     }
 }
 
+pub fn compose_projected_token_stream(entity_def: &EntityDef, field_names: &[Ident], struct_inits_projected: &[TokenStream]) -> FunctionDef {
+    let EntityDef { key_def, entity_name, entity_type, read_ctx_type, ..} = &entity_def;
+    let pk_type: &Type = &key_def.field_def().tpe;
+    FunctionDef {
+        fn_stream: quote! {
+            pub fn compose_projected(tx_context: &#read_ctx_type, pk: #pk_type, projection: &Projection) -> Result<#entity_type, AppError> {
+                #(#struct_inits_projected)*
+                Ok(#entity_type {
+                    #(#field_names,)*
+                })
+            }
+        },
+        endpoint: None,
+        test_stream: Some(quote! {
+            #[test]
+            fn compose_projected_valid_entity() -> Result<(), AppError> {
+                let (storage_owner, storage) = random_storage();
+                let pk = #pk_type::default();
+                #entity_name::persist(Arc::clone(&storage), #entity_name::sample())?;
+                let tx_context = #entity_name::begin_read_ctx(&storage)?;
+                let entity = #entity_name::compose_projected(&tx_context, pk, &Projection::default())?;
+                assert_eq!(entity, #entity_name::compose(&tx_context, pk)?, "Default projection should compose the whole entity");
+                let bare_projection = #entity_name::projection(&ProjectionQuery { fields: Some(String::new()), expand: Some(String::new()) }.paths())?;
+                let bare_entity = #entity_name::compose_projected(&tx_context, pk, &bare_projection)?;
+                assert_eq!(bare_entity, #entity_name::project(entity, &bare_projection), "Projecting a composed entity should skip the same fields");
+                Ok(())
+            }
+        }),
+        bench_stream: None,
+    }
+}
+
+pub fn compose_projected_with_filter_token_stream(
+    entity_def: &EntityDef,
+    field_names: &[Ident],
+    struct_inits_filtered: &[TokenStream],
+    struct_inits_projected_rest: &[TokenStream],
+) -> FunctionDef {
+    let EntityDef { key_def, entity_name, entity_type, query_type, read_ctx_type, ..} = &entity_def;
+    let pk_type: &Type = &key_def.field_def().tpe;
+    FunctionDef {
+        fn_stream: quote! {
+            /// Reads and matches only the fields `stream_query` refers to, the other projected fields are read for matching entities.
+            fn compose_projected_with_filter(tx_context: &#read_ctx_type, pk: #pk_type, stream_query: &#query_type, projection: &Projection) -> Result<Option<#entity_type>, AppError> {
+                #(#struct_inits_filtered)*
+                #(#struct_inits_projected_rest)*
+                let entity = #entity_type {
+                    #(#field_names,)*
+                };
+                Ok(stream_query.matches_groups(&entity).then(|| Self::project(entity, projection)))
+            }
+        },
+        endpoint: None,
+        test_stream: Some(quote! {
+            #[test]
+            fn compose_projected_with_filter_valid_entity() -> Result<(), AppError> {
+                let (storage_owner, storage) = random_storage();
+                let pk = #pk_type::default();
+                #entity_name::persist(Arc::clone(&storage), #entity_name::sample())?;
+                let tx_context = #entity_name::begin_read_ctx(&storage)?;
+                let bare_projection = #entity_name::projection(&ProjectionQuery { fields: Some(String::new()), expand: Some(String::new()) }.paths())?;
+                let query = #query_type::default();
+                let entity = #entity_name::compose_projected_with_filter(&tx_context, pk, &query, &bare_projection)?;
+                assert_eq!(entity, Some(#entity_name::compose_projected(&tx_context, pk, &bare_projection)?), "A matching entity holds only the projected fields");
+                let nothing = #query_type { not: Some(Box::new(#query_type::default())), ..Default::default() };
+                assert!(#entity_name::compose_projected_with_filter(&tx_context, pk, &nothing, &bare_projection)?.is_none(), "$not of match-all should match nothing");
+                Ok(())
+            }
+        }),
+        bench_stream: None,
+    }
+}
+
+pub fn project_token_stream(entity_def: &EntityDef, project_statements: &[TokenStream]) -> FunctionDef {
+    let entity_type = &entity_def.entity_type;
+    let body = if project_statements.is_empty() {
+        quote! {
+            let _ = projection;
+            entity
+        }
+    } else {
+        quote! {
+            let mut entity = entity;
+            #(#project_statements)*
+            entity
+        }
+    };
+    FunctionDef {
+        fn_stream: quote! {
+            /// Resets the fields `projection` does not load, for entities composed in full, e.g. to match a filter.
+            pub fn project(entity: #entity_type, projection: &Projection) -> #entity_type {
+                #body
+            }
+        },
+        endpoint: None,
+        test_stream: None,
+        bench_stream: None,
+    }
+}
+
+pub fn projection_token_stream(entity_def: &EntityDef, items: &[ProjectionItem]) -> FunctionDef {
+    let EntityDef { key_def, entity_name, ..} = &entity_def;
+    let pk_name = &key_def.field_def().name;
+    let columns: Vec<&Ident> = items.iter().filter_map(|item| match item {
+        ProjectionItem::Column(name) => Some(name),
+        ProjectionItem::Relationship(..) => None,
+    }).collect();
+    let relations: Vec<TokenStream> = items.iter().filter_map(|item| match item {
+        ProjectionItem::Relationship(name, child_type) => Some(quote! { (stringify!(#name), #child_type::projection as ProjectionResolver) }),
+        ProjectionItem::Column(_) => None,
+    }).collect();
+    FunctionDef {
+        fn_stream: quote! {
+            pub fn projection(paths: &ProjectionPaths) -> Result<Projection, AppError> {
+                paths.resolve(stringify!(#entity_name), stringify!(#pk_name), &[#(stringify!(#columns)),*], &[#(#relations),*])
+            }
+        },
+        endpoint: None,
+        test_stream: None,
+        bench_stream: None,
+    }
+}
+
 pub fn compose_with_filter_token_stream(entity_def: &EntityDef, field_names: &[Ident], struct_inits_with_query: &[TokenStream]) -> FunctionDef {
     let EntityDef { key_def, entity_type, query_type, read_ctx_type, ..} = &entity_def;
     let pk_type: &Type = &key_def.field_def().tpe;
@@ -48,9 +177,10 @@ pub fn compose_with_filter_token_stream(entity_def: &EntityDef, field_names: &[I
             fn compose_with_filter(tx_context: &#read_ctx_type, pk: #pk_type, stream_query: &#query_type) -> Result<Option<#entity_type>, AppError> {
                 // First: fetch & filter every column, short‑circuit on mismatch
                 #(#struct_inits_with_query)*
-                Ok(Some(#entity_type {
+                let entity = #entity_type {
                     #(#field_names,)*
-                }))
+                };
+                Ok(stream_query.matches_groups(&entity).then_some(entity))
             }
         },
         endpoint: None,
@@ -70,6 +200,20 @@ pub fn compose_with_filter_token_stream(entity_def: &EntityDef, field_names: &[I
                 assert!(serialization_result.is_ok(), "Failed to serialize entity to JSON");
                 Ok(())
             }
+
+            #[test]
+            fn compose_with_filter_boolean_groups() -> Result<(), AppError> {
+                let (storage_owner, storage) = random_storage();
+                let pk = #pk_type::default();
+                #entity_type::persist(Arc::clone(&storage), #entity_type::sample())?;
+                let tx_context = #entity_type::begin_read_ctx(&storage)?;
+                let nothing = #query_type { not: Some(Box::new(#query_type::default())), ..Default::default() };
+                let either = #query_type { or: Some(vec![nothing.clone(), #query_type::default()]), ..Default::default() };
+                assert!(serde_json::to_string(&either).expect("Failed to serialize query").contains("$or"));
+                assert!(#entity_type::compose_with_filter(&tx_context, pk, &either)?.is_some(), "One of $or branches should match");
+                assert!(#entity_type::compose_with_filter(&tx_context, pk, &nothing)?.is_none(), "$not of match-all should match nothing");
+                Ok(())
+            }
         }),
         bench_stream: None,
     }
@@ -147,3 +291,42 @@ pub fn compose_many_stream_token_stream(entity_def: &EntityDef) -> FunctionDef {
     }
 }
 
+pub fn compose_many_stream_projected_token_stream(entity_def: &EntityDef) -> FunctionDef {
+    let pk_type: &Type = &entity_def.key_def.field_def().tpe;
+    let EntityDef { entity_type, query_type, read_ctx_type, ..} = &entity_def;
+    FunctionDef {
+        fn_stream: quote! {
+            /// Like `compose_many_stream`, a filtered entity is matched on the fields its filter refers to before the projected ones are read.
+            pub fn compose_many_stream_projected<I: Iterator<Item = redb::Result<#pk_type>> + Send>(
+                tx_context: #read_ctx_type,
+                pk_values: I,
+                stream_query: Option<#query_type>,
+                projection: Projection,
+            ) -> Result<impl futures::Stream<Item = Result<#entity_type, AppError>> + Send, AppError> {
+                let iter = pk_values.filter_map(move |item_res| {
+                    match item_res {
+                        Err(e) => Some(Err(AppError::from(e))),
+                        Ok(pk) => {
+                            if let Some(ref q) = stream_query {
+                                match Self::compose_projected_with_filter(&tx_context, pk, q, &projection) {
+                                    Ok(Some(item)) => Some(Ok(item)),
+                                    Ok(None) => None, // skip
+                                    Err(err) => Some(Err(AppError::Internal(err.into()))),
+                                }
+                            } else {
+                                match Self::compose_projected(&tx_context, pk, &projection) {
+                                    Ok(item) => Some(Ok(item)),
+                                    Err(err) => Some(Err(AppError::Internal(err.into()))),
+                                }
+                            }
+                        }
+                    }
+                });
+                Ok(stream::iter(iter))
+            }
+        },
+        endpoint: None,
+        test_stream: None,
+        bench_stream: None,
+    }
+}
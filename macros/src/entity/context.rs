@@ -3,7 +3,7 @@ use crate::table::{DictTableDefs, IndexTableDefs, PlainTableDef};
 use proc_macro2::{Ident, Literal, TokenStream};
 use quote::{format_ident, quote};
 use syn::Type;
-use crate::field_parser::EntityDef;
+use crate::field_parser::{EntityDef, KeyPartition};
 
 pub static TX_CONTEXT: &str = "TxContext";
 
@@ -152,26 +152,33 @@ pub fn tx_context_plain_item(def: &PlainTableDef) -> TxContextItem {
     let table_def = &def.underlying.definition;
     let shards= def.column_props.shards;
     let root_pk= def.root_pk;
+    let (key_partitioner, partitioning) = match def.column_props.key_partition {
+        KeyPartition::Bytes => (quote!(BytesPartitioner), quote!(Partitioning::by_key(#shards))),
+        KeyPartition::Range(bucket) => {
+            let bucket = bucket.map(|b| quote!(#b)).unwrap_or_else(|| quote!(RangePartitioner::DEFAULT_BUCKET));
+            (quote!(RangePartitioner), quote!(Partitioning::ByKey(RangePartitioner::new(#shards, #bucket))))
+        }
+    };
 
     let definition =
         quote! {
-            pub #var_ident: RedbitTableDefinition<#key_ty, #val_ty, BytesPartitioner, Xxh3Partitioner, PlainFactory<#key_ty, #val_ty>>
+            pub #var_ident: RedbitTableDefinition<#key_ty, #val_ty, #key_partitioner, Xxh3Partitioner, PlainFactory<#key_ty, #val_ty>>
         };
 
     let write_definition =
         quote! {
-            pub #var_ident: ShardedTableWriter<#key_ty, #val_ty, BytesPartitioner, Xxh3Partitioner, PlainFactory<#key_ty, #val_ty>>
+            pub #var_ident: ShardedTableWriter<#key_ty, #val_ty, #key_partitioner, Xxh3Partitioner, PlainFactory<#key_ty, #val_ty>>
         };
 
     let read_definition =
         quote! {
-            pub #var_ident: ShardedTableReader<#key_ty, #val_ty, BytesPartitioner, Xxh3Partitioner>
+            pub #var_ident: ShardedTableReader<#key_ty, #val_ty, #key_partitioner, Xxh3Partitioner>
         };
 
     let def_constructor = quote! {
         #var_ident: RedbitTableDefinition::new(
             #root_pk,
-            Partitioning::by_key(#shards),
+            #partitioning,
             PlainFactory::new(#name_lit, #table_def),
         )
     };
@@ -196,6 +203,8 @@ pub fn tx_context_index_item(defs: &IndexTableDefs) -> TxContextItem {
     let index_by_pk  = &defs.index_by_pk.definition;
     let lru_cache    = defs.column_props.lru_cache_size;
     let shards       = defs.column_props.shards;          // compile-time choice
+    let bloom        = defs.column_props.bloom;
+    let unique       = defs.column_props.unique;
 
     let definition =
         quote! {
@@ -216,7 +225,7 @@ pub fn tx_context_index_item(defs: &IndexTableDefs) -> TxContextItem {
         #var_ident: RedbitTableDefinition::new(
             false,
             Partitioning::by_value(#shards),
-            IndexFactory::new(#name_lit, #lru_cache, #pk_by_index, #index_by_pk),
+            IndexFactory::new(#name_lit, #lru_cache, #bloom, #unique, #pk_by_index, #index_by_pk),
         )
     };
     let write_shutdown = quote! { self.#var_ident.shutdown_async()? };
@@ -348,4 +357,21 @@ pub fn begin_read_fn_def(entity_def: &EntityDef) -> FunctionDef {
         bench_stream: None,
     }
 
-}
\ No newline at end of file
+}
+pub fn begin_pinned_read_fn_def(entity_def: &EntityDef) -> FunctionDef {
+    let tx_context_ty = &entity_def.ctx_type;
+    let read_tx_context_ty = &entity_def.read_ctx_type;
+    let fn_name = format_ident!("begin_pinned_read_ctx");
+    let fn_stream = quote! {
+        pub fn #fn_name(storage: &Arc<Storage>) -> Result<PinnedRead<#read_tx_context_ty>, AppError> {
+            #tx_context_ty::definition()?.#fn_name(&storage)
+        }
+    };
+
+    FunctionDef {
+        fn_stream,
+        endpoint: None,
+        test_stream: None,
+        bench_stream: None,
+    }
+}
@@ -0,0 +1,255 @@
+use crate::endpoint::EndpointDef;
+use crate::field_parser::EntityDef;
+use crate::rest::HttpParams::{Body, Query};
+use crate::rest::{BodyExpr, EndpointTag, FunctionDef, HttpMethod, QueryExpr};
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use syn::parse_quote;
+
+/// A column `find` can drive its scan by, `estimate` pushes a `ScanCandidate` for the column's point condition and
+/// `range_estimate` for its range condition, `scan` returns the keys of the column once the plan has chosen it.
+#[derive(Clone)]
+pub struct FindScan {
+    pub estimate: TokenStream,
+    pub range_estimate: TokenStream,
+    pub scan: TokenStream,
+}
+
+pub fn find_defs(entity_def: &EntityDef, pk_table: &Ident, scans: &[FindScan]) -> Vec<FunctionDef> {
+    vec![explain_def(entity_def, scans), find_keys_def(entity_def, pk_table, scans), find_def(entity_def, !scans.is_empty()), stream_find_def(entity_def)]
+}
+
+fn explain_def(entity_def: &EntityDef, scans: &[FindScan]) -> FunctionDef {
+    let EntityDef { entity_name, query_type, read_ctx_type, ..} = &entity_def;
+    let fn_name = format_ident!("explain");
+    let estimates: Vec<TokenStream> = scans.iter().map(|s| s.estimate.clone()).collect();
+    let range_estimates: Vec<TokenStream> = scans.iter().map(|s| s.range_estimate.clone()).collect();
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: &#read_ctx_type, query: &#query_type) -> Result<QueryPlan, AppError> {
+            let mut candidates: Vec<ScanCandidate> = Vec::new();
+            #(#estimates)*
+            #(#range_estimates)*
+            Ok(QueryPlan::choose(candidates, query.filtered_fields()))
+        }
+    };
+
+    let plan_assert =
+        if scans.is_empty() {
+            quote! { assert_eq!(plan.scan, ScanKind::Full, "Nothing to drive the scan by, plan: {:?}", plan); }
+        } else {
+            quote! { assert_eq!(plan.estimated_keys, Some(1), "Sample values are unique, plan: {:?}", plan); }
+        };
+    let test_stream = Some(quote! {
+        #[test]
+        fn #fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let full_scan = #entity_name::#fn_name(&tx_context, &#query_type::default())?;
+            assert_eq!(full_scan.scan, ScanKind::Full, "A query without conditions scans all keys");
+            let plan = #entity_name::#fn_name(&tx_context, &#query_type::sample())?;
+            #plan_assert
+            Ok(())
+        }
+    });
+
+    let handler_fn_name = format!("{}_{}", entity_name.to_string().to_lowercase(), fn_name);
+
+    FunctionDef {
+        fn_stream,
+        endpoint: Some(EndpointDef {
+            return_type: Some(parse_quote! { QueryPlan }),
+            tag: EndpointTag::DataRead,
+            fn_name: fn_name.clone(),
+            params: vec![Body(BodyExpr {
+                ty: parse_quote! { Option<#query_type> },
+                extraction: quote! { MaybeJson(body): MaybeJson<#query_type> },
+                samples: quote! { vec![#query_type::sample()] },
+                required: false,
+            })],
+            method: HttpMethod::POST,
+            handler_name: format_ident!("{}", handler_fn_name),
+            handler_impl_stream: quote! {
+               Result<PinnedRead<AppJson<QueryPlan>>, AppError> {
+                    state.read(move |state| {
+                        #entity_name::begin_pinned_read_ctx(&state.storage)?.try_map(|tx_context| {
+                            let plan = #entity_name::#fn_name(&tx_context, &body.unwrap_or_default())?;
+                            Ok(AppJson(plan))
+                        })
+                    }).await
+                }
+            },
+            utoipa_responses: quote! {
+                responses(
+                    (status = OK, content_type = "application/json", body = QueryPlan),
+                    (status = 500, content_type = "application/json", body = ErrorResponse),
+                )
+            },
+            endpoint: format!("/{}/find/explain", entity_name.to_string().to_lowercase()),
+        }.to_endpoint()),
+        test_stream,
+        bench_stream: None,
+    }
+}
+
+fn find_keys_def(entity_def: &EntityDef, pk_table: &Ident, scans: &[FindScan]) -> FunctionDef {
+    let EntityDef { key_def, query_type, read_ctx_type, ..} = &entity_def;
+    let pk_type = key_def.field_def().tpe;
+    let scan_stream: Vec<TokenStream> = scans.iter().map(|s| s.scan.clone()).collect();
+    let (query, plan) = if scans.is_empty() { (format_ident!("_query"), format_ident!("_plan")) } else { (format_ident!("query"), format_ident!("plan")) };
+    FunctionDef {
+        fn_stream: quote! {
+            fn find_keys(
+                tx_context: &#read_ctx_type,
+                #query: &#query_type,
+                #plan: &QueryPlan
+            ) -> Result<Box<dyn Iterator<Item = redb::Result<#pk_type>> + Send>, AppError> {
+                #(#scan_stream)*
+                Ok(Box::new(tx_context.#pk_table.range::<#pk_type>(..)?.map(|res| res.map(|(kg, _)| kg.value()))))
+            }
+        },
+        endpoint: None,
+        test_stream: None,
+        bench_stream: None,
+    }
+}
+
+fn find_def(entity_def: &EntityDef, has_scans: bool) -> FunctionDef {
+    let EntityDef { key_def, entity_name, entity_type, query_type, read_ctx_type, ..} = &entity_def;
+    let pk_type = key_def.field_def().tpe;
+    let fn_name = format_ident!("find");
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: &#read_ctx_type, query: &#query_type, limit: usize) -> Result<Vec<#entity_type>, AppError> {
+            if limit > MAX_FIND_LIMIT {
+                return Err(AppError::BadRequest(format!("Cannot find more than {} entities at once", MAX_FIND_LIMIT)));
+            }
+            let plan = Self::explain(tx_context, query)?;
+            let mut results = Vec::new();
+            for pk in Self::find_keys(tx_context, query, &plan)? {
+                if results.len() == limit {
+                    break;
+                }
+                if let Some(entity) = Self::compose_with_filter(tx_context, pk?, query)? {
+                    results.push(entity);
+                }
+            }
+            Ok(results)
+        }
+    };
+
+    let unique_assert = has_scans.then(|| quote! { assert_eq!(found.len(), 1, "Sample values are unique"); });
+    let test_stream = Some(quote! {
+        #[test]
+        fn #fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let unfiltered = #entity_name::#fn_name(&tx_context, &#query_type::default(), 2)?;
+            assert_eq!(unfiltered, #entity_name::take(&tx_context, 2)?, "Without conditions all entities are found in pk order");
+            let query = #query_type::sample();
+            let found = #entity_name::#fn_name(&tx_context, &query, 10)?;
+            let expected_entity = #entity_type::sample_with_query(#pk_type::default(), &query).expect("Failed to create sample entity");
+            assert!(found.contains(&expected_entity), "Expected the sample entity to be found, query: {:?}", query);
+            assert!(found.iter().all(|entity| query.matches(entity)), "Found entities must match the query {:?}", query);
+            #unique_assert
+            assert!(#entity_name::#fn_name(&tx_context, &query, MAX_FIND_LIMIT + 1).is_err(), "Expected the limit to be capped");
+            Ok(())
+        }
+    });
+
+    let bench_fn_name = format_ident!("_{}", fn_name);
+    let bench_stream = Some(quote! {
+        #[bench]
+        fn #bench_fn_name(b: &mut Bencher) {
+            let (storage_owner, storage) = &*STORAGE;
+            let query = #query_type::sample();
+            let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
+            b.iter(|| {
+                #entity_name::#fn_name(&tx_context, &query, 10).expect("Failed to find entities");
+            });
+        }
+    });
+
+    FunctionDef {
+        fn_stream,
+        endpoint: None,
+        test_stream,
+        bench_stream,
+    }
+}
+
+fn stream_find_def(entity_def: &EntityDef) -> FunctionDef {
+    let EntityDef { key_def, entity_name, entity_type, query_type, read_ctx_type, ..} = &entity_def;
+    let pk_type = key_def.field_def().tpe;
+    let fn_name = format_ident!("stream_find");
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: #read_ctx_type, query: #query_type, limit: usize) -> Result<impl futures::Stream<Item = Result<#entity_type, AppError>> + Send, AppError> {
+            if limit > MAX_FIND_LIMIT {
+                return Err(AppError::BadRequest(format!("Cannot find more than {} entities at once", MAX_FIND_LIMIT)));
+            }
+            let plan = Self::explain(&tx_context, &query)?;
+            let pks = Self::find_keys(&tx_context, &query, &plan)?;
+            Ok(Self::compose_many_stream(tx_context, pks, Some(query))?.take(limit))
+        }
+    };
+
+    let test_stream = Some(quote! {
+        #[tokio::test]
+        async fn #fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let query = #query_type::sample();
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let entities = #entity_name::#fn_name(tx_context, query.clone(), 10)?.try_collect::<Vec<#entity_type>>().await?;
+            let expected_entity = #entity_type::sample_with_query(#pk_type::default(), &query).expect("Failed to create sample entity");
+            assert!(entities.contains(&expected_entity), "Expected the sample entity to be streamed, query: {:?}", query);
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let limited = #entity_name::#fn_name(tx_context, #query_type::default(), 1)?.try_collect::<Vec<#entity_type>>().await?;
+            assert_eq!(limited.len(), 1, "Expected the stream to stop at the limit");
+            Ok(())
+        }
+    });
+
+    let handler_fn_name = format!("{}_{}", entity_name.to_string().to_lowercase(), fn_name);
+
+    FunctionDef {
+        fn_stream,
+        endpoint: Some(EndpointDef {
+            return_type: Some(entity_type.clone()),
+            tag: EndpointTag::DataRead,
+            fn_name: fn_name.clone(),
+            params: vec![
+                Query(QueryExpr {
+                    ty: parse_quote!(FindQuery),
+                    extraction: quote! { extract::Query(query): extract::Query<FindQuery> },
+                    samples: quote! { vec![FindQuery::sample()] },
+                }),
+                Body(BodyExpr {
+                    ty: parse_quote! { Option<#query_type> },
+                    extraction: quote! { MaybeJson(body): MaybeJson<#query_type> },
+                    samples: quote! { vec![#query_type::sample()] },
+                    required: false,
+                })
+            ],
+            method: HttpMethod::POST,
+            handler_name: format_ident!("{}", handler_fn_name),
+            handler_impl_stream: quote! {
+               impl IntoResponse {
+                   let limit = query.limit.unwrap_or(DEFAULT_FIND_LIMIT);
+                   match state.read_stream(move |state| #entity_name::begin_pinned_read_ctx(&state.storage)
+                        .and_then(|pinned| pinned.try_map(|tx_context| #entity_name::#fn_name(tx_context, body.unwrap_or_default(), limit)))).await {
+                            Ok(pinned) => pinned.map(|stream| axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap())).into_response(),
+                            Err(err)   => err.into_response(),
+                    }
+                }
+            },
+            utoipa_responses: quote! {
+                responses(
+                    (status = OK, content_type = "application/x-ndjson", body = #entity_type),
+                    (status = 400, content_type = "application/json", body = ErrorResponse),
+                    (status = 500, content_type = "application/json", body = ErrorResponse),
+                )
+            },
+            endpoint: format!("/{}/find", entity_name.to_string().to_lowercase()),
+        }.to_endpoint()),
+        test_stream,
+        bench_stream: None,
+    }
+}
@@ -0,0 +1,56 @@
+use crate::field::FieldMacros;
+use crate::field_parser::{EntityDef, ReadFrom};
+use crate::macro_utils;
+use crate::rest::FunctionDef;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+/// Children without a parent and pointers of `read_from` relationships that do not resolve, see `redbit::fsck`.
+pub fn fsck_refs_def(entity_def: &EntityDef, field_macros: &[FieldMacros]) -> FunctionDef {
+    let mut statements: Vec<TokenStream> = Vec::new();
+    for field_macro in field_macros {
+        match field_macro {
+            FieldMacros::Relationship(rel) => statements.push(rel.fsck_statement.clone()),
+            FieldMacros::TransientRel(rel) => {
+                let Some(ReadFrom { outer, inner }) = &rel.read_from else { continue };
+                let outer_type = field_macros.iter().find_map(|f| match f {
+                    FieldMacros::Relationship(r) if &r.field_def.name == outer => Some(r.field_def.tpe.clone()),
+                    _ => None,
+                });
+                let Some(outer_type) = outer_type else { continue };
+                let target_type = &rel.field_def.tpe;
+                let target_ctx = macro_utils::one_to_many_field_name_from_type(target_type);
+                let dangling_fn = format_ident!("dangling_{}", inner);
+                statements.push(quote! {
+                    for (pk, pointer) in #outer_type::#dangling_fn(&tx_context.#outer, |ptr| #target_type::exists(&tx_context.#target_ctx, *ptr))? {
+                        issues.push(FsckIssue::new(stringify!(#outer_type), format!("{:?}.{} {:?} does not resolve to {}", pk, stringify!(#inner), pointer, stringify!(#target_type))));
+                    }
+                });
+            }
+            _ => {}
+        }
+    }
+    let fn_stream = if statements.is_empty() {
+        quote! {
+            pub fn fsck_refs(_storage: &Arc<Storage>, _repair: bool) -> Result<Vec<FsckIssue>, AppError> {
+                Ok(Vec::new())
+            }
+        }
+    } else {
+        let entity_name = &entity_def.entity_name;
+        quote! {
+            pub fn fsck_refs(storage: &Arc<Storage>, repair: bool) -> Result<Vec<FsckIssue>, AppError> {
+                let mut issues: Vec<FsckIssue> = Vec::new();
+                let tx_context = #entity_name::begin_read_ctx(storage)?;
+                #(#statements)*
+                Ok(issues)
+            }
+        }
+    };
+    FunctionDef {
+        fn_stream,
+        endpoint: None,
+        test_stream: None,
+        bench_stream: None,
+    }
+}
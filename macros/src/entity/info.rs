@@ -41,7 +41,7 @@ pub fn table_info_fn(entity_def: &EntityDef) -> FunctionDef {
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
                Result<AppJson<#table_info_type>, AppError> {
-                    #entity_name::#fn_name(&state.storage).map(AppJson)
+                    state.read(move |state| #entity_name::#fn_name(&state.storage)).await.map(AppJson)
                 }
             },
             utoipa_responses: quote! {
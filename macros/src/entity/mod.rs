@@ -1,4 +1,6 @@
+use crate::column::composite::DbCompositeIndexMacros;
 use crate::field::FieldMacros;
+use crate::field_parser;
 use crate::field_parser::{FieldDef, KeyDef};
 use crate::rest::Rest;
 use crate::storage;
@@ -12,12 +14,14 @@ pub mod query;
 mod store;
 mod delete;
 mod sample;
-mod compose;
+pub mod compose;
 mod tests;
 pub mod info;
 pub mod init;
 pub mod chain;
 pub mod context;
+pub mod find;
+mod fsck;
 
 pub fn new(item_struct: &ItemStruct) -> Result<(KeyDef, Vec<FieldDef>, TokenStream), syn::Error> {
     let entity_name = &item_struct.ident;
@@ -29,10 +33,16 @@ pub fn new(item_struct: &ItemStruct) -> Result<(KeyDef, Vec<FieldDef>, TokenStre
     let mut dict_table_defs: Vec<DictTableDefs> = Vec::new();
     let mut range_queries = Vec::new();
     let mut filter_queries = Vec::new();
+    let mut find_scans = Vec::new();
     let mut table_info_items = Vec::new();
     let mut tx_context_items = Vec::new();
     let mut struct_inits = Vec::new();
     let mut struct_inits_with_query = Vec::new();
+    let mut struct_inits_projected = Vec::new();
+    let mut struct_inits_filtered = Vec::new();
+    let mut struct_inits_projected_rest = Vec::new();
+    let mut project_statements = Vec::new();
+    let mut projection_items = Vec::new();
     let mut struct_default_inits = Vec::new();
     let mut struct_default_inits_with_query = Vec::new();
     let mut store_statements: Vec<StoreStatement> = Vec::new();
@@ -47,10 +57,17 @@ pub fn new(item_struct: &ItemStruct) -> Result<(KeyDef, Vec<FieldDef>, TokenStre
         dict_table_defs.extend(field_macro.dict_table_definitions());
         range_queries.extend(field_macro.range_queries());
         filter_queries.extend(field_macro.stream_queries());
+        find_scans.extend(field_macro.find_scans());
         tx_context_items.extend(field_macro.tx_context_items());
         table_info_items.extend(field_macro.table_info_items());
         struct_inits.push(field_macro.struct_init());
         struct_inits_with_query.push(field_macro.struct_init_with_query());
+        struct_inits_projected.push(field_macro.struct_init_projected());
+        let (filtered, projected_rest) = field_macro.struct_init_projected_with_query();
+        struct_inits_filtered.push(filtered);
+        struct_inits_projected_rest.push(projected_rest);
+        project_statements.extend(field_macro.project_statements());
+        projection_items.extend(field_macro.projection_items());
         struct_default_inits.push(field_macro.struct_default_init());
         struct_default_inits_with_query.push(field_macro.struct_default_init_with_query());
         store_statements.extend(field_macro.store_statements());
@@ -59,6 +76,24 @@ pub fn new(item_struct: &ItemStruct) -> Result<(KeyDef, Vec<FieldDef>, TokenStre
         column_function_defs.extend(field_macro.function_defs())
     }
 
+    let plain_columns: Vec<FieldDef> = field_macros.iter().filter_map(|f| match f {
+        FieldMacros::Plain(column) => Some(column.field_def.clone()),
+        _ => None,
+    }).collect();
+    let composite_indexes: Vec<DbCompositeIndexMacros> =
+        field_parser::get_composite_indexes(item_struct, &plain_columns)?.iter().map(|def| DbCompositeIndexMacros::new(&entity_def, def)).collect();
+    // composite values are cloned before the column store statements move them out of the instance
+    store_statements.splice(0..0, composite_indexes.iter().map(|c| StoreStatement::Plain(c.store_statement.clone())));
+    for composite in composite_indexes {
+        index_table_defs.push(composite.index_tables);
+        range_queries.push(composite.range_query);
+        tx_context_items.push(composite.tx_context_item);
+        table_info_items.push(composite.table_info_item);
+        delete_statements.push(composite.delete_statement);
+        delete_many_statements.push(composite.delete_many_statement);
+        column_function_defs.extend(composite.function_defs);
+    }
+
     let field_names: Vec<Ident> = field_defs.iter().map(|f| f.name.clone()).collect();
     let key_def = &entity_def.key_def.clone();
 
@@ -71,6 +106,7 @@ pub fn new(item_struct: &ItemStruct) -> Result<(KeyDef, Vec<FieldDef>, TokenStre
         context::begin_write_fn_def(&entity_def),
         context::new_write_fn_def(&entity_def),
         context::begin_read_fn_def(&entity_def),
+        context::begin_pinned_read_fn_def(&entity_def),
         delete::remove_def(&entity_def, &delete_statements),
         delete::delete_def(&entity_def, &delete_statements),
         delete::delete_many_def(&entity_def, &delete_many_statements),
@@ -78,19 +114,29 @@ pub fn new(item_struct: &ItemStruct) -> Result<(KeyDef, Vec<FieldDef>, TokenStre
         compose::compose_with_filter_token_stream(&entity_def, &field_names, &struct_inits_with_query),
         compose::compose_many_token_stream(&entity_def),
         compose::compose_many_stream_token_stream(&entity_def),
+        compose::compose_projected_token_stream(&entity_def, &field_names, &struct_inits_projected),
+        compose::compose_projected_with_filter_token_stream(&entity_def, &field_names, &struct_inits_filtered, &struct_inits_projected_rest),
+        compose::compose_many_stream_projected_token_stream(&entity_def),
+        compose::project_token_stream(&entity_def, &project_statements),
+        compose::projection_token_stream(&entity_def, &projection_items),
+        fsck::fsck_refs_def(&entity_def, &field_macros),
     ];
+    if let Some(FieldMacros::Pk(pk)) = field_macros.iter().find(|f| matches!(f, FieldMacros::Pk(_))) {
+        function_defs.extend(find::find_defs(&entity_def, &pk.plain_table_def.var_name, &find_scans));
+    }
     function_defs.extend(sample::sample_token_fns(&entity_def, &struct_default_inits, &struct_default_inits_with_query, &field_names));
     function_defs.extend(column_function_defs.clone());
     function_defs.extend(init::init(entity_name, key_def));
 
     let table_info_struct = info::table_info_struct(&entity_def, &table_info_items);
-    let filter_query_struct = query::filter_query(&entity_def.query_type, &filter_queries);
+    let filter_query_struct = query::filter_query(&entity_def.query_type, &entity_def.entity_type, &filter_queries);
     let tx_context_structs = context::tx_context(&entity_def, &tx_context_items);
     let range_query_structs = range_queries.into_iter().map(|rq| rq.stream).collect::<Vec<_>>();
 
     let api_functions: Vec<TokenStream> = function_defs.iter().map(|f| f.fn_stream.clone()).collect::<Vec<_>>();
 
     let db_defs = storage::get_db_defs(&plain_table_defs, &dict_table_defs, &index_table_defs);
+    let column_tables = storage::get_column_tables(&entity_def.ctx_type, &plain_table_defs, &dict_table_defs, &index_table_defs);
 
     let Rest { endpoint_handlers, routes: api_routes } =
         Rest::new(&function_defs);
@@ -117,6 +163,7 @@ pub fn new(item_struct: &ItemStruct) -> Result<(KeyDef, Vec<FieldDef>, TokenStre
                 #api_routes
                 // entity fields have their own dbs
                 #db_defs
+                #column_tables
             }
             // unit tests and rest api tests
             #test_suite
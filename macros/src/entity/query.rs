@@ -18,25 +18,73 @@ pub fn filter_query_type(entity_type: &Type) -> Type {
 
 #[derive(Clone)]
 pub struct FilterQueryItem {
+    pub field: Ident,
     pub definition: TokenStream,
     pub init: TokenStream,
+    /// Whether an already composed `entity` satisfies the field's condition
+    pub matches: TokenStream,
 }
 
-pub fn filter_query(filter_query_ty: &Type, filter_queries: &[FilterQueryItem]) -> TokenStream {
+/// Field conditions are combined by AND, `$or` and `$not` groups nest whole queries of the same entity.
+pub fn filter_query(filter_query_ty: &Type, entity_type: &Type, filter_queries: &[FilterQueryItem]) -> TokenStream {
     let definitions: Vec<TokenStream> = filter_queries.iter().map(|item| item.definition.clone()).collect();
     let inits: Vec<TokenStream> = filter_queries.iter().map(|item| item.init.clone()).collect();
+    let matches: Vec<TokenStream> = filter_queries.iter().map(|item| item.matches.clone()).collect();
+    let fields: Vec<Ident> = filter_queries.iter().map(|item| item.field.clone()).collect();
+    let field_names: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
     quote! {
         #[derive(Clone, Debug, IntoParams, Serialize, Deserialize, Default, ToSchema)]
         #[schema(example = json!(#filter_query_ty::sample()))]
         pub struct #filter_query_ty {
-            #(#definitions),*
+            #(#definitions,)*
+            /// Matches when any of the nested queries does
+            #[serde(rename = "$or", default, skip_serializing_if = "Option::is_none")]
+            #[schema(no_recursion)]
+            #[param(value_type = Option<Vec<Object>>)]
+            pub or: Option<Vec<#filter_query_ty>>,
+            /// Matches when the nested query does not
+            #[serde(rename = "$not", default, skip_serializing_if = "Option::is_none")]
+            #[schema(no_recursion)]
+            #[param(value_type = Option<Object>)]
+            pub not: Option<Box<#filter_query_ty>>,
         }
         impl #filter_query_ty {
             pub fn sample() -> Self {
                 Self {
-                    #(#inits),*
+                    #(#inits,)*
+                    or: None,
+                    not: None,
                 }
             }
+
+            /// Evaluates the whole query against an entity in memory.
+            pub fn matches(&self, entity: &#entity_type) -> bool {
+                #(#matches &&)* self.matches_groups(entity)
+            }
+
+            /// Evaluates only the `$or` and `$not` groups, field conditions are applied while the entity is read.
+            pub fn matches_groups(&self, entity: &#entity_type) -> bool {
+                self.or.as_ref().is_none_or(|queries| queries.iter().any(|q| q.matches(entity)))
+                    && self.not.as_ref().is_none_or(|q| !q.matches(entity))
+            }
+
+            /// Whether the query or any of its nested groups has a condition on `field`.
+            pub fn references(&self, field: &str) -> bool {
+                let direct = match field {
+                    #(#field_names => self.#fields.is_some(),)*
+                    _ => false,
+                };
+                direct || self.or.iter().flatten().any(|q| q.references(field)) || self.not.as_ref().is_some_and(|q| q.references(field))
+            }
+
+            /// Names of the fields and groups the query has conditions for.
+            pub fn filtered_fields(&self) -> Vec<&'static str> {
+                let mut fields = Vec::new();
+                #(if self.#fields.is_some() { fields.push(#field_names); })*
+                if self.or.is_some() { fields.push("$or"); }
+                if self.not.is_some() { fields.push("$not"); }
+                fields
+            }
         }
     }
 }
@@ -62,14 +110,27 @@ pub fn pk_range_query(entity_def: &EntityDef) -> RangeQuery {
             pub struct #entity_range_query {
                 pub from: #pk_type,
                 pub until: #pk_type,
+                #[param(required = false, inline, example = "desc")]
+                pub order: Option<Order>,
+                #[param(required = false)]
+                pub cursor: Option<String>,
+                #[param(required = false, example = 20)]
+                pub limit: Option<usize>,
             }
             impl #entity_range_query {
                 pub fn sample() -> Self {
                     Self {
                         from: #pk_type::default(),
-                        until: #pk_type::default().next_index().next_index().next_index()
+                        until: #pk_type::default().next_index().next_index().next_index(),
+                        order: Some(Order::Desc),
+                        cursor: None,
+                        limit: Some(2),
                     }
                 }
+
+                pub fn page(&self) -> PageQuery {
+                    PageQuery { order: self.order, cursor: self.cursor.clone(), limit: self.limit }
+                }
             }
         };
     RangeQuery {
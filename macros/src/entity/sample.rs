@@ -96,11 +96,10 @@ pub fn sample_token_fns(
                 pub fn sample_with_query(pk: #pk_type, stream_query: &#query_type) -> Option<#entity_type> {
                     // First: fetch & filter every column, short‑circuit on mismatch
                     #(#struct_default_inits_with_query)*
-                    Some(
-                        #entity_type {
-                            #(#field_names,)*
-                        }
-                    )
+                    let entity = #entity_type {
+                        #(#field_names,)*
+                    };
+                    stream_query.matches_groups(&entity).then_some(entity)
                 }
             },
             endpoint: None,
@@ -1,6 +1,6 @@
 use crate::field_parser::{EntityDef, OneToManyParentDef};
 use crate::rest::FunctionDef;
-use proc_macro2::{Literal, TokenStream};
+use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 
 pub fn test_suite(entity_def: &EntityDef, parent_def: Option<OneToManyParentDef>, fn_defs: &[FunctionDef]) -> TokenStream {
@@ -9,7 +9,6 @@ pub fn test_suite(entity_def: &EntityDef, parent_def: Option<OneToManyParentDef>
     let entity_tests = format_ident!("{}", entity_name.to_string().to_lowercase());
     let entity_integration_tests = format_ident!("{}_integration", entity_name.to_string().to_lowercase());
     let entity_benches = format_ident!("{}_bench", entity_name.to_string().to_lowercase());
-    let entity_literal = Literal::string(&entity_name.to_string());
     let http_tests = fn_defs.iter().filter_map(|f| f.endpoint.clone().map(|e| e.tests)).flatten().collect::<Vec<_>>();
     let unit_tests = fn_defs.iter().filter_map(|f| f.test_stream.clone()).collect::<Vec<_>>();
     let benches = fn_defs.iter().filter_map(|f| f.bench_stream.clone()).collect::<Vec<_>>();
@@ -30,10 +29,7 @@ pub fn test_suite(entity_def: &EntityDef, parent_def: Option<OneToManyParentDef>
 
     let db_init = quote! {
         fn random_storage() -> (StorageOwner, Arc<Storage>) {
-            tokio::runtime::Runtime::new()
-                .expect("tokio runtime")
-                .block_on(StorageOwner::temp(#entity_literal, 0, true))
-                .expect("Failed to create temporary storage")
+            StorageOwner::in_memory(0).expect("Failed to create in-memory storage")
         }
 
         fn initialize_storage(storage: Arc<Storage>) {
@@ -83,22 +79,18 @@ pub fn test_suite(entity_def: &EntityDef, parent_def: Option<OneToManyParentDef>
 
             static SERVER: OnceCell<(StorageOwner, Arc<axum_test::TestServer>)> = OnceCell::const_new();
 
-            async fn random_storage_async() -> (StorageOwner, Arc<Storage>) {
-               StorageOwner::temp(#entity_literal, 0, true).await.expect("Failed to create temporary storage")
-            }
-
             async fn get_delete_server() -> (StorageOwner, Arc<axum_test::TestServer>) {
-                let (storage_owner, storage) = random_storage_async().await;
+                let (storage_owner, storage) = random_storage();
                 initialize_storage(Arc::clone(&storage));
-                let router = redbit::rest::build_router(RequestState { storage }, None, None);
+                let router = redbit::rest::build_router(RequestState::new(storage), None, None);
                 (storage_owner, Arc::new(axum_test::TestServer::new(router).unwrap()))
             }
 
             async fn get_test_server() -> (&'static StorageOwner, Arc<axum_test::TestServer>) {
                 let (owner, server) = SERVER.get_or_init(|| async {
-                    let (storage_owner, storage) = random_storage_async().await;
+                    let (storage_owner, storage) = random_storage();
                     initialize_storage(Arc::clone(&storage));
-                    let router = redbit::rest::build_router(RequestState { storage }, None, None);
+                    let router = redbit::rest::build_router(RequestState::new(storage), None, None);
                     (storage_owner, Arc::new(axum_test::TestServer::new(router).unwrap()))
                 }).await;
 
@@ -1,6 +1,8 @@
 use crate::column::transient::TransientMacros;
 use crate::column::DbColumnMacros;
+use crate::entity::compose::ProjectionItem;
 use crate::entity::context::{TxContextItem, TxType};
+use crate::entity::find::FindScan;
 use crate::entity::info::TableInfoItem;
 use crate::entity::query::{FilterQueryItem, RangeQuery};
 use crate::entity::{context, query};
@@ -12,6 +14,7 @@ use crate::relationship::{DbRelationshipMacros, StoreStatement};
 use crate::rest::FunctionDef;
 use crate::table::{DictTableDefs, IndexTableDefs, PlainTableDef};
 use proc_macro2::{Ident, TokenStream};
+use quote::quote;
 use syn::{ItemStruct, Type};
 
 pub enum FieldMacros {
@@ -95,6 +98,53 @@ impl FieldMacros {
         }
     }
     
+    pub fn struct_init_projected(&self) -> TokenStream {
+        match self {
+            FieldMacros::Pk(pk) => pk.struct_init.clone(),
+            FieldMacros::Plain(column) => column.struct_init_projected.clone(),
+            FieldMacros::Relationship(relationship) => relationship.struct_init_projected.clone(),
+            FieldMacros::Transient(transient) => transient.struct_init.clone(),
+            FieldMacros::TransientRel(transient_rel) => transient_rel.struct_init_projected.clone(),
+        }
+    }
+
+    /// Fields a filter can refer to are read and matched first, the projected rest only once the entity passes.
+    pub fn struct_init_projected_with_query(&self) -> (TokenStream, TokenStream) {
+        let name = &self.field_def().name;
+        let projected = self.struct_init_projected();
+        match self {
+            FieldMacros::Plain(_) | FieldMacros::Relationship(_) => {
+                let with_query = self.struct_init_with_query();
+                (
+                    quote! { let #name = if stream_query.references(stringify!(#name)) { #with_query Some(#name) } else { None }; },
+                    quote! { let #name = match #name { Some(#name) => #name, None => { #projected #name } }; },
+                )
+            }
+            _ => (quote! {}, projected),
+        }
+    }
+
+    pub fn project_statements(&self) -> Vec<TokenStream> {
+        match self {
+            FieldMacros::Plain(column) => vec![column.project_statement.clone()],
+            FieldMacros::Relationship(relationship) => vec![relationship.project_statement.clone()],
+            FieldMacros::TransientRel(transient_rel) => vec![transient_rel.project_statement.clone()],
+            _ => vec![],
+        }
+    }
+
+    pub fn projection_items(&self) -> Vec<ProjectionItem> {
+        match self {
+            FieldMacros::Pk(_) => vec![],
+            FieldMacros::Plain(column) => vec![ProjectionItem::Column(column.field_def.name.clone())],
+            FieldMacros::Transient(transient) => vec![ProjectionItem::Column(transient.field_def.name.clone())],
+            FieldMacros::Relationship(relationship) => vec![ProjectionItem::Relationship(relationship.field_def.name.clone(), Box::new(relationship.field_def.tpe.clone()))],
+            FieldMacros::TransientRel(transient_rel) if transient_rel.read_from.is_some() =>
+                vec![ProjectionItem::Relationship(transient_rel.field_def.name.clone(), Box::new(transient_rel.field_def.tpe.clone()))],
+            FieldMacros::TransientRel(transient_rel) => vec![ProjectionItem::Column(transient_rel.field_def.name.clone())],
+        }
+    }
+
     pub fn struct_init_with_query(&self) -> TokenStream {
         match self {
             FieldMacros::Pk(pk) => pk.struct_init_with_query.clone(),
@@ -163,6 +213,13 @@ impl FieldMacros {
         }
     }
 
+    pub fn find_scans(&self) -> Vec<FindScan> {
+        match self {
+            FieldMacros::Plain(column) => column.find_scan.clone().into_iter().collect(),
+            _ => vec![],
+        }
+    }
+
     pub fn tx_context_items(&self) -> Vec<TxContextItem> {
         match self {
             FieldMacros::Pk(pk) => vec![pk.tx_context_item.clone()],
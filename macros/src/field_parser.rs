@@ -88,17 +88,38 @@ pub struct ColumnProps {
     pub shards: usize,
     pub db_cache_weight: usize,
     pub lru_cache_size: usize,
+    pub key_partition: KeyPartition,
+    /// `bloom` keeps a bloom filter per index shard to answer lookups of missing values without touching redb
+    pub bloom: bool,
+    /// `unique` rejects a second key for an index value and narrows `get_by_*` to `Option`
+    pub unique: bool,
+}
+
+/// How a plain column spreads its keys over shards.
+#[derive(Clone)]
+pub enum KeyPartition {
+    Bytes,
+    /// `partition = "range"` keeps buckets of root keys on one shard, `bucket = N` overrides the default size
+    Range(Option<u64>),
 }
 
 impl ColumnProps {
     pub fn new(shards: usize, db_cache_weight: usize, lru_cache_size_m: usize) -> Self {
-        ColumnProps { shards, db_cache_weight, lru_cache_size: lru_cache_size_m * 1_000_000 }
+        ColumnProps { shards, db_cache_weight, lru_cache_size: lru_cache_size_m * 1_000_000, key_partition: KeyPartition::Bytes, bloom: false, unique: false }
     }
     pub fn for_key(db_cache_weight: usize) -> Self {
-        ColumnProps { shards: 1, db_cache_weight, lru_cache_size: 0 }
+        ColumnProps { shards: 1, db_cache_weight, lru_cache_size: 0, key_partition: KeyPartition::Bytes, bloom: false, unique: false }
     }
 }
 
+/// Entity level `#[index(columns(a, b))]` over plain columns, its value is the tuple of the column values.
+#[derive(Clone)]
+pub struct CompositeIndexDef {
+    pub name: Ident,
+    pub columns: Vec<FieldDef>,
+    pub column_props: ColumnProps,
+}
+
 #[derive(Clone)]
 pub enum IndexingType {
     Off(ColumnProps),
@@ -412,6 +433,10 @@ fn parse_entity_field(field: &Field) -> syn::Result<ColumnDef> {
                     let mut is_range = false;
                     let mut is_transient = false;
                     let mut read_from: Option<ReadFrom> = None;
+                    let mut partition: Option<syn::LitStr> = None;
+                    let mut bucket: Option<u64> = None;
+                    let mut bloom = false;
+                    let mut unique = false;
 
                     let _ = attr.parse_nested_meta(|nested| {
                         if nested.path.is_ident("pointer") {
@@ -427,6 +452,11 @@ fn parse_entity_field(field: &Field) -> syn::Result<ColumnDef> {
                         } else if nested.path.is_ident("shards") {
                             let lit: syn::LitInt = nested.value()?.parse()?;
                             shards = lit.base10_parse::<usize>()?;
+                        } else if nested.path.is_ident("partition") {
+                            partition = Some(nested.value()?.parse()?);
+                        } else if nested.path.is_ident("bucket") {
+                            let lit: syn::LitInt = nested.value()?.parse()?;
+                            bucket = Some(lit.base10_parse::<u64>()?);
                         } else if nested.path.is_ident("transient") {
                             is_transient = true;
                             let _ = nested.parse_nested_meta(|inner| {
@@ -449,10 +479,35 @@ fn parse_entity_field(field: &Field) -> syn::Result<ColumnDef> {
                             is_dictionary = true;
                         } else if nested.path.is_ident("range") {
                             is_range = true;
+                        } else if nested.path.is_ident("bloom") {
+                            bloom = true;
+                        } else if nested.path.is_ident("unique") {
+                            unique = true;
                         }
                         Ok(())
                     });
-                    let column_props = ColumnProps::new(shards, db_cache_weight, lru_cache_size_mil);
+                    let mut column_props = ColumnProps::new(shards, db_cache_weight, lru_cache_size_mil);
+                    match partition {
+                        Some(p) if p.value() != "range" => {
+                            return Err(syn::Error::new(p.span(), "Unsupported partition, only `partition = \"range\"` is available"));
+                        }
+                        Some(p) if is_index || is_dictionary || is_range || is_transient => {
+                            return Err(syn::Error::new(p.span(), "`partition = \"range\"` applies to plain columns only, indexes are partitioned by value"));
+                        }
+                        Some(_) => column_props.key_partition = KeyPartition::Range(bucket),
+                        None if bucket.is_some() => {
+                            return Err(syn::Error::new(attr.span(), "`bucket` requires `partition = \"range\"`"));
+                        }
+                        None => {}
+                    }
+                    if bloom && (!is_index || is_dictionary || is_range || is_transient) {
+                        return Err(syn::Error::new(attr.span(), "`bloom` applies to `#[column(index)]` columns only"));
+                    }
+                    column_props.bloom = bloom;
+                    if unique && (!(is_index || is_range) || is_dictionary || is_transient) {
+                        return Err(syn::Error::new(attr.span(), "`unique` applies to `#[column(index)]` and `#[column(range)]` columns only"));
+                    }
+                    column_props.unique = unique;
                     let column_def = if is_transient {
                         match get_relationship(field, column_name, &column_type, true, read_from)? {
                             None => ColumnDef::Transient(field_def.clone()),
@@ -637,6 +692,62 @@ pub fn get_field_macros(ast: &ItemStruct) -> syn::Result<(KeyDef, Vec<ColumnDef>
     Ok((key, columns))
 }
 
+/// Parses `#[index(columns(a, b), shards = 4, db_cache = 2, lru_cache = 1)]` attributes of the entity, `columns` are the persisted plain columns.
+pub fn get_composite_indexes(ast: &ItemStruct, columns: &[FieldDef]) -> syn::Result<Vec<CompositeIndexDef>> {
+    let mut indexes: Vec<CompositeIndexDef> = Vec::new();
+    for attr in ast.attrs.iter().filter(|a| a.path().is_ident("index")) {
+        let mut names: Vec<Ident> = Vec::new();
+        let mut shards = 1;
+        let mut db_cache_weight = 0;
+        let mut lru_cache_size_mil = 0;
+        attr.parse_nested_meta(|nested| {
+            if nested.path.is_ident("columns") {
+                nested.parse_nested_meta(|column| {
+                    match column.path.get_ident() {
+                        Some(name) => names.push(name.clone()),
+                        None => return Err(syn::Error::new(column.path.span(), "expected a column name")),
+                    }
+                    Ok(())
+                })
+            } else if nested.path.is_ident("shards") {
+                let lit: syn::LitInt = nested.value()?.parse()?;
+                shards = lit.base10_parse::<usize>()?;
+                Ok(())
+            } else if nested.path.is_ident("db_cache") {
+                let lit: syn::LitInt = nested.value()?.parse()?;
+                db_cache_weight = lit.base10_parse::<usize>()?;
+                Ok(())
+            } else if nested.path.is_ident("lru_cache") {
+                let lit: syn::LitInt = nested.value()?.parse()?;
+                lru_cache_size_mil = lit.base10_parse::<usize>()?;
+                Ok(())
+            } else {
+                Err(syn::Error::new(nested.path.span(), "Unsupported form. Use `#[index(columns(a, b), shards = 4, db_cache = 2, lru_cache = 1)]`"))
+            }
+        })?;
+        if !(2..=3).contains(&names.len()) {
+            return Err(syn::Error::new(attr.span(), "Composite index takes 2 or 3 columns, `#[index(columns(a, b))]`"));
+        }
+        let mut index_columns = Vec::with_capacity(names.len());
+        for name in &names {
+            if names.iter().filter(|n| *n == name).count() > 1 {
+                return Err(syn::Error::new(name.span(), format!("Column `{}` is listed twice", name)));
+            }
+            match columns.iter().find(|c| &c.name == name) {
+                Some(column) => index_columns.push(column.clone()),
+                None => return Err(syn::Error::new(name.span(), format!("`{}` is not a persisted #[column] of this entity", name))),
+            }
+        }
+        let name_str = names.iter().map(|n| n.to_string()).collect::<Vec<_>>().join("_and_");
+        indexes.push(CompositeIndexDef {
+            name: Ident::new(&name_str, attr.span()),
+            columns: index_columns,
+            column_props: ColumnProps::new(shards, db_cache_weight, lru_cache_size_mil),
+        });
+    }
+    Ok(indexes)
+}
+
 /// Extracts and validates the required fields (parent & index) for root or pointer.
 pub fn extract_pointer_key_fields(input: &DeriveInput, pointer_type: &PointerType) -> Result<(Option<Field>, Field), syn::Error> {
     let data_struct = match input.data.clone() {
@@ -38,7 +38,7 @@ pub fn column(attr: TokenStream, item: TokenStream) -> TokenStream {
             Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
                 let inner_ty = &fields.unnamed[0].ty;
                 let (impls, maybe_field_attr, extra_derive_impls) =
-                    column::column_impls::generate_column_impls(struct_ident, &struct_type, inner_ty, attr_args.literal);
+                    column::column_impls::generate_column_impls(struct_ident, &struct_type, inner_ty, attr_args.literal, attr_args.compression);
 
                 if let Some(attr) = maybe_field_attr {
                     input.attrs.push(syn::parse_quote! { #[serde_with::serde_as] });
@@ -74,21 +74,45 @@ pub fn column(attr: TokenStream, item: TokenStream) -> TokenStream {
 
 struct LiteralAttr {
     literal: Option<String>,
+    compression: Option<column::column_impls::ZstdAttr>,
 }
 
+/// `#[column("hex", compress = "zstd", level = 3, dict = "dicts/script_hash.zdict")]`, only the encoding literal is positional.
 impl Parse for LiteralAttr {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        if input.is_empty() {
-            Ok(LiteralAttr { literal: None })
-        } else {
-            let literal: Lit = input.parse()?;
-            match literal {
-                Lit::Str(lit_str) => Ok(LiteralAttr {
-                    literal: Some(lit_str.value()), // unquoted, unescaped
-                }),
-                _ => Err(syn::Error::new_spanned(literal, "Expected a string literal")),
+        let mut literal = None;
+        if input.peek(Lit) {
+            match input.parse()? {
+                Lit::Str(lit_str) => literal = Some(lit_str.value()), // unquoted, unescaped
+                other => return Err(syn::Error::new_spanned(other, "Expected a string literal")),
+            }
+            if !input.is_empty() {
+                input.parse::<syn::Token![,]>()?;
             }
         }
+        let mut compress: Option<syn::LitStr> = None;
+        let mut level: Option<syn::LitInt> = None;
+        let mut dict: Option<syn::LitStr> = None;
+        let options = Punctuated::<syn::MetaNameValue, Comma>::parse_terminated(input)?;
+        for option in options {
+            let value = &option.value;
+            if option.path.is_ident("compress") {
+                compress = Some(syn::parse2(quote! { #value })?);
+            } else if option.path.is_ident("level") {
+                level = Some(syn::parse2(quote! { #value })?);
+            } else if option.path.is_ident("dict") {
+                dict = Some(syn::parse2(quote! { #value })?);
+            } else {
+                return Err(syn::Error::new_spanned(option.path, "Unsupported option, use `compress = \"zstd\"`, `level = 3` or `dict = \"path\"`"));
+            }
+        }
+        let compression = match compress {
+            Some(c) if c.value() != "zstd" => return Err(syn::Error::new(c.span(), "Unsupported compression, only `compress = \"zstd\"` is available")),
+            Some(_) => Some(column::column_impls::ZstdAttr { level: level.map(|l| l.base10_parse::<i32>()).transpose()?, dict }),
+            None if level.is_some() || dict.is_some() => return Err(syn::Error::new(input.span(), "`level` and `dict` require `compress = \"zstd\"`")),
+            None => None,
+        };
+        Ok(LiteralAttr { literal, compression })
     }
 }
 
@@ -213,7 +237,7 @@ pub fn entity(_attr: TokenStream, item: TokenStream) -> TokenStream {
     expansion::submit_struct_to_stream(stream, "entity", struct_ident, "_attr.rs")
 }
 
-#[proc_macro_derive(Entity, attributes(pk, fk, column, write_from_using))]
+#[proc_macro_derive(Entity, attributes(pk, fk, column, write_from_using, index))]
 #[proc_macro_error]
 pub fn derive_entity(input: TokenStream) -> TokenStream {
     let item_struct = parse_macro_input!(input as ItemStruct);
@@ -246,7 +270,10 @@ pub fn derive_entity(input: TokenStream) -> TokenStream {
                 name: stringify!(#struct_ident),
                 root: #root,
                 routes_fn: #struct_ident::routes,
-                db_defs: #struct_ident::db_defs
+                db_defs: #struct_ident::db_defs,
+                column_tables: #struct_ident::column_tables,
+                fsck_refs: #struct_ident::fsck_refs,
+                root_height: #struct_ident::root_height
             }
         }
     };
@@ -0,0 +1,89 @@
+use crate::endpoint::EndpointDef;
+use crate::field_parser::EntityDef;
+use crate::rest::HttpParams::Query;
+use crate::rest::{EndpointTag, FunctionDef, HttpMethod, QueryExpr};
+use proc_macro2::Ident;
+use quote::{format_ident, quote};
+use syn::Type;
+
+/// Counts root entities in `from..until` by walking the pk table keys only, nothing is composed.
+pub fn fn_def(entity_def: &EntityDef, table: &Ident, range_query_ty: &Type) -> FunctionDef {
+    let EntityDef { key_def, entity_name, read_ctx_type, ..} = &entity_def;
+    let pk_type = key_def.field_def().tpe;
+    let fn_name = format_ident!("count");
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: &#read_ctx_type, from: #pk_type, until: #pk_type) -> Result<u64, AppError> {
+            let mut count = 0;
+            for entry in tx_context.#table.range::<#pk_type>(from..until)? {
+                entry?;
+                count += 1;
+            }
+            Ok(count)
+        }
+    };
+
+    let test_stream = Some(quote! {
+        #[test]
+        fn #fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let from_value = #pk_type::default();
+            let until_value = #pk_type::default().next_index().next_index();
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let count = #entity_name::#fn_name(&tx_context, from_value, until_value)?;
+            let entities = #entity_name::range(&tx_context, from_value, until_value, None)?;
+            assert_eq!(count, entities.len() as u64, "Count should match the number of entities in the range");
+            Ok(())
+        }
+    });
+
+    let bench_fn_name = format_ident!("_{}", fn_name);
+    let bench_stream = Some(quote! {
+        #[bench]
+        fn #bench_fn_name(b: &mut Bencher) {
+            let (storage_owner, storage) = &*STORAGE;
+            let from_value = #pk_type::default();
+            let until_value = #pk_type::default().next_index().next_index();
+            let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
+            b.iter(|| {
+                #entity_name::#fn_name(&tx_context, from_value, until_value).expect("Failed to count entities");
+            });
+        }
+    });
+
+    let handler_fn_name = format!("{}_{}", entity_name.to_string().to_lowercase(), fn_name);
+
+    FunctionDef {
+        fn_stream,
+        endpoint: Some(EndpointDef {
+            return_type: Some(syn::parse_quote!(u64)),
+            tag: EndpointTag::DataRead,
+            fn_name: fn_name.clone(),
+            params: vec![Query(QueryExpr {
+                ty: range_query_ty.clone(),
+                extraction: quote! { extract::Query(query): extract::Query<#range_query_ty> },
+                samples: quote! { vec![#range_query_ty::sample()] },
+            })],
+            method: HttpMethod::GET,
+            handler_name: format_ident!("{}", handler_fn_name),
+            handler_impl_stream: quote! {
+               Result<PinnedRead<AppJson<u64>>, AppError> {
+                    state.read(move |state| {
+                        #entity_name::begin_pinned_read_ctx(&state.storage)?.try_map(|tx_context| {
+                            let result = #entity_name::#fn_name(&tx_context, query.from, query.until)?;
+                            Ok(AppJson(result))
+                        })
+                    }).await
+                }
+            },
+            utoipa_responses: quote! {
+                responses(
+                    (status = OK, content_type = "application/json", body = u64),
+                    (status = 500, content_type = "application/json", body = ErrorResponse),
+                )
+            },
+            endpoint: format!("/{}/{}", entity_name.to_string().to_lowercase(), fn_name),
+        }.to_endpoint()),
+        test_stream,
+        bench_stream,
+    }
+}
@@ -1,7 +1,7 @@
 use crate::endpoint::EndpointDef;
 use crate::field_parser::EntityDef;
-use crate::rest::HttpParams::Path;
-use crate::rest::{EndpointTag, FunctionDef, HttpMethod, PathExpr};
+use crate::rest::HttpParams::{Path, Query};
+use crate::rest::{EndpointTag, FunctionDef, HttpMethod, PathExpr, QueryExpr};
 use proc_macro2::Ident;
 use quote::{format_ident, quote};
 
@@ -61,21 +61,30 @@ pub fn fn_def(entity_def: &EntityDef, table: &Ident) -> FunctionDef {
                 ty: pk_type.clone(),
                 description: "Primary key".to_string(),
                 sample: quote! { #pk_type::default().url_encode() },
-            }])],
+            }]), Query(QueryExpr {
+                ty: syn::parse_quote!(ProjectionQuery),
+                extraction: quote! { extract::Query(projection): extract::Query<ProjectionQuery> },
+                samples: quote! { vec![ProjectionQuery::default(), ProjectionQuery::sample()] },
+            })],
             method: HttpMethod::GET,
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
               impl IntoResponse {
-                 match #entity_name::begin_read_ctx(&state.storage)
-                   .and_then(|tx_context| #entity_name::#fn_name(&tx_context, #pk_name) ) {
-                       Ok(Some(entity)) => {
-                           (StatusCode::OK, AppJson(entity)).into_response()
-                       },
-                       Ok(None) => {
-                            let message = format!("{} not found", stringify!(#entity_name));
-                            let response = ErrorResponse { message, code: StatusCode::NOT_FOUND.as_u16() };
-                            (StatusCode::NOT_FOUND, AppJson(response)).into_response()
-                       },
+                 match state.read(move |state| #entity_name::begin_pinned_read_ctx(&state.storage)
+                   .and_then(|pinned| pinned.try_map(|tx_context| {
+                       let projection = Arc::new(#entity_name::projection(&projection.paths())?);
+                       Ok(#entity_name::get_projected(&tx_context, #pk_name, &projection)?.map(|entity| Projected::new(entity, projection)))
+                   }))).await {
+                       Ok(pinned) => pinned.map(|found| match found {
+                           Some(entity) => {
+                               (StatusCode::OK, AppJson(entity)).into_response()
+                           },
+                           None => {
+                               let message = format!("{} not found", stringify!(#entity_name));
+                               let response = ErrorResponse { message, code: StatusCode::NOT_FOUND.as_u16() };
+                               (StatusCode::NOT_FOUND, AppJson(response)).into_response()
+                           },
+                       }).into_response(),
                        Err(err) => err.into_response(),
                    }
                }
@@ -83,6 +92,7 @@ pub fn fn_def(entity_def: &EntityDef, table: &Ident) -> FunctionDef {
             utoipa_responses: quote! {
                 responses(
                     (status = OK, content_type = "application/json", body = #entity_type),
+                    (status = 400, content_type = "application/json", body = ErrorResponse),
                     (status = NOT_FOUND, content_type = "application/json", body = ErrorResponse)
                 )
             },
@@ -92,3 +102,39 @@ pub fn fn_def(entity_def: &EntityDef, table: &Ident) -> FunctionDef {
         bench_stream,
     }
 }
+
+pub fn projected_fn_def(entity_def: &EntityDef, table: &Ident) -> FunctionDef {
+    let fn_name = format_ident!("get_projected");
+    let EntityDef { key_def, entity_name, entity_type, read_ctx_type, ..} = &entity_def;
+    let pk_type = &key_def.field_def().tpe;
+
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: &#read_ctx_type, pk: #pk_type, projection: &Projection) -> Result<Option<#entity_type>, AppError> {
+            if tx_context.#table.get_value(pk)?.is_some() {
+                Ok(Some(Self::compose_projected(&tx_context, pk, projection)?))
+            } else {
+                Ok(None)
+            }
+        }
+    };
+
+    let test_stream = Some(quote! {
+        #[test]
+        fn #fn_name() -> Result<(), AppError> {
+            let (storage_owner, storage) = &*STORAGE;
+            let pk_value = #pk_type::default();
+            let tx_context = #entity_name::begin_read_ctx(&storage)?;
+            let projection = #entity_name::projection(&ProjectionQuery::sample().paths())?;
+            let entity = #entity_name::#fn_name(&tx_context, pk_value, &projection)?.expect("Expected entity to exist");
+            assert_eq!(entity, #entity_type::project(#entity_type::sample(), &projection), "Entity should hold only the projected fields");
+            Ok(())
+        }
+    });
+
+    FunctionDef {
+        fn_stream,
+        endpoint: None,
+        test_stream,
+        bench_stream: None,
+    }
+}
@@ -56,10 +56,13 @@ pub fn fn_def(entity_def: &EntityDef, table: &Ident) -> FunctionDef {
             method: HttpMethod::GET,
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
-               Result<AppJson<Vec<#entity_type>>, AppError> {
-                    let tx_context = #entity_name::begin_read_ctx(&state.storage)?;
-                    let result: Vec<#entity_type> = #entity_name::#fn_name(&tx_context).map(|r| r.into_iter().collect())?;
-                    Ok(AppJson(result))
+               Result<PinnedRead<AppJson<Vec<#entity_type>>>, AppError> {
+                    state.read(move |state| {
+                        #entity_name::begin_pinned_read_ctx(&state.storage)?.try_map(|tx_context| {
+                            let result: Vec<#entity_type> = #entity_name::#fn_name(&tx_context).map(|r| r.into_iter().collect())?;
+                            Ok(AppJson(result))
+                        })
+                    }).await
                 }
             },
             utoipa_responses: quote! {
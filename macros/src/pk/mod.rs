@@ -1,4 +1,6 @@
 mod exists;
+mod count;
+mod orphans;
 mod get;
 mod tail;
 mod take;
@@ -10,6 +12,7 @@ mod stream_range;
 mod pk_range;
 mod store;
 mod parent_key;
+mod root_height;
 mod init;
 pub mod delete;
 pub mod pointer_impls;
@@ -56,6 +59,7 @@ impl DbPkMacros {
 
         let mut function_defs: Vec<FunctionDef> = vec![
             get::fn_def(entity_def, &plain_table_def.var_name),
+            get::projected_fn_def(entity_def, &plain_table_def.var_name),
             filter::fn_def(entity_def, &plain_table_def.var_name, no_columns),
             take::fn_def(entity_def, &plain_table_def.var_name),
             tail::fn_def(entity_def, &plain_table_def.var_name),
@@ -63,13 +67,21 @@ impl DbPkMacros {
             last::fn_def(entity_def, &plain_table_def.var_name),
             exists::fn_def(entity_def, &plain_table_def.var_name),
             range::fn_def(entity_def, &plain_table_def.var_name, no_columns),
+            range::projected_fn_def(entity_def, &plain_table_def.var_name),
             stream_range::fn_def(entity_def, &plain_table_def.var_name, &range_query.ty, no_columns),
             pk_range::fn_def(entity_def, &plain_table_def.var_name),
+            root_height::fn_def(entity_def, &plain_table_def.var_name),
         ];
 
         if let Some(Multiplicity::OneToMany) = multiplicity {
             function_defs.push(parent_key::fn_def(entity_def));
         }
+        if is_root {
+            function_defs.push(count::fn_def(entity_def, &plain_table_def.var_name, &range_query.ty));
+        }
+        if !is_root {
+            function_defs.push(orphans::fn_def(entity_def, &plain_table_def.var_name));
+        }
 
         let pk_init = init::pk_init(pk_name);
         DbPkMacros {
@@ -0,0 +1,28 @@
+use crate::field_parser::EntityDef;
+use crate::rest::FunctionDef;
+use proc_macro2::Ident;
+use quote::{format_ident, quote};
+
+pub fn fn_def(entity_def: &EntityDef, table: &Ident) -> FunctionDef {
+    let read_ctx_type = &entity_def.read_ctx_type;
+    let pk_type = &entity_def.key_def.field_def().tpe;
+    let fn_name = format_ident!("orphans");
+    let fn_stream = quote! {
+        pub fn #fn_name(tx_context: &#read_ctx_type, has_parent: impl Fn(&#pk_type) -> Result<bool, AppError>) -> Result<Vec<#pk_type>, AppError> {
+            let mut orphans = Vec::new();
+            for entry_res in tx_context.#table.iter_keys()? {
+                let pk = entry_res?.0.value();
+                if !has_parent(&pk)? {
+                    orphans.push(pk);
+                }
+            }
+            Ok(orphans)
+        }
+    };
+    FunctionDef {
+        fn_stream,
+        endpoint: None,
+        test_stream: None,
+        bench_stream: None,
+    }
+}
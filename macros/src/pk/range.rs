@@ -75,4 +75,29 @@ pub fn fn_def(entity_def: &EntityDef, table: &Ident, no_columns: bool) -> Functi
         test_stream,
         bench_stream
     }
-}
\ No newline at end of file
+}
+pub fn projected_fn_def(entity_def: &EntityDef, table: &Ident) -> FunctionDef {
+    let EntityDef { key_def, entity_type, read_ctx_type, ..} = &entity_def;
+    let pk_type = key_def.field_def().tpe;
+
+    let fn_name = format_ident!("range_projected");
+    let fn_stream =
+        quote! {
+            pub fn #fn_name(tx_context: &#read_ctx_type, from: #pk_type, until: #pk_type, projection: &Projection) -> Result<Vec<#entity_type>, AppError> {
+                let range = from..until;
+                let mut results = Vec::new();
+                for res in tx_context.#table.range::<#pk_type>(range)? {
+                    let (kg, _) = res?;
+                    results.push(Self::compose_projected(&tx_context, kg.value(), projection)?);
+                }
+                Ok(results)
+            }
+        };
+
+    FunctionDef {
+        fn_stream,
+        endpoint: None,
+        test_stream: None,
+        bench_stream: None
+    }
+}
@@ -0,0 +1,29 @@
+use crate::field_parser::EntityDef;
+use crate::rest::FunctionDef;
+use proc_macro2::Ident;
+use quote::{format_ident, quote};
+
+pub fn fn_def(entity_def: &EntityDef, table: &Ident) -> FunctionDef {
+    let tx_context_ty = &entity_def.ctx_type;
+    let fn_name = format_ident!("root_height");
+    let fn_stream = if entity_def.key_def.is_root() {
+        quote! {
+            pub fn #fn_name(storage: &Arc<Storage>) -> Result<Option<u128>, AppError> {
+                let table = #tx_context_ty::definition()?.#table.to_read_field(storage)?;
+                Ok(table.last_key()?.map(|(k, _)| k.value().root_index()))
+            }
+        }
+    } else {
+        quote! {
+            pub fn #fn_name(_storage: &Arc<Storage>) -> Result<Option<u128>, AppError> {
+                Ok(None)
+            }
+        }
+    };
+    FunctionDef {
+        fn_stream,
+        endpoint: None,
+        test_stream: None,
+        bench_stream: None,
+    }
+}
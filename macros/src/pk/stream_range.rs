@@ -14,10 +14,11 @@ pub fn fn_def(entity_def: &EntityDef, table: &Ident, range_query_ty: &Type, no_c
     let fn_name = format_ident!("stream_range");
     let fn_stream =
         quote! {
-            pub fn #fn_name(tx_context: #read_ctx_type, from: #pk_type, until: #pk_type, query: Option<#query_type>) -> Result<impl futures::Stream<Item = Result<#entity_type, AppError>> + Send, AppError> {
-                let range = from..until;
+            pub fn #fn_name(tx_context: #read_ctx_type, from: #pk_type, until: #pk_type, page: PageQuery, projection: Projection, query: Option<#query_type>) -> Result<Paged<#entity_type>, AppError> {
+                let range = page.resume_range(from, until)?;
                 let iter = tx_context.#table.range::<#pk_type>(range)?.map(|res| res.map(|(kg, _)| kg.value()));
-                Self::compose_many_stream(tx_context, iter, query)
+                let (pks, cursor) = page.paginate(page.ordered(iter))?;
+                Ok(Paged::new(Self::compose_many_stream_projected(tx_context, pks, query, projection.clone())?, cursor).projected(projection))
             }
         };
 
@@ -34,7 +35,7 @@ pub fn fn_def(entity_def: &EntityDef, table: &Ident, range_query_ty: &Type, no_c
                 let until_value = #pk_type::default().next_index().next_index().next_index();
                 let query = #query_type::sample();
                 let tx_context = #entity_name::begin_read_ctx(&storage)?;
-                let entity_stream = #entity_name::#fn_name(tx_context, from_value, until_value, Some(query.clone()))?;
+                let entity_stream = #entity_name::#fn_name(tx_context, from_value, until_value, PageQuery::default(), Projection::default(), Some(query.clone()))?;
                 let entities = entity_stream.try_collect::<Vec<#entity_type>>().await?;
                 let expected_entity = #entity_type::sample_with_query(pk, &query).expect("Failed to create sample entity");
                 assert_eq!(entities.len(), 1, "Expected only one entity to be returned for the given stream range with filter");
@@ -51,7 +52,7 @@ pub fn fn_def(entity_def: &EntityDef, table: &Ident, range_query_ty: &Type, no_c
             let from_value = #pk_type::default();
             let until_value = #pk_type::default().next_index().next_index();
             let tx_context = #entity_name::begin_read_ctx(&storage)?;
-            let entity_stream = #entity_name::#fn_name(tx_context, from_value, until_value, None)?;
+            let entity_stream = #entity_name::#fn_name(tx_context, from_value, until_value, PageQuery::default(), Projection::default(), None)?;
             let entities = entity_stream.try_collect::<Vec<#entity_type>>().await?;
             let expected_entities = #entity_type::sample_many(Default::default(), 2);
             assert_eq!(expected_entities, entities, "Expected entities to be returned for the given range");
@@ -72,7 +73,7 @@ pub fn fn_def(entity_def: &EntityDef, table: &Ident, range_query_ty: &Type, no_c
                     let from_value = #pk_type::default();
                     let until_value = #pk_type::default().next_index().next_index().next_index();
                     let tx_context = #entity_name::begin_read_ctx(&storage).expect("Failed to begin read transaction context");
-                    let entity_stream = #entity_name::#fn_name(tx_context, from_value, until_value, Some(query.clone())).expect("Failed to range entities by pk");
+                    let entity_stream = #entity_name::#fn_name(tx_context, from_value, until_value, PageQuery::default(), Projection::default(), Some(query.clone())).expect("Failed to range entities by pk");
                     entity_stream.try_collect::<Vec<#entity_type>>().await.expect("Failed to collect entity stream");
                 })
             });
@@ -91,6 +92,10 @@ pub fn fn_def(entity_def: &EntityDef, table: &Ident, range_query_ty: &Type, no_c
                 ty: range_query_ty.clone(),
                 extraction: quote! { extract::Query(query): extract::Query<#range_query_ty> },
                 samples: quote! { vec![#range_query_ty::sample()] },
+            }), Query(QueryExpr {
+                ty: syn::parse_quote!(ProjectionQuery),
+                extraction: quote! { extract::Query(projection): extract::Query<ProjectionQuery> },
+                samples: quote! { vec![ProjectionQuery::default(), ProjectionQuery::sample()] },
             }), Body(BodyExpr {
                 ty: syn::parse_quote! { Option<#query_type> },
                 extraction: quote! { MaybeJson(body): MaybeJson<#query_type> },
@@ -101,9 +106,9 @@ pub fn fn_def(entity_def: &EntityDef, table: &Ident, range_query_ty: &Type, no_c
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
                impl IntoResponse {
-                   match #entity_name::begin_read_ctx(&state.storage)
-                        .and_then(|tx_context| #entity_name::#fn_name(tx_context, query.from, query.until, body)) {
-                            Ok(stream) => axum_streams::StreamBodyAs::json_nl_with_errors(stream).header("Content-Type", HeaderValue::from_str("application/x-ndjson").unwrap()).into_response(),
+                   match state.read_paged(move |state| #entity_name::begin_pinned_read_ctx(&state.storage)
+                        .and_then(|pinned| pinned.try_map(|tx_context| #entity_name::#fn_name(tx_context, query.from, query.until, query.page(), #entity_name::projection(&projection.paths())?, body)))).await {
+                            Ok(pinned) => pinned.map(|paged| paged.into_ndjson()).into_response(),
                             Err(err)   => err.into_response(),
                     }
                 }
@@ -111,6 +116,7 @@ pub fn fn_def(entity_def: &EntityDef, table: &Ident, range_query_ty: &Type, no_c
             utoipa_responses: quote! {
                 responses(
                     (status = OK, content_type = "application/x-ndjson", body = #entity_type),
+                    (status = 400, content_type = "application/json", body = ErrorResponse),
                     (status = 500, content_type = "application/json", body = ErrorResponse),
                 )
             },
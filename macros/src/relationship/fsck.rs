@@ -0,0 +1,28 @@
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::Type;
+
+fn orphans_def(child_name: &Ident, child_type: &Type, has_parent: TokenStream) -> TokenStream {
+    quote! {
+        let orphans = #child_type::orphans(&tx_context.#child_name, #has_parent)?;
+        if !orphans.is_empty() {
+            let repaired = repair && {
+                let ctx = #child_type::begin_write_ctx(storage, Durability::Immediate)?;
+                ctx.two_phase_commit_or_rollback_and_close_with(|child_context| {
+                    #child_type::delete_many(child_context, &orphans)?;
+                    Ok(())
+                })?;
+                true
+            };
+            issues.extend(orphans.iter().map(|pk| FsckIssue::new(stringify!(#child_type), format!("{:?} has no parent", pk)).repaired(repaired)));
+        }
+    }
+}
+
+pub fn one2one_fsck_def(child_name: &Ident, child_type: &Type) -> TokenStream {
+    orphans_def(child_name, child_type, quote! { |pk| Self::exists(&tx_context, *pk) })
+}
+
+pub fn one2many_fsck_def(child_name: &Ident, child_type: &Type) -> TokenStream {
+    orphans_def(child_name, child_type, quote! { |pk| Self::exists(&tx_context, pk.parent()) })
+}
@@ -56,10 +56,13 @@ pub fn one2one_def(entity_name: &Ident, child_name: &Ident, child_type: &Type, p
             method: HttpMethod::GET,
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
-               Result<AppJson<#child_type>, AppError> {
-                    let tx_context = #child_type::begin_read_ctx(&state.storage)?;
-                    let result = #entity_name::#fn_name(&tx_context, #pk_name)?;
-                    Ok(AppJson(result))
+               Result<PinnedRead<AppJson<#child_type>>, AppError> {
+                    state.read(move |state| {
+                        #child_type::begin_pinned_read_ctx(&state.storage)?.try_map(|tx_context| {
+                            let result = #entity_name::#fn_name(&tx_context, #pk_name)?;
+                            Ok(AppJson(result))
+                        })
+                    }).await
                 }
             },
             utoipa_responses: quote! {
@@ -124,12 +127,15 @@ pub fn one2opt_def(entity_name: &Ident, child_name: &Ident, child_type: &Type, p
             method: HttpMethod::GET,
             handler_name: format_ident!("{}", handler_fn_name),
             handler_impl_stream: quote! {
-               Result<AppJson<#child_type>, AppError> {
-                 #child_type::begin_read_ctx(&state.storage)
-                    .and_then(|tx_context| #entity_name::#fn_name(&tx_context, #pk_name)
-                        .and_then(|opt| {
-                            opt.ok_or_else(|| AppError::NotFound(format!("Not {} found", stringify!(#child_name)))) }) )
-                    .map(AppJson)
+               Result<PinnedRead<AppJson<#child_type>>, AppError> {
+                    state.read(move |state| {
+                        #child_type::begin_pinned_read_ctx(&state.storage)?.try_map(|tx_context| {
+                            #entity_name::#fn_name(&tx_context, #pk_name)
+                                .and_then(|opt| {
+                                    opt.ok_or_else(|| AppError::NotFound(format!("Not {} found", stringify!(#child_name)))) })
+                                .map(AppJson)
+                        })
+                    }).await
                 }
             },
             utoipa_responses: quote! {
@@ -201,10 +207,13 @@ pub fn one2many_def(entity_name: &Ident, child_name: &Ident, child_type: &Type,
                 )
             },
             handler_impl_stream: quote! {
-               Result<AppJson<Vec<#child_type>>, AppError> {
-                    let tx_context = #child_type::begin_read_ctx(&state.storage)?;
-                    let result = #entity_name::#fn_name(&tx_context, #pk_name)?;
-                    Ok(AppJson(result))
+               Result<PinnedRead<AppJson<Vec<#child_type>>>, AppError> {
+                    state.read(move |state| {
+                        #child_type::begin_pinned_read_ctx(&state.storage)?.try_map(|tx_context| {
+                            let result = #entity_name::#fn_name(&tx_context, #pk_name)?;
+                            Ok(AppJson(result))
+                        })
+                    }).await
                 }
             },
             endpoint: format!("/{}/{{{}}}/{}", entity_name.to_string().to_lowercase(), pk_name, child_name),
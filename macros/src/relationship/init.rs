@@ -128,4 +128,60 @@ pub fn one2many_relation_default_init_with_query(child_name: &Ident, child_type:
             }
         };
     }
-}
\ No newline at end of file
+}
+pub fn one2one_relation_init_projected(child_name: &Ident, child_type: &Type) -> TokenStream {
+    quote! {
+        let #child_name = match projection.child(stringify!(#child_name)) {
+            Some(projection) => #child_type::get_projected(&tx_context.#child_name, pk, projection)?.ok_or_else(|| AppError::NotFound(format!("Missing one-to-one child {:?}", pk)))?,
+            None => Default::default(),
+        };
+    }
+}
+
+pub fn one2one_project_statement(child_name: &Ident, child_type: &Type) -> TokenStream {
+    quote! {
+        entity.#child_name = match projection.child(stringify!(#child_name)) {
+            Some(projection) => #child_type::project(std::mem::take(&mut entity.#child_name), projection),
+            None => Default::default(),
+        };
+    }
+}
+
+pub fn one2opt_relation_init_projected(child_name: &Ident, child_type: &Type) -> TokenStream {
+    quote! {
+        let #child_name = match projection.child(stringify!(#child_name)) {
+            Some(projection) => #child_type::get_projected(&tx_context.#child_name, pk, projection)?,
+            None => None,
+        };
+    }
+}
+
+pub fn one2opt_project_statement(child_name: &Ident, child_type: &Type) -> TokenStream {
+    quote! {
+        entity.#child_name = match projection.child(stringify!(#child_name)) {
+            Some(projection) => entity.#child_name.take().map(|child| #child_type::project(child, projection)),
+            None => None,
+        };
+    }
+}
+
+pub fn one2many_relation_init_projected(child_name: &Ident, child_type: &Type) -> TokenStream {
+    quote! {
+        let #child_name = match projection.child(stringify!(#child_name)) {
+            Some(projection) => {
+                let (from, to) = pk.fk_range();
+                #child_type::range_projected(&tx_context.#child_name, from, to, projection)?
+            }
+            None => Vec::new(),
+        };
+    }
+}
+
+pub fn one2many_project_statement(child_name: &Ident, child_type: &Type) -> TokenStream {
+    quote! {
+        entity.#child_name = match projection.child(stringify!(#child_name)) {
+            Some(projection) => std::mem::take(&mut entity.#child_name).into_iter().map(|child| #child_type::project(child, projection)).collect(),
+            None => Vec::new(),
+        };
+    }
+}
@@ -5,6 +5,7 @@ mod delete;
 mod query;
 mod context;
 mod info;
+mod fsck;
 pub mod transient;
 
 use crate::entity;
@@ -31,6 +32,8 @@ pub struct WriteFromStatement {
 pub struct DbRelationshipMacros {
     pub field_def: FieldDef,
     pub struct_init: TokenStream,
+    pub struct_init_projected: TokenStream,
+    pub project_statement: TokenStream,
     pub stream_query_init: FilterQueryItem,
     pub tx_context_item: TxContextItem,
     pub table_info_item: TableInfoItem,
@@ -40,6 +43,7 @@ pub struct DbRelationshipMacros {
     pub store_statement: StoreStatement,
     pub delete_statement: TokenStream,
     pub delete_many_statement: TokenStream,
+    pub fsck_statement: TokenStream,
     pub function_def: FunctionDef,
 }
 
@@ -61,7 +65,9 @@ impl DbRelationshipMacros {
                 DbRelationshipMacros {
                     field_def: field_def.clone(),
                     struct_init: init::one2one_relation_init(child_name, child_type),
-                    stream_query_init: query::query_init(child_name, &child_stream_query_type),
+                    struct_init_projected: init::one2one_relation_init_projected(child_name, child_type),
+                    project_statement: init::one2one_project_statement(child_name, child_type),
+                    stream_query_init: query::query_init(child_name, &child_stream_query_type, &multiplicity),
                     tx_context_item: context::tx_context_item(child_name, &child_tx_context_type, &write_child_tx_context_type, &read_child_tx_context_type),
                     table_info_item: info::table_info_init(child_name, &child_table_info_type),
                     struct_init_with_query: init::one2one_relation_init_with_query(child_name, child_type),
@@ -70,6 +76,7 @@ impl DbRelationshipMacros {
                     store_statement: StoreStatement::Plain(store::one2one_store_def(child_name, child_type)),
                     delete_statement: delete::one2one_delete_def(child_name, child_type),
                     delete_many_statement: delete::one2one_delete_many_def(child_name, child_type),
+                    fsck_statement: fsck::one2one_fsck_def(child_name, child_type),
                     function_def: get::one2one_def(entity_name, child_name, child_type, pk_name, pk_type, &read_child_tx_context_type)
                 }
             }
@@ -77,7 +84,9 @@ impl DbRelationshipMacros {
                 DbRelationshipMacros {
                     field_def: field_def.clone(),
                     struct_init: init::one2opt_relation_init(child_name, child_type),
-                    stream_query_init: query::query_init(child_name, &child_stream_query_type),
+                    struct_init_projected: init::one2opt_relation_init_projected(child_name, child_type),
+                    project_statement: init::one2opt_project_statement(child_name, child_type),
+                    stream_query_init: query::query_init(child_name, &child_stream_query_type, &multiplicity),
                     tx_context_item: context::tx_context_item(child_name, &child_tx_context_type, &write_child_tx_context_type, &read_child_tx_context_type),
                     table_info_item: info::table_info_init(child_name, &child_table_info_type),
                     struct_init_with_query: init::one2opt_relation_init_with_query(child_name, child_type),
@@ -86,6 +95,7 @@ impl DbRelationshipMacros {
                     store_statement: StoreStatement::Plain(store::one2opt_store_def(child_name, child_type)),
                     delete_statement: delete::one2opt_delete_def(child_name, child_type),
                     delete_many_statement: delete::one2opt_delete_many_def(child_name, child_type),
+                    fsck_statement: fsck::one2one_fsck_def(child_name, child_type),
                     function_def: get::one2opt_def(entity_name, child_name, child_type, pk_name, pk_type, &read_child_tx_context_type)
                 }
             }
@@ -102,7 +112,9 @@ impl DbRelationshipMacros {
                 DbRelationshipMacros {
                     field_def: field_def.clone(),
                     struct_init: init::one2many_relation_init(child_name, child_type),
-                    stream_query_init: query::query_init(child_name, &child_stream_query_type),
+                    struct_init_projected: init::one2many_relation_init_projected(child_name, child_type),
+                    project_statement: init::one2many_project_statement(child_name, child_type),
+                    stream_query_init: query::query_init(child_name, &child_stream_query_type, &multiplicity),
                     tx_context_item: context::tx_context_item(child_name, &child_tx_context_type, &write_child_tx_context_type, &read_child_tx_context_type),
                     table_info_item: info::table_info_init(child_name, &child_table_info_type),
                     struct_init_with_query: init::one2many_relation_init_with_query(child_name, child_type),
@@ -111,6 +123,7 @@ impl DbRelationshipMacros {
                     store_statement,
                     delete_statement: delete::one2many_delete_def(child_name, child_type),
                     delete_many_statement: delete::one2many_delete_many_def(child_name, child_type),
+                    fsck_statement: fsck::one2many_fsck_def(child_name, child_type),
                     function_def: get::one2many_def(entity_name, child_name, child_type, pk_name, pk_type, &read_child_tx_context_type)
                 }
             }
@@ -1,10 +1,17 @@
 use crate::entity::query::FilterQueryItem;
+use crate::field_parser::Multiplicity;
 use proc_macro2::Ident;
 use quote::quote;
 use syn::Type;
 
-pub fn query_init(child_name: &Ident, child_stream_query_type: &Type) -> FilterQueryItem {
+/// A one-to-many child query matches when any of the children does, like it keeps the parent when filtering.
+pub fn query_init(child_name: &Ident, child_stream_query_type: &Type, multiplicity: &Multiplicity) -> FilterQueryItem {
     let definition = quote! { pub #child_name: Option<#child_stream_query_type> };
     let init = quote! { #child_name: Some(#child_stream_query_type::sample()) };
-    FilterQueryItem { definition, init }
+    let matches = match multiplicity {
+        Multiplicity::OneToOne => quote! { self.#child_name.as_ref().is_none_or(|q| q.matches(&entity.#child_name)) },
+        Multiplicity::OneToOption => quote! { self.#child_name.as_ref().is_none_or(|q| entity.#child_name.as_ref().is_some_and(|child| q.matches(child))) },
+        Multiplicity::OneToMany => quote! { self.#child_name.as_ref().is_none_or(|q| entity.#child_name.iter().any(|child| q.matches(child))) },
+    };
+    FilterQueryItem { field: child_name.clone(), definition, init, matches }
 }
@@ -6,7 +6,10 @@ use syn::Type;
 
 pub struct TransientRelationshipMacros {
     pub field_def: FieldDef,
+    pub read_from: Option<ReadFrom>,
     pub struct_init: TokenStream,
+    pub struct_init_projected: TokenStream,
+    pub project_statement: TokenStream,
     pub struct_init_with_query: TokenStream,
     pub struct_default_init: TokenStream,
     pub struct_default_init_with_query: TokenStream,
@@ -40,11 +43,37 @@ impl TransientRelationshipMacros {
         )
     }
 
+    pub fn read_from_projected(field_name: &Ident, child_type: &Type, outer: Ident, inner: Ident) -> (TokenStream, TokenStream) {
+        let inner_tx_context = macro_utils::one_to_many_field_name_from_type(child_type);
+        (
+            quote! {
+                let #field_name = match projection.child(stringify!(#field_name)) {
+                    Some(projection) => {
+                        let mut result = Vec::with_capacity(#outer.len());
+                        for in_field in &#outer {
+                            if let Some(out_field) = #child_type::get_projected(&tx_context.#inner_tx_context, in_field.#inner, projection)? {
+                                result.push(out_field);
+                            }
+                        }
+                        result
+                    }
+                    None => Vec::new(),
+                };
+            },
+            quote! {
+                entity.#field_name = match projection.child(stringify!(#field_name)) {
+                    Some(projection) => std::mem::take(&mut entity.#field_name).into_iter().map(|child| #child_type::project(child, projection)).collect(),
+                    None => Vec::new(),
+                };
+            }
+        )
+    }
+
     pub fn new(field_def: FieldDef, read_from: Option<ReadFrom>) -> TransientRelationshipMacros {
         let child_name = &field_def.name; // e.g., "input_refs / input_utxos"
         let child_type = &field_def.tpe; // e.g., the type `InputRef` from Vec<InputRef>
 
-        let (struct_init, default_init) = if let Some(ReadFrom { outer, inner }) = read_from {
+        let (struct_init, default_init) = if let Some(ReadFrom { outer, inner }) = read_from.clone() {
             Self::read_from(child_name, child_type, outer, inner)
         } else {
             let default_init =
@@ -53,10 +82,31 @@ impl TransientRelationshipMacros {
                 };
             (default_init.clone(), default_init)
         };
+        let (struct_init_projected, project_statement) = if let Some(ReadFrom { outer, inner }) = read_from.clone() {
+            Self::read_from_projected(child_name, child_type, outer, inner)
+        } else {
+            (
+                quote! {
+                    let #child_name = if projection.loads(stringify!(#child_name)) {
+                        <#child_type as Sampleable>::sample_many_from(3, pk.total_index() as usize)
+                    } else {
+                        Vec::new()
+                    };
+                },
+                quote! {
+                    if !projection.loads(stringify!(#child_name)) {
+                        entity.#child_name = Vec::new();
+                    }
+                }
+            )
+        };
 
         TransientRelationshipMacros {
             field_def: field_def.clone(),
+            read_from,
             struct_init: struct_init.clone(),
+            struct_init_projected,
+            project_statement,
             struct_init_with_query: struct_init,
             struct_default_init: default_init.clone(),
             struct_default_init_with_query: default_init
@@ -1,5 +1,6 @@
 use crate::table::{DictTableDefs, IndexTableDefs, PlainTableDef};
 use proc_macro2::{Ident, TokenStream};
+use syn::Type;
 use quote::quote;
 
 pub fn get_db_defs(plain_table_defs: &[PlainTableDef], dict_table_defs: &[DictTableDefs], index_table_defs: &[IndexTableDefs]) -> TokenStream {
@@ -31,4 +32,18 @@ pub fn get_db_defs(plain_table_defs: &[PlainTableDef], dict_table_defs: &[DictTa
             vec![#( DbDef { name: String::from(stringify!(#idents)), shards: #shards, db_cache_weight_or_zero: #db_caches, lru_cache_size_or_zero: #lru_cache_sizes } ),*]
         }
     }
-}
\ No newline at end of file
+}
+pub fn get_column_tables(ctx_type: &Type, plain_table_defs: &[PlainTableDef], dict_table_defs: &[DictTableDefs], index_table_defs: &[IndexTableDefs]) -> TokenStream {
+    let idents: Vec<Ident> =
+        plain_table_defs.iter().map(|d| d.var_name.clone())
+            .chain(index_table_defs.iter().map(|d| d.var_name.clone()))
+            .chain(dict_table_defs.iter().map(|d| d.var_name.clone()))
+            .collect();
+
+    quote! {
+        pub fn column_tables() -> Result<Vec<Arc<dyn ColumnTables>>, AppError> {
+            let defs = <#ctx_type as TxContext>::definition()?;
+            Ok(vec![#( Arc::new(defs.#idents) as Arc<dyn ColumnTables> ),*])
+        }
+    }
+}
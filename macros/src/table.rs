@@ -159,7 +159,7 @@ impl TableDef {
         let name = format_ident!("{}_{}_INDEX", entity_name.to_string().to_uppercase(), column_name.to_string().to_uppercase());
         let var_name = Ident::new(&format!("{}", name).to_lowercase(), name.span());
         let name_str = &name.to_string();
-        let definition = quote! { MultimapTableDefinition::<'static, #column_type, #pk_type>::new(#name_str) };
+        let definition = quote! { MultimapTableDefinition::<'static, <#column_type as CacheKey>::Key, #pk_type>::new(#name_str) };
         TableDef {
             var_name,
             key_type: column_type.clone(),
@@ -203,7 +203,7 @@ impl TableDef {
         let name = format_ident!("{}_{}_TO_DICT_PK", entity_name.to_string().to_uppercase(), column_name.to_string().to_uppercase());
         let var_name = Ident::new(&format!("{}", name).to_lowercase(), name.span());
         let name_str = &name.to_string();
-        let definition = quote! { TableDefinition::<'static, #column_type, #pk_type>::new(#name_str) };
+        let definition = quote! { TableDefinition::<'static, <#column_type as CacheKey>::Key, #pk_type>::new(#name_str) };
 
         TableDef {
             var_name,
@@ -1,8 +1,94 @@
-use crate::ByteVecColumnSerde;
+use crate::{AppError, ByteVecColumnSerde};
 use base64::{engine::general_purpose, Engine as _};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::ops::Bound;
 use serde_with::SerializeAs;
 
+/// Leading bits shared by the raw values whose text encoding starts with a given prefix.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BitPrefix {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitPrefix {
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        let bits = bytes.len() * 8;
+        BitPrefix { bytes, bits }
+    }
+
+    /// Appends the `width` low bits of `value`, most significant first.
+    pub fn push_bits(&mut self, value: u32, width: usize) {
+        for shift in (0..width).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> shift) & 1 == 1 {
+                let last = self.bytes.len() - 1;
+                self.bytes[last] |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// The smallest value with the prefix and the first value past all of them, `None` when nothing sorts after them.
+    pub fn bounds(&self) -> (Vec<u8>, Option<Vec<u8>>) {
+        let mut until = self.bytes.clone();
+        let mut step = match self.bits % 8 {
+            0 => 1u8,
+            used => 1u8 << (8 - used),
+        };
+        for idx in (0..until.len()).rev() {
+            let (sum, carry) = until[idx].overflowing_add(step);
+            until[idx] = sum;
+            if !carry {
+                // bytes that wrapped around to zero would make shorter values with the prefix sort past the bound
+                until.truncate(idx + 1);
+                return (self.bytes.clone(), Some(until));
+            }
+            step = 1;
+        }
+        (self.bytes.clone(), None)
+    }
+
+    /// `bounds` of fixed width values, padded with zeros.
+    pub fn array_bounds<const N: usize>(&self) -> Result<([u8; N], Option<[u8; N]>), AppError> {
+        if self.bytes.len() > N {
+            return Err(AppError::BadRequest(format!("prefix of {} bits is longer than the {N} bytes of the value", self.bits)));
+        }
+        let pad = |bytes: Vec<u8>| {
+            let mut arr = [0u8; N];
+            arr[..bytes.len()].copy_from_slice(&bytes);
+            arr
+        };
+        let (from, until) = self.bounds();
+        Ok((pad(from), until.map(pad)))
+    }
+
+    pub fn range<T>(&self, value: impl Fn(Vec<u8>) -> T) -> (Bound<T>, Bound<T>) {
+        let (from, until) = self.bounds();
+        (Bound::Included(value(from)), until.map_or(Bound::Unbounded, |until| Bound::Excluded(value(until))))
+    }
+
+    pub fn array_range<const N: usize, T>(&self, value: impl Fn([u8; N]) -> T) -> Result<(Bound<T>, Bound<T>), AppError> {
+        let (from, until) = self.array_bounds::<N>()?;
+        Ok((Bound::Included(value(from)), until.map_or(Bound::Unbounded, |until| Bound::Excluded(value(until)))))
+    }
+}
+
+/// Range of the single value `encoded` decodes to, the prefix range of columns whose encoding is not bit aligned.
+pub fn exact_range<T: DeserializeOwned + Clone>(encoded: &str) -> Result<(Bound<T>, Bound<T>), AppError> {
+    let value: T = serde_json::from_str(encoded)
+        .or_else(|_| serde_json::from_value(serde_json::Value::String(encoded.to_string())))
+        .map_err(|e| AppError::BadRequest(format!("`{encoded}` is neither a searchable prefix nor a whole value: {e}")))?;
+    Ok((Bound::Included(value.clone()), Bound::Included(value)))
+}
+
 pub struct Base64;
 
 impl ByteVecColumnSerde for Base64 {
@@ -12,6 +98,25 @@ impl ByteVecColumnSerde for Base64 {
     fn encoded_example() -> String {
         "YQ==".to_string()
     }
+    fn prefix(prefix: &str) -> Option<BitPrefix> {
+        if prefix.ends_with('=') {
+            // padding closes the value, the prefix is the whole of it
+            return general_purpose::STANDARD.decode(prefix).ok().map(BitPrefix::from_bytes);
+        }
+        let mut bits = BitPrefix::default();
+        for c in prefix.bytes() {
+            let sextet = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => return None,
+            };
+            bits.push_bits(sextet as u32, 6);
+        }
+        Some(bits)
+    }
 }
 
 impl<T> SerializeAs<T> for Base64
@@ -48,6 +153,13 @@ impl ByteVecColumnSerde for Hex {
     fn encoded_example() -> String {
         "61".to_string()
     }
+    fn prefix(prefix: &str) -> Option<BitPrefix> {
+        let mut bits = BitPrefix::default();
+        for c in prefix.chars() {
+            bits.push_bits(c.to_digit(16)?, 4);
+        }
+        Some(bits)
+    }
 }
 
 impl<T> SerializeAs<T> for Hex
@@ -105,6 +217,9 @@ impl ByteVecColumnSerde for Utf8 {
     fn encoded_example() -> String {
         "a".to_string()
     }
+    fn prefix(prefix: &str) -> Option<BitPrefix> {
+        Some(BitPrefix::from_bytes(prefix.as_bytes().to_vec()))
+    }
     fn next_value(value: &[u8]) -> Vec<u8> {
         let s = match String::from_utf8(value.to_vec()) {
             Ok(s) => s,
@@ -158,7 +273,7 @@ mod tests {
 
 
     #[serde_as]
-    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
     struct HexWrap(
         #[serde_as(as = "Hex")] Vec<u8>
     );
@@ -204,4 +319,29 @@ mod tests {
         assert_eq!(Hex::decoded_example(), b"a".to_vec());
         assert_eq!(Hex::encoded_example(), "61".to_string());
     }
+
+    #[test]
+    fn prefixes_bound_the_values_they_start() {
+        assert_eq!(Hex::prefix("ab").unwrap().bounds(), (vec![0xab], Some(vec![0xac])));
+        assert_eq!(Hex::prefix("abc").unwrap().bounds(), (vec![0xab, 0xc0], Some(vec![0xab, 0xd0])));
+        assert_eq!(Hex::prefix("12ff").unwrap().bounds(), (vec![0x12, 0xff], Some(vec![0x13])));
+        assert_eq!(Hex::prefix("ff").unwrap().bounds(), (vec![0xff], None));
+        assert_eq!(Hex::prefix("").unwrap().bounds(), (vec![], None));
+        assert!(Hex::prefix("xy").is_none());
+
+        assert_eq!(Base64::prefix("YQ==").unwrap().bounds(), (b"a".to_vec(), Some(b"b".to_vec())));
+        let abc = Base64::prefix("YWJ").unwrap();
+        assert_eq!(abc.bits(), 18);
+        let (from, until) = abc.bounds();
+        assert!(from.as_slice() <= b"abc".as_slice() && b"abc".as_slice() < until.unwrap().as_slice());
+        assert_eq!(Utf8::prefix("bc1q").unwrap().bounds(), (b"bc1q".to_vec(), Some(b"bc1r".to_vec())));
+
+        let (from, until) = Hex::prefix("abc").unwrap().array_bounds::<4>().unwrap();
+        assert_eq!((from, until), ([0xab, 0xc0, 0, 0], Some([0xab, 0xd0, 0, 0])));
+        assert!(Hex::prefix("0102").unwrap().array_bounds::<1>().is_err());
+
+        assert_eq!(exact_range::<u32>("42").unwrap(), (Bound::Included(42), Bound::Included(42)));
+        assert_eq!(exact_range::<HexWrap>("61").unwrap().0, Bound::Included(HexWrap(b"a".to_vec())));
+        assert!(exact_range::<u32>("x").is_err());
+    }
 }
\ No newline at end of file
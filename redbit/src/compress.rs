@@ -0,0 +1,294 @@
+use crate::AppError;
+use redb::{Key, TypeName, Value};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::marker::PhantomData;
+use zstd::bulk::{Compressor, Decompressor};
+
+/// Tag of a value stored as it is, short values and values zstd cannot shrink.
+pub const RAW_TAG: u8 = 0;
+/// Tag of a value stored as a zstd frame.
+pub const ZSTD_TAG: u8 = 1;
+/// Values shorter than this are not worth a zstd frame header.
+pub const MIN_COMPRESSED_LEN: usize = 48;
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+/// Suffix of the redb type name of compressed columns, switching compression on or changing the dictionary is a schema change.
+pub const ZSTD_TYPE_SUFFIX: &str = "+zstd";
+/// Values read by `TableInfo` to estimate stored and raw bytes of a table.
+pub const VALUE_BYTES_SAMPLE: usize = 1024;
+
+/// zstd contexts of one `#[column(compress = "zstd")]` type, the generated codec keeps one per thread.
+/// Only values are compressed, index and dictionary keys of compressed types are stored as `Raw` bytes, so they are
+/// hashed by partitioners and bloom filters and compared by redb like any other byte key.
+///
+/// Values are written by `encode` only, a value that does not decode means the shard file is corrupted. redb cannot
+/// fail `Value::from_bytes`, so reading such a value panics naming the column type, `fsck` finds them without reading them.
+pub struct ZstdCodec {
+    compressor: Compressor<'static>,
+    decompressor: Decompressor<'static>,
+}
+
+impl ZstdCodec {
+    /// An empty `dict` compresses every value on its own, a dictionary trained on the column helps short values a lot.
+    pub fn new(level: i32, dict: &[u8]) -> Self {
+        let (compressor, decompressor) =
+            if dict.is_empty() {
+                (Compressor::new(level), Decompressor::new())
+            } else {
+                (Compressor::with_dictionary(level, dict), Decompressor::with_dictionary(dict))
+            };
+        ZstdCodec {
+            compressor: compressor.expect("Invalid zstd compression level or dictionary"),
+            decompressor: decompressor.expect("Invalid zstd dictionary"),
+        }
+    }
+
+    pub fn encode(&mut self, raw: &[u8]) -> Vec<u8> {
+        if raw.len() >= MIN_COMPRESSED_LEN {
+            let frame = self.compressor.compress(raw).expect("zstd compression failed");
+            if frame.len() < raw.len() {
+                let mut out = Vec::with_capacity(frame.len() + 1);
+                out.push(ZSTD_TAG);
+                out.extend_from_slice(&frame);
+                return out;
+            }
+        }
+        let mut out = Vec::with_capacity(raw.len() + 1);
+        out.push(RAW_TAG);
+        out.extend_from_slice(raw);
+        out
+    }
+
+    pub fn decode<'a>(&mut self, stored: &'a [u8]) -> Result<Cow<'a, [u8]>, String> {
+        match stored.split_first() {
+            Some((&RAW_TAG, raw)) => Ok(Cow::Borrowed(raw)),
+            Some((&ZSTD_TAG, frame)) => {
+                let len = frame_content_size(frame)?;
+                let raw = self.decompressor.decompress(frame, len).map_err(|e| format!("corrupted zstd frame: {e}"))?;
+                Ok(Cow::Owned(raw))
+            }
+            other => Err(unknown_tag(other.map(|(tag, _)| *tag))),
+        }
+    }
+
+    /// Orders stored values by their raw bytes, redb never calls it as compressed types are not keys of any table.
+    pub fn compare(&mut self, stored1: &[u8], stored2: &[u8]) -> Ordering {
+        match (stored1.split_first(), stored2.split_first()) {
+            (Some((&RAW_TAG, raw1)), Some((&RAW_TAG, raw2))) => raw1.cmp(raw2),
+            _ => {
+                let raw1 = self.decode(stored1).map(Cow::into_owned);
+                let raw2 = self.decode(stored2);
+                match (raw1, raw2) {
+                    (Ok(raw1), Ok(raw2)) => raw1.as_slice().cmp(raw2.as_ref()),
+                    (Err(e), _) | (_, Err(e)) => panic!("Compressed column value cannot be compared: {e}"),
+                }
+            }
+        }
+    }
+}
+
+/// Checks the tag and the frame of a stored value without decompressing it.
+pub fn verify(stored: &[u8]) -> Result<(), String> {
+    match stored.split_first() {
+        Some((&RAW_TAG, _)) => Ok(()),
+        Some((&ZSTD_TAG, frame)) => {
+            frame_content_size(frame)?;
+            match zstd::zstd_safe::find_frame_compressed_size(frame) {
+                Ok(len) if len == frame.len() => Ok(()),
+                Ok(len) => Err(format!("zstd frame of {len} bytes stored in {} bytes", frame.len())),
+                Err(code) => Err(format!("invalid zstd frame: {}", zstd::zstd_safe::get_error_name(code))),
+            }
+        }
+        other => Err(unknown_tag(other.map(|(tag, _)| *tag))),
+    }
+}
+
+fn unknown_tag(tag: Option<u8>) -> String {
+    match tag {
+        Some(tag) => format!("unknown compressed value tag {tag}"),
+        None => "empty compressed value".to_string(),
+    }
+}
+
+fn frame_content_size(frame: &[u8]) -> Result<usize, String> {
+    match zstd::zstd_safe::get_frame_content_size(frame) {
+        Ok(Some(len)) => Ok(len as usize),
+        _ => Err("zstd frame without content size".to_string()),
+    }
+}
+
+/// Uncompressed bytes of a `#[column(compress = "zstd")]` value.
+pub trait RawBytes: fmt::Debug + 'static {
+    fn from_raw(raw: &[u8]) -> Self;
+    fn raw(&self) -> &[u8];
+}
+
+/// `CacheKey::Key` of compressed types, the raw value as a redb key of `pk_by_index` and `value_to_dict_pk`.
+pub struct Raw<V>(PhantomData<fn() -> V>);
+
+impl<V> Clone for Raw<V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V> Copy for Raw<V> {}
+
+impl<V> fmt::Debug for Raw<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Raw")
+    }
+}
+
+impl<V: RawBytes> Value for Raw<V> {
+    type SelfType<'a> = V where Self: 'a;
+    type AsBytes<'a> = &'a [u8] where Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> V
+    where
+        Self: 'a,
+    {
+        V::from_raw(data)
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a V) -> &'a [u8]
+    where
+        Self: 'b,
+    {
+        value.raw()
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new("Vec<u8>")
+    }
+}
+
+impl<V: RawBytes> Key for Raw<V> {
+    fn compare(data1: &[u8], data2: &[u8]) -> Ordering {
+        data1.cmp(data2)
+    }
+}
+
+/// True for values of `#[column(compress = "zstd")]` types.
+pub fn is_compressed<V: Value>() -> bool {
+    V::type_name().name().contains(ZSTD_TYPE_SUFFIX)
+}
+
+/// redb type name of a compressed column, the dictionary id is part of it because values are unreadable without their dictionary.
+pub fn zstd_type_name(raw_type: &str, dict: &[u8]) -> String {
+    match zstd::zstd_safe::get_dict_id_from_dict(dict) {
+        Some(id) => format!("{raw_type}{ZSTD_TYPE_SUFFIX}:{id}"),
+        None => format!("{raw_type}{ZSTD_TYPE_SUFFIX}"),
+    }
+}
+
+/// Length of the value `V` encoded into `stored` before compression.
+pub fn raw_len<V: Value>(stored: &[u8]) -> usize {
+    if !is_compressed::<V>() {
+        return stored.len();
+    }
+    match stored.split_first() {
+        Some((&ZSTD_TAG, frame)) => frame_content_size(frame).unwrap_or(frame.len()),
+        Some((_, raw)) => raw.len(),
+        None => 0,
+    }
+}
+
+/// Trains a dictionary for `#[column(compress = "zstd", dict = "path")]` from a sample of the column values.
+pub fn train_dictionary<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> Result<Vec<u8>, AppError> {
+    Ok(zstd::dict::from_samples(samples, max_size)?)
+}
+
+/// Stored and raw bytes of sampled values of one table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ValueBytes {
+    pub values: u64,
+    pub stored: u64,
+    pub raw: u64,
+}
+
+impl ValueBytes {
+    pub fn add<V: Value>(&mut self, value: &V::SelfType<'_>) {
+        let bytes = V::as_bytes(value);
+        let stored = bytes.as_ref();
+        self.values += 1;
+        self.stored += stored.len() as u64;
+        self.raw += raw_len::<V>(stored) as u64;
+    }
+
+    /// Stored and raw bytes of `entries` values of the same average size as the sampled ones.
+    pub fn extrapolate(&self, entries: u64) -> (u64, u64) {
+        if self.values == 0 {
+            return (0, 0);
+        }
+        let scale = |bytes: u64| (bytes as u128 * entries as u128 / self.values as u128) as u64;
+        (scale(self.stored), scale(self.raw))
+    }
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+
+    fn script(i: u8) -> Vec<u8> {
+        let mut v = b"OP_DUP OP_HASH160 0123456789abcdef0123456789abcdef OP_EQUALVERIFY OP_CHECKSIG".to_vec();
+        v.push(i);
+        v
+    }
+
+    #[test]
+    fn encoding_roundtrips_and_orders_by_raw_bytes() {
+        let mut codec = ZstdCodec::new(DEFAULT_ZSTD_LEVEL, &[]);
+        let short = b"short".to_vec();
+        let long = script(1);
+        let (short_enc, long_enc) = (codec.encode(&short), codec.encode(&long));
+        assert_eq!(short_enc[0], RAW_TAG);
+        assert_eq!(long_enc[0], ZSTD_TAG);
+        assert!(long_enc.len() < long.len());
+        assert_eq!(codec.encode(&long), long_enc, "encoding is deterministic");
+        assert_eq!(codec.decode(&short_enc).unwrap().as_ref(), short.as_slice());
+        assert_eq!(codec.decode(&long_enc).unwrap().as_ref(), long.as_slice());
+        assert_eq!(verify(&long_enc), Ok(()));
+        assert!(verify(&long_enc[..long_enc.len() - 1]).is_err());
+        assert!(verify(&[7, 1, 2]).is_err());
+        assert!(codec.decode(&[7, 1, 2]).is_err());
+
+        let mut raws = vec![script(3), b"OP_A".to_vec(), script(1), b"OP_Z".to_vec(), Vec::new()];
+        let mut stored: Vec<Vec<u8>> = raws.iter().map(|r| codec.encode(r)).collect();
+        raws.sort();
+        stored.sort_by(|a, b| codec.compare(a, b));
+        let decoded: Vec<Vec<u8>> = stored.iter().map(|s| codec.decode(s).unwrap().into_owned()).collect();
+        assert_eq!(decoded, raws);
+    }
+
+    #[test]
+    fn trained_dictionary_is_part_of_type_name_and_shrinks_values() {
+        let samples: Vec<Vec<u8>> = (0..2000u64)
+            .map(|i| format!("OP_DUP OP_HASH160 {:016x}{:016x} OP_EQUALVERIFY OP_CHECKSIG", i.wrapping_mul(0x9E3779B97F4A7C15), i.wrapping_mul(0xC2B2AE3D27D4EB4F)).into_bytes())
+            .collect();
+        let dict = train_dictionary(&samples, 4096).unwrap();
+        assert!(zstd_type_name("Vec<u8>", &dict).starts_with("Vec<u8>+zstd:"));
+        assert_eq!(zstd_type_name("Vec<u8>", &[]), "Vec<u8>+zstd");
+
+        let mut plain = ZstdCodec::new(DEFAULT_ZSTD_LEVEL, &[]);
+        let mut trained = ZstdCodec::new(DEFAULT_ZSTD_LEVEL, &dict);
+        let value = samples[1234].clone();
+        let with_dict = trained.encode(&value);
+        assert!(with_dict.len() < plain.encode(&value).len());
+        assert_eq!(trained.decode(&with_dict).unwrap().as_ref(), value.as_slice());
+    }
+
+    #[test]
+    fn value_bytes_extrapolate_the_sample() {
+        let mut bytes = ValueBytes::default();
+        bytes.add::<&[u8]>(&b"abcd".as_slice());
+        bytes.add::<&[u8]>(&b"ab".as_slice());
+        assert_eq!(bytes.extrapolate(10), (30, 30));
+        assert_eq!(ValueBytes::default().extrapolate(10), (0, 0));
+    }
+}
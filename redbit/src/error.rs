@@ -26,6 +26,9 @@ pub enum AppError {
     #[error("redb commit error: {0}")]
     RedbCommit(#[from] redb::CommitError),
 
+    #[error("redb savepoint error: {0}")]
+    RedbSavepoint(#[from] redb::SavepointError),
+
     #[error("serde error: {0}")]
     SerdeError(#[from] serde_json::Error),
 
@@ -50,6 +53,15 @@ pub enum AppError {
     #[error("Bad Request: {0}")]
     BadRequest(String),
 
+    #[error("Read only: {0}")]
+    ReadOnly(String),
+
+    #[error("Unique constraint violation: {0}")]
+    UniqueViolation(String),
+
+    #[error("Unavailable, retry later: {0}")]
+    Unavailable(String),
+
     #[error("Internal error: {0}")]
     Internal(#[source] Box<dyn std::error::Error + Send + Sync>),
 
@@ -62,6 +74,9 @@ impl AppError {
         match self {
             AppError::NotFound(_)      => StatusCode::NOT_FOUND,
             AppError::BadRequest(_)    => StatusCode::BAD_REQUEST,
+            AppError::ReadOnly(_)      => StatusCode::FORBIDDEN,
+            AppError::UniqueViolation(_) => StatusCode::CONFLICT,
+            AppError::Unavailable(_)   => StatusCode::SERVICE_UNAVAILABLE,
             AppError::JsonRejection(r) => r.status(),
             _                          => StatusCode::INTERNAL_SERVER_ERROR,
         }
@@ -2,6 +2,7 @@
 extern crate test;
 
 pub mod query;
+pub mod projection;
 pub mod retry;
 pub mod logger;
 pub mod storage;
@@ -9,6 +10,7 @@ pub mod utils;
 pub mod error;
 pub mod rest;
 pub mod codec;
+pub mod compress;
 mod macro_rules;
 
 pub use axum;
@@ -38,6 +40,7 @@ pub use macros::PointerKey;
 pub use macros::RootKey;
 pub use once_cell;
 pub use query::*;
+pub use projection::*;
 pub use rand;
 pub use redb;
 pub use redb::{
@@ -59,17 +62,23 @@ pub use std::collections::HashMap;
 pub use std::collections::HashSet;
 pub use std::collections::VecDeque;
 pub use std::fmt::Debug;
+pub use std::ops::Bound;
 pub use std::pin::Pin;
 pub use std::sync::Arc;
 pub use std::sync::Weak;
 pub use std::thread;
 pub use std::time::Duration;
 pub use std::time::Instant;
-pub use rest::{RequestState, ErrorResponse, MaybeJson, AppJson, FilterOp};
+pub use rest::{RequestState, ReadExecutor, DEFAULT_READ_CONCURRENCY, DEFAULT_STREAM_CONCURRENCY, ErrorResponse, MaybeJson, AppJson, FilterOp};
 pub use error::{AppError, ParsePointerError};
 pub use storage::context::{ReadTxContext, ToReadField, ToWriteField, TxContext, WriteTxContext};
-pub use storage::init::{Storage, DbDef, StorageOwner};
-pub use storage::partitioning::{BytesPartitioner, KeyPartitioner, Partitioning, ValuePartitioner, Xxh3Partitioner};
+pub use storage::bloom::{BloomFilter, BloomFilters};
+pub use storage::cache::{LruStats, MeteredLru};
+pub use storage::backpressure::{QueueBudget, QueueStats, DEFAULT_WRITER_QUEUE_BUDGET};
+pub use storage::spill::{SpillConfig, SPILL_DIR};
+pub use storage::memory::MemoryBackend;
+pub use storage::init::{Storage, DbDef, ShardDb, StorageOwner};
+pub use storage::partitioning::{BytesPartitioner, KeyPartitioner, KeyRange, MergedRange, ValueRange, Partitioning, RangePartitioner, ValuePartitioner, Xxh3Partitioner};
 pub use storage::table_dict::DictFactory;
 pub use storage::table_dict_read::ShardedReadOnlyDictTable;
 pub use storage::table_dict_write::DictTable;
@@ -78,8 +87,12 @@ pub use storage::table_index_read::ShardedReadOnlyIndexTable;
 pub use storage::table_plain::PlainFactory;
 pub use storage::table_plain_read::ShardedReadOnlyPlainTable;
 pub use storage::table_writer::ShardedTableWriter;
-pub use storage::table_writer_api::{FlushFuture, RedbitTableDefinition, ShardedTableReader, StartFuture, StopFuture, TaskResult, TableInfo, ReadTableLike, WriteComponentRef, WriteTableLike, WriterLike};
-pub use storage::tx_fsm::TxFSM;
+pub use storage::layout::{DbLayout, MountPoint};
+pub use storage::fsck::{fsck, FsckIssue, FsckReport};
+pub use storage::schema::{ColumnKind, ColumnSchema, SchemaChange, SchemaManifest, SchemaMigration, TypeSchema};
+pub use storage::snapshot::{CommitFence, PinnedRead, SnapshotColumn, SnapshotFile, SnapshotManifest};
+pub use storage::table_writer_api::{ColumnTables, FlushFuture, RedbitTableDefinition, ShardedTableReader, StartFuture, StopFuture, TaskResult, TableInfo, ReadTableLike, WriteComponentRef, WriteTableLike, WriterLike};
+pub use storage::tx_fsm::{TxFSM, WriterConfig};
 pub use urlencoding;
 pub use utoipa;
 pub use utoipa::openapi;
@@ -104,6 +117,8 @@ pub trait IndexedPointer: Copy {
     fn next_index(&self) -> Self;
     fn nth_index(&self, n: usize) -> Self;
     fn rollback_or_init(&self, n: u32) -> Self;
+    /// Index of the root key this key belongs to, e.g. the height of a utxo pointer, keys are ordered by it first.
+    fn root_index(&self) -> u128;
 }
 
 pub trait RootPointer: IndexedPointer + Copy {
@@ -189,10 +204,45 @@ macro_rules! impl_sampleable_for_primitive {
 
 impl_sampleable_for_primitive!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
 
+/// Primitive columns can be components of composite indexes.
+macro_rules! impl_index_key_for_primitive {
+    ($($t:ty),*) => {
+        $(
+            impl CacheKey for $t {
+                type CK = $t;
+                type Key = $t;
+                fn cache_key<'a>(v: &Self::SelfType<'a>) -> Self::CK where Self: 'a {
+                    *v
+                }
+            }
+            impl UrlEncoded for $t {
+                fn url_encode(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+impl_index_key_for_primitive!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+/// Values of pk tables.
+impl CacheKey for () {
+    type CK = ();
+    type Key = ();
+    fn cache_key<'a>(_v: &Self::SelfType<'a>) -> Self::CK where Self: 'a {}
+}
+
 pub trait UrlEncoded {
     fn url_encode(&self) -> String;
 }
 
+/// Column values searchable by a prefix of their url encoding.
+pub trait PrefixEncoded: Sized {
+    /// Range of the values whose encoding starts with `prefix`, encodings that are not bit aligned match whole values only.
+    fn prefix_range(prefix: &str) -> Result<(Bound<Self>, Bound<Self>), AppError>;
+}
+
 pub trait DbKey: Key + Copy + 'static
 where
     Self: Borrow<<Self as Value>::SelfType<'static>> {
@@ -227,15 +277,37 @@ where
     for<'a> T: Borrow<<T as Value>::SelfType<'a>>,
 {
 }
+/// Values of index, dictionary and composite index columns, looked up by value.
 pub trait CacheKey: DbVal
 where for<'a> Self: Borrow<<Self as Value>::SelfType<'a>>,
 {
     type CK: Eq + Hash + Clone;
+    /// Encoding of the value where it is a key of `pk_by_index` or `value_to_dict_pk`, partitioned and bloom filtered,
+    /// `Self` except for compressed types which keep their keys raw.
+    type Key: Key + Clone + Send + Sync + 'static + for<'a> Value<SelfType<'a> = <Self as Value>::SelfType<'a>>;
     fn cache_key<'a>(v: &Self::SelfType<'a>) -> Self::CK
     where
         Self: 'a;
 }
 
+/// Values of composite indexes `#[index(columns(a, b))]`, redb orders tuples by their components.
+macro_rules! impl_cachekey_tuple {
+    ($($t:ident $i:tt),+) => {
+        impl<$($t: CacheKey),+> CacheKey for ($($t,)+)
+        where for<'a> ($($t,)+): Borrow<<($($t,)+) as Value>::SelfType<'a>>
+        {
+            type CK = ($($t::CK,)+);
+            type Key = ($($t::Key,)+);
+            fn cache_key<'a>(v: &Self::SelfType<'a>) -> Self::CK where Self: 'a {
+                ($($t::cache_key(&v.$i),)+)
+            }
+        }
+    };
+}
+
+impl_cachekey_tuple!(A 0, B 1);
+impl_cachekey_tuple!(A 0, B 1, C 2);
+
 pub trait BinaryCodec {
     fn from_le_bytes(bytes: &[u8]) -> Self;
     fn as_le_bytes(&self) -> Vec<u8>;
@@ -248,6 +320,10 @@ pub trait BinaryCodec {
 pub trait ByteVecColumnSerde {
     fn decoded_example() -> Vec<u8>;
     fn encoded_example() -> String;
+    /// Leading bits of the values whose encoding starts with `prefix`, `None` if the encoding is not bit aligned.
+    fn prefix(_prefix: &str) -> Option<codec::BitPrefix> {
+        None
+    }
     fn next_value(value: &[u8]) -> Vec<u8> {
         let mut vec = value.to_owned();
         if let Some(last) = vec.last_mut() {
@@ -264,6 +340,9 @@ pub struct StructInfo {
     pub root: bool,
     pub routes_fn: fn() -> OpenApiRouter<RequestState>,
     pub db_defs: fn() -> Vec<DbDef>,
+    pub column_tables: fn() -> Result<Vec<Arc<dyn ColumnTables>>, AppError>,
+    pub fsck_refs: fn(&Arc<Storage>, bool) -> Result<Vec<FsckIssue>, AppError>,
+    pub root_height: fn(&Arc<Storage>) -> Result<Option<u128>, AppError>,
 }
 
 inventory::collect!(StructInfo);
@@ -110,6 +110,67 @@ macro_rules! impl_redb_newtype_vec {
     };
 }
 
+/// `impl_redb_newtype_vec` for `#[column(compress = "zstd")]`, values are zstd frames, keys are the `Raw` bytes.
+#[macro_export]
+macro_rules! impl_redb_newtype_vec_zstd {
+    ($New:ident, $level:expr, $dict:expr) => {
+        impl $New {
+            fn with_zstd_codec<R>(f: impl FnOnce(&mut $crate::compress::ZstdCodec) -> R) -> R {
+                thread_local! {
+                    static CODEC: std::cell::RefCell<$crate::compress::ZstdCodec> =
+                        std::cell::RefCell::new($crate::compress::ZstdCodec::new($level, $dict));
+                }
+                CODEC.with(|codec| f(&mut codec.borrow_mut()))
+            }
+        }
+
+        impl redb::Value for $New {
+            type SelfType<'a> = $New where Self: 'a;
+            type AsBytes<'a> = Vec<u8> where Self: 'a;
+
+            fn fixed_width() -> Option<usize> { None }
+
+            fn from_bytes<'a>(data: &'a [u8]) -> $New
+            where
+                Self: 'a,
+            {
+                Self::with_zstd_codec(|codec| match codec.decode(data) {
+                    Ok(raw) => $New(raw.into_owned()),
+                    Err(e) => panic!("{} value is corrupted, {e}, run fsck to list the broken rows", stringify!($New)),
+                })
+            }
+
+            fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Vec<u8>
+            where
+                Self: 'a,
+                Self: 'b,
+            {
+                Self::with_zstd_codec(|codec| codec.encode(value.0.as_ref()))
+            }
+
+            fn type_name() -> redb::TypeName {
+                redb::TypeName::new(&$crate::compress::zstd_type_name("Vec<u8>", $dict))
+            }
+        }
+
+        impl redb::Key for $New {
+            fn compare(data1: &[u8], data2: &[u8]) -> std::cmp::Ordering {
+                Self::with_zstd_codec(|codec| codec.compare(data1, data2))
+            }
+        }
+
+        impl $crate::compress::RawBytes for $New {
+            fn from_raw(raw: &[u8]) -> Self {
+                $New(raw.to_vec())
+            }
+
+            fn raw(&self) -> &[u8] {
+                self.0.as_ref()
+            }
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! impl_redb_newtype_integer {
     ($New:ident, $Int:ty) => {
@@ -228,6 +289,7 @@ macro_rules! impl_cachekey_array {
     ($T:ty, $N:expr) => {
         impl CacheKey for $T {
             type CK = [u8; $N];
+            type Key = $T;
 
             #[inline]
             fn cache_key<'a>(v: &<$T as redb::Value>::SelfType<'a>) -> Self::CK
@@ -247,6 +309,7 @@ macro_rules! impl_cachekey_integer {
     ($T:ty, $Int:ty) => {
         impl CacheKey for $T {
             type CK = $Int;
+            type Key = $T;
 
             #[inline]
             fn cache_key<'a>(v: &<$T as redb::Value>::SelfType<'a>) -> Self::CK
@@ -266,6 +329,7 @@ macro_rules! impl_cachekey_binary {
     ($T:ty) => {
         impl CacheKey for $T {
             type CK = Vec<u8>;
+            type Key = $T;
 
             #[inline]
             fn cache_key<'a>(v: &<$T as redb::Value>::SelfType<'a>) -> Self::CK
@@ -285,6 +349,7 @@ macro_rules! impl_cachekey_bincode {
     ($T:ty) => {
         impl CacheKey for $T {
             type CK = Vec<u8>;
+            type Key = $T;
 
             #[inline]
             fn cache_key<'a>(v: &<$T as redb::Value>::SelfType<'a>) -> Self::CK
@@ -304,6 +369,26 @@ macro_rules! impl_cachekey_vec {
     ($T:ty) => {
         impl CacheKey for $T {
             type CK = Vec<u8>;
+            type Key = $T;
+
+            #[inline]
+            fn cache_key<'a>(v: &<$T as redb::Value>::SelfType<'a>) -> Self::CK
+            where
+                $T: 'a,
+            {
+                v.0.clone()
+            }
+        }
+    };
+}
+
+/// Generates CacheKey impl for `#[column(compress = "zstd")]` Vec<u8> newtypes, their keys stay raw.
+#[macro_export]
+macro_rules! impl_cachekey_vec_zstd {
+    ($T:ty) => {
+        impl CacheKey for $T {
+            type CK = Vec<u8>;
+            type Key = $crate::compress::Raw<$T>;
 
             #[inline]
             fn cache_key<'a>(v: &<$T as redb::Value>::SelfType<'a>) -> Self::CK
@@ -413,6 +498,7 @@ macro_rules! impl_indexed_pointer {
                 let prev_index = self.0.checked_sub(n).unwrap_or(0);
                 $Struct(prev_index)
             }
+            fn root_index(&self) -> u128 { self.0.into() }
         }
     };
 
@@ -440,6 +526,7 @@ macro_rules! impl_indexed_pointer {
                     $index_field: 0,
                 }
             }
+            fn root_index(&self) -> u128 { self.$parent_field.root_index() }
         }
     };
 }
@@ -0,0 +1,232 @@
+use crate::{AppError, Deserialize, IntoParams, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+/// `fields` selects plain columns and `expand` relationships to load, both comma separated and dotted to reach into
+/// relationships, e.g. `?fields=hash,transactions.hash&expand=header,transactions`. The pk is always loaded, all columns
+/// of an entity are unless `fields` names some of them and all relationships are without `expand`. An expanded
+/// relationship loads none of its own unless expanded further, `fields=` loads just the pk and `expand=` no relationships.
+#[derive(IntoParams, Serialize, Deserialize, Default, Clone, Debug)]
+pub struct ProjectionQuery {
+    #[param(required = false, example = "hash,timestamp")]
+    pub fields: Option<String>,
+    #[param(required = false, example = "header")]
+    pub expand: Option<String>,
+}
+
+impl ProjectionQuery {
+    pub fn sample() -> ProjectionQuery {
+        ProjectionQuery { fields: None, expand: Some(String::new()) }
+    }
+
+    /// Paths of the query per entity level, resolved against the entity schema by its generated `projection`.
+    pub fn paths(&self) -> ProjectionPaths {
+        fn split(paths: &Option<String>) -> impl Iterator<Item = Vec<&str>> {
+            paths.iter().flat_map(|p| p.split(',')).map(str::trim).filter(|p| !p.is_empty()).map(|p| p.split('.').collect())
+        }
+        let mut root = ProjectionPaths::default();
+        if self.fields.is_some() && split(&self.fields).next().is_none() {
+            root.fields.get_or_insert_with(BTreeSet::new);
+        }
+        if self.expand.is_some() {
+            root.expand.get_or_insert_with(BTreeSet::new);
+        }
+        for path in split(&self.expand) {
+            let mut node = &mut root;
+            for relation in path {
+                node.expand.get_or_insert_with(BTreeSet::new).insert(relation.to_string());
+                node = node.children.entry(relation.to_string()).or_default();
+            }
+            node.expand.get_or_insert_with(BTreeSet::new);
+        }
+        for mut path in split(&self.fields) {
+            let column = path.pop().unwrap_or_default();
+            let node = path.into_iter().fold(&mut root, |node, relation| node.children.entry(relation.to_string()).or_default());
+            node.fields.get_or_insert_with(BTreeSet::new).insert(column.to_string());
+        }
+        root
+    }
+}
+
+/// Resolves the paths of a relationship against the schema of its entity.
+pub type ProjectionResolver = fn(&ProjectionPaths) -> Result<Projection, AppError>;
+
+/// Unresolved `ProjectionQuery` of one entity level, names are checked only by `resolve`.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ProjectionPaths {
+    fields: Option<BTreeSet<String>>,
+    expand: Option<BTreeSet<String>>,
+    children: BTreeMap<String, ProjectionPaths>,
+}
+
+impl ProjectionPaths {
+    pub fn resolve(&self, entity: &str, pk: &str, columns: &[&str], relations: &[(&str, ProjectionResolver)]) -> Result<Projection, AppError> {
+        if let Some(column) = self.fields.iter().flatten().find(|c| c.as_str() != pk && !columns.contains(&c.as_str())) {
+            return Err(AppError::BadRequest(format!("{} has no column {}", entity, column)));
+        }
+        let is_relation = |name: &str| relations.iter().any(|(r, _)| *r == name);
+        if let Some(relation) = self.expand.iter().flatten().chain(self.children.keys()).find(|r| !is_relation(r)) {
+            return Err(AppError::BadRequest(format!("{} has no relationship {}", entity, relation)));
+        }
+        let fields = if self.fields.is_none() && self.expand.is_none() {
+            None
+        } else {
+            let mut fields = BTreeSet::from([pk.to_string()]);
+            match &self.fields {
+                Some(selected) => fields.extend(selected.iter().cloned()),
+                None => fields.extend(columns.iter().map(|c| c.to_string())),
+            }
+            match &self.expand {
+                Some(expanded) => fields.extend(expanded.iter().chain(self.children.keys()).cloned()),
+                None => fields.extend(relations.iter().map(|(r, _)| r.to_string())),
+            }
+            Some(fields)
+        };
+        let mut children = BTreeMap::new();
+        for (relation, resolve) in relations {
+            if let Some(paths) = self.children.get(*relation) {
+                children.insert(relation.to_string(), resolve(paths)?);
+            }
+        }
+        Ok(Projection { fields, children })
+    }
+}
+
+static ALL: Projection = Projection { fields: None, children: BTreeMap::new() };
+
+/// Columns and relationships `compose_projected` loads, the others keep their default value and `Projected` leaves
+/// them out of the json. `Projection::default()` loads the whole entity tree like `compose`.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Projection {
+    fields: Option<BTreeSet<String>>,
+    children: BTreeMap<String, Projection>,
+}
+
+impl Projection {
+    pub fn loads(&self, field: &str) -> bool {
+        self.fields.as_ref().is_none_or(|fields| fields.contains(field))
+    }
+
+    /// Projection of a relationship, `None` if it is not loaded.
+    pub fn child(&self, relation: &str) -> Option<&Projection> {
+        self.loads(relation).then(|| self.children.get(relation).unwrap_or(&ALL))
+    }
+
+    pub fn is_whole(&self) -> bool {
+        self.fields.is_none() && self.children.values().all(Projection::is_whole)
+    }
+
+    /// Drops the fields of a serialized entity, or of each of an array of them, that the projection does not load.
+    pub fn retain(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                if let Some(fields) = &self.fields {
+                    object.retain(|field, _| fields.contains(field));
+                }
+                for (relation, projection) in &self.children {
+                    if let Some(child) = object.get_mut(relation) {
+                        projection.retain(child);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.retain(item)),
+            _ => {}
+        }
+    }
+}
+
+/// Entity serialized with only the fields of its projection instead of defaults in place of the others.
+pub struct Projected<T> {
+    entity: T,
+    projection: Arc<Projection>,
+}
+
+impl<T> Projected<T> {
+    pub fn new(entity: T, projection: Arc<Projection>) -> Self {
+        Projected { entity, projection }
+    }
+}
+
+impl<T: Serialize> Serialize for Projected<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.projection.is_whole() {
+            return self.entity.serialize(serializer);
+        }
+        let mut value = serde_json::to_value(&self.entity).map_err(serde::ser::Error::custom)?;
+        self.projection.retain(&mut value);
+        value.serialize(serializer)
+    }
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+
+    fn query(fields: Option<&str>, expand: Option<&str>) -> ProjectionQuery {
+        ProjectionQuery { fields: fields.map(String::from), expand: expand.map(String::from) }
+    }
+
+    fn resolve_tx(paths: &ProjectionPaths) -> Result<Projection, AppError> {
+        paths.resolve("Transaction", "id", &["hash"], &[("utxos", resolve_utxo)])
+    }
+
+    fn resolve_utxo(paths: &ProjectionPaths) -> Result<Projection, AppError> {
+        paths.resolve("Utxo", "id", &["amount", "address"], &[])
+    }
+
+    fn resolve_block(paths: &ProjectionPaths) -> Result<Projection, AppError> {
+        paths.resolve("Block", "height", &[], &[("header", resolve_header), ("transactions", resolve_tx)])
+    }
+
+    fn resolve_header(paths: &ProjectionPaths) -> Result<Projection, AppError> {
+        paths.resolve("Header", "height", &["hash", "timestamp"], &[])
+    }
+
+    #[test]
+    fn expanded_relationships_load_no_relationships_of_their_own() {
+        let block = resolve_block(&query(None, Some("header")).paths()).unwrap();
+        assert!(block.loads("height"));
+        assert!(block.child("header").is_some_and(|header| header.loads("timestamp")));
+        assert_eq!(block.child("transactions"), None);
+
+        let block = resolve_block(&query(None, Some("transactions")).paths()).unwrap();
+        let tx = block.child("transactions").unwrap();
+        assert!(tx.loads("hash") && tx.child("utxos").is_none());
+
+        let block = resolve_block(&query(None, Some("transactions.utxos")).paths()).unwrap();
+        assert!(block.child("transactions").unwrap().child("utxos").is_some());
+
+        let block = resolve_block(&query(None, Some("")).paths()).unwrap();
+        assert!(block.loads("height") && block.child("header").is_none() && block.child("transactions").is_none());
+    }
+
+    #[test]
+    fn fields_select_columns_at_any_level() {
+        let block = resolve_block(&query(Some("header.hash,transactions.utxos.amount"), None).paths()).unwrap();
+        let header = block.child("header").unwrap();
+        assert!(header.loads("height") && header.loads("hash") && !header.loads("timestamp"));
+        let tx = block.child("transactions").unwrap();
+        assert!(tx.loads("hash"), "Columns of a level without fields are all loaded");
+        let utxo = tx.child("utxos").unwrap();
+        assert!(utxo.loads("amount") && !utxo.loads("address"));
+        assert_eq!(Projection::default().child("anything"), Some(&Projection::default()));
+    }
+
+    #[test]
+    fn projected_entities_serialize_only_loaded_fields() {
+        let block = resolve_block(&query(Some("header.hash"), Some("header")).paths()).unwrap();
+        let entity = serde_json::json!({"height": 1, "header": {"height": 1, "hash": "ab", "timestamp": 0}, "transactions": []});
+        let projected = serde_json::to_value(Projected::new(entity.clone(), Arc::new(block))).unwrap();
+        assert_eq!(projected, serde_json::json!({"height": 1, "header": {"height": 1, "hash": "ab"}}));
+        let whole = serde_json::to_value(Projected::new(entity.clone(), Arc::new(Projection::default()))).unwrap();
+        assert_eq!(whole, entity);
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        assert!(matches!(resolve_block(&query(None, Some("headers")).paths()), Err(AppError::BadRequest(_))));
+        assert!(matches!(resolve_block(&query(Some("header.nonce"), None).paths()), Err(AppError::BadRequest(_))));
+        assert!(matches!(resolve_block(&query(Some("hash.x"), None).paths()), Err(AppError::BadRequest(_))));
+    }
+}
@@ -1,4 +1,11 @@
-use crate::{Deserialize, IntoParams, Serialize};
+use crate::{AppError, Deserialize, IntoParams, Key, Projection, Serialize, ToSchema, Value};
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use std::cmp::Ordering;
+use std::ops::Bound;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
 
 #[derive(IntoParams, Serialize, Deserialize, Default)]
 pub struct TakeQuery {
@@ -23,3 +30,298 @@ impl TailQuery {
         TailQuery { tail: 2 }
     }
 }
+
+/// Entities a prefix search returns unless asked for fewer or more, up to `MAX_PREFIX_LIMIT`.
+pub const DEFAULT_PREFIX_LIMIT: usize = 100;
+pub const MAX_PREFIX_LIMIT: usize = 1000;
+
+#[derive(IntoParams, Serialize, Deserialize, Default, Clone)]
+pub struct PrefixQuery {
+    #[param(required = false, example = 10)]
+    pub limit: Option<usize>,
+}
+
+impl PrefixQuery {
+    pub fn sample() -> PrefixQuery {
+        PrefixQuery { limit: Some(10) }
+    }
+}
+
+/// Entities `find` returns unless asked for fewer or more, up to `MAX_FIND_LIMIT`.
+pub const DEFAULT_FIND_LIMIT: usize = 100;
+pub const MAX_FIND_LIMIT: usize = 1000;
+/// Keys counted at most when estimating a range scan, wider ranges are estimated at this many. A range is counted only up
+/// to the best estimate so far, reaching it means the range cannot win the plan anyway.
+pub const RANGE_ESTIMATE_BUDGET: usize = 10_000;
+
+/// Keys of a page in the page order, boxed as either order or a resumed range yields them.
+pub type KeyIter<K> = Box<dyn Iterator<Item = K> + Send>;
+
+#[derive(IntoParams, Serialize, Deserialize, Default, Clone)]
+pub struct FindQuery {
+    #[param(required = false, example = 10)]
+    pub limit: Option<usize>,
+}
+
+impl FindQuery {
+    pub fn sample() -> FindQuery {
+        FindQuery { limit: Some(10) }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum ScanKind {
+    /// Keys of `Eq` / `In` values of an index column
+    Index,
+    /// Keys of `Eq` / `In` values of a dictionary column
+    Dictionary,
+    /// Keys of the values of a range column within `Lt` / `Le` / `Gt` / `Ge` bounds
+    Range,
+    /// All keys in pk order
+    Full,
+}
+
+/// A column whose filter condition could drive `find`, with the number of keys scanning it yields.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ScanCandidate {
+    pub column: String,
+    pub scan: ScanKind,
+    pub estimated_keys: u64,
+}
+
+impl ScanCandidate {
+    pub fn new(column: &str, scan: ScanKind, estimated_keys: u64) -> Self {
+        ScanCandidate { column: column.to_string(), scan, estimated_keys }
+    }
+}
+
+/// Plan `find` executes, the candidate yielding the fewest keys drives the scan and the `residual` conditions are
+/// checked while composing the entities of its keys.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct QueryPlan {
+    pub scan: ScanKind,
+    pub column: Option<String>,
+    pub estimated_keys: Option<u64>,
+    pub candidates: Vec<ScanCandidate>,
+    pub residual: Vec<String>,
+}
+
+impl QueryPlan {
+    /// The first of equally selective candidates wins, without any the whole pk table is scanned.
+    pub fn choose(candidates: Vec<ScanCandidate>, filtered: Vec<&str>) -> Self {
+        let driving = candidates.iter().min_by_key(|c| c.estimated_keys).cloned();
+        let column = driving.as_ref().map(|c| c.column.clone());
+        QueryPlan {
+            scan: driving.as_ref().map_or(ScanKind::Full, |c| c.scan),
+            estimated_keys: driving.map(|c| c.estimated_keys),
+            residual: filtered.into_iter().filter(|f| column.as_deref() != Some(*f)).map(|f| f.to_string()).collect(),
+            column,
+            candidates,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Order of a stream and where it resumes, the `cursor` of the previous page is the hex encoded last key it scanned.
+/// `limit` caps the keys of a page before the optional filter, up to `MAX_FIND_LIMIT`, a filtered page may hold fewer
+/// entities and still not be the last one.
+#[derive(IntoParams, Serialize, Deserialize, Default, Clone, Debug)]
+pub struct PageQuery {
+    #[param(required = false, inline, example = "desc")]
+    pub order: Option<Order>,
+    #[param(required = false)]
+    pub cursor: Option<String>,
+    #[param(required = false, example = 20)]
+    pub limit: Option<usize>,
+}
+
+impl PageQuery {
+    pub fn sample() -> PageQuery {
+        PageQuery { order: Some(Order::Desc), cursor: None, limit: Some(2) }
+    }
+
+    pub fn desc(limit: usize) -> PageQuery {
+        PageQuery { order: Some(Order::Desc), cursor: None, limit: Some(limit) }
+    }
+
+    /// The page following the one `cursor` was returned with.
+    pub fn after(self, cursor: String) -> PageQuery {
+        PageQuery { cursor: Some(cursor), ..self }
+    }
+
+    pub fn order(&self) -> Order {
+        self.order.unwrap_or_default()
+    }
+
+    pub fn encode_cursor<K>(key: &K) -> String
+    where
+        K: Key + 'static + for<'a> Value<SelfType<'a> = K>,
+    {
+        hex::encode(K::as_bytes(key).as_ref())
+    }
+
+    pub fn cursor_key<K>(&self) -> Result<Option<K>, AppError>
+    where
+        K: Key + 'static + for<'a> Value<SelfType<'a> = K>,
+    {
+        let Some(cursor) = self.cursor.as_ref() else { return Ok(None) };
+        let bytes = hex::decode(cursor).map_err(|e| AppError::BadRequest(format!("Invalid cursor {}: {}", cursor, e)))?;
+        if K::fixed_width().is_some_and(|width| width != bytes.len()) {
+            return Err(AppError::BadRequest(format!("Invalid cursor {}: unexpected length", cursor)));
+        }
+        Ok(Some(K::from_bytes(&bytes)))
+    }
+
+    /// Narrows `from..until` to the keys following the cursor in the page order, so the range seeks to the next page.
+    pub fn resume_range<K>(&self, from: K, until: K) -> Result<(Bound<K>, Bound<K>), AppError>
+    where
+        K: Key + Copy + 'static + for<'a> Value<SelfType<'a> = K>,
+    {
+        let cmp = |a: &K, b: &K| K::compare(K::as_bytes(a).as_ref(), K::as_bytes(b).as_ref());
+        Ok(match (self.cursor_key::<K>()?, self.order()) {
+            (Some(cursor), Order::Asc) if cmp(&cursor, &from) != Ordering::Less => (Bound::Excluded(cursor), Bound::Excluded(until)),
+            (Some(cursor), Order::Desc) if cmp(&cursor, &until) == Ordering::Less => (Bound::Included(from), Bound::Excluded(cursor)),
+            _ => (Bound::Included(from), Bound::Excluded(until)),
+        })
+    }
+
+    /// Items of an ascending iterator in the page order.
+    pub fn ordered<T, I>(&self, iter: I) -> KeyIter<T>
+    where
+        I: DoubleEndedIterator<Item = T> + Send + 'static,
+    {
+        match self.order() {
+            Order::Asc => Box::new(iter),
+            Order::Desc => Box::new(iter.rev()),
+        }
+    }
+
+    /// Skips the `keys` up to the cursor, they are expected in the page order, and takes at most `limit` of them.
+    /// The cursor of the next page is returned only if any key remains. Pk ranges seek past the cursor with
+    /// `resume_range` so nothing is skipped here, the keys of an index or dictionary value are read from the start.
+    pub fn paginate<K, I>(&self, keys: I) -> Result<(KeyIter<redb::Result<K>>, Option<String>), AppError>
+    where
+        K: Key + Copy + Send + 'static + for<'a> Value<SelfType<'a> = K>,
+        I: Iterator<Item = redb::Result<K>> + Send + 'static,
+    {
+        let order = self.order();
+        let resumed: KeyIter<redb::Result<K>> = match self.cursor_key::<K>()? {
+            None => Box::new(keys),
+            Some(cursor) => Box::new(keys.skip_while(move |key| match key {
+                Ok(key) => {
+                    let ordering = K::compare(K::as_bytes(key).as_ref(), K::as_bytes(&cursor).as_ref());
+                    if order == Order::Asc { ordering != Ordering::Greater } else { ordering != Ordering::Less }
+                }
+                Err(_) => false,
+            })),
+        };
+        let Some(limit) = self.limit.map(|limit| limit.min(MAX_FIND_LIMIT)) else { return Ok((resumed, None)) };
+        let mut page = Vec::with_capacity(limit);
+        let mut more = false;
+        for key in resumed {
+            if page.len() == limit {
+                more = true;
+                break;
+            }
+            page.push(key?);
+        }
+        let cursor = if more { page.last().map(Self::encode_cursor) } else { None };
+        Ok((Box::new(page.into_iter().map(Ok)), cursor))
+    }
+}
+
+/// Stream of a page, `cursor` resumes after it and is `None` on the last page.
+pub struct Paged<T> {
+    stream: BoxStream<'static, Result<T, AppError>>,
+    pub cursor: Option<String>,
+    pub projection: Option<Arc<Projection>>,
+}
+
+impl<T> Paged<T> {
+    pub fn new(stream: impl Stream<Item = Result<T, AppError>> + Send + 'static, cursor: Option<String>) -> Self {
+        Paged { stream: stream.boxed(), cursor, projection: None }
+    }
+
+    /// Entities of the page are serialized with only the fields `projection` loads, see `Projected`.
+    pub fn projected(mut self, projection: Projection) -> Self {
+        self.projection = Some(Arc::new(projection));
+        self
+    }
+}
+
+impl<T> Stream for Paged<T> {
+    type Item = Result<T, AppError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.stream.poll_next_unpin(cx)
+    }
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+
+    fn pages(query: PageQuery, keys: Vec<u32>) -> (Vec<u32>, Option<String>) {
+        let (page, cursor) = query.paginate(query.ordered(keys.into_iter().map(Ok))).unwrap();
+        (page.map(|k| k.unwrap()).collect(), cursor)
+    }
+
+    #[test]
+    fn pages_resume_after_the_cursor_in_either_order() {
+        let keys: Vec<u32> = (0..5).collect();
+        let (first, cursor) = pages(PageQuery::desc(2), keys.clone());
+        assert_eq!(first, vec![4, 3]);
+        let (second, cursor) = pages(PageQuery::desc(2).after(cursor.unwrap()), keys.clone());
+        assert_eq!(second, vec![2, 1]);
+        let (last, cursor) = pages(PageQuery::desc(2).after(cursor.unwrap()), keys.clone());
+        assert_eq!((last, cursor), (vec![0], None));
+
+        let asc = PageQuery { limit: Some(3), ..Default::default() };
+        let (first, cursor) = pages(asc.clone(), keys.clone());
+        assert_eq!(first, vec![0, 1, 2]);
+        let (rest, cursor) = pages(asc.after(cursor.unwrap()), keys.clone());
+        assert_eq!((rest, cursor), (vec![3, 4], None), "No cursor when the page ends exactly with the keys");
+        assert_eq!(pages(PageQuery::default(), keys.clone()), (keys, None));
+    }
+
+    #[test]
+    fn page_limit_is_capped() {
+        let keys: Vec<u32> = (0..MAX_FIND_LIMIT as u32 + 5).collect();
+        let (page, cursor) = pages(PageQuery { limit: Some(usize::MAX), ..Default::default() }, keys);
+        assert_eq!(page.len(), MAX_FIND_LIMIT);
+        assert_eq!(cursor, Some(PageQuery::encode_cursor(&(MAX_FIND_LIMIT as u32 - 1))));
+    }
+
+    #[test]
+    fn resumed_range_seeks_past_the_cursor() {
+        let cursor = PageQuery::encode_cursor(&7u32);
+        let asc = PageQuery { cursor: Some(cursor.clone()), ..Default::default() };
+        assert_eq!(asc.resume_range(2u32, 10).unwrap(), (Bound::Excluded(7), Bound::Excluded(10)));
+        assert_eq!(PageQuery::desc(1).after(cursor).resume_range(2u32, 10).unwrap(), (Bound::Included(2), Bound::Excluded(7)));
+        assert!(PageQuery::desc(1).after("xyz".to_string()).cursor_key::<u32>().is_err());
+        assert!(PageQuery::desc(1).after("0102".to_string()).cursor_key::<u32>().is_err(), "A cursor of another key type is rejected");
+    }
+
+    #[test]
+    fn plan_is_driven_by_the_most_selective_candidate() {
+        let candidates = vec![
+            ScanCandidate::new("timestamp", ScanKind::Range, 40),
+            ScanCandidate::new("hash", ScanKind::Index, 1),
+            ScanCandidate::new("prev_hash", ScanKind::Index, 1),
+        ];
+        let plan = QueryPlan::choose(candidates, vec!["hash", "timestamp", "prev_hash", "$not"]);
+        assert_eq!((plan.scan, plan.column.as_deref(), plan.estimated_keys), (ScanKind::Index, Some("hash"), Some(1)));
+        assert_eq!(plan.residual, vec!["timestamp", "prev_hash", "$not"]);
+
+        let full = QueryPlan::choose(vec![], vec!["weight"]);
+        assert_eq!((full.scan, full.column, full.estimated_keys), (ScanKind::Full, None, None));
+        assert_eq!(full.residual, vec!["weight"]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{info, AppError, Deserialize, IntoResponse, Response, Serialize, Storage, StructInfo, ToSchema};
+use crate::{info, AppError, Deserialize, IntoResponse, Paged, PinnedRead, Projected, Response, Serialize, StatusCode, Storage, StructInfo, ToSchema};
 use axum::body::Bytes;
 use axum::extract::{FromRequest, Request};
 use axum::routing::MethodRouter;
@@ -6,9 +6,11 @@ use axum::Router;
 use serde::de::DeserializeOwned;
 use serde_json::Value;
 use std::net::SocketAddr;
+use std::ops::Bound;
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use tokio::sync::watch;
+use futures::{Stream, TryStreamExt};
+use tokio::sync::{mpsc, oneshot, watch, Semaphore};
 use tower_http::cors::CorsLayer;
 use utoipa::openapi::extensions::Extensions;
 use utoipa::openapi::schema::SchemaType;
@@ -32,6 +34,39 @@ where
     }
 }
 
+/// Root height the data of a pinned read is consistent with, see `Storage::pin_read`.
+pub const HEIGHT_HEADER: &str = "x-redbit-height";
+
+impl<T: IntoResponse> IntoResponse for PinnedRead<T> {
+    fn into_response(self) -> Response {
+        let mut response = self.value.into_response();
+        if let Some(height) = self.height {
+            response.headers_mut().insert(HEIGHT_HEADER, http::HeaderValue::from(height as u64));
+        }
+        response
+    }
+}
+
+/// Cursor of the next page of a paged stream, absent on the last page, see `PageQuery`.
+pub const CURSOR_HEADER: &str = "x-redbit-cursor";
+
+impl<T: Serialize + Send + Sync + 'static> Paged<T> {
+    /// Streams the page as ndjson with the cursor of the next page in `CURSOR_HEADER`.
+    pub fn into_ndjson(self) -> axum_streams::StreamBodyAs<'static> {
+        let cursor = self.cursor.clone();
+        let body =
+            match self.projection.clone() {
+                Some(projection) => axum_streams::StreamBodyAs::json_nl_with_errors(self.map_ok(move |entity| Projected::new(entity, Arc::clone(&projection)))),
+                None => axum_streams::StreamBodyAs::json_nl_with_errors(self),
+            };
+        let body = body.header("Content-Type", http::HeaderValue::from_static("application/x-ndjson"));
+        match cursor.and_then(|c| http::HeaderValue::from_str(&c).ok()) {
+            Some(cursor) => body.header(CURSOR_HEADER, cursor),
+            None => body,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct MaybeJson<T>(pub Option<T>);
 
@@ -71,19 +106,189 @@ impl IntoResponse for AppError {
             AppError::JsonRejection(rej) => rej.body_text(),
             other                        => other.to_string(),
         };
-        (status, AppJson(ErrorResponse { message, code: status.as_u16() })).into_response()
+        let mut response = (status, AppJson(ErrorResponse { message, code: status.as_u16() })).into_response();
+        if status == StatusCode::SERVICE_UNAVAILABLE {
+            response.headers_mut().insert(http::header::RETRY_AFTER, http::HeaderValue::from_static("1"));
+        }
+        response
+    }
+}
+
+/// Default number of read requests served at once, the others wait for a permit without occupying a thread.
+pub const DEFAULT_READ_CONCURRENCY: usize = 16;
+/// Default number of streamed responses open at once, each keeps a blocking thread until its client has read it all.
+pub const DEFAULT_STREAM_CONCURRENCY: usize = 64;
+/// Items of a streamed response produced ahead of the client.
+const READ_STREAM_BUFFER: usize = 64;
+
+/// Runs the blocking reads of http handlers on tokio's blocking pool so that heavy requests cannot starve
+/// the runtime workers and the indexer tasks scheduled on them, at most `max_concurrent` reads at once.
+/// Streams are limited to `max_streams` on their own as a slow client keeps its stream open.
+#[derive(Clone)]
+pub struct ReadExecutor {
+    permits: Arc<Semaphore>,
+    streams: Arc<Semaphore>,
+}
+
+impl ReadExecutor {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self::with_streams(max_concurrent, DEFAULT_STREAM_CONCURRENCY)
+    }
+
+    pub fn with_streams(max_concurrent: usize, max_streams: usize) -> Self {
+        Self { permits: Arc::new(Semaphore::new(max_concurrent.max(1))), streams: Arc::new(Semaphore::new(max_streams.max(1))) }
+    }
+
+    async fn permit(&self) -> Result<tokio::sync::OwnedSemaphorePermit, AppError> {
+        Arc::clone(&self.permits).acquire_owned().await.map_err(|e| AppError::Internal(e.into()))
+    }
+
+    async fn stream_permit(&self) -> Result<tokio::sync::OwnedSemaphorePermit, AppError> {
+        Arc::clone(&self.streams).acquire_owned().await.map_err(|e| AppError::Internal(e.into()))
+    }
+
+    /// A request dropped while waiting for a permit, e.g. by a disconnected client, never starts its read.
+    pub async fn run<T, F>(&self, f: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, AppError> + Send + 'static,
+    {
+        let permit = self.permit().await?;
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            f()
+        }).await.map_err(|e| AppError::Internal(e.into()))?
+    }
+
+    /// Polls the stream on the blocking pool and hands its items over a bounded channel, the stream permit is held
+    /// until the stream ends and the producer stops at the next item once the client disconnects. The read permit
+    /// is released while the channel is full, so a client reading slowly does not hold back other reads.
+    pub async fn stream<T, S, F>(&self, f: F) -> Result<PinnedRead<impl Stream<Item = Result<T, AppError>> + Send + 'static>, AppError>
+    where
+        T: Send + 'static,
+        S: Stream<Item = Result<T, AppError>> + Send + 'static,
+        F: FnOnce() -> Result<PinnedRead<S>, AppError> + Send + 'static,
+    {
+        let stream_permit = self.stream_permit().await?;
+        let permit = self.permit().await?;
+        let permits = Arc::clone(&self.permits);
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let (items_tx, items_rx) = mpsc::channel(READ_STREAM_BUFFER);
+        tokio::task::spawn_blocking(move || {
+            let _stream_permit = stream_permit;
+            let mut permit = Some(permit);
+            match f() {
+                Err(err) => {
+                    let _ = ready_tx.send(Err(err));
+                }
+                Ok(PinnedRead { value: stream, height }) => {
+                    if ready_tx.send(Ok(height)).is_ok() {
+                        for item in futures::executor::block_on_stream(Box::pin(stream)) {
+                            match items_tx.try_send(item) {
+                                Ok(()) => {}
+                                Err(mpsc::error::TrySendError::Closed(_)) => break,
+                                Err(mpsc::error::TrySendError::Full(item)) => {
+                                    drop(permit.take());
+                                    if items_tx.blocking_send(item).is_err() {
+                                        break;
+                                    }
+                                    match futures::executor::block_on(Arc::clone(&permits).acquire_owned()) {
+                                        Ok(reacquired) => permit = Some(reacquired),
+                                        Err(_) => break,
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        let height = ready_rx.await.map_err(|e| AppError::Internal(e.into()))??;
+        let value = futures::stream::unfold(items_rx, |mut rx| async move { rx.recv().await.map(|item| (item, rx)) });
+        Ok(PinnedRead { value, height })
+    }
+
+    /// Streams a page like [`ReadExecutor::stream`], its cursor and projection are known once the page is opened and are
+    /// handed back with it.
+    pub async fn paged<T, F>(&self, f: F) -> Result<PinnedRead<Paged<T>>, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<PinnedRead<Paged<T>>, AppError> + Send + 'static,
+    {
+        let (page_tx, page_rx) = oneshot::channel();
+        let pinned = self.stream(move || {
+            f().map(|pinned| pinned.map(|paged| {
+                let _ = page_tx.send((paged.cursor.clone(), paged.projection.clone()));
+                paged
+            }))
+        }).await?;
+        let (cursor, projection) = page_rx.await.map_err(|e| AppError::Internal(e.into()))?;
+        Ok(pinned.map(|stream| {
+            let mut paged = Paged::new(stream, cursor);
+            paged.projection = projection;
+            paged
+        }))
     }
 }
 
 #[derive(Clone)]
 pub struct RequestState {
     pub storage: Arc<Storage>,
+    pub reads: ReadExecutor,
+}
+
+impl RequestState {
+    pub fn new(storage: Arc<Storage>) -> Self {
+        Self { storage, reads: ReadExecutor::new(DEFAULT_READ_CONCURRENCY) }
+    }
+
+    pub fn with_read_concurrency(self, max_concurrent: usize) -> Self {
+        Self { reads: ReadExecutor::new(max_concurrent), ..self }
+    }
+
+    pub fn with_read_limits(self, max_concurrent: usize, max_streams: usize) -> Self {
+        Self { reads: ReadExecutor::with_streams(max_concurrent, max_streams), ..self }
+    }
+
+    /// Runs a blocking read against the storage off the runtime workers, see [`ReadExecutor::run`].
+    pub async fn read<T, F>(&self, f: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(RequestState) -> Result<T, AppError> + Send + 'static,
+    {
+        let state = self.clone();
+        self.reads.run(move || f(state)).await
+    }
+
+    /// Streams a blocking read against the storage off the runtime workers, see [`ReadExecutor::stream`].
+    pub async fn read_stream<T, S, F>(&self, f: F) -> Result<PinnedRead<impl Stream<Item = Result<T, AppError>> + Send + 'static>, AppError>
+    where
+        T: Send + 'static,
+        S: Stream<Item = Result<T, AppError>> + Send + 'static,
+        F: FnOnce(RequestState) -> Result<PinnedRead<S>, AppError> + Send + 'static,
+    {
+        let state = self.clone();
+        self.reads.stream(move || f(state)).await
+    }
+
+    /// Streams a page of a blocking read off the runtime workers, see [`ReadExecutor::paged`].
+    pub async fn read_paged<T, F>(&self, f: F) -> Result<PinnedRead<Paged<T>>, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(RequestState) -> Result<PinnedRead<Paged<T>>, AppError> + Send + 'static,
+    {
+        let state = self.clone();
+        self.reads.paged(move || f(state)).await
+    }
 }
 
 #[derive(OpenApi)]
 #[openapi(info(license(name = "MIT")))]
 pub struct ApiDoc;
 
+/// Condition on a single column, `And`, `Or` and `Not` combine conditions of the same column,
+/// e.g. `{"Or": [{"Lt": 10}, {"Gt": 100}]}`. Conditions across columns are combined by the `$or` and `$not`
+/// groups of the generated `FilterQuery`.
 #[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
 pub enum FilterOp<T> {
     Eq(T),
@@ -93,6 +298,12 @@ pub enum FilterOp<T> {
     Gt(T),
     Ge(T),
     In(Vec<T>),
+    #[schema(no_recursion)]
+    And(Vec<FilterOp<T>>),
+    #[schema(no_recursion)]
+    Or(Vec<FilterOp<T>>),
+    #[schema(no_recursion)]
+    Not(Box<FilterOp<T>>),
 }
 
 impl<T: PartialOrd + PartialEq> FilterOp<T> {
@@ -104,7 +315,56 @@ impl<T: PartialOrd + PartialEq> FilterOp<T> {
             FilterOp::Le(expected) => value <= expected,
             FilterOp::Gt(expected) => value > expected,
             FilterOp::Ge(expected) => value >= expected,
-            FilterOp::In(options) => options.contains(value),        }
+            FilterOp::In(options) => options.contains(value),
+            FilterOp::And(ops) => ops.iter().all(|op| op.matches(value)),
+            FilterOp::Or(ops) => ops.iter().any(|op| op.matches(value)),
+            FilterOp::Not(op) => !op.matches(value),
+        }
+    }
+
+    /// Distinct values of an `Eq` or `In` condition, looking each up in an index yields exactly the matching keys.
+    /// `And` is narrowed by its first such condition, the rest is checked on the composed entity.
+    pub fn point_values(&self) -> Option<Vec<&T>> {
+        match self {
+            FilterOp::Eq(expected) => Some(vec![expected]),
+            FilterOp::In(options) => {
+                let mut values: Vec<&T> = Vec::with_capacity(options.len());
+                for option in options {
+                    if !values.contains(&option) {
+                        values.push(option);
+                    }
+                }
+                Some(values)
+            }
+            FilterOp::And(ops) => ops.iter().find_map(|op| op.point_values()),
+            _ => None,
+        }
+    }
+
+    /// Bounds of a `Lt`, `Le`, `Gt` or `Ge` condition for ranging over an index, `And` intersects the bounds of all its conditions.
+    pub fn bounds(&self) -> Option<(Bound<&T>, Bound<&T>)> {
+        match self {
+            FilterOp::Lt(v) => Some((Bound::Unbounded, Bound::Excluded(v))),
+            FilterOp::Le(v) => Some((Bound::Unbounded, Bound::Included(v))),
+            FilterOp::Gt(v) => Some((Bound::Excluded(v), Bound::Unbounded)),
+            FilterOp::Ge(v) => Some((Bound::Included(v), Bound::Unbounded)),
+            FilterOp::And(ops) => ops.iter().filter_map(|op| op.bounds()).reduce(|(from, until), (other_from, other_until)| {
+                (tighter(from, other_from, |a, b| a > b), tighter(until, other_until, |a, b| a < b))
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The narrower of two bounds on the same side, `narrows(a, b)` tells whether value `a` is tighter than `b`.
+fn tighter<'a, T: PartialOrd>(a: Bound<&'a T>, b: Bound<&'a T>, narrows: impl Fn(&T, &T) -> bool) -> Bound<&'a T> {
+    match (a, b) {
+        (Bound::Unbounded, other) | (other, Bound::Unbounded) => other,
+        (Bound::Included(x), Bound::Included(y)) => Bound::Included(if narrows(y, x) { y } else { x }),
+        (Bound::Excluded(x), Bound::Excluded(y)) => Bound::Excluded(if narrows(y, x) { y } else { x }),
+        (Bound::Included(x), Bound::Excluded(y)) | (Bound::Excluded(y), Bound::Included(x)) => {
+            if narrows(x, y) { Bound::Included(x) } else { Bound::Excluded(y) }
+        }
     }
 }
 
@@ -175,3 +435,119 @@ pub fn schema<I: IntoIterator<Item = V>, V: Into<Value>>(schema_type: SchemaType
         ObjectBuilder::new().schema_type(schema_type).examples(examples).extensions(extensions).build()
     ).into()
 }
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+    use crate::Projection;
+    use futures::StreamExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn filter_op_combines_conditions_of_one_column() {
+        let outside: FilterOp<u64> = serde_json::from_str(r#"{"Or": [{"Lt": 10}, {"Not": {"Le": 100}}]}"#).unwrap();
+        assert!(outside.matches(&5));
+        assert!(!outside.matches(&10));
+        assert!(!outside.matches(&100));
+        assert!(outside.matches(&101));
+
+        let within = FilterOp::And(vec![FilterOp::Ge(10), FilterOp::Not(Box::new(FilterOp::In(vec![20, 30])))]);
+        assert!(within.matches(&10));
+        assert!(!within.matches(&20));
+        assert!(!within.matches(&9));
+        assert!(!FilterOp::<u64>::Or(vec![]).matches(&1), "an empty `Or` matches nothing");
+    }
+
+    #[test]
+    fn filter_op_yields_what_an_index_scan_can_drive() {
+        assert_eq!(FilterOp::In(vec![1, 2, 1]).point_values(), Some(vec![&1, &2]));
+        assert_eq!(FilterOp::And(vec![FilterOp::Ne(3), FilterOp::Eq(4)]).point_values(), Some(vec![&4]));
+        assert_eq!(FilterOp::Ge(5).bounds(), Some((Bound::Included(&5), Bound::Unbounded)));
+        assert_eq!(FilterOp::And(vec![FilterOp::Eq(1), FilterOp::Lt(6)]).bounds(), Some((Bound::Unbounded, Bound::Excluded(&6))));
+        assert_eq!(
+            FilterOp::And(vec![FilterOp::Ge(2), FilterOp::Lt(6), FilterOp::Gt(2), FilterOp::Le(9)]).bounds(),
+            Some((Bound::Excluded(&2), Bound::Excluded(&6)))
+        );
+        assert_eq!(FilterOp::And(vec![FilterOp::Le(4), FilterOp::Lt(5)]).bounds(), Some((Bound::Unbounded, Bound::Included(&4))));
+        assert!(FilterOp::Ne(1).point_values().is_none() && FilterOp::Or(vec![FilterOp::Lt(1)]).bounds().is_none());
+    }
+
+    #[tokio::test]
+    async fn reads_run_off_the_runtime_within_the_concurrency_limit() {
+        let executor = ReadExecutor::new(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let reads = (0..6).map(|i| {
+            let (running, peak) = (Arc::clone(&running), Arc::clone(&peak));
+            executor.run(move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(i)
+            })
+        });
+        let results: Vec<usize> = futures::future::try_join_all(reads).await.unwrap();
+        assert_eq!(results, (0..6).collect::<Vec<_>>());
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn stream_producer_stops_once_the_client_is_gone() {
+        let executor = ReadExecutor::new(1);
+        let produced = Arc::new(AtomicUsize::new(0));
+        let stream = {
+            let produced = Arc::clone(&produced);
+            executor.stream(move || {
+                let value = futures::stream::iter((0..usize::MAX).map(move |i| {
+                    produced.fetch_add(1, Ordering::SeqCst);
+                    Ok::<_, AppError>(i)
+                }));
+                Ok(PinnedRead { value, height: Some(7) })
+            }).await.unwrap()
+        };
+        assert_eq!(stream.height, Some(7));
+        let first: Vec<usize> = stream.value.take(3).map(|r| r.unwrap()).collect().await;
+        assert_eq!(first, vec![0, 1, 2]);
+
+        // the permit is released once the producer notices the dropped receiver
+        let next = tokio::time::timeout(Duration::from_secs(5), executor.run(|| Ok(()))).await;
+        assert!(next.is_ok(), "producer kept the permit after the client disconnected");
+        assert!(produced.load(Ordering::SeqCst) <= 3 + READ_STREAM_BUFFER + 1);
+
+        let err = executor.stream(|| Err::<PinnedRead<futures::stream::Empty<Result<(), AppError>>>, _>(AppError::NotFound("gone".into()))).await;
+        assert!(matches!(err, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn slow_stream_client_does_not_hold_the_read_permit() {
+        let executor = ReadExecutor::with_streams(1, 2);
+        let stream = executor.stream(|| {
+            let value = futures::stream::iter((0..usize::MAX).map(Ok::<_, AppError>));
+            Ok(PinnedRead { value, height: None })
+        }).await.unwrap();
+
+        // nobody reads the stream, its producer waits on the full channel without the only read permit
+        let read = tokio::time::timeout(Duration::from_secs(5), executor.run(|| Ok(1))).await;
+        assert_eq!(read.expect("read waited for the stream client").unwrap(), 1);
+
+        let second = executor.stream(|| Ok(PinnedRead { value: futures::stream::iter((0..usize::MAX).map(Ok::<_, AppError>)), height: None })).await.unwrap();
+        let third = tokio::time::timeout(Duration::from_millis(100), executor.stream(|| Ok(PinnedRead { value: futures::stream::empty::<Result<usize, AppError>>(), height: None }))).await;
+        assert!(third.is_err(), "streams are limited on their own");
+        drop((stream, second));
+    }
+
+    #[tokio::test]
+    async fn paged_stream_keeps_its_cursor() {
+        let executor = ReadExecutor::new(1);
+        let paged = executor.paged(|| {
+            let value = Paged::new(futures::stream::iter(vec![Ok::<_, AppError>(3), Ok(2)]), Some("02".to_string())).projected(Projection::default());
+            Ok(PinnedRead { value, height: None })
+        }).await.unwrap();
+        assert_eq!(paged.value.cursor.as_deref(), Some("02"));
+        assert!(paged.value.projection.is_some(), "projection is lost on the way from the blocking pool");
+        let items: Vec<usize> = paged.value.map(|r| r.unwrap()).collect().await;
+        assert_eq!(items, vec![3, 2]);
+    }
+}
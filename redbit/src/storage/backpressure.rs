@@ -0,0 +1,172 @@
+use crate::AppError;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Default byte budget of one writer's queue, a few writers falling behind during catch-up cannot exhaust RAM.
+pub const DEFAULT_WRITER_QUEUE_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Peaks of one writer's (shard's) queue since the previous commit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct QueueStats {
+    /// Most commands waiting for the writer at once.
+    pub peak_depth: usize,
+    /// Most encoded bytes of `(K, V)` pairs waiting for the writer at once.
+    pub peak_bytes: usize,
+    /// Commands whose producer had to wait for the budget.
+    pub blocked_sends: usize,
+    /// Time producers spent blocked on the budget.
+    pub blocked_ms: u128,
+}
+
+#[derive(Default)]
+struct QueueState {
+    bytes: usize,
+    depth: usize,
+    peak_bytes: usize,
+    peak_depth: usize,
+    waiting: usize,
+    blocked_sends: usize,
+    blocked: Duration,
+    closed: bool,
+}
+
+/// Byte budget shared by the producers of one writer and the writer thread. Producers acquire the encoded
+/// size of the pairs they send and block while the writer is behind by more than `max_bytes`, the writer
+/// releases it once the command is handled. A command bigger than the whole budget still passes into an empty queue.
+pub struct QueueBudget {
+    max_bytes: usize,
+    state: Mutex<QueueState>,
+    released: Condvar,
+}
+
+impl QueueBudget {
+    pub fn new(max_bytes: usize) -> Self {
+        Self { max_bytes, state: Mutex::new(QueueState::default()), released: Condvar::new() }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn acquire(&self, bytes: usize) -> Result<(), AppError> {
+        let mut st = self.lock();
+        if st.bytes > 0 && st.bytes + bytes > self.max_bytes && !st.closed {
+            let start = Instant::now();
+            st.waiting += 1;
+            while st.bytes > 0 && st.bytes + bytes > self.max_bytes && !st.closed {
+                st = self.released.wait(st).unwrap_or_else(|poisoned| poisoned.into_inner());
+            }
+            st.waiting -= 1;
+            st.blocked_sends += 1;
+            st.blocked += start.elapsed();
+        }
+        if st.closed {
+            return Err(AppError::Custom("writer thread disconnected".into()));
+        }
+        st.bytes += bytes;
+        st.depth += 1;
+        st.peak_bytes = st.peak_bytes.max(st.bytes);
+        st.peak_depth = st.peak_depth.max(st.depth);
+        Ok(())
+    }
+
+    pub fn release(&self, bytes: usize) {
+        let mut st = self.lock();
+        st.bytes = st.bytes.saturating_sub(bytes);
+        st.depth = st.depth.saturating_sub(1);
+        drop(st);
+        self.released.notify_all();
+    }
+
+    /// Wakes up blocked producers for good, called when the writer thread terminates.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.released.notify_all();
+    }
+
+    /// Closes the budget when dropped, the writer thread holds it so that producers are woken up even if it panics.
+    pub fn close_on_drop(self: &Arc<Self>) -> CloseOnDrop {
+        CloseOnDrop(Arc::clone(self))
+    }
+
+    /// Producers blocked on the budget right now.
+    pub fn waiting(&self) -> usize {
+        self.lock().waiting
+    }
+
+    /// Returns the peaks since the previous call and starts measuring from the current state.
+    pub fn take_stats(&self) -> QueueStats {
+        let mut st = self.lock();
+        let stats = QueueStats { peak_depth: st.peak_depth, peak_bytes: st.peak_bytes, blocked_sends: st.blocked_sends, blocked_ms: st.blocked.as_millis() };
+        st.peak_depth = st.depth;
+        st.peak_bytes = st.bytes;
+        st.blocked_sends = 0;
+        st.blocked = Duration::ZERO;
+        stats
+    }
+}
+
+pub struct CloseOnDrop(Arc<QueueBudget>);
+
+impl Drop for CloseOnDrop {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn producer_blocks_until_writer_releases() {
+        let budget = Arc::new(QueueBudget::new(100));
+        budget.acquire(80).unwrap();
+        let producer = {
+            let budget = Arc::clone(&budget);
+            thread::spawn(move || budget.acquire(50))
+        };
+        while budget.waiting() == 0 {
+            thread::yield_now();
+        }
+        assert!(!producer.is_finished(), "producer should wait for the writer");
+        budget.release(80);
+        producer.join().unwrap().unwrap();
+
+        let stats = budget.take_stats();
+        assert_eq!((stats.peak_depth, stats.peak_bytes, stats.blocked_sends), (1, 80, 1));
+        assert_eq!(budget.waiting(), 0);
+        assert_eq!(budget.take_stats(), QueueStats { peak_depth: 1, peak_bytes: 50, blocked_sends: 0, blocked_ms: 0 });
+    }
+
+    #[test]
+    fn oversized_command_passes_into_empty_queue_and_close_unblocks() {
+        let budget = Arc::new(QueueBudget::new(10));
+        budget.acquire(1000).unwrap();
+        let producer = {
+            let budget = Arc::clone(&budget);
+            thread::spawn(move || budget.acquire(1))
+        };
+        budget.close();
+        assert!(producer.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn dropped_writer_thread_closes_the_budget() {
+        let budget = Arc::new(QueueBudget::new(10));
+        budget.acquire(10).unwrap();
+        let writer = {
+            let closer = budget.close_on_drop();
+            thread::spawn(move || {
+                let _closer = closer;
+                panic!("writer failed");
+            })
+        };
+        assert!(writer.join().is_err());
+        assert!(budget.acquire(1).is_err(), "producers must not block on a dead writer");
+    }
+}
@@ -0,0 +1,371 @@
+use crate::storage::commit::CommitMarker;
+use crate::storage::init::{shard_suffix, DbSetOwned, ShardDb};
+use crate::storage::layout::DbLayout;
+use crate::storage::table_writer_api::ColumnTables;
+use crate::{info, AppError};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::thread;
+use xxhash_rust::xxh3::xxh3_128_with_seed;
+
+const MAGIC: &[u8; 8] = b"RBBLOOM1";
+const SEED: u64 = 0x5EED_B100_F11E_0001;
+/// False positive rate of the first layer, every next layer halves it so that all layers together stay under 1%.
+const FIRST_LAYER_FP_RATE: f64 = 0.005;
+const MAX_HASHES: u32 = 16;
+
+struct BloomLayer {
+    words: Vec<AtomicU64>,
+    hashes: u32,
+    capacity: u64,
+    items: AtomicU64,
+}
+
+impl BloomLayer {
+    fn new(capacity: u64, fp_rate: f64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(capacity as f64) * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let words = bits.div_ceil(64);
+        let hashes = ((words * 64) as f64 / capacity as f64 * ln2).round().clamp(1.0, MAX_HASHES as f64) as u32;
+        BloomLayer {
+            words: (0..words).map(|_| AtomicU64::new(0)).collect(),
+            hashes,
+            capacity,
+            items: AtomicU64::new(0),
+        }
+    }
+
+    #[inline]
+    fn bit_positions(&self, (h1, h2): (u64, u64)) -> impl Iterator<Item = (usize, u64)> + '_ {
+        let bits = self.words.len() as u64 * 64;
+        (0..self.hashes as u64).map(move |i| {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % bits;
+            ((bit / 64) as usize, 1u64 << (bit % 64))
+        })
+    }
+
+    fn insert(&self, h: (u64, u64)) {
+        for (word, mask) in self.bit_positions(h) {
+            self.words[word].fetch_or(mask, Ordering::Relaxed);
+        }
+        self.items.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn contains(&self, h: (u64, u64)) -> bool {
+        self.bit_positions(h).all(|(word, mask)| self.words[word].load(Ordering::Relaxed) & mask != 0)
+    }
+
+    fn is_full(&self) -> bool {
+        self.items.load(Ordering::Relaxed) >= self.capacity
+    }
+}
+
+/// Scalable bloom filter over the values of one index shard. It never answers "absent" for an inserted value,
+/// when a layer fills up a twice as big one with half the false positive rate is stacked on top of it.
+/// Bits are atomic so that the shard writer inserts while readers query without locking each other.
+pub struct BloomFilter {
+    layers: RwLock<Vec<BloomLayer>>,
+}
+
+impl BloomFilter {
+    pub const MIN_CAPACITY: u64 = 1 << 16;
+
+    pub fn with_capacity(capacity: u64) -> Self {
+        BloomFilter { layers: RwLock::new(vec![BloomLayer::new(capacity.max(Self::MIN_CAPACITY), FIRST_LAYER_FP_RATE)]) }
+    }
+
+    #[inline]
+    fn hash(bytes: &[u8]) -> (u64, u64) {
+        let h = xxh3_128_with_seed(bytes, SEED);
+        (h as u64, ((h >> 64) as u64) | 1)
+    }
+
+    pub fn insert(&self, bytes: &[u8]) {
+        let h = Self::hash(bytes);
+        {
+            let layers = self.layers.read().expect("bloom filter lock poisoned");
+            if layers.iter().any(|l| l.contains(h)) {
+                return;
+            }
+            let last = layers.last().expect("bloom filter has a layer");
+            if !last.is_full() {
+                last.insert(h);
+                return;
+            }
+        }
+        let mut layers = self.layers.write().expect("bloom filter lock poisoned");
+        let last = layers.last().expect("bloom filter has a layer");
+        if last.is_full() {
+            let fp_rate = FIRST_LAYER_FP_RATE / 2f64.powi(layers.len() as i32);
+            let next = BloomLayer::new(last.capacity.saturating_mul(2), fp_rate);
+            layers.push(next);
+        }
+        layers.last().expect("bloom filter has a layer").insert(h);
+    }
+
+    /// False means the value was never inserted, true means it probably was.
+    pub fn may_contain(&self, bytes: &[u8]) -> bool {
+        let h = Self::hash(bytes);
+        self.layers.read().expect("bloom filter lock poisoned").iter().any(|l| l.contains(h))
+    }
+
+    /// Number of distinct values inserted, give or take the false positives.
+    pub fn items(&self) -> u64 {
+        self.layers.read().expect("bloom filter lock poisoned").iter().map(|l| l.items.load(Ordering::Relaxed)).sum()
+    }
+
+    pub fn layers(&self) -> usize {
+        self.layers.read().expect("bloom filter lock poisoned").len()
+    }
+
+    fn save(&self, path: &Path, stamp: Option<u64>) -> Result<(), AppError> {
+        let tmp = path.with_extension("bloom.tmp");
+        {
+            let mut w = BufWriter::new(File::create(&tmp)?);
+            w.write_all(MAGIC)?;
+            w.write_all(&[stamp.is_some() as u8])?;
+            w.write_all(&stamp.unwrap_or(0).to_le_bytes())?;
+            let layers = self.layers.read().expect("bloom filter lock poisoned");
+            w.write_all(&(layers.len() as u32).to_le_bytes())?;
+            for l in layers.iter() {
+                w.write_all(&l.capacity.to_le_bytes())?;
+                w.write_all(&l.items.load(Ordering::Relaxed).to_le_bytes())?;
+                w.write_all(&l.hashes.to_le_bytes())?;
+                w.write_all(&(l.words.len() as u64).to_le_bytes())?;
+                for word in &l.words {
+                    w.write_all(&word.load(Ordering::Relaxed).to_le_bytes())?;
+                }
+            }
+            w.flush()?;
+        }
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// None if there is no file or it was saved at another commit than `stamp`, the filter must be rebuilt then.
+    fn load(path: &Path, stamp: Option<u64>) -> Result<Option<Self>, AppError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut r = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(AppError::Custom(format!("{:?} is not a bloom filter file", path)));
+        }
+        let saved_stamp = match read_u8(&mut r)? {
+            0 => { read_u64(&mut r)?; None }
+            _ => Some(read_u64(&mut r)?),
+        };
+        if saved_stamp != stamp {
+            return Ok(None);
+        }
+        let layer_count = read_u32(&mut r)?;
+        let mut layers = Vec::with_capacity(layer_count as usize);
+        for _ in 0..layer_count {
+            let capacity = read_u64(&mut r)?;
+            let items = read_u64(&mut r)?;
+            let hashes = read_u32(&mut r)?;
+            let word_count = read_u64(&mut r)?;
+            let mut words = Vec::with_capacity(word_count as usize);
+            for _ in 0..word_count {
+                words.push(AtomicU64::new(read_u64(&mut r)?));
+            }
+            layers.push(BloomLayer { words, hashes, capacity, items: AtomicU64::new(items) });
+        }
+        if layers.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(BloomFilter { layers: RwLock::new(layers) }))
+    }
+}
+
+fn read_u8(r: &mut impl Read) -> std::io::Result<u8> {
+    let mut b = [0u8; 1];
+    r.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+fn read_u32(r: &mut impl Read) -> std::io::Result<u32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn read_u64(r: &mut impl Read) -> std::io::Result<u64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+/// Bloom filters of the index columns declared with `bloom`, one per shard. They are loaded or rebuilt from the
+/// index when the storage is opened and saved as `name[-idx].bloom` next to the shard files by `StorageOwner::close`.
+/// In-memory storage has no layout, its filters are always built from the index and never saved.
+pub struct BloomFilters {
+    layout: Option<DbLayout>,
+    read_only: bool,
+    loaded: RwLock<HashMap<String, Vec<Arc<BloomFilter>>>>,
+}
+
+impl BloomFilters {
+    pub fn new(layout: DbLayout, read_only: bool) -> Self {
+        BloomFilters { layout: Some(layout), read_only, loaded: RwLock::new(HashMap::new()) }
+    }
+
+    pub fn in_memory() -> Self {
+        BloomFilters { layout: None, read_only: false, loaded: RwLock::new(HashMap::new()) }
+    }
+
+    pub fn file_path(layout: &DbLayout, name: &str, shard_idx: Option<usize>) -> PathBuf {
+        layout.file_path(name, shard_idx).with_extension("bloom")
+    }
+
+    pub(crate) fn remove_files(layout: &DbLayout, name: &str, shards: usize) -> Result<(), AppError> {
+        for idx in 0..shards {
+            let path = Self::file_path(layout, name, shard_suffix(shards, idx));
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_, HashMap<String, Vec<Arc<BloomFilter>>>>, AppError> {
+        self.loaded.read().map_err(|_| AppError::Custom("bloom filters lock poisoned".to_string()))
+    }
+
+    fn write(&self) -> Result<RwLockWriteGuard<'_, HashMap<String, Vec<Arc<BloomFilter>>>>, AppError> {
+        self.loaded.write().map_err(|_| AppError::Custom("bloom filters lock poisoned".to_string()))
+    }
+
+    /// Loads the filters of all bloom columns, shards are loaded in parallel. A saved filter is used only if no
+    /// commit landed after it was saved, otherwise it is rebuilt from the index.
+    pub fn load(&self, columns: &[Arc<dyn ColumnTables>], index_dbs: &HashMap<String, DbSetOwned>) -> Result<(), AppError> {
+        for column in columns.iter().filter(|c| c.bloom()) {
+            let name = column.name();
+            let Some(shards) = index_dbs.get(&name).map(|set| set.shards()) else {
+                continue;
+            };
+            let filters = thread::scope(|s| {
+                let handles: Vec<_> = shards.iter().enumerate()
+                    .map(|(idx, db)| s.spawn(move || self.load_shard(column.as_ref(), db, shard_suffix(shards.len(), idx))))
+                    .collect();
+                handles.into_iter()
+                    .map(|h| h.join().map_err(|_| AppError::Custom(format!("building bloom filter of `{}` panicked", name)))?)
+                    .collect::<Result<Vec<_>, AppError>>()
+            })?;
+            self.write()?.insert(name, filters.into_iter().map(Arc::new).collect());
+        }
+        Ok(())
+    }
+
+    fn load_shard(&self, column: &dyn ColumnTables, db: &ShardDb, shard_idx: Option<usize>) -> Result<BloomFilter, AppError> {
+        let name = column.name();
+        if let Some(layout) = &self.layout {
+            let stamp = CommitMarker::read(db)?.map(|m| m.epoch);
+            if let Some(filter) = BloomFilter::load(&Self::file_path(layout, &name, shard_idx), stamp)? {
+                return Ok(filter);
+            }
+        }
+        info!("Building bloom filter of {} shard {}", name, shard_idx.unwrap_or(0));
+        column.build_bloom(db)
+    }
+
+    /// Filters of all shards of a column in shard order, None if they were not loaded with the storage, the column
+    /// is then queried without them.
+    pub fn shard_filters(&self, name: &str) -> Result<Option<Vec<Arc<BloomFilter>>>, AppError> {
+        Ok(self.read()?.get(name).cloned())
+    }
+
+    /// Stamps every filter with the last commit of its shard, the stamp is read before the bits so that a commit
+    /// landing in between only makes the saved filter look stale.
+    pub fn save(&self, index_dbs: &HashMap<String, DbSetOwned>) -> Result<(), AppError> {
+        let Some(layout) = self.layout.as_ref().filter(|_| !self.read_only) else {
+            return Ok(());
+        };
+        for (name, filters) in self.read()?.iter() {
+            let shards = index_dbs.get(name).map(|set| set.shards()).unwrap_or_default();
+            for (idx, (filter, db)) in filters.iter().zip(shards).enumerate() {
+                let stamp = CommitMarker::read(db.as_ref())?.map(|m| m.epoch);
+                filter.save(&Self::file_path(layout, name, shard_suffix(shards.len(), idx)), stamp)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the filters of a column from its index and deletes their files. Needed when index entries are
+    /// written outside of marked commits, like by a fsck repair, readers and writers created before keep the old ones.
+    pub fn rebuild(&self, column: &dyn ColumnTables, dbs: &[Weak<ShardDb>]) -> Result<(), AppError> {
+        let name = column.name();
+        if !column.bloom() {
+            return Ok(());
+        }
+        if let Some(layout) = self.layout.as_ref().filter(|_| !self.read_only) {
+            Self::remove_files(layout, &name, dbs.len())?;
+        }
+        let mut filters = Vec::with_capacity(dbs.len());
+        for db_weak in dbs {
+            let db = db_weak.upgrade().ok_or_else(|| AppError::Custom("database closed".to_string()))?;
+            filters.push(Arc::new(column.build_bloom(&db)?));
+        }
+        self.write()?.insert(name, filters);
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+    use crate::storage::test_utils::mk_tmp_dir;
+
+    fn value(i: u64) -> [u8; 32] {
+        let mut v = [0u8; 32];
+        v[..8].copy_from_slice(&i.to_le_bytes());
+        v[24..].copy_from_slice(&(i.wrapping_mul(0x9E37_79B9_7F4A_7C15)).to_le_bytes());
+        v
+    }
+
+    #[test]
+    fn inserted_values_are_never_reported_absent() {
+        let filter = BloomFilter::with_capacity(10_000);
+        for i in 0..50_000 {
+            filter.insert(&value(i));
+        }
+        assert!((0..50_000).all(|i| filter.may_contain(&value(i))));
+    }
+
+    #[test]
+    fn false_positive_rate_stays_low_while_growing() {
+        let filter = BloomFilter::with_capacity(BloomFilter::MIN_CAPACITY);
+        let inserted = BloomFilter::MIN_CAPACITY * 5;
+        for i in 0..inserted {
+            filter.insert(&value(i));
+        }
+        assert!(filter.layers() > 1, "filter must have grown");
+        let probes = 100_000u64;
+        let false_positives = (inserted..inserted + probes).filter(|&i| filter.may_contain(&value(i))).count();
+        assert!((false_positives as f64) / (probes as f64) < 0.015, "{false_positives} false positives");
+    }
+
+    #[test]
+    fn saved_filter_loads_only_at_the_same_commit() {
+        let dir = mk_tmp_dir("bloom");
+        let path = dir.join("hash.bloom");
+        let filter = BloomFilter::with_capacity(100);
+        filter.insert(b"present");
+        filter.save(&path, Some(7)).expect("save");
+
+        let loaded = BloomFilter::load(&path, Some(7)).expect("load").expect("same commit");
+        assert!(loaded.may_contain(b"present"));
+        assert_eq!(loaded.items(), 1);
+        assert!(BloomFilter::load(&path, Some(8)).expect("load").is_none());
+        assert!(BloomFilter::load(&path, None).expect("load").is_none());
+        assert!(BloomFilter::load(&dir.join("missing.bloom"), Some(7)).expect("load").is_none());
+        fs::remove_dir_all(dir).expect("cleanup");
+    }
+}
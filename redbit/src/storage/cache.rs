@@ -1,4 +1,10 @@
 use crate::storage::init::{DbDef, DbDefWithCache};
+use crate::storage::layout::DbLayout;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use utoipa::ToSchema;
 
 /// Weighted proportional allocation using largest remainder (Hamilton),
 /// operating purely in **MB**. Zero weights get 0 MB.
@@ -6,14 +12,15 @@ use crate::storage::init::{DbDef, DbDefWithCache};
 ///
 /// NOTE: `db_cache_in_mb` in the *result* is **per-shard**. We first allocate
 /// per-column MB, then divide by `shards` (asserting shards >= 2).
-pub fn allocate_cache_mb(db_defs: &[DbDef], total_mb: u64) -> Vec<DbDefWithCache> {
+/// Each result also carries the mounted dir of every shard as resolved by `layout`.
+pub fn allocate_cache_mb(db_defs: &[DbDef], total_mb: u64, layout: &DbLayout) -> Vec<DbDefWithCache> {
     if db_defs.is_empty() || total_mb == 0 {
-        return db_defs.iter().map(|d| DbDefWithCache::new(d.clone(), 0)).collect();
+        return db_defs.iter().map(|d| DbDefWithCache::placed(d.clone(), 0, layout)).collect();
     }
 
     let sum_w = sum_positive_weights(db_defs);
     if sum_w == 0 {
-        return db_defs.iter().map(|d| DbDefWithCache::new(d.clone(), 0)).collect();
+        return db_defs.iter().map(|d| DbDefWithCache::placed(d.clone(), 0, layout)).collect();
     }
 
     let mut shares = compute_shares_mb(db_defs, total_mb, sum_w);
@@ -23,7 +30,7 @@ pub fn allocate_cache_mb(db_defs: &[DbDef], total_mb: u64) -> Vec<DbDefWithCache
         distribute_remainder_mb(&mut shares, remainder, db_defs);
     }
 
-    collect_allocations_mb(&shares, db_defs)
+    collect_allocations_mb(&shares, db_defs, layout)
 }
 
 fn sum_positive_weights(db_defs: &[DbDef]) -> u64 {
@@ -67,7 +74,7 @@ fn distribute_remainder_mb(shares: &mut [Share], mut remainder: u64, db_defs: &[
     shares.sort_by_key(|s| s.idx);
 }
 
-fn collect_allocations_mb(shares: &[Share], db_defs: &[DbDef]) -> Vec<DbDefWithCache> {
+fn collect_allocations_mb(shares: &[Share], db_defs: &[DbDef], layout: &DbLayout) -> Vec<DbDefWithCache> {
     shares.iter().map(|s| {
         let def = &db_defs[s.idx];
         let shards = def.shards;
@@ -81,6 +88,7 @@ fn collect_allocations_mb(shares: &[Share], db_defs: &[DbDef]) -> Vec<DbDefWithC
             db_cache_in_mb: cast_u64_to_usize(per_shard_mb),
             lru_cache: def.lru_cache_size_or_zero,
             shards,
+            shard_dirs: layout.shard_dirs(&def.name, shards),
         }
     }).collect()
 }
@@ -89,9 +97,87 @@ fn cast_u64_to_usize(x: u64) -> usize {
     if x > (usize::MAX as u64) { usize::MAX } else { x as usize }
 }
 
+/// Counters of one LRU cache, they tell whether `lru_cache = N` of a column is worth its memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct LruStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub inserts: u64,
+    pub evictions: u64,
+}
+
+impl LruStats {
+    pub fn lookups(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    /// Percentage of lookups served by the cache, `None` if there were no lookups.
+    pub fn hit_rate(&self) -> Option<f64> {
+        match self.lookups() {
+            0 => None,
+            n => Some(100.0 * self.hits as f64 / n as f64),
+        }
+    }
+
+    pub fn merge(&mut self, other: &LruStats) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.inserts += other.inserts;
+        self.evictions += other.evictions;
+    }
+}
+
+/// `LruCache` counting its hits, misses, inserts and evictions.
+pub struct MeteredLru<K: Hash + Eq, V> {
+    lru: LruCache<K, V>,
+    stats: LruStats,
+}
+
+impl<K: Hash + Eq + Clone, V> MeteredLru<K, V> {
+    pub fn new(cap: NonZeroUsize) -> Self {
+        MeteredLru { lru: LruCache::new(cap), stats: LruStats::default() }
+    }
+
+    pub fn get(&mut self, k: &K) -> Option<&V> {
+        match self.lru.get(k) {
+            Some(v) => { self.stats.hits += 1; Some(v) }
+            None => { self.stats.misses += 1; None }
+        }
+    }
+
+    pub fn put(&mut self, k: K, v: V) {
+        self.stats.inserts += 1;
+        if let Some((old, _)) = self.lru.push(k.clone(), v) && old != k {
+            self.stats.evictions += 1;
+        }
+    }
+
+    pub fn pop(&mut self, k: &K) -> Option<V> {
+        self.lru.pop(k)
+    }
+
+    pub fn cap(&self) -> NonZeroUsize {
+        self.lru.cap()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lru.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lru.is_empty()
+    }
+
+    pub fn stats(&self) -> LruStats {
+        self.stats
+    }
+}
+
 #[cfg(all(test, not(feature = "integration")))]
 mod tests {
     use super::*;
+    use crate::storage::layout::MountPoint;
+    use std::path::PathBuf;
 
     // Build DbDefs with a fixed shard count (must be ≥ 2 now).
     fn defs(ws: &[usize], shards: usize) -> Vec<DbDef> {
@@ -118,10 +204,10 @@ mod tests {
 
     #[test]
     fn empty_or_zero_total_all_zero() {
-        let out = allocate_cache_mb(&[], 42);
+        let out = allocate_cache_mb(&[], 42, &DbLayout::default());
         assert!(out.is_empty());
 
-        let out2 = allocate_cache_mb(&defs(&[1,2,3], 2), 0);
+        let out2 = allocate_cache_mb(&defs(&[1,2,3], 2), 0, &DbLayout::default());
         assert_eq!(out2.len(), 3);
         assert!(out2.iter().all(|d| d.db_cache_in_mb == 0));
         assert_eq!(sum_total_mb(&out2), 0);
@@ -129,7 +215,7 @@ mod tests {
 
     #[test]
     fn all_zero_weights_all_zero() {
-        let out = allocate_cache_mb(&defs(&[0,0,0], 2), 10_000);
+        let out = allocate_cache_mb(&defs(&[0,0,0], 2), 10_000, &DbLayout::default());
         assert_eq!(out.len(), 3);
         assert!(out.iter().all(|d| d.db_cache_in_mb == 0));
         assert_eq!(sum_total_mb(&out), 0);
@@ -141,7 +227,7 @@ mod tests {
         // Column-level Hamilton gives 6827 and 3413 MB.
         // With shards=2, per-shard becomes floor(6827/2)=3413 and floor(3413/2)=1706.
         let total_mb = 10_u64 * 1024;
-        let out = allocate_cache_mb(&defs(&[10, 5], 2), total_mb);
+        let out = allocate_cache_mb(&defs(&[10, 5], 2), total_mb, &DbLayout::default());
         assert_eq!(out.len(), 2);
 
         // Check per-SHARD expectations (deterministic):
@@ -160,7 +246,7 @@ mod tests {
     fn zero_weight_entries_get_zero_even_with_remainder() {
         // total 5 MB; weights: 0,1,0,1; shards=2
         // Active columns get 2 and 3 MB at the column level -> per-shard 1 and 1.
-        let out = allocate_cache_mb(&defs(&[0,1,0,1], 2), 5);
+        let out = allocate_cache_mb(&defs(&[0,1,0,1], 2), 5, &DbLayout::default());
         assert_eq!(out.len(), 4);
 
         assert_eq!(out[0].db_cache_in_mb, 0);
@@ -179,7 +265,7 @@ mod tests {
         // 3 equal weights, total 5 MB -> column-level bases 1 each, 2 remainder MB -> first two get them
         // Column totals: [2,2,1]
         // With shards=2, per-shard: [1,1,0]
-        let out = allocate_cache_mb(&defs(&[1,1,1], 2), 5);
+        let out = allocate_cache_mb(&defs(&[1,1,1], 2), 5, &DbLayout::default());
         let bytes: Vec<usize> = out.iter().map(|d| d.db_cache_in_mb).collect();
         assert_eq!(bytes, vec![1,1,0]);
 
@@ -195,13 +281,25 @@ mod tests {
         let n = 37;
         let out = allocate_cache_mb(
             &vec![DbDef { name: "x".into(), shards: 2, db_cache_weight_or_zero: 1, lru_cache_size_or_zero: 0 }; n],
-            1
+            1,
+            &DbLayout::default(),
         );
         assert_eq!(out.len(), n);
         assert!(out.iter().all(|d| d.db_cache_in_mb == 0));
         assert_eq!(sum_total_mb(&out), 0);
     }
 
+    #[test]
+    fn allocations_carry_shard_placement() {
+        let layout = DbLayout::with_mounts(PathBuf::from("/db"), vec![
+            MountPoint { column: Some("db0".into()), shard: Some(1), dir: PathBuf::from("/nvme1") },
+        ]);
+        let out = allocate_cache_mb(&defs(&[1, 1], 2), 8, &layout);
+        assert_eq!(out[0].shard_dirs, vec![PathBuf::from("/db"), PathBuf::from("/nvme1")]);
+        assert_eq!(out[1].shard_dirs, vec![PathBuf::from("/db"), PathBuf::from("/db")]);
+        assert_eq!(out[0].db_cache_in_mb, 2);
+    }
+
     #[test]
     fn compute_shares_mb_basic_invariants() {
         // This exercises the pre-division Hamilton stage; unchanged by shards.
@@ -244,6 +342,26 @@ mod tests {
     }
 }
 
+#[cfg(all(test, not(feature = "integration")))]
+mod lru_tests {
+    use super::*;
+
+    #[test]
+    fn metered_lru_counts_hits_misses_inserts_and_evictions() {
+        let mut lru: MeteredLru<u32, u32> = MeteredLru::new(NonZeroUsize::new(2).unwrap());
+        lru.put(1, 10);
+        lru.put(2, 20);
+        lru.put(2, 21); // replacing a key is no eviction
+        assert_eq!(lru.get(&1), Some(&10));
+        lru.put(3, 30); // evicts 2, the least recently used
+        assert_eq!(lru.get(&2), None);
+        assert_eq!(lru.get(&3), Some(&30));
+        assert_eq!(lru.stats(), LruStats { hits: 2, misses: 1, inserts: 4, evictions: 1 });
+        assert_eq!(lru.stats().hit_rate().map(|r| r.round()), Some(67.0));
+        assert_eq!(LruStats::default().hit_rate(), None);
+    }
+}
+
 #[cfg(all(test, not(feature = "integration")))]
 mod bench {
     use std::num::NonZeroUsize;
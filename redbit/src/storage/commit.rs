@@ -0,0 +1,282 @@
+use crate::storage::init::{shard_suffix, ShardDb, StorageOwner};
+use crate::{info, warn, AppError};
+use redb::{Durability, ReadableDatabase, TableDefinition, TableError, WriteTransaction};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Lives in every db next to the column tables, written in the same transaction as the data it describes.
+pub const COMMIT_MARKER: TableDefinition<'static, &'static str, (u64, Vec<String>)> = TableDefinition::new("redbit_commit_marker");
+const MARKER_KEY: &str = "last";
+
+/// Epoch of the two-phase commit that last touched a db and the dbs (shards) that took part in it.
+/// A commit is complete when all `participants` carry its epoch or moved past it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommitMarker {
+    pub epoch: u64,
+    pub participants: Vec<String>,
+}
+
+impl CommitMarker {
+    pub fn new(epoch: u64, participants: Vec<String>) -> Self {
+        CommitMarker { epoch, participants }
+    }
+
+    pub fn read(db: &impl ReadableDatabase) -> Result<Option<Self>, AppError> {
+        let tx = db.begin_read()?;
+        let table = match tx.open_table(COMMIT_MARKER) {
+            Ok(t) => t,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(table.get(MARKER_KEY)?.map(|g| {
+            let (epoch, participants) = g.value();
+            CommitMarker { epoch, participants }
+        }))
+    }
+
+    pub(crate) fn write(&self, tx: &WriteTransaction) -> Result<(), AppError> {
+        tx.open_table(COMMIT_MARKER)?.insert(MARKER_KEY, (self.epoch, self.participants.clone()))?;
+        Ok(())
+    }
+}
+
+/// Db file name of a column shard, like `utxo_address_dict-2`, the name commit markers refer to participants by.
+pub(crate) fn shard_file_name(name: &str, shards: usize, idx: usize) -> String {
+    match shard_suffix(shards, idx) {
+        Some(i) => format!("{}-{}", name, i),
+        None => name.to_string(),
+    }
+}
+
+/// Begins a write transaction, optionally replacing the savepoint of the db first. Each db keeps a single persistent
+/// savepoint holding its last durable state, the one a torn commit rolls back to, so it is taken by the first write
+/// transaction after a durable commit. A non-durable transaction cannot hold a persistent savepoint, so it is taken in
+/// a separate durable one right before.
+pub(crate) fn begin_write(db: &ShardDb, durability: Durability, savepoint: bool) -> Result<WriteTransaction, AppError> {
+    let durable = matches!(durability, Durability::Immediate);
+    if savepoint && !durable {
+        let mut sp_tx = db.begin_write()?;
+        sp_tx.set_durability(Durability::Immediate).map_err(|e| AppError::Custom(e.to_string()))?;
+        replace_savepoint(&sp_tx)?;
+        sp_tx.commit()?;
+    }
+    let mut tx = db.begin_write()?;
+    tx.set_durability(durability).map_err(|e| AppError::Custom(e.to_string()))?;
+    if savepoint && durable {
+        replace_savepoint(&tx)?;
+    }
+    Ok(tx)
+}
+
+fn replace_savepoint(tx: &WriteTransaction) -> Result<(), AppError> {
+    // a savepoint must be taken before anything touches the transaction, listing included
+    let current = tx.persistent_savepoint()?;
+    let previous: Vec<u64> = tx.list_persistent_savepoints()?.filter(|id| *id != current).collect();
+    for id in previous {
+        tx.delete_persistent_savepoint(id)?;
+    }
+    Ok(())
+}
+
+fn rollback_to_savepoint(db: &ShardDb) -> Result<(), AppError> {
+    let mut tx = db.begin_write()?;
+    tx.set_durability(Durability::Immediate).map_err(|e| AppError::Custom(e.to_string()))?;
+    let ids: Vec<u64> = tx.list_persistent_savepoints()?.collect();
+    let id = match ids.as_slice() {
+        [id] => *id,
+        _ => return Err(AppError::Custom(format!("expected a single savepoint, found {}", ids.len()))),
+    };
+    let savepoint = tx.get_persistent_savepoint(id)?;
+    tx.restore_savepoint(&savepoint)?;
+    tx.delete_persistent_savepoint(id)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Db file name, like `utxo_address_dict-2`, and the db.
+pub type NamedDb = (String, Arc<ShardDb>);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommitRecovery {
+    /// Highest epoch found on disk, new commits continue after it.
+    pub last_epoch: u64,
+    /// Dbs that were ahead of a torn commit and got rolled back.
+    pub rolled_back: Vec<String>,
+}
+
+/// A commit that reached only some of its participants, the rest are still at an earlier epoch.
+struct TornCommit<'a> {
+    marker: CommitMarker,
+    ahead: Vec<&'a NamedDb>,
+}
+
+/// Highest epoch on disk and every torn commit. Write contexts commit concurrently, so any epoch can be torn, not just
+/// the last one. A participant that moved past an epoch took part in a later commit, like a child entity context does
+/// on a subset of its parent's dbs, so only participants still behind an epoch make it torn.
+fn find_torn_commits(dbs: &[NamedDb]) -> Result<(u64, Vec<TornCommit<'_>>), AppError> {
+    let mut epochs: HashMap<&str, u64> = HashMap::with_capacity(dbs.len());
+    let mut commits: BTreeMap<u64, (CommitMarker, Vec<&NamedDb>)> = BTreeMap::new();
+    for entry in dbs {
+        let marker = CommitMarker::read(entry.1.as_ref())?;
+        epochs.insert(entry.0.as_str(), marker.as_ref().map_or(0, |m| m.epoch));
+        if let Some(marker) = marker {
+            commits.entry(marker.epoch).or_insert_with(|| (marker, Vec::new())).1.push(entry);
+        }
+    }
+    let last_epoch = commits.keys().next_back().copied().unwrap_or_default();
+    let torn = commits.into_values().filter(|(marker, _)| {
+        // participants missing on disk belong to columns that are not opened, they cannot tell
+        marker.participants.iter().any(|p| epochs.get(p.as_str()).is_some_and(|e| *e < marker.epoch))
+    }).map(|(marker, ahead)| TornCommit { marker, ahead }).collect();
+    Ok((last_epoch, torn))
+}
+
+/// A crash in the middle of `two_phase_commit` leaves some participants of a commit at its epoch and the rest at their
+/// previous state. Finds every such commit and rolls its ahead dbs back to their savepoint, so that all columns agree
+/// again.
+pub fn recover_torn_commit(dbs: &[NamedDb]) -> Result<CommitRecovery, AppError> {
+    let (last_epoch, torn) = find_torn_commits(dbs)?;
+    let mut recovery = CommitRecovery { last_epoch, rolled_back: Vec::new() };
+    for TornCommit { marker, ahead } in torn {
+        warn!("Commit {} reached only {} of {} dbs, rolling them back", marker.epoch, ahead.len(), marker.participants.len());
+        for (name, db) in ahead {
+            rollback_to_savepoint(db).map_err(|e| AppError::Custom(format!(
+                "db `{}` is ahead of a torn commit {} and cannot be rolled back: {}, restore it from a snapshot", name, marker.epoch, e
+            )))?;
+            recovery.rolled_back.push(name.to_string());
+        }
+    }
+    Ok(recovery)
+}
+
+impl StorageOwner {
+    /// Rolls back torn commits and lets the commit fence continue after the last epoch on disk, read-only storage
+    /// cannot roll back so it refuses to open torn commits instead.
+    pub(crate) fn recover_commits(&self) -> Result<CommitRecovery, AppError> {
+        let mut dbs = Vec::new();
+        for (name, set) in &self.index_dbs {
+            let shards = set.shards();
+            for (idx, db) in shards.iter().enumerate() {
+                dbs.push((shard_file_name(name, shards.len(), idx), Arc::clone(db)));
+            }
+        }
+        if self.read_only {
+            let (last_epoch, torn) = find_torn_commits(&dbs)?;
+            if let Some(TornCommit { marker, ahead }) = torn.first() {
+                let names: Vec<&str> = ahead.iter().map(|(name, _)| name.as_str()).collect();
+                return Err(AppError::ReadOnly(format!(
+                    "commit {} reached only {}, open the storage writable once to roll it back", marker.epoch, names.join(", ")
+                )));
+            }
+            return Ok(CommitRecovery { last_epoch, rolled_back: Vec::new() });
+        }
+        let recovery = recover_torn_commit(&dbs)?;
+        if !recovery.rolled_back.is_empty() {
+            info!("Rolled back {} to commit before {}", recovery.rolled_back.join(", "), recovery.last_epoch);
+        }
+        self.commit_fence.resume_after(recovery.last_epoch);
+        Ok(recovery)
+    }
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+    use crate::storage::test_utils::mk_db;
+    use redb::ReadableTable;
+
+    const DATA: TableDefinition<'static, u32, u32> = TableDefinition::new("commit_data");
+
+    fn commit(db: &ShardDb, durability: Durability, marker: CommitMarker, savepoint: bool, k: u32) {
+        let tx = begin_write(db, durability, savepoint).expect("write");
+        tx.open_table(DATA).expect("table").insert(k, k).expect("insert");
+        marker.write(&tx).expect("marker");
+        tx.commit().expect("commit");
+    }
+
+    fn marker(epoch: u64, participants: &[&str]) -> CommitMarker {
+        CommitMarker::new(epoch, participants.iter().map(|p| p.to_string()).collect())
+    }
+
+    fn keys(db: &ShardDb) -> Vec<u32> {
+        let tx = db.begin_read().expect("read");
+        let table = tx.open_table(DATA).expect("table");
+        table.iter().expect("iter").map(|e| e.expect("entry").0.value()).collect()
+    }
+
+    #[test]
+    fn torn_commit_rolls_ahead_dbs_back_to_last_durable_state() {
+        let (a, _) = mk_db("redbit_commit_a");
+        let (b, _) = mk_db("redbit_commit_b");
+        commit(&a, Durability::Immediate, marker(1, &["a", "b"]), true, 1);
+        commit(&b, Durability::Immediate, marker(1, &["a", "b"]), true, 1);
+        // batch mode, the savepoint of epoch 1 is taken by the first non-durable transaction
+        commit(&a, Durability::None, marker(2, &["a", "b"]), true, 2);
+        // epoch 3 made it only into `a`, `b` lost its non-durable epoch 2 in the crash and is back at 1
+        commit(&a, Durability::Immediate, marker(3, &["a", "b"]), false, 3);
+
+        let dbs = vec![("a".to_string(), Arc::clone(&a)), ("b".to_string(), b)];
+        let recovery = recover_torn_commit(&dbs).expect("recover");
+        assert_eq!(recovery, CommitRecovery { last_epoch: 3, rolled_back: vec!["a".to_string()] });
+        assert_eq!(keys(&a), vec![1]);
+        assert_eq!(CommitMarker::read(a.as_ref()).expect("marker"), Some(marker(1, &["a", "b"])));
+
+        let again = recover_torn_commit(&dbs).expect("recover");
+        assert!(again.rolled_back.is_empty(), "{again:?}");
+        assert_eq!(again.last_epoch, 1);
+    }
+
+    #[test]
+    fn complete_commit_is_left_alone() {
+        let (a, _) = mk_db("redbit_commit_a");
+        let (b, _) = mk_db("redbit_commit_b");
+        let (c, _) = mk_db("redbit_commit_c");
+        for db in [&a, &b] {
+            commit(db, Durability::Immediate, marker(5, &["a", "b"]), true, 1);
+        }
+        commit(&c, Durability::Immediate, marker(4, &["c"]), true, 1);
+        let dbs = vec![("a".to_string(), a), ("b".to_string(), b), ("c".to_string(), c)];
+        let recovery = recover_torn_commit(&dbs).expect("recover");
+        assert_eq!(recovery, CommitRecovery { last_epoch: 5, rolled_back: vec![] });
+    }
+
+    #[test]
+    fn torn_commit_of_an_older_context_is_rolled_back() {
+        let (a, _) = mk_db("redbit_commit_a");
+        let (b, _) = mk_db("redbit_commit_b");
+        let (c, _) = mk_db("redbit_commit_c");
+        let (d, _) = mk_db("redbit_commit_d");
+        for db in [&a, &b] {
+            commit(db, Durability::Immediate, marker(1, &["a", "b"]), true, 1);
+        }
+        for db in [&c, &d] {
+            commit(db, Durability::Immediate, marker(2, &["c", "d"]), true, 1);
+        }
+        // both contexts were committing at the crash, epoch 3 made it only into `a` while epoch 4 completed
+        commit(&a, Durability::Immediate, marker(3, &["a", "b"]), true, 3);
+        for db in [&c, &d] {
+            commit(db, Durability::Immediate, marker(4, &["c", "d"]), true, 4);
+        }
+
+        let dbs = vec![("a".to_string(), Arc::clone(&a)), ("b".to_string(), b), ("c".to_string(), Arc::clone(&c)), ("d".to_string(), d)];
+        let recovery = recover_torn_commit(&dbs).expect("recover");
+        assert_eq!(recovery, CommitRecovery { last_epoch: 4, rolled_back: vec!["a".to_string()] });
+        assert_eq!(keys(&a), vec![1]);
+        assert_eq!(keys(&c), vec![1, 4]);
+    }
+
+    #[test]
+    fn commit_superseded_by_a_later_one_on_some_participants_is_complete() {
+        let (a, _) = mk_db("redbit_commit_a");
+        let (b, _) = mk_db("redbit_commit_b");
+        for db in [&a, &b] {
+            commit(db, Durability::Immediate, marker(1, &["a", "b"]), true, 1);
+        }
+        // a child context commits only `b`
+        commit(&b, Durability::Immediate, marker(2, &["b"]), true, 2);
+        let dbs = vec![("a".to_string(), Arc::clone(&a)), ("b".to_string(), b)];
+        let recovery = recover_torn_commit(&dbs).expect("recover");
+        assert_eq!(recovery, CommitRecovery { last_epoch: 2, rolled_back: vec![] });
+        assert_eq!(keys(&a), vec![1]);
+    }
+}
@@ -3,6 +3,8 @@ use redb::Durability;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Instant;
+use crate::storage::commit::CommitMarker;
+use crate::storage::snapshot::{CommitFence, PinnedRead};
 use crate::storage::table_writer_api::WriteComponentRef;
 
 pub trait WriteTxContext {
@@ -17,10 +19,17 @@ pub trait WriteTxContext {
 
     fn writer_refs(&self) -> Self::WriterRefs<'_>;
 
+    /// Every shard of the context commits with the same marker, so that a torn commit can be detected on open.
     fn begin_writing_async(&self, d: Durability) -> redb::Result<Vec<StartFuture>, AppError> {
+        let marker = self.writer_refs().into_iter().find_map(|c| c.commit_fence())
+            .map(|fence| CommitMarker::new(fence.next_commit(), self.writer_refs().into_iter().flat_map(|c| c.participants()).collect()));
+        self.begin_writing_marked_async(d, marker)
+    }
+
+    fn begin_writing_marked_async(&self, d: Durability, marker: Option<CommitMarker>) -> redb::Result<Vec<StartFuture>, AppError> {
         let mut v = Vec::new();
         for c in self.writer_refs() {
-            v.extend(c.begin_async_ref(d)?);
+            v.extend(c.begin_async_ref(d, marker.clone())?);
         }
         Ok(v)
     }
@@ -33,6 +42,41 @@ pub trait WriteTxContext {
         Ok(v)
     }
 
+    fn prepare_ctx_async(&self) -> Result<Vec<StartFuture>, AppError> {
+        let mut v = Vec::new();
+        for c in self.writer_refs() {
+            v.extend(c.prepare_with_ref()?);
+        }
+        Ok(v)
+    }
+
+    fn abort_ctx(&self) -> Result<(), AppError> {
+        for c in self.writer_refs() {
+            c.abort_with_ref()?;
+        }
+        Ok(())
+    }
+
+    /// Waits for all prepared writers, a failed one aborts every shard of the context instead of committing it.
+    fn prepare_or_abort(&self) -> Result<(), AppError> {
+        let mut failure = None;
+        for f in self.prepare_ctx_async()? {
+            if let Err(err) = f.wait() {
+                failure.get_or_insert(err);
+            }
+        }
+        match failure {
+            None => Ok(()),
+            Some(err) => {
+                self.abort_ctx()?;
+                for f in self.commit_ctx_async()? {
+                    let _ = f.wait();
+                }
+                Err(err)
+            }
+        }
+    }
+
     fn begin_writing(&self, durability: Durability) -> redb::Result<(), AppError> {
         let futures = self.begin_writing_async(durability)?;
         for f in futures {
@@ -53,6 +97,8 @@ pub trait WriteTxContext {
         Ok(ctx)
     }
     fn two_phase_commit(&self) -> Result<HashMap<String, TaskResult>, AppError> {
+        let _guard = self.writer_refs().into_iter().find_map(|c| c.commit_fence()).map(|fence| fence.enter());
+        self.prepare_or_abort()?;
         FlushFuture::dedup_tasks_keep_slowest(self.commit_ctx_async()?)
     }
     fn two_phase_commit_and_close(self) -> Result<HashMap<String, TaskResult>, AppError> where Self: Sized {
@@ -103,12 +149,24 @@ pub trait WriteTxContext {
 }
 
 impl<C: WriteTxContext + Send + 'static> WriteComponentRef for C {
-    fn begin_async_ref(&self, d: Durability) -> redb::Result<Vec<StartFuture>, AppError> {
-        self.begin_writing_async(d)
+    fn begin_async_ref(&self, d: Durability, marker: Option<CommitMarker>) -> redb::Result<Vec<StartFuture>, AppError> {
+        self.begin_writing_marked_async(d, marker)
+    }
+    fn participants(&self) -> Vec<String> {
+        self.writer_refs().into_iter().flat_map(|c| c.participants()).collect()
     }
     fn commit_with_ref(&self) -> Result<Vec<FlushFuture>, AppError> {
         self.commit_ctx_async()
     }
+    fn prepare_with_ref(&self) -> Result<Vec<StartFuture>, AppError> {
+        self.prepare_ctx_async()
+    }
+    fn abort_with_ref(&self) -> Result<(), AppError> {
+        self.abort_ctx()
+    }
+    fn commit_fence(&self) -> Option<Arc<CommitFence>> {
+        self.writer_refs().into_iter().find_map(|c| c.commit_fence())
+    }
 }
 
 pub trait ReadTxContext {
@@ -131,6 +189,11 @@ pub trait TxContext {
         <Self::ReadCtx as ReadTxContext>::begin_read_ctx(self, storage)
     }
 
+    /// Read context whose tables all reflect the same commit, see `Storage::pin_read`.
+    fn begin_pinned_read_ctx(&self, storage: &Arc<Storage>) -> redb::Result<PinnedRead<Self::ReadCtx>, AppError> {
+        storage.pin_read(|storage| self.begin_read_ctx(storage))
+    }
+
     fn new_write_ctx(&self, storage: &Arc<Storage>) -> redb::Result<Self::WriteCtx, AppError> {
         <Self::WriteCtx as WriteTxContext>::new_write_ctx(self, storage)
     }
@@ -0,0 +1,522 @@
+use crate::compress;
+use crate::storage::init::{ShardDb, Storage};
+use crate::storage::table_writer_api::ColumnTables;
+use crate::{info, AppError, CacheKey, StructInfo};
+use redb::{Key, MultimapTableDefinition, ReadTransaction, ReadableDatabase, ReadableMultimapTable, ReadableTable, ReadableTableMetadata, TableDefinition, TableError, TableHandle, TypeName, Value};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// Single broken invariant, `subject` is the column for structural problems and the entity for referential ones.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FsckIssue {
+    pub subject: String,
+    pub shard: Option<usize>,
+    pub problem: String,
+    pub repaired: bool,
+}
+
+impl FsckIssue {
+    pub fn new(subject: impl Into<String>, problem: String) -> Self {
+        FsckIssue { subject: subject.into(), shard: None, problem, repaired: false }
+    }
+
+    pub fn repaired(mut self, repaired: bool) -> Self {
+        self.repaired = repaired;
+        self
+    }
+}
+
+impl fmt::Display for FsckIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.repaired { "repaired" } else { "broken" };
+        match self.shard {
+            Some(shard) => write!(f, "[{}] {}-{}: {}", status, self.subject, shard, self.problem),
+            None => write!(f, "[{}] {}: {}", status, self.subject, self.problem),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FsckReport {
+    pub columns: usize,
+    pub entries: u64,
+    pub issues: Vec<FsckIssue>,
+}
+
+impl FsckReport {
+    /// True when nothing is broken anymore, repaired issues do not count.
+    pub fn is_clean(&self) -> bool {
+        self.issues.iter().all(|i| i.repaired)
+    }
+}
+
+impl fmt::Display for FsckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let repaired = self.issues.iter().filter(|i| i.repaired).count();
+        writeln!(f, "{} columns, {} entries checked, {} issues, {} repaired", self.columns, self.entries, self.issues.len(), repaired)?;
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+fn bytes<T: Value>(v: &T::SelfType<'_>) -> Vec<u8> {
+    T::as_bytes(v).as_ref().to_vec()
+}
+
+fn same<T: Value>(a: &T::SelfType<'_>, b: &T::SelfType<'_>) -> bool {
+    T::as_bytes(a).as_ref() == T::as_bytes(b).as_ref()
+}
+
+fn compare<T: Key>(a: &T::SelfType<'_>, b: &T::SelfType<'_>) -> Ordering {
+    T::compare(T::as_bytes(a).as_ref(), T::as_bytes(b).as_ref())
+}
+
+/// Unrepaired issues first, then the fixable ones marked as repaired if the fix was committed.
+fn collect(mut broken: Vec<FsckIssue>, fixable: Vec<FsckIssue>, repaired: bool) -> Vec<FsckIssue> {
+    broken.extend(fixable.into_iter().map(|i| i.repaired(repaired)));
+    broken
+}
+
+/// Shards that were never written to have no tables at all, there is nothing to check.
+fn table_exists<K: Key + 'static, V: Value + 'static>(tx: &ReadTransaction, def: TableDefinition<K, V>) -> Result<bool, AppError> {
+    match tx.open_table(def) {
+        Ok(_) => Ok(true),
+        Err(TableError::TableDoesNotExist(_)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Stored bytes of `V` values, opens a table of `V` without decoding its values.
+struct StoredBytes<V>(PhantomData<V>);
+
+impl<V> fmt::Debug for StoredBytes<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StoredBytes")
+    }
+}
+
+impl<V: Value + 'static> Value for StoredBytes<V> {
+    type SelfType<'a> = &'a [u8] where Self: 'a;
+    type AsBytes<'a> = &'a [u8] where Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        V::fixed_width()
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> &'a [u8]
+    where
+        Self: 'a,
+    {
+        data
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a &'b [u8]) -> &'a [u8]
+    where
+        Self: 'b,
+    {
+        value
+    }
+
+    fn type_name() -> TypeName {
+        V::type_name()
+    }
+}
+
+/// Plain rows have nothing to cross-check, but a corrupted compressed value would abort every read of it,
+/// so the stored frames of `#[column(compress = "zstd")]` columns are verified without decoding them.
+pub(crate) fn check_plain<K: Key + 'static, V: Value + 'static>(db: &ShardDb, column: &str, def: TableDefinition<K, V>) -> Result<(u64, Vec<FsckIssue>), AppError> {
+    let tx = db.begin_read()?;
+    if !table_exists(&tx, def)? {
+        return Ok((0, Vec::new()));
+    }
+    if !compress::is_compressed::<V>() {
+        return Ok((tx.open_table(def)?.len()?, Vec::new()));
+    }
+    let table = tx.open_table(TableDefinition::<K, StoredBytes<V>>::new(def.name()))?;
+    let mut broken = Vec::new();
+    for entry in table.iter()? {
+        let (key, stored) = entry?;
+        if let Err(e) = compress::verify(stored.value()) {
+            broken.push(FsckIssue::new(column, format!("value of {:?} is corrupted, {e}", key.value())));
+        }
+    }
+    Ok((table.len()?, broken))
+}
+
+/// Every `pk_by_index` entry must match its `index_by_pk` row and vice versa. The `index_by_pk` rows are
+/// authoritative, a repair drops stale `pk_by_index` entries and re-adds missing ones.
+pub(crate) fn check_index<K: Key + 'static, V: CacheKey>(
+    db: &ShardDb,
+    column: &str,
+    pk_by_index_def: MultimapTableDefinition<V::Key, K>,
+    index_by_pk_def: TableDefinition<K, V>,
+    repair: bool,
+) -> Result<(u64, Vec<FsckIssue>), AppError> {
+    let mut fixable = Vec::new();
+    let mut stale: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    let mut missing: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    let tx = db.begin_read()?;
+    if !table_exists(&tx, index_by_pk_def)? {
+        return Ok((0, Vec::new()));
+    }
+    let pk_by_index = tx.open_multimap_table(pk_by_index_def)?;
+    let index_by_pk = tx.open_table(index_by_pk_def)?;
+
+    let mut valid = 0u64;
+    for entry in pk_by_index.iter()? {
+        let (value, pks) = entry?;
+        for pk in pks {
+            let pk = pk?;
+            if index_by_pk.get(pk.value())?.is_some_and(|v| same::<V::Key>(&v.value(), &value.value())) {
+                valid += 1;
+            } else {
+                fixable.push(FsckIssue::new(column, format!("pk_by_index {:?} -> {:?} has no matching index_by_pk row", value.value(), pk.value())));
+                stale.push((bytes::<V::Key>(&value.value()), bytes::<K>(&pk.value())));
+            }
+        }
+    }
+    // every valid pair covers exactly one row, so equal counts mean nothing is missing
+    let rows = index_by_pk.len()?;
+    if valid != rows {
+        for entry in index_by_pk.iter()? {
+            let (pk, value) = entry?;
+            let mut found = false;
+            for p in pk_by_index.get(value.value())? {
+                if same::<K>(&p?.value(), &pk.value()) {
+                    found = true;
+                    break;
+                }
+            }
+            if !found {
+                fixable.push(FsckIssue::new(column, format!("index_by_pk {:?} -> {:?} is missing in pk_by_index", pk.value(), value.value())));
+                missing.push((bytes::<V::Key>(&value.value()), bytes::<K>(&pk.value())));
+            }
+        }
+    }
+    drop((pk_by_index, index_by_pk, tx));
+
+    let repaired = repair && !fixable.is_empty() && {
+        let tx = db.begin_write()?;
+        {
+            let mut pk_by_index = tx.open_multimap_table(pk_by_index_def)?;
+            for (value, pk) in &stale {
+                pk_by_index.remove(V::Key::from_bytes(value), K::from_bytes(pk))?;
+            }
+            for (value, pk) in &missing {
+                pk_by_index.insert(V::Key::from_bytes(value), K::from_bytes(pk))?;
+            }
+        }
+        tx.commit()?;
+        true
+    };
+    Ok((rows, collect(Vec::new(), fixable, repaired)))
+}
+
+/// Every `dict_pk_by_id` must point to a `value_by_dict_pk` that round-trips through `value_to_dict_pk`, and
+/// `dict_pk_to_ids` must mirror `dict_pk_by_id`. Missing reverse entries and orphaned values no id points to
+/// are repairable, dangling dict pks and values owned by two dict pks are not.
+pub(crate) fn check_dict<K: Key + 'static, V: CacheKey>(
+    db: &ShardDb,
+    column: &str,
+    dict_pk_to_ids_def: MultimapTableDefinition<K, K>,
+    value_by_dict_pk_def: TableDefinition<K, V>,
+    value_to_dict_pk_def: TableDefinition<V::Key, K>,
+    dict_pk_by_id_def: TableDefinition<K, K>,
+    repair: bool,
+) -> Result<(u64, Vec<FsckIssue>), AppError> {
+    let mut broken = Vec::new();
+    let mut fixable = Vec::new();
+    let mut missing_back: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    let mut stale_ids: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    let mut missing_ids: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    let mut orphans: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    let tx = db.begin_read()?;
+    if !table_exists(&tx, dict_pk_by_id_def)? {
+        return Ok((0, Vec::new()));
+    }
+    let dict_pk_to_ids = tx.open_multimap_table(dict_pk_to_ids_def)?;
+    let value_by_dict_pk = tx.open_table(value_by_dict_pk_def)?;
+    let value_to_dict_pk = tx.open_table(value_to_dict_pk_def)?;
+    let dict_pk_by_id = tx.open_table(dict_pk_by_id_def)?;
+
+    for entry in dict_pk_by_id.iter()? {
+        let (id, dict_pk) = entry?;
+        if value_by_dict_pk.get(dict_pk.value())?.is_none() {
+            broken.push(FsckIssue::new(column, format!("dict_pk_by_id {:?} -> {:?} has no value_by_dict_pk", id.value(), dict_pk.value())));
+        }
+    }
+    let mut valid = 0u64;
+    for entry in dict_pk_to_ids.iter()? {
+        let (dict_pk, ids) = entry?;
+        for id in ids {
+            let id = id?;
+            if dict_pk_by_id.get(id.value())?.is_some_and(|dp| same::<K>(&dp.value(), &dict_pk.value())) {
+                valid += 1;
+            } else {
+                fixable.push(FsckIssue::new(column, format!("dict_pk_to_ids {:?} -> {:?} has no matching dict_pk_by_id", dict_pk.value(), id.value())));
+                stale_ids.push((bytes::<K>(&dict_pk.value()), bytes::<K>(&id.value())));
+            }
+        }
+    }
+    let rows = dict_pk_by_id.len()?;
+    if valid != rows {
+        for entry in dict_pk_by_id.iter()? {
+            let (id, dict_pk) = entry?;
+            let mut found = false;
+            for i in dict_pk_to_ids.get(dict_pk.value())? {
+                if same::<K>(&i?.value(), &id.value()) {
+                    found = true;
+                    break;
+                }
+            }
+            if !found {
+                fixable.push(FsckIssue::new(column, format!("dict_pk_by_id {:?} -> {:?} is missing in dict_pk_to_ids", id.value(), dict_pk.value())));
+                missing_ids.push((bytes::<K>(&dict_pk.value()), bytes::<K>(&id.value())));
+            }
+        }
+    }
+    // both tables are sorted by dict pk, a value is referenced if one of its ids points back or is missing in dict_pk_to_ids
+    let missing_dict_pks: HashSet<&[u8]> = missing_ids.iter().map(|(dict_pk, _)| dict_pk.as_slice()).collect();
+    let mut ids_by_dict_pk = dict_pk_to_ids.iter()?.peekable();
+    for entry in value_by_dict_pk.iter()? {
+        let (dict_pk, value) = entry?;
+        let mut ids = None;
+        while let Some(next) = ids_by_dict_pk.peek() {
+            match next.as_ref().map_or(Ordering::Less, |(pk, _)| compare::<K>(&pk.value(), &dict_pk.value())) {
+                Ordering::Less => { ids_by_dict_pk.next().transpose()?; }
+                Ordering::Equal => { ids = ids_by_dict_pk.next().transpose()?.map(|(_, ids)| ids); break; }
+                Ordering::Greater => break,
+            }
+        }
+        let mut referenced = missing_dict_pks.contains(bytes::<K>(&dict_pk.value()).as_slice());
+        for id in ids.into_iter().flatten() {
+            if referenced {
+                break;
+            }
+            referenced = dict_pk_by_id.get(id?.value())?.is_some_and(|dp| same::<K>(&dp.value(), &dict_pk.value()));
+        }
+        if !referenced {
+            fixable.push(FsckIssue::new(column, format!("value {:?} of dict_pk {:?} has no ids left", value.value(), dict_pk.value())));
+            orphans.push((bytes::<K>(&dict_pk.value()), bytes::<V::Key>(&value.value())));
+            continue;
+        }
+        match value_to_dict_pk.get(value.value())? {
+            Some(back) if same::<K>(&back.value(), &dict_pk.value()) => {}
+            Some(back) => broken.push(FsckIssue::new(
+                column, format!("value {:?} of dict_pk {:?} maps back to dict_pk {:?}", value.value(), dict_pk.value(), back.value())
+            )),
+            None => {
+                fixable.push(FsckIssue::new(column, format!("value {:?} of dict_pk {:?} is missing in value_to_dict_pk", value.value(), dict_pk.value())));
+                missing_back.push((bytes::<V::Key>(&value.value()), bytes::<K>(&dict_pk.value())));
+            }
+        }
+    }
+    drop(ids_by_dict_pk);
+    drop((dict_pk_to_ids, value_by_dict_pk, value_to_dict_pk, dict_pk_by_id, tx));
+
+    let repaired = repair && !fixable.is_empty() && {
+        let tx = db.begin_write()?;
+        {
+            let mut value_to_dict_pk = tx.open_table(value_to_dict_pk_def)?;
+            for (value, dict_pk) in &missing_back {
+                value_to_dict_pk.insert(V::Key::from_bytes(value), K::from_bytes(dict_pk))?;
+            }
+            let mut dict_pk_to_ids = tx.open_multimap_table(dict_pk_to_ids_def)?;
+            for (dict_pk, id) in &stale_ids {
+                dict_pk_to_ids.remove(K::from_bytes(dict_pk), K::from_bytes(id))?;
+            }
+            for (dict_pk, id) in &missing_ids {
+                dict_pk_to_ids.insert(K::from_bytes(dict_pk), K::from_bytes(id))?;
+            }
+            let mut value_by_dict_pk = tx.open_table(value_by_dict_pk_def)?;
+            for (dict_pk, value) in &orphans {
+                value_by_dict_pk.remove(K::from_bytes(dict_pk))?;
+                let owned = value_to_dict_pk.get(V::Key::from_bytes(value))?.is_some_and(|back| bytes::<K>(&back.value()) == *dict_pk);
+                if owned {
+                    value_to_dict_pk.remove(V::Key::from_bytes(value))?;
+                }
+            }
+        }
+        tx.commit()?;
+        true
+    };
+    Ok((rows, collect(broken, fixable, repaired)))
+}
+
+/// Checks every shard of the given columns, see `fsck` for the entity level checks.
+pub fn fsck_columns(storage: &Arc<Storage>, columns: Vec<Arc<dyn ColumnTables>>, repair: bool) -> Result<FsckReport, AppError> {
+    let mut report = FsckReport::default();
+    for column in columns {
+        let name = column.name();
+        let shards = storage.fetch_dbs(&name)?;
+        let mut repaired = false;
+        for (idx, db_weak) in shards.iter().enumerate() {
+            let db = db_weak.upgrade().ok_or_else(|| AppError::Custom(format!("column `{}`: database closed", name)))?;
+            let (entries, issues) = column.fsck_tables(&db, repair)?;
+            repaired |= issues.iter().any(|i| i.repaired);
+            report.entries += entries;
+            report.issues.extend(issues.into_iter().map(|i| FsckIssue { shard: (shards.len() > 1).then_some(idx), ..i }));
+        }
+        if repaired {
+            // repaired entries are written without a commit marker, a saved bloom filter would miss them
+            storage.bloom_filters.rebuild(column.as_ref(), &shards)?;
+        }
+        report.columns += 1;
+    }
+    Ok(report)
+}
+
+/// Verifies index and dictionary tables of all registered entities, then that every child has a parent and every
+/// pointer column resolves. With `repair` the fixable issues are fixed in place, orphaned children get deleted.
+/// Repair must not run while the indexer is writing.
+pub fn fsck(storage: &Arc<Storage>, repair: bool) -> Result<FsckReport, AppError> {
+    let mut report = FsckReport::default();
+    for info in inventory::iter::<StructInfo> {
+        let columns = fsck_columns(storage, (info.column_tables)()?, repair)?;
+        report.columns += columns.columns;
+        report.entries += columns.entries;
+        report.issues.extend(columns.issues);
+        report.issues.extend((info.fsck_refs)(storage, repair)?);
+        info!("Checked {}", info.name);
+    }
+    Ok(report)
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+    use crate::storage::init::{DbDef, StorageOwner};
+    use crate::storage::layout::DbLayout;
+    use crate::storage::table_dict::DictFactory;
+    use crate::storage::table_index::IndexFactory;
+    use crate::storage::table_writer_api::{RedbitTableDefinition, WriterLike};
+    use crate::storage::test_utils::{mk_db, tmp_dir, txh, TxHash};
+    use crate::{BytesPartitioner, Partitioning, ValuePartitioner, Xxh3Partitioner};
+    use redb::Durability;
+
+    type IndexDef = RedbitTableDefinition<u32, TxHash, BytesPartitioner, Xxh3Partitioner, IndexFactory<u32, TxHash>>;
+    type DictDef = RedbitTableDefinition<u32, TxHash, BytesPartitioner, Xxh3Partitioner, DictFactory<u32, TxHash>>;
+
+    const PK_BY_INDEX: MultimapTableDefinition<'static, TxHash, u32> = MultimapTableDefinition::new("fsck_pk_by_index");
+    const INDEX_BY_PK: TableDefinition<'static, u32, TxHash> = TableDefinition::new("fsck_index_by_pk");
+    const DICT_PK_TO_IDS: MultimapTableDefinition<'static, u32, u32> = MultimapTableDefinition::new("fsck_dict_pk_to_ids");
+    const VALUE_BY_DICT_PK: TableDefinition<'static, u32, TxHash> = TableDefinition::new("fsck_value_by_dict_pk");
+    const VALUE_TO_DICT_PK: TableDefinition<'static, TxHash, u32> = TableDefinition::new("fsck_value_to_dict_pk");
+    const DICT_PK_BY_ID: TableDefinition<'static, u32, u32> = TableDefinition::new("fsck_dict_pk_by_id");
+    const SCRIPTS: TableDefinition<'static, u32, Script> = TableDefinition::new("fsck_scripts");
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Script(Vec<u8>);
+    crate::impl_redb_newtype_vec_zstd!(Script, compress::DEFAULT_ZSTD_LEVEL, &[]);
+
+    fn index_def() -> IndexDef {
+        RedbitTableDefinition::new(false, Partitioning::by_value(2), IndexFactory::new("fsck_index", 0, false, false, PK_BY_INDEX, INDEX_BY_PK))
+    }
+
+    fn dict_def() -> DictDef {
+        let factory = DictFactory::new("fsck_dict", 0, DICT_PK_TO_IDS, VALUE_BY_DICT_PK, VALUE_TO_DICT_PK, DICT_PK_BY_ID);
+        RedbitTableDefinition::new(false, Partitioning::by_value(2), factory)
+    }
+
+    fn db_defs() -> Vec<DbDef> {
+        ["fsck_index", "fsck_dict"].iter()
+            .map(|name| DbDef { name: name.to_string(), shards: 2, db_cache_weight_or_zero: 0, lru_cache_size_or_zero: 0 })
+            .collect()
+    }
+
+    fn columns() -> Vec<Arc<dyn ColumnTables>> {
+        vec![Arc::new(index_def()), Arc::new(dict_def())]
+    }
+
+    /// Applies `f` to the shard that holds `value`.
+    fn corrupt(storage: &Arc<Storage>, name: &str, value: &TxHash, f: impl FnOnce(&redb::WriteTransaction)) {
+        let shard = ValuePartitioner::<TxHash>::partition_value(&Xxh3Partitioner::new(2), value);
+        let db = storage.fetch_dbs(name).expect("dbs")[shard].upgrade().expect("db");
+        let tx = db.begin_write().expect("write");
+        f(&tx);
+        tx.commit().expect("commit");
+    }
+
+    #[tokio::test]
+    async fn fsck_detects_and_repairs_broken_index_and_dictionary() {
+        let layout = DbLayout::new(tmp_dir("fsck"));
+        let (_, _owner, storage) = StorageOwner::init_with_layout(layout, db_defs(), 0, false).await.expect("storage");
+        let index = index_def().writer(&storage).expect("index writer");
+        let dict = dict_def().writer(&storage).expect("dict writer");
+        index.begin(Durability::Immediate).expect("begin");
+        dict.begin(Durability::Immediate).expect("begin");
+        for k in 1u32..=20 {
+            index.insert_on_flush(k, txh(&[(k % 4) as u8])).expect("insert");
+            dict.insert_now(k, txh(&[(k % 3) as u8])).expect("insert");
+        }
+        index.flush().expect("flush");
+        dict.flush().expect("flush");
+        index.shutdown().expect("shutdown");
+        dict.shutdown().expect("shutdown");
+
+        let clean = fsck_columns(&storage, columns(), false).expect("fsck");
+        assert!(clean.issues.is_empty(), "{clean}");
+        assert_eq!(clean.entries, 40);
+
+        corrupt(&storage, "fsck_index", &txh(&[1]), |tx| {
+            tx.open_multimap_table(PK_BY_INDEX).expect("table").remove(txh(&[1]), 5u32).expect("remove");
+        });
+        corrupt(&storage, "fsck_index", &txh(&[2]), |tx| {
+            tx.open_multimap_table(PK_BY_INDEX).expect("table").insert(txh(&[2]), 99u32).expect("insert");
+        });
+        corrupt(&storage, "fsck_dict", &txh(&[1]), |tx| {
+            tx.open_multimap_table(DICT_PK_TO_IDS).expect("table").remove(1u32, 4u32).expect("remove");
+            tx.open_table(VALUE_TO_DICT_PK).expect("table").remove(txh(&[1])).expect("remove");
+        });
+        corrupt(&storage, "fsck_dict", &txh(&[7]), |tx| {
+            tx.open_table(VALUE_BY_DICT_PK).expect("table").insert(77u32, txh(&[7])).expect("insert");
+            tx.open_table(VALUE_TO_DICT_PK).expect("table").insert(txh(&[7]), 77u32).expect("insert");
+        });
+
+        let broken = fsck_columns(&storage, columns(), false).expect("fsck");
+        assert_eq!(broken.issues.len(), 5, "{broken}");
+        assert!(!broken.is_clean());
+        assert!(broken.issues.iter().all(|i| i.shard.is_some()));
+
+        let repaired = fsck_columns(&storage, columns(), true).expect("repair");
+        assert_eq!(repaired.issues.len(), 5, "{repaired}");
+        assert!(repaired.is_clean(), "{repaired}");
+        let after = fsck_columns(&storage, columns(), false).expect("fsck");
+        assert!(after.issues.is_empty(), "{after}");
+    }
+
+    #[test]
+    fn fsck_reports_corrupted_compressed_values() {
+        let (db, _) = mk_db("fsck_zstd");
+        let script = |i: u32| Script(format!("OP_DUP OP_HASH160 {i:040} OP_EQUALVERIFY OP_CHECKSIG").into_bytes());
+        let tx = db.begin_write().expect("write");
+        {
+            let mut scripts = tx.open_table(SCRIPTS).expect("table");
+            for k in 1u32..=10 {
+                scripts.insert(k, script(k)).expect("insert");
+            }
+        }
+        tx.commit().expect("commit");
+        assert_eq!(check_plain(&db, "fsck_scripts", SCRIPTS).expect("fsck"), (10, Vec::new()));
+
+        let tx = db.begin_write().expect("write");
+        {
+            let mut stored = tx.open_table(TableDefinition::<u32, StoredBytes<Script>>::new("fsck_scripts")).expect("table");
+            let frame = stored.get(3u32).expect("get").expect("row").value().to_vec();
+            assert_eq!(frame[0], compress::ZSTD_TAG);
+            stored.insert(3u32, &frame[..frame.len() - 4]).expect("insert");
+            stored.insert(4u32, [9u8, 1, 2].as_slice()).expect("insert");
+        }
+        tx.commit().expect("commit");
+        let (rows, issues) = check_plain(&db, "fsck_scripts", SCRIPTS).expect("fsck");
+        assert_eq!(rows, 10);
+        assert_eq!(issues.len(), 2, "{issues:?}");
+        assert!(issues.iter().all(|i| !i.repaired));
+    }
+}
@@ -1,7 +1,14 @@
+use crate::storage::bloom::BloomFilters;
 use crate::storage::cache;
+use crate::storage::snapshot::CommitFence;
+use crate::storage::table_writer_api::ColumnTables;
+use crate::storage::tx_fsm::WriterConfig;
+use crate::storage::layout::DbLayout;
+use crate::storage::memory::MemoryBackend;
+use crate::storage::schema::SchemaManifest;
 use crate::{error, info, AppError, StructInfo};
 use futures_util::future::try_join_all;
-use redb::{Database, DatabaseError};
+use redb::{CacheStats, Database, DatabaseError, ReadOnlyDatabase, ReadTransaction, ReadableDatabase, TransactionError, WriteTransaction};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Weak};
@@ -10,7 +17,7 @@ use std::{env, fs};
 #[derive(Clone, Debug)]
 pub struct DbDef { pub name: String, pub shards: usize, pub db_cache_weight_or_zero: usize, pub lru_cache_size_or_zero: usize }
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct DbDefWithCache { pub name: String, pub shards: usize, pub db_cache_weight: usize, pub db_cache_in_mb: usize, pub lru_cache: usize }
+pub struct DbDefWithCache { pub name: String, pub shards: usize, pub db_cache_weight: usize, pub db_cache_in_mb: usize, pub lru_cache: usize, pub shard_dirs: Vec<PathBuf> }
 impl DbDefWithCache {
     pub fn new(dn_def: DbDef, db_cache_in_mb: usize) -> Self {
         DbDefWithCache {
@@ -19,8 +26,13 @@ impl DbDefWithCache {
             db_cache_weight: dn_def.db_cache_weight_or_zero,
             lru_cache: dn_def.lru_cache_size_or_zero,
             db_cache_in_mb,
+            shard_dirs: Vec::new(),
         }
     }
+    pub fn placed(dn_def: DbDef, db_cache_in_mb: usize, layout: &DbLayout) -> Self {
+        let shard_dirs = layout.shard_dirs(&dn_def.name, dn_def.shards);
+        DbDefWithCache { shard_dirs, ..Self::new(dn_def, db_cache_in_mb) }
+    }
     pub fn validate(&self) -> Result<(), AppError> {
         if self.shards == 0 {
             Err(AppError::Custom(format!(
@@ -32,15 +44,69 @@ impl DbDefWithCache {
     }
 }
 
+/// Columns of all registered entities.
+pub(crate) fn inventory_columns() -> Result<Vec<Arc<dyn ColumnTables>>, AppError> {
+    let mut columns = Vec::new();
+    for info in inventory::iter::<StructInfo> {
+        columns.extend((info.column_tables)()?);
+    }
+    Ok(columns)
+}
+
+/// Single-shard columns are stored as `name.db`, sharded ones as `name-{idx}.db`.
+pub(crate) fn shard_suffix(shards: usize, idx: usize) -> Option<usize> {
+    match shards {
+        1 => None,
+        _ => Some(idx),
+    }
+}
+
+/// Column shard db, read-only ones can be opened by any number of processes but never next to a writable one.
+pub enum ShardDb {
+    Writable(Database),
+    ReadOnly(ReadOnlyDatabase),
+}
+
+impl ShardDb {
+    pub fn begin_write(&self) -> Result<WriteTransaction, AppError> {
+        match self {
+            ShardDb::Writable(db) => Ok(db.begin_write()?),
+            ShardDb::ReadOnly(_) => Err(AppError::ReadOnly("cannot begin a write transaction".to_string())),
+        }
+    }
+}
+
+impl ReadableDatabase for ShardDb {
+    fn begin_read(&self) -> Result<ReadTransaction, TransactionError> {
+        match self {
+            ShardDb::Writable(db) => db.begin_read(),
+            ShardDb::ReadOnly(db) => db.begin_read(),
+        }
+    }
+
+    fn cache_stats(&self) -> CacheStats {
+        match self {
+            ShardDb::Writable(db) => db.cache_stats(),
+            ShardDb::ReadOnly(db) => db.cache_stats(),
+        }
+    }
+}
+
+impl From<Database> for ShardDb {
+    fn from(db: Database) -> Self {
+        ShardDb::Writable(db)
+    }
+}
+
 #[derive(Clone)]
-pub struct DbSetOwned(Vec<Arc<Database>>);
+pub struct DbSetOwned(Vec<Arc<ShardDb>>);
 
 #[derive(Clone)]
-pub struct DbSetWeak(Vec<Weak<Database>>);
+pub struct DbSetWeak(Vec<Weak<ShardDb>>);
 
 impl DbSetOwned {
-    pub fn new(name_index_dbs: Vec<(String, usize, Arc<Database>)>) -> HashMap<String, DbSetOwned> {
-        let mut shards:  HashMap<String, Vec<(usize, Arc<Database>)>> = HashMap::new();
+    pub fn new(name_index_dbs: Vec<(String, usize, Arc<ShardDb>)>) -> HashMap<String, DbSetOwned> {
+        let mut shards:  HashMap<String, Vec<(usize, Arc<ShardDb>)>> = HashMap::new();
         for (name, idx, db) in name_index_dbs {
             shards.entry(name).or_default().push((idx, db));
         }
@@ -56,6 +122,10 @@ impl DbSetOwned {
         DbSetWeak(self.0.iter().map(Arc::downgrade).collect())
     }
 
+    pub fn shards(&self) -> &[Arc<ShardDb>] {
+        &self.0
+    }
+
     pub fn assert_last_ref(&self, name: &str) {
         let sc: usize = self.0.iter().map(|db|Arc::strong_count(db)).sum();
         if sc != self.0.len() {
@@ -67,25 +137,85 @@ impl DbSetOwned {
 #[derive(Clone)]
 pub struct Storage {
     pub index_dbs: HashMap<String, DbSetWeak>,
+    pub commit_fence: Arc<CommitFence>,
+    pub bloom_filters: Arc<BloomFilters>,
+    /// Queue budget and spilling of column writers.
+    pub writer_config: WriterConfig,
+    pub read_only: bool,
 }
 
 impl Storage {
     /// Fetch **all shards** (clone the Vec<Weak<_>>). Optionally enforce an expected shard count.
-    pub fn fetch_dbs(&self, name: &str) -> Result<Vec<Weak<Database>>, AppError> {
+    pub fn fetch_dbs(&self, name: &str) -> Result<Vec<Weak<ShardDb>>, AppError> {
         match self.index_dbs.get(name) {
             Some(DbSetWeak(v)) => Ok(v.clone()),
             None => Err(AppError::Custom(format!("column `{}`: not found", name))),
         }
     }
+
+    pub fn check_writable(&self, name: &str) -> Result<(), AppError> {
+        if self.read_only {
+            Err(AppError::ReadOnly(format!("column `{}`: storage is opened read-only", name)))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 pub struct StorageOwner {
     pub index_dbs: HashMap<String, DbSetOwned>,
+    pub commit_fence: Arc<CommitFence>,
+    pub bloom_filters: Arc<BloomFilters>,
+    /// Queue budget and spilling of column writers.
+    pub writer_config: WriterConfig,
+    pub read_only: bool,
 }
 
 impl StorageOwner {
-    pub fn new(index_dbs: HashMap<String, DbSetOwned>) -> Self {
-        Self { index_dbs }
+    pub fn new(layout: &DbLayout, index_dbs: HashMap<String, DbSetOwned>) -> Self {
+        Self {
+            index_dbs,
+            commit_fence: Arc::new(CommitFence::default()),
+            bloom_filters: Arc::new(BloomFilters::new(layout.clone(), false)),
+            writer_config: WriterConfig::default(),
+            read_only: false,
+        }
+    }
+
+    pub fn new_read_only(layout: &DbLayout, index_dbs: HashMap<String, DbSetOwned>) -> Self {
+        Self {
+            index_dbs,
+            commit_fence: Arc::new(CommitFence::default()),
+            bloom_filters: Arc::new(BloomFilters::new(layout.clone(), true)),
+            writer_config: WriterConfig::default(),
+            read_only: true,
+        }
+    }
+
+    pub fn new_in_memory(index_dbs: HashMap<String, DbSetOwned>) -> Self {
+        Self {
+            index_dbs,
+            commit_fence: Arc::new(CommitFence::default()),
+            bloom_filters: Arc::new(BloomFilters::in_memory()),
+            writer_config: WriterConfig::default(),
+            read_only: false,
+        }
+    }
+
+    pub fn with_writer_config(mut self, config: WriterConfig) -> Self {
+        self.writer_config = config;
+        self
+    }
+
+    /// Loads or builds the bloom filters of the given columns, see `BloomFilters::load`.
+    pub fn load_bloom_filters(&self, columns: &[Arc<dyn ColumnTables>]) -> Result<(), AppError> {
+        self.bloom_filters.load(columns, &self.index_dbs)
+    }
+
+    /// Every commit is already durable in redb, only the bloom filters live in memory and get saved here.
+    /// Dropping the owner without closing it just makes the next open rebuild them from the index.
+    pub fn close(self) -> Result<(), AppError> {
+        self.bloom_filters.save(&self.index_dbs)
     }
 
     pub fn assert_last_refs(&self) {
@@ -99,15 +229,66 @@ impl StorageOwner {
         for (k, v) in &self.index_dbs {
             m.insert(k.clone(), v.downgrade());
         }
-        Arc::new(Storage { index_dbs: m })
+        Arc::new(Storage {
+            index_dbs: m,
+            commit_fence: Arc::clone(&self.commit_fence),
+            bloom_filters: Arc::clone(&self.bloom_filters),
+            writer_config: self.writer_config.clone(),
+            read_only: self.read_only,
+        })
     }
 
-    pub async fn build_storage(db_dir: PathBuf, db_cache_size_gb: u8) -> redb::Result<(bool, StorageOwner, Arc<Storage>), AppError> {
+    pub async fn build_storage(layout: DbLayout, db_cache_size_gb: u8) -> redb::Result<(bool, StorageOwner, Arc<Storage>), AppError> {
         let mut db_defs: Vec<DbDef> = Vec::new();
         for info in inventory::iter::<StructInfo> {
             db_defs.extend((info.db_defs)())
         }
-        Self::init(db_dir, db_defs, db_cache_size_gb, true).await
+        let columns = inventory_columns()?;
+        let existed = layout.root.exists();
+        if existed {
+            Self::check_schema(&layout.root)?;
+            Self::reshard_if_needed(&layout, &db_defs, columns.clone()).await?;
+        }
+        let result = Self::init_with_layout(layout.clone(), db_defs, db_cache_size_gb, true).await?;
+        if !existed {
+            SchemaManifest::from_inventory()?.write(&layout.root)?;
+        }
+        result.1.load_bloom_filters(&columns)?;
+        Ok(result)
+    }
+
+    /// Opens existing storage for processes that only read, like analytics jobs or another REST server. Nothing is
+    /// created, migrated, resharded or rolled back, so the storage must be in the state the indexer left it in.
+    /// Read-only processes can share the storage with each other but never with a running indexer: redb locks the
+    /// file of a writable db exclusively and has no mode for reading it from another process, so opening fails with
+    /// `AppError::ReadOnly` while the indexer runs. Point them to a snapshot directory to run next to it.
+    pub async fn open_read_only(layout: DbLayout, db_cache_size_gb: u8) -> redb::Result<(StorageOwner, Arc<Storage>), AppError> {
+        let mut db_defs: Vec<DbDef> = Vec::new();
+        for info in inventory::iter::<StructInfo> {
+            db_defs.extend((info.db_defs)())
+        }
+        if !layout.root.exists() {
+            return Err(AppError::NotFound(format!("no storage at {:?} to open read-only", layout.root)));
+        }
+        Self::check_schema_read_only(&layout.root)?;
+        let (owner, view) = Self::open_read_only_with_layout(layout, db_defs, db_cache_size_gb, true).await?;
+        owner.load_bloom_filters(&inventory_columns()?)?;
+        Ok((owner, view))
+    }
+
+    pub async fn open_read_only_with_layout(layout: DbLayout, db_defs: Vec<DbDef>, total_cache_size_gb: u8, log_info: bool) -> redb::Result<(StorageOwner, Arc<Storage>), AppError> {
+        layout.validate(&db_defs)?;
+        Self::validate_layout(&layout, &db_defs)?;
+        let defs_with_cache: Vec<DbDefWithCache> = cache::allocate_cache_mb(&db_defs, (total_cache_size_gb as u64) * 1024, &layout);
+        info!("Opening dbs at {:?} read-only with total cache size {} GB", layout.root, total_cache_size_gb);
+        let index_dbs = Self::build_owned_map_open(&layout, &defs_with_cache, true).await?;
+        let owner = StorageOwner::new_read_only(&layout, index_dbs);
+        owner.recover_commits()?;
+        if log_info {
+            info!("DB report:\n{}", Self::log_name_with_cache_table(&layout, &defs_with_cache).join("\n"));
+        }
+        let view = owner.view();
+        Ok((owner, view))
     }
 
     pub async fn temp(name: &str, db_cache_size_gb: u8, random: bool) -> redb::Result<(StorageOwner, Arc<Storage>), AppError> {
@@ -121,57 +302,115 @@ impl StorageOwner {
             db_defs.extend((info.db_defs)())
         }
         let (_, owner, view) = StorageOwner::init(db_path, db_defs, db_cache_size_gb, false).await?;
+        owner.load_bloom_filters(&inventory_columns()?)?;
+        Ok((owner, view))
+    }
+
+    /// Storage of all registered entities built on redb's in-memory backend, nothing touches the disk and the data
+    /// is gone with the owner. Meant for tests and ephemeral indexes like mempool data. Fresh dbs have nothing to
+    /// reshard or roll back, but the entities are still checked against the dbs, see `check_schema_defs`.
+    pub fn in_memory(db_cache_size_gb: u8) -> redb::Result<(StorageOwner, Arc<Storage>), AppError> {
+        let mut db_defs = Vec::new();
+        for info in inventory::iter::<StructInfo> {
+            db_defs.extend((info.db_defs)())
+        }
+        Self::check_schema_defs(&SchemaManifest::from_inventory()?, &db_defs)?;
+        let (owner, view) = Self::in_memory_with_defs(db_defs, db_cache_size_gb)?;
+        owner.load_bloom_filters(&inventory_columns()?)?;
+        Ok((owner, view))
+    }
+
+    pub fn in_memory_with_defs(db_defs: Vec<DbDef>, total_cache_size_gb: u8) -> redb::Result<(StorageOwner, Arc<Storage>), AppError> {
+        let defs_with_cache: Vec<DbDefWithCache> = cache::allocate_cache_mb(&db_defs, (total_cache_size_gb as u64) * 1024, &DbLayout::default());
+        let index_dbs = Self::build_owned_map_with(&defs_with_cache, |dbc, _| {
+            Database::builder().set_cache_size(dbc.db_cache_in_mb).create_with_backend(MemoryBackend::new())
+        })?;
+        let owner = StorageOwner::new_in_memory(index_dbs);
+        let view = owner.view();
         Ok((owner, view))
     }
 
-    fn log_name_with_cache_table(db_defs: &[DbDefWithCache]) -> Vec<String> {
+    fn log_name_with_cache_table(layout: &DbLayout, db_defs: &[DbDefWithCache]) -> Vec<String> {
         let name_width = db_defs.iter().map(|d| d.name.len()).max().unwrap_or(4); // at least "name"
         let mut lines = Vec::new();
-        lines.push(format!("{:<name_width$}  {:>10}   {:>10}   {:>10}   {:>10}", "DB NAME", "weight", "size", "lru", "per shards", name_width = name_width));
+        lines.push(format!("{:<name_width$}  {:>10}   {:>10}   {:>10}   {:>10}   {}", "DB NAME", "weight", "size", "lru", "per shards", "location", name_width = name_width));
         lines.extend(db_defs.iter().map(|d| {
             format!(
-                "{:<name_width$}  {:>10}   {:>10}   {:>10}   {:>10}",
-                d.name, d.db_cache_weight, d.db_cache_in_mb, d.lru_cache, d.shards, name_width = name_width,
+                "{:<name_width$}  {:>10}   {:>10}   {:>10}   {:>10}   {}",
+                d.name, d.db_cache_weight, d.db_cache_in_mb, d.lru_cache, d.shards, Self::location(layout, d), name_width = name_width,
             )
         }));
+        if !layout.mounts.is_empty() {
+            let mut per_dir: Vec<(PathBuf, usize, usize)> = Vec::new();
+            for d in db_defs {
+                for dir in &d.shard_dirs {
+                    match per_dir.iter_mut().find(|(p, _, _)| p == dir) {
+                        Some((_, shards, mb)) => { *shards += 1; *mb += d.db_cache_in_mb; }
+                        None => per_dir.push((dir.clone(), 1, d.db_cache_in_mb)),
+                    }
+                }
+            }
+            lines.push(format!("{:>10}   {:>10}   {}", "shards", "cache", "mount"));
+            lines.extend(per_dir.iter().map(|(dir, shards, mb)| format!("{:>10}   {:>10}   {}", shards, mb, dir.display())));
+        }
         lines
     }
 
-    fn build_owned_map_create(db_dir: &Path, defs: &[DbDefWithCache]) -> redb::Result<HashMap<String, DbSetOwned>, AppError> {
+    /// `-` when every shard is in the root dir, otherwise the mounted dir of each shard.
+    fn location(layout: &DbLayout, d: &DbDefWithCache) -> String {
+        if d.shard_dirs.iter().all(|dir| dir == &layout.root) {
+            "-".to_string()
+        } else {
+            d.shard_dirs.iter().map(|dir| dir.display().to_string()).collect::<Vec<_>>().join(", ")
+        }
+    }
+
+    fn build_owned_map_create(layout: &DbLayout, defs: &[DbDefWithCache]) -> redb::Result<HashMap<String, DbSetOwned>, AppError> {
+        Self::build_owned_map_with(defs, |dbc, shard_idx| {
+            Database::builder()
+                .set_cache_size(dbc.db_cache_in_mb)
+                .create(layout.file_path(&dbc.name, shard_suffix(dbc.shards, shard_idx)))
+        })
+    }
+
+    fn build_owned_map_with(
+        defs: &[DbDefWithCache],
+        create: impl Fn(&DbDefWithCache, usize) -> redb::Result<Database, DatabaseError>,
+    ) -> redb::Result<HashMap<String, DbSetOwned>, AppError> {
         let mut out = HashMap::with_capacity(defs.len());
         for dbc in defs {
             dbc.validate()?;
             let mut v = Vec::with_capacity(dbc.shards);
             for shard_idx in 0..dbc.shards {
-                let suffix = match dbc.shards {
-                    1 => None,
-                    _ => Some(shard_idx),
-                };
-                let db = Database::builder()
-                    .set_cache_size(dbc.db_cache_in_mb)
-                    .create(Self::db_file_path(db_dir, &dbc.name, suffix))?;
-                v.push(Arc::new(db));
+                v.push(Arc::new(ShardDb::Writable(create(dbc, shard_idx)?)));
             }
             out.insert(dbc.name.clone(), DbSetOwned(v));
         }
         Ok(out)
     }
 
-    async fn build_owned_map_open(db_dir: &Path, defs: &[DbDefWithCache]) -> redb::Result<HashMap<String, DbSetOwned>, AppError> {
+    async fn build_owned_map_open(layout: &DbLayout, defs: &[DbDefWithCache], read_only: bool) -> redb::Result<HashMap<String, DbSetOwned>, AppError> {
         for dbc in defs {
             dbc.validate()?;
         }
         let db_opening_tasks = defs.into_iter().flat_map(|dbc| {
             (0..dbc.shards).map(move |idx| {
                 let name = dbc.name.clone();
-                let suffix = match dbc.shards {
-                    1 => None,
-                    _ => Some(idx),
-                };
-                let path = Self::db_file_path(db_dir, &name, suffix);
+                let path = layout.file_path(&name, shard_suffix(dbc.shards, idx));
+                let cache_in_mb = dbc.db_cache_in_mb;
                 tokio::task::spawn_blocking(move ||
-                    -> redb::Result<(String, usize, Arc<Database>), DatabaseError> {
-                        let db = Database::open(path)?;
+                    -> redb::Result<(String, usize, Arc<ShardDb>), AppError> {
+                        let db = if read_only {
+                            match Database::builder().set_cache_size(cache_in_mb).open_read_only(&path) {
+                                Ok(db) => ShardDb::ReadOnly(db),
+                                Err(DatabaseError::DatabaseAlreadyOpen) => return Err(AppError::ReadOnly(format!(
+                                    "{:?} is opened by a writer, redb locks writable dbs exclusively so no other process can read them, open a snapshot instead", path
+                                ))),
+                                Err(e) => return Err(e.into()),
+                            }
+                        } else {
+                            ShardDb::Writable(Database::open(path)?)
+                        };
                         Ok((name, idx, Arc::new(db)))
                     }
                 )
@@ -181,12 +420,12 @@ impl StorageOwner {
         let opened = try_join_all(db_opening_tasks)
             .await?
             .into_iter()
-            .collect::<redb::Result<Vec<(String, usize, Arc<Database>)>, DatabaseError>>()?;
+            .collect::<redb::Result<Vec<(String, usize, Arc<ShardDb>)>, AppError>>()?;
 
         Ok(DbSetOwned::new(opened))
     }
 
-    fn db_file_path(dir: &Path, name: &str, shard_idx: Option<usize>) -> PathBuf {
+    pub(crate) fn db_file_path(dir: &Path, name: &str, shard_idx: Option<usize>) -> PathBuf {
         match shard_idx {
             Some(i) => dir.join(format!("{}-{}.db", name, i)),
             None    => dir.join(format!("{}.db",    name)),
@@ -194,14 +433,20 @@ impl StorageOwner {
     }
 
     pub async fn init(db_dir: PathBuf, db_defs: Vec<DbDef>, total_cache_size_gb: u8, log_info: bool) -> redb::Result<(bool, StorageOwner, Arc<Storage>), AppError> {
-        let defs_with_cache: Vec<DbDefWithCache> = cache::allocate_cache_mb(&db_defs, (total_cache_size_gb as u64) * 1024);
+        Self::init_with_layout(DbLayout::new(db_dir), db_defs, total_cache_size_gb, log_info).await
+    }
+
+    pub async fn init_with_layout(layout: DbLayout, db_defs: Vec<DbDef>, total_cache_size_gb: u8, log_info: bool) -> redb::Result<(bool, StorageOwner, Arc<Storage>), AppError> {
+        layout.validate(&db_defs)?;
+        let defs_with_cache: Vec<DbDefWithCache> = cache::allocate_cache_mb(&db_defs, (total_cache_size_gb as u64) * 1024, &layout);
+        let db_dir = &layout.root;
 
         let result =
             if !db_dir.exists() {
-                fs::create_dir_all(&db_dir)?;
+                layout.create_dirs(&db_defs)?;
                 info!("Creating dbs at {:?} with total cache size {} GB", db_dir, total_cache_size_gb);
-                let index_dbs = Self::build_owned_map_create(&db_dir, &defs_with_cache)?;
-                let owner = StorageOwner::new(index_dbs);
+                let index_dbs = Self::build_owned_map_create(&layout, &defs_with_cache)?;
+                let owner = StorageOwner::new(&layout, index_dbs);
                 let view = owner.view();
                 Ok((true, owner, view))
             } else {
@@ -209,13 +454,15 @@ impl StorageOwner {
                     "Opening existing dbs at {:?} with total cache size {} GB, it might take a while in case previous process was killed",
                     db_dir, total_cache_size_gb
                 );
-                let index_dbs = Self::build_owned_map_open(&db_dir, &defs_with_cache).await?;
-                let owner = StorageOwner::new(index_dbs);
+                Self::validate_layout(&layout, &db_defs)?;
+                let index_dbs = Self::build_owned_map_open(&layout, &defs_with_cache, false).await?;
+                let owner = StorageOwner::new(&layout, index_dbs);
+                owner.recover_commits()?;
                 let view = owner.view();
                 Ok((false, owner, view))
             };
         if log_info {
-            info!("DB report:\n{}", Self::log_name_with_cache_table(&defs_with_cache).join("\n"));
+            info!("DB report:\n{}", Self::log_name_with_cache_table(&layout, &defs_with_cache).join("\n"));
         }
         result
     }
@@ -262,6 +509,36 @@ mod tests {
         assert_eq!(alive_after, 0, "all dbs must be dropped when owner is dropped");
     }
 
+    #[test]
+    fn in_memory_storage_roundtrips_without_files() {
+        use crate::storage::table_plain::PlainFactory;
+        use crate::storage::table_writer_api::{ReadTableLike, RedbitTableDefinition, ShardedTableReader, WriterLike};
+        use crate::storage::test_utils::{addr, Address};
+        use crate::{BytesPartitioner, DbDef, Partitioning, Xxh3Partitioner};
+        use redb::{Durability, TableDefinition};
+
+        let def: RedbitTableDefinition<u32, Address, BytesPartitioner, Xxh3Partitioner, PlainFactory<u32, Address>> =
+            RedbitTableDefinition::new(true, Partitioning::by_key(2), PlainFactory::new("mem_plain", TableDefinition::new("mem_plain")));
+        let db_def = DbDef { name: "mem_plain".to_string(), shards: 2, db_cache_weight_or_zero: 0, lru_cache_size_or_zero: 0 };
+        let (owner, storage) = StorageOwner::in_memory_with_defs(vec![db_def], 0).expect("in-memory storage");
+
+        let writer = def.writer(&storage).expect("writer");
+        writer.begin(Durability::Immediate).expect("begin");
+        for k in 1u32..=10 {
+            writer.insert_on_flush(k, addr(&[k as u8])).expect("insert");
+        }
+        writer.flush().expect("flush");
+        writer.shutdown().expect("shutdown");
+
+        let ShardedTableReader::Plain(reader) = def.reader(&storage).expect("reader") else { panic!("plain reader expected") };
+        for k in 1u32..=10 {
+            assert_eq!(reader.get_value(&k).expect("get").expect("some").value().0, vec![k as u8]);
+        }
+        drop(reader);
+        drop(owner);
+        assert_eq!(count_weak_upgrades(&storage).1, 0, "data is gone with the owner");
+    }
+
     #[tokio::test]
     async fn test_storage_groups_have_consistent_shape() {
         let (_owner, storage) = StorageOwner::temp("shape_test", 1, true)
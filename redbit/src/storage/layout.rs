@@ -0,0 +1,193 @@
+use crate::storage::init::{shard_suffix, DbDef};
+use crate::AppError;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Places the shard files of a column (or a single shard of it, or the same shard of every column) into `dir`.
+/// The most specific matching mount wins: column + shard, then column, then shard. A shard-only mount applies to
+/// sharded columns only, an unsharded column has no shard 0 to move.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct MountPoint {
+    #[serde(default)]
+    pub column: Option<String>,
+    #[serde(default)]
+    pub shard: Option<usize>,
+    pub dir: PathBuf,
+}
+
+impl MountPoint {
+    fn specificity(&self, name: &str, shard_idx: Option<usize>) -> Option<u8> {
+        match (&self.column, self.shard) {
+            (Some(c), Some(s)) if c == name && s == shard_idx.unwrap_or(0) => Some(3),
+            (Some(c), None) if c == name => Some(2),
+            (None, Some(s)) if shard_idx == Some(s) => Some(1),
+            _ => None,
+        }
+    }
+}
+
+/// Where the db files live. Everything that is not mounted elsewhere, plus schema/reshard bookkeeping, stays in `root`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DbLayout {
+    pub root: PathBuf,
+    pub mounts: Vec<MountPoint>,
+}
+
+impl DbLayout {
+    pub fn new(root: PathBuf) -> Self {
+        DbLayout { root, mounts: Vec::new() }
+    }
+
+    pub fn with_mounts(root: PathBuf, mounts: Vec<MountPoint>) -> Self {
+        DbLayout { root, mounts }
+    }
+
+    /// `shard_idx` is None for unsharded columns, see `shard_suffix`.
+    pub fn shard_dir(&self, name: &str, shard_idx: Option<usize>) -> &Path {
+        self.mounts.iter()
+            .filter_map(|m| m.specificity(name, shard_idx).map(|s| (s, m)))
+            .max_by_key(|(s, _)| *s)
+            .map(|(_, m)| m.dir.as_path())
+            .unwrap_or(&self.root)
+    }
+
+    /// Same naming as `StorageOwner::db_file_path`, just in the mounted dir of the shard.
+    pub fn file_path(&self, name: &str, shard_idx: Option<usize>) -> PathBuf {
+        let dir = self.shard_dir(name, shard_idx);
+        match shard_idx {
+            Some(i) => dir.join(format!("{}-{}.db", name, i)),
+            None    => dir.join(format!("{}.db",    name)),
+        }
+    }
+
+    /// Dirs of all shards of a column, in shard order.
+    pub fn shard_dirs(&self, name: &str, shards: usize) -> Vec<PathBuf> {
+        (0..shards).map(|idx| self.shard_dir(name, shard_suffix(shards, idx)).to_path_buf()).collect()
+    }
+
+    /// Rejects mounts that match no column or shard, they are almost always a typo in the config.
+    pub fn validate(&self, db_defs: &[DbDef]) -> Result<(), AppError> {
+        let max_shards = db_defs.iter().map(|d| d.shards).max().unwrap_or(0);
+        let mut problems = Vec::new();
+        for m in &self.mounts {
+            if !m.dir.is_absolute() {
+                problems.push(format!("mount {:?} must be an absolute path", m.dir));
+            }
+            match (&m.column, m.shard) {
+                (None, None) => problems.push(format!("mount {:?} needs a column, a shard or both", m.dir)),
+                (Some(c), shard) => match db_defs.iter().find(|d| &d.name == c) {
+                    None => problems.push(format!("mount {:?}: unknown column `{}`", m.dir, c)),
+                    Some(d) if shard.is_some_and(|s| s >= d.shards) =>
+                        problems.push(format!("mount {:?}: column `{}` has only {} shards", m.dir, c, d.shards)),
+                    Some(_) => {}
+                },
+                (None, Some(s)) if s >= max_shards =>
+                    problems.push(format!("mount {:?}: no column has shard {}", m.dir, s)),
+                (None, Some(_)) => {}
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Custom(format!("invalid mount points:\n{}", problems.join("\n"))))
+        }
+    }
+
+    pub(crate) fn create_dirs(&self, db_defs: &[DbDef]) -> Result<(), AppError> {
+        fs::create_dir_all(&self.root)?;
+        for def in db_defs {
+            for idx in 0..def.shards {
+                fs::create_dir_all(self.shard_dir(&def.name, shard_suffix(def.shards, idx)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Files in `root` (or another mount) that the current mounts place elsewhere, they would be silently ignored otherwise.
+    pub(crate) fn misplaced_files(&self, db_defs: &[DbDef]) -> Vec<(PathBuf, PathBuf)> {
+        let mut dirs: Vec<&Path> = self.mounts.iter().map(|m| m.dir.as_path()).collect();
+        dirs.push(&self.root);
+        let mut out = Vec::new();
+        for def in db_defs {
+            for idx in 0..def.shards {
+                let expected = self.file_path(&def.name, shard_suffix(def.shards, idx));
+                if expected.exists() {
+                    continue;
+                }
+                let file_name = expected.file_name().expect("db file name");
+                if let Some(found) = dirs.iter().map(|d| d.join(file_name)).find(|p| p.exists()) {
+                    out.push((found, expected));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// `fs::rename` that also works across devices, the usual case when shards live on separate drives.
+pub(crate) fn move_file(from: &Path, to: &Path) -> Result<(), AppError> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+
+    fn mount(column: Option<&str>, shard: Option<usize>, dir: &str) -> MountPoint {
+        MountPoint { column: column.map(str::to_string), shard, dir: PathBuf::from(dir) }
+    }
+
+    fn def(name: &str, shards: usize) -> DbDef {
+        DbDef { name: name.to_string(), shards, db_cache_weight_or_zero: 1, lru_cache_size_or_zero: 0 }
+    }
+
+    #[test]
+    fn most_specific_mount_wins() {
+        let layout = DbLayout::with_mounts(PathBuf::from("/db"), vec![
+            mount(None, Some(1), "/nvme1"),
+            mount(Some("transaction_hash_index"), None, "/nvme2"),
+            mount(Some("transaction_hash_index"), Some(0), "/nvme0"),
+        ]);
+        assert_eq!(layout.file_path("transaction_hash_index", Some(0)), PathBuf::from("/nvme0/transaction_hash_index-0.db"));
+        assert_eq!(layout.file_path("transaction_hash_index", Some(1)), PathBuf::from("/nvme2/transaction_hash_index-1.db"));
+        assert_eq!(layout.file_path("utxo_amount", Some(1)), PathBuf::from("/nvme1/utxo_amount-1.db"));
+        assert_eq!(layout.file_path("utxo_amount", Some(0)), PathBuf::from("/db/utxo_amount-0.db"));
+        assert_eq!(layout.file_path("block_header", None), PathBuf::from("/db/block_header.db"));
+    }
+
+    #[test]
+    fn validate_rejects_mounts_matching_nothing() {
+        let defs = vec![def("utxo_amount", 2), def("block_header", 1)];
+        let ok = DbLayout::with_mounts(PathBuf::from("/db"), vec![mount(Some("utxo_amount"), Some(1), "/nvme1"), mount(None, Some(0), "/nvme0")]);
+        ok.validate(&defs).expect("valid");
+
+        let bad = DbLayout::with_mounts(PathBuf::from("/db"), vec![
+            mount(Some("utxo_amount"), Some(2), "/nvme1"),
+            mount(Some("utxo_amout"), None, "/nvme1"),
+            mount(None, None, "/nvme2"),
+            mount(None, Some(0), "relative"),
+        ]);
+        let err = bad.validate(&defs).expect_err("invalid").to_string();
+        assert!(err.contains("has only 2 shards"), "{err}");
+        assert!(err.contains("unknown column `utxo_amout`"), "{err}");
+        assert!(err.contains("needs a column"), "{err}");
+        assert!(err.contains("absolute path"), "{err}");
+    }
+
+    #[test]
+    fn shard_mounts_leave_unsharded_columns_in_root() {
+        let layout = DbLayout::with_mounts(PathBuf::from("/db"), vec![
+            mount(None, Some(0), "/nvme0"),
+            mount(Some("block_hash_index"), Some(0), "/nvme1"),
+        ]);
+        assert_eq!(layout.file_path("utxo_amount", Some(0)), PathBuf::from("/nvme0/utxo_amount-0.db"));
+        assert_eq!(layout.file_path("block_header", None), PathBuf::from("/db/block_header.db"));
+        assert_eq!(layout.shard_dirs("block_header", 1), vec![PathBuf::from("/db")]);
+        assert_eq!(layout.file_path("block_hash_index", None), PathBuf::from("/nvme1/block_hash_index.db"));
+    }
+}
@@ -0,0 +1,72 @@
+use redb::StorageBackend;
+use std::io;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Heap backed storage of in-memory dbs. Same as redb's `InMemoryBackend` except that it grows its buffer at once,
+/// which keeps creating dozens of dbs per test module cheap in debug builds, see the `memory_backend` bench of demo.
+#[derive(Debug, Default)]
+pub struct MemoryBackend(RwLock<Vec<u8>>);
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn out_of_range() -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, "offset out of range of in-memory db")
+    }
+
+    fn read_buf(&self) -> RwLockReadGuard<'_, Vec<u8>> {
+        self.0.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write_buf(&self) -> RwLockWriteGuard<'_, Vec<u8>> {
+        self.0.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn range(offset: u64, len: usize, buf_len: usize) -> Result<std::ops::Range<usize>, io::Error> {
+        let start = usize::try_from(offset).map_err(|_| Self::out_of_range())?;
+        match start.checked_add(len) {
+            Some(end) if end <= buf_len => Ok(start..end),
+            _ => Err(Self::out_of_range()),
+        }
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn len(&self) -> Result<u64, io::Error> {
+        Ok(self.read_buf().len() as u64)
+    }
+
+    fn read(&self, offset: u64, out: &mut [u8]) -> Result<(), io::Error> {
+        let buf = self.read_buf();
+        let range = Self::range(offset, out.len(), buf.len())?;
+        out.copy_from_slice(&buf[range]);
+        Ok(())
+    }
+
+    fn set_len(&self, len: u64) -> Result<(), io::Error> {
+        let len = usize::try_from(len).map_err(|_| Self::out_of_range())?;
+        let mut buf = self.write_buf();
+        if len > buf.len() {
+            // zeroed allocation is served by the allocator, unlike filling the grown part
+            let mut grown = vec![0u8; len];
+            grown[..buf.len()].copy_from_slice(&buf);
+            *buf = grown;
+        } else {
+            buf.truncate(len);
+        }
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> Result<(), io::Error> {
+        let mut buf = self.write_buf();
+        let range = Self::range(offset, data.len(), buf.len())?;
+        buf[range].copy_from_slice(data);
+        Ok(())
+    }
+}
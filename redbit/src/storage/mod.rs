@@ -15,15 +15,27 @@ pub mod table_writer_api;
 pub mod table_dict;
 pub mod table_index;
 pub mod table_plain;
+pub mod snapshot;
+pub mod reshard;
+pub mod schema;
+pub mod layout;
+pub mod fsck;
+pub mod commit;
+pub mod bloom;
+pub mod backpressure;
+pub mod spill;
+pub mod memory;
 mod router;
 mod sort_buffer;
 
 #[cfg(all(test, not(feature = "integration")))]
 pub mod test_utils {
-    use crate::{impl_copy_owned_value_identity, DbKey, CacheKey};
+    use crate::{impl_copy_owned_value_identity, DbKey, CacheKey, ShardDb};
     use redb::{Database, Key, TypeName, Value};
     use std::cmp::Ordering;
+    use std::path::PathBuf;
     use std::sync::{Arc, Weak};
+    use std::{env, fs};
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct TxHash(pub [u8; 32]);
@@ -58,6 +70,7 @@ pub mod test_utils {
     impl_copy_owned_value_identity!(TxHash);
     impl CacheKey for TxHash {
         type CK = [u8; 32];
+        type Key = TxHash;
         #[inline]
         fn cache_key<'a>(v: &<TxHash as Value>::SelfType<'a>) -> Self::CK
         where
@@ -103,6 +116,7 @@ pub mod test_utils {
     }
     impl CacheKey for Address {
         type CK = Vec<u8>;
+        type Key = Address;
 
         #[inline]
         fn cache_key<'a>(v: &<Address as Value>::SelfType<'a>) -> Self::CK
@@ -122,21 +136,32 @@ pub mod test_utils {
         TxHash(b)
     }
 
-    pub(crate) fn mk_db(prefix: &str) -> (Arc<Database>, Weak<Database>) {
+    /// Unique path under `<tmp>/redbit`, not created so that storage opened there starts out fresh.
+    pub(crate) fn tmp_dir(prefix: &str) -> PathBuf {
+        env::temp_dir().join("redbit").join(format!("{}_{}", prefix, rand::random::<u64>()))
+    }
+
+    pub(crate) fn mk_tmp_dir(prefix: &str) -> PathBuf {
+        let dir = tmp_dir(prefix);
+        fs::create_dir_all(&dir).expect("create tmp dir");
+        dir
+    }
+
+    pub(crate) fn mk_db(prefix: &str) -> (Arc<ShardDb>, Weak<ShardDb>) {
         let path = std::env::temp_dir().join(format!("{}_{}", prefix, rand::random::<u64>()));
         let db = Database::builder().create(path).expect("create db");
-        let owned = Arc::new(db);
+        let owned = Arc::new(ShardDb::from(db));
         let weak = Arc::downgrade(&owned);
         (owned, weak)
     }
 
-    pub(crate) fn mk_shard_dbs(n: usize, prefix: &str) -> (Vec<Arc<Database>>, Vec<Weak<Database>>) {
+    pub(crate) fn mk_shard_dbs(n: usize, prefix: &str) -> (Vec<Arc<ShardDb>>, Vec<Weak<ShardDb>>) {
         assert!(n >= 2);
         let mut owned = Vec::with_capacity(n);
         for i in 0..n {
             let path = std::env::temp_dir().join(format!("{}_{}_{}", prefix, i, rand::random::<u64>()));
             let db = Database::builder().create(path).expect("create db");
-            owned.push(Arc::new(db));
+            owned.push(Arc::new(ShardDb::from(db)));
         }
         let weak = owned.iter().map(Arc::downgrade).collect::<Vec<_>>();
         (owned, weak)
@@ -150,7 +175,10 @@ pub mod plain_test_utils {
     use crate::storage::table_plain::{PlainFactory, PlainTable};
     use crate::storage::table_writer_api::RedbitTableDefinition;
 
-    pub(crate) fn mk_sharded_reader<V: CacheKey + Send + Clone>(name: &str, n: usize, weak_dbs: Vec<Weak<Database>>, plain_def: TableDefinition<'static, u32, V>) -> ShardedReadOnlyPlainTable<u32, V, BytesPartitioner> {
+    /// Key partitioned plain writer over `u32` keys and its table definition.
+    pub(crate) type PlainWriter<V> = (ShardedTableWriter<u32, V, BytesPartitioner, Xxh3Partitioner, PlainFactory<u32, V>>, TableDefinition<'static, u32, V>);
+
+    pub(crate) fn mk_sharded_reader<V: CacheKey + Send + Clone>(name: &str, n: usize, weak_dbs: Vec<Weak<ShardDb>>, plain_def: TableDefinition<'static, u32, V>) -> ShardedReadOnlyPlainTable<u32, V, BytesPartitioner> {
         ShardedReadOnlyPlainTable::new(
             BytesPartitioner::new(n),
             weak_dbs.clone(),
@@ -158,7 +186,11 @@ pub mod plain_test_utils {
         ).expect("reader")
     }
 
-    pub(crate) fn mk_sharded_writer<V: CacheKey + Send + Clone>(name: &str, n: usize, weak_dbs: Vec<Weak<Database>>) -> (ShardedTableWriter<u32, V, BytesPartitioner, Xxh3Partitioner, PlainFactory<u32, V>>, TableDefinition<'static, u32, V>) {
+    pub(crate) fn mk_sharded_writer<V: CacheKey + Send + Clone>(name: &str, n: usize, weak_dbs: Vec<Weak<ShardDb>>) -> PlainWriter<V> {
+        mk_sharded_writer_with(name, n, weak_dbs, WriterConfig::default())
+    }
+
+    pub(crate) fn mk_sharded_writer_with<V: CacheKey + Send + Clone>(name: &str, n: usize, weak_dbs: Vec<Weak<ShardDb>>, config: WriterConfig) -> PlainWriter<V> {
         let plain_def = TableDefinition::<u32, V>::new("plain_underlying");
 
         let def = RedbitTableDefinition::new(
@@ -166,7 +198,7 @@ pub mod plain_test_utils {
             Partitioning::by_key(n),
             PlainFactory::new(name, plain_def),
         );
-        let writer = def.writer_from_dbs(weak_dbs.clone()).expect("Building writer failed");
+        let writer = def.writer_from_dbs(weak_dbs.clone(), config).expect("Building writer failed");
         (writer, plain_def)
     }
 
@@ -193,60 +225,62 @@ pub mod plain_test_utils {
 pub mod index_test_utils {
     use crate::storage::test_utils;
     use crate::*;
-    use lru::LruCache;
-    use redb::{Database, MultimapTableDefinition, TableDefinition, WriteTransaction};
+    use crate::storage::cache::MeteredLru;
+    use redb::{MultimapTableDefinition, TableDefinition, WriteTransaction};
     use std::num::NonZeroUsize;
     use crate::storage::table_index::{IndexFactory, IndexTable};
     use crate::storage::table_writer_api::RedbitTableDefinition;
 
-    pub(crate) fn mk_sharded_reader<V: CacheKey + Send + Clone>(name: &str, n: usize, lru_cache: usize, weak_dbs: Vec<Weak<Database>>, pk_by_index_def: MultimapTableDefinition<'static, V, u32>, index_by_pk_def: TableDefinition<'static, u32, V>) -> ShardedReadOnlyIndexTable<u32, V, Xxh3Partitioner> {
+    pub(crate) fn mk_sharded_reader<V: CacheKey + Send + Clone>(name: &str, n: usize, lru_cache: usize, weak_dbs: Vec<Weak<ShardDb>>, pk_by_index_def: MultimapTableDefinition<'static, V::Key, u32>, index_by_pk_def: TableDefinition<'static, u32, V>) -> ShardedReadOnlyIndexTable<u32, V, Xxh3Partitioner> {
         ShardedReadOnlyIndexTable::new(
             Xxh3Partitioner::new(n),
             weak_dbs.clone(),
-            &IndexFactory::new(name, lru_cache, pk_by_index_def, index_by_pk_def)
+            &IndexFactory::new(name, lru_cache, false, false, pk_by_index_def, index_by_pk_def)
         ).expect("reader")
     }
 
-    pub(crate) fn mk_sharded_writer<V: CacheKey + Send + Clone>(name: &str, n: usize, lru_cache: usize, weak_dbs: Vec<Weak<Database>>) -> (ShardedTableWriter<u32, V, BytesPartitioner, Xxh3Partitioner, IndexFactory<u32, V>>, MultimapTableDefinition<'static, V, u32>, TableDefinition<'static, u32, V>) {
-        let pk_by_index_def = MultimapTableDefinition::<V, u32>::new("pk_by_index");
+    pub(crate) fn mk_sharded_writer<V: CacheKey + Send + Clone>(name: &str, n: usize, lru_cache: usize, weak_dbs: Vec<Weak<ShardDb>>) -> (ShardedTableWriter<u32, V, BytesPartitioner, Xxh3Partitioner, IndexFactory<u32, V>>, MultimapTableDefinition<'static, V::Key, u32>, TableDefinition<'static, u32, V>) {
+        let pk_by_index_def = MultimapTableDefinition::<V::Key, u32>::new("pk_by_index");
         let index_by_pk_def = TableDefinition::<u32, V>::new("index_by_pk");
 
         let def = RedbitTableDefinition::new(
             false,
             Partitioning::by_value(n),
-            IndexFactory::new(name, lru_cache, pk_by_index_def, index_by_pk_def),
+            IndexFactory::new(name, lru_cache, false, false, pk_by_index_def, index_by_pk_def),
         );
-        let writer = def.writer_from_dbs(weak_dbs.clone()).expect("Building writer failed");
+        let writer = def.writer_from_dbs(weak_dbs.clone(), WriterConfig::default()).expect("Building writer failed");
         (writer, pk_by_index_def, index_by_pk_def)
     }
 
     pub(crate) fn setup_index_defs<K: DbKey + Send, V: CacheKey + Send + Clone>
         (name: &str, lru_cap: usize) -> (
-        Arc<Database>,
+        Arc<ShardDb>,
         TxFSM<K, V, IndexFactory<K, V>>,
-        LruCache<V::CK, K::Unit>,
-        MultimapTableDefinition<'static, V, K>, // pk_by_index
+        MeteredLru<V::CK, K::Unit>,
+        MultimapTableDefinition<'static, V::Key, K>, // pk_by_index
         TableDefinition<'static, K, V>,         // index_by_pk
     ) {
         let (owner_db, weak_db)   = test_utils::mk_db("redbit_index_test");
-        let lru  = LruCache::new(NonZeroUsize::new(lru_cap).unwrap());
+        let lru  = MeteredLru::new(NonZeroUsize::new(lru_cap).unwrap());
 
-        let pk_by_index   = MultimapTableDefinition::<V, K>::new("pk_by_index");
+        let pk_by_index   = MultimapTableDefinition::<V::Key, K>::new("pk_by_index");
         let index_by_pk   = TableDefinition::<K, V>::new("index_by_pk");
-        let writer = TxFSM::new(weak_db, IndexFactory::new(name, lru_cap, pk_by_index, index_by_pk)).expect("new writer");
+        let writer = TxFSM::new(weak_db, IndexFactory::new(name, lru_cap, false, false, pk_by_index, index_by_pk), WriterConfig::default()).expect("new writer");
         (owner_db, writer, lru, pk_by_index, index_by_pk)
     }
 
     pub(crate) fn mk_index<'txn, 'c, K: DbKey, V: CacheKey>(
         tx: &'txn WriteTransaction,
-        cache: &'c mut LruCache<V::CK, K::Unit>,
-        pk_by_index_def: MultimapTableDefinition<'static, V, K>,
+        cache: &'c mut MeteredLru<V::CK, K::Unit>,
+        pk_by_index_def: MultimapTableDefinition<'static, V::Key, K>,
         index_by_pk_def: TableDefinition<'static, K, V>,
     ) -> IndexTable<'txn, 'c, K, V> {
         IndexTable {
             pk_by_index: tx.open_multimap_table(pk_by_index_def).expect("open pk_by_index"),
             index_by_pk: tx.open_table(index_by_pk_def).expect("open index_by_pk"),
             cache: Some(cache),
+            bloom: None,
+            unique: false,
         }
     }
 
@@ -255,12 +289,12 @@ pub mod index_test_utils {
 #[cfg(all(test, not(feature = "integration")))]
 pub mod dict_test_utils {
     use crate::*;
-    use lru::LruCache;
+    use crate::storage::cache::MeteredLru;
     use std::num::NonZeroUsize;
     use crate::storage::table_dict::DictFactory;
     use crate::storage::table_writer_api::RedbitTableDefinition;
 
-    pub (crate) fn mk_sharder_reader<V: CacheKey + Send + Clone>(name: &str, n: usize, weak_dbs: Vec<Weak<Database>>, dict_pk_to_ids: MultimapTableDefinition<'static, u32, u32>, value_by_dict_pk: TableDefinition<'static, u32, V>, value_to_dict_pk: TableDefinition<'static, V, u32>, dict_pk_by_id: TableDefinition<'static, u32, u32>) -> ShardedReadOnlyDictTable<u32, V, Xxh3Partitioner> {
+    pub (crate) fn mk_sharder_reader<V: CacheKey + Send + Clone>(name: &str, n: usize, weak_dbs: Vec<Weak<ShardDb>>, dict_pk_to_ids: MultimapTableDefinition<'static, u32, u32>, value_by_dict_pk: TableDefinition<'static, u32, V>, value_to_dict_pk: TableDefinition<'static, V::Key, u32>, dict_pk_by_id: TableDefinition<'static, u32, u32>) -> ShardedReadOnlyDictTable<u32, V, Xxh3Partitioner> {
         ShardedReadOnlyDictTable::new(
             Xxh3Partitioner::new(n),
             weak_dbs.clone(),
@@ -275,11 +309,11 @@ pub mod dict_test_utils {
         ).expect("reader")
     }
 
-    pub(crate) fn mk_sharded_writer<V: CacheKey + Send + Clone>(name: &str, n: usize, weak_dbs: Vec<Weak<Database>>) -> (ShardedTableWriter<u32, V, BytesPartitioner, Xxh3Partitioner, DictFactory<u32, V>>, MultimapTableDefinition<'static, u32, u32>, TableDefinition<'static, u32, V>, TableDefinition<'static, V, u32>, TableDefinition<'static, u32, u32>) {
+    pub(crate) fn mk_sharded_writer<V: CacheKey + Send + Clone>(name: &str, n: usize, weak_dbs: Vec<Weak<ShardDb>>) -> (ShardedTableWriter<u32, V, BytesPartitioner, Xxh3Partitioner, DictFactory<u32, V>>, MultimapTableDefinition<'static, u32, u32>, TableDefinition<'static, u32, V>, TableDefinition<'static, V::Key, u32>, TableDefinition<'static, u32, u32>) {
         // Table defs
         let dict_pk_to_ids   = MultimapTableDefinition::<u32, u32>::new("dict_pk_to_ids");
         let value_by_dict_pk = TableDefinition::<u32, V>::new("value_by_dict_pk");
-        let value_to_dict_pk = TableDefinition::<V, u32>::new("value_to_dict_pk");
+        let value_to_dict_pk = TableDefinition::<V::Key, u32>::new("value_to_dict_pk");
         let dict_pk_by_id    = TableDefinition::<u32, u32>::new("dict_pk_by_id");
 
         // Writer by value (so identical values go to the same shard):
@@ -295,27 +329,27 @@ pub mod dict_test_utils {
                 dict_pk_by_id,
             ),
         );
-        let writer = def.writer_from_dbs(weak_dbs.clone()).expect("Building writer failed");
+        let writer = def.writer_from_dbs(weak_dbs.clone(), WriterConfig::default()).expect("Building writer failed");
         (writer, dict_pk_to_ids, value_by_dict_pk, value_to_dict_pk, dict_pk_by_id)
     }
 
     pub(crate) fn setup_dict_defs<K: DbKey + Send, V: CacheKey + Send + Clone>(cap: usize) -> (
         Database,
         WriteTransaction,
-        LruCache<V::CK, K::Unit>,
+        MeteredLru<V::CK, K::Unit>,
         MultimapTableDefinition<'static, K, K>,
         TableDefinition<'static, K, V>,
-        TableDefinition<'static, V, K>,
+        TableDefinition<'static, V::Key, K>,
         TableDefinition<'static, K, K>,
     ) {
         let random_db_path = std::env::temp_dir().join(format!("redbit_test_{}", rand::random::<u64>()));
         let random_db = Database::builder().create(random_db_path).expect("Failed to create test db");
         let write_tx = random_db.begin_write().expect("Failed to begin write tx");
-        let lru_cache = LruCache::new(NonZeroUsize::new(cap).unwrap());
+        let lru_cache = MeteredLru::new(NonZeroUsize::new(cap).unwrap());
 
         let dict_pk_to_ids: MultimapTableDefinition<'static, K, K> = MultimapTableDefinition::new("dict_pk_to_ids");
         let value_by_dict_pk: TableDefinition<'static, K, V> = TableDefinition::new("value_by_dict_pk");
-        let value_to_dict_pk: TableDefinition<'static, V, K> = TableDefinition::new("value_to_dict_pk");
+        let value_to_dict_pk: TableDefinition<'static, V::Key, K> = TableDefinition::new("value_to_dict_pk");
         let dict_pk_by_id: TableDefinition<'static, K, K> = TableDefinition::new("dict_pk_by_id");
 
         (random_db, write_tx, lru_cache, dict_pk_to_ids, value_by_dict_pk, value_to_dict_pk, dict_pk_by_id)
@@ -323,10 +357,10 @@ pub mod dict_test_utils {
 
     pub(crate) fn mk_dict<'txn, 'c, K: DbKey, V: CacheKey>(
         tx: &'txn WriteTransaction,
-        cache: &'c mut LruCache<V::CK, K::Unit>,
+        cache: &'c mut MeteredLru<V::CK, K::Unit>,
         dict_pk_to_ids: MultimapTableDefinition<'static, K, K>,
         value_by_dict_pk: TableDefinition<'static, K, V>,
-        value_to_dict_pk: TableDefinition<'static, V, K>,
+        value_to_dict_pk: TableDefinition<'static, V::Key, K>,
         dict_pk_by_id: TableDefinition<'static, K, K>,
     ) -> DictTable<'txn, 'c, K, V> {
         DictTable::new(tx, Some(cache), dict_pk_to_ids, value_by_dict_pk, value_to_dict_pk, dict_pk_by_id).expect("Failed to create DictTable")
@@ -1,7 +1,9 @@
-use redb::{Key, Value};
+use redb::{AccessGuard, Key, Value};
 use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::ops::Bound;
 use xxhash_rust::xxh3::{xxh3_64};
-use crate::{DbKey, DbVal};
+use crate::{AppError, CacheKey, DbKey, IndexedPointer};
 /*
 use wyhash::wyhash;
 
@@ -41,7 +43,8 @@ impl Partitioning<BytesPartitioner, Xxh3Partitioner> {
     }
 }
 
-pub trait ValuePartitioner<V: DbVal>: Clone + Send + Sync + 'static {
+/// Partitions values by their `CacheKey::Key` bytes, the raw bytes of compressed values.
+pub trait ValuePartitioner<V: CacheKey>: Clone + Send + Sync + 'static {
     fn partition_value<'v>(&self, value: impl Borrow<V::SelfType<'v>>) -> usize;
 }
 
@@ -62,10 +65,10 @@ impl Xxh3Partitioner {
     }
 }
 
-impl<V: DbVal> ValuePartitioner<V> for Xxh3Partitioner {
+impl<V: CacheKey> ValuePartitioner<V> for Xxh3Partitioner {
     #[inline]
     fn partition_value<'v>(&self, value: impl Borrow<V::SelfType<'v>>) -> usize {
-        let bytes_view = <V as Value>::as_bytes(value.borrow());
+        let bytes_view = <V::Key as Value>::as_bytes(value.borrow());
         self.partition_bytes(bytes_view.as_ref())
     }
 }
@@ -73,6 +76,16 @@ impl<V: DbVal> ValuePartitioner<V> for Xxh3Partitioner {
 // ---------- Partitioning trait ----------
 pub trait KeyPartitioner<K: DbKey>: Clone + Send + Sync + 'static {
     fn partition_key<'k>(&self, key: impl Borrow<K::SelfType<'k>>) -> usize;
+
+    /// Shards holding the keys of a range in the order the range visits them, `None` when any shard can.
+    fn partition_range<'k>(&self, _from: Bound<&K::SelfType<'k>>, _until: Bound<&K::SelfType<'k>>) -> Option<Vec<usize>> {
+        None
+    }
+
+    /// Recorded in the schema manifest, `None` for the default bytes partitioning.
+    fn layout(&self) -> Option<String> {
+        None
+    }
 }
 
 // ---------- Struct adapter (kept) ----------
@@ -98,6 +111,70 @@ impl<K: DbKey> KeyPartitioner<K> for BytesPartitioner {
     }
 }
 
+/// Keeps each bucket of `bucket` consecutive root indexes, like a window of block heights, on a single shard and
+/// rotates the buckets over the shards. Reads of a window hit a single file, writes of a window do too.
+#[derive(Clone, Debug)]
+pub struct RangePartitioner {
+    shards: usize,
+    bucket: u128,
+}
+
+impl RangePartitioner {
+    pub const DEFAULT_BUCKET: u64 = 1000;
+
+    pub fn new(n: usize, bucket: u64) -> Self {
+        assert!(n > 0, "shard count must be > 0");
+        assert!(bucket > 0, "bucket must be > 0");
+        Self { shards: n, bucket: bucket as u128 }
+    }
+
+    #[inline]
+    pub fn partition_root(&self, root: u128) -> usize {
+        ((root / self.bucket) % self.shards as u128) as usize
+    }
+
+    /// Shards of the buckets that roots `from..=until` fall into, each shard once.
+    pub fn partition_roots(&self, from: u128, until: u128) -> Vec<usize> {
+        if until < from {
+            return Vec::new();
+        }
+        let buckets = (until / self.bucket - from / self.bucket).saturating_add(1);
+        let first = self.partition_root(from);
+        (0..buckets.min(self.shards as u128) as usize).map(|i| (first + i) % self.shards).collect()
+    }
+}
+
+impl<K: DbKey> KeyPartitioner<K> for RangePartitioner where K::Unit: IndexedPointer {
+    #[inline]
+    fn partition_key<'k>(&self, key: impl Borrow<K::SelfType<'k>>) -> usize {
+        self.partition_root(K::to_unit_ref(key.borrow()).root_index())
+    }
+
+    fn partition_range<'k>(&self, from: Bound<&K::SelfType<'k>>, until: Bound<&K::SelfType<'k>>) -> Option<Vec<usize>> {
+        let from_root = match from {
+            Bound::Included(k) | Bound::Excluded(k) => K::to_unit_ref(k).root_index(),
+            Bound::Unbounded => 0,
+        };
+        let until_root = match until {
+            Bound::Included(k) => K::to_unit_ref(k).root_index(),
+            Bound::Excluded(k) => {
+                let unit = K::to_unit_ref(k);
+                let root = unit.root_index();
+                // a range ending right before the first key of a root does not reach that root
+                let first_of_root = unit.rollback_or_init(0);
+                let starts_root = <K as Value>::as_bytes(&K::as_value_from_unit(&first_of_root)).as_ref() == <K as Value>::as_bytes(k).as_ref();
+                if starts_root && root > from_root { root - 1 } else { root }
+            }
+            Bound::Unbounded => return None,
+        };
+        Some(self.partition_roots(from_root, until_root))
+    }
+
+    fn layout(&self) -> Option<String> {
+        Some(format!("range({})", self.bucket))
+    }
+}
+
 // ---------- Zero-alloc functional core (shared) ----------
 
 /// Partition a redb key given a borrow of its `SelfType<'_>`.
@@ -132,6 +209,139 @@ fn le_mod_fold_chunk(m: u128, pow: &mut u128, acc: &mut u128, chunk: &[u8]) {
     *acc = a;
 }
 
+/// Walks the value ordered ranges of value partitioned shards as one range, `f` gets the shard of each entry
+/// and returns false to stop.
+pub(crate) fn merge_value_ranges<V, T, I, F>(mut ranges: Vec<I>, mut f: F) -> Result<(), AppError>
+where
+    V: Key + 'static,
+    I: Iterator<Item = redb::Result<(AccessGuard<'static, V>, T)>>,
+    F: FnMut(usize, AccessGuard<'static, V>, T) -> Result<bool, AppError>,
+{
+    let mut heads = Vec::with_capacity(ranges.len());
+    for range in ranges.iter_mut() {
+        heads.push(range.next().transpose()?);
+    }
+    loop {
+        let mut min: Option<usize> = None;
+        for (shard, head) in heads.iter().enumerate() {
+            let Some((value, _)) = head else { continue };
+            let smaller = match min.and_then(|m| heads[m].as_ref()) {
+                Some((min_value, _)) => V::compare(V::as_bytes(&value.value()).as_ref(), V::as_bytes(&min_value.value()).as_ref()).is_lt(),
+                None => true,
+            };
+            if smaller {
+                min = Some(shard);
+            }
+        }
+        let Some(shard) = min else { return Ok(()) };
+        let next = ranges[shard].next().transpose()?;
+        let (value, item) = std::mem::replace(&mut heads[shard], next).expect("merged shard has a head");
+        if !f(shard, value, item)? {
+            return Ok(());
+        }
+    }
+}
+
+/// Key ordered ranges of several shards read as one range from either end, each shard keeps at most one
+/// entry per end buffered.
+pub struct MergedRange<K: Key + 'static, T, I> {
+    ranges: Vec<I>,
+    fronts: Vec<Option<(AccessGuard<'static, K>, T)>>,
+    backs: Vec<Option<(AccessGuard<'static, K>, T)>>,
+    exhausted: Vec<bool>,
+}
+
+/// Entries of the key ranges of a key partitioned table, see `MergedRange`.
+pub type KeyRange<K, V> = MergedRange<K, AccessGuard<'static, V>, redb::Range<'static, K, V>>;
+
+/// Values with their ids of the value ranges of a value partitioned index, see `MergedRange`.
+pub type ValueRange<V, K> = MergedRange<V, redb::MultimapValue<'static, K>, redb::MultimapRange<'static, V, K>>;
+
+impl<K, T, I> MergedRange<K, T, I>
+where
+    K: Key + 'static,
+    I: DoubleEndedIterator<Item = redb::Result<(AccessGuard<'static, K>, T)>>,
+{
+    pub fn new(ranges: Vec<I>) -> Self {
+        let n = ranges.len();
+        MergedRange {
+            ranges,
+            fronts: (0..n).map(|_| None).collect(),
+            backs: (0..n).map(|_| None).collect(),
+            exhausted: vec![false; n],
+        }
+    }
+
+    /// Buffers the next entry of shard `idx` at one end, once its range is drained the entry buffered
+    /// at the other end is the last one left.
+    fn fill(&mut self, idx: usize, front: bool) -> redb::Result<()> {
+        let (this, other) = if front { (&mut self.fronts, &mut self.backs) } else { (&mut self.backs, &mut self.fronts) };
+        if this[idx].is_some() {
+            return Ok(());
+        }
+        if !self.exhausted[idx] {
+            let next = if front { self.ranges[idx].next() } else { self.ranges[idx].next_back() };
+            match next.transpose()? {
+                Some(entry) => {
+                    this[idx] = Some(entry);
+                    return Ok(());
+                }
+                None => self.exhausted[idx] = true,
+            }
+        }
+        this[idx] = other[idx].take();
+        Ok(())
+    }
+
+    /// Takes the smallest buffered entry from the front or the largest from the back.
+    fn take(&mut self, front: bool) -> Option<redb::Result<(AccessGuard<'static, K>, T)>> {
+        if self.ranges.len() == 1 {
+            return if front { self.ranges[0].next() } else { self.ranges[0].next_back() };
+        }
+        for idx in 0..self.ranges.len() {
+            if let Err(e) = self.fill(idx, front) {
+                return Some(Err(e));
+            }
+        }
+        let heads = if front { &mut self.fronts } else { &mut self.backs };
+        let wanted = if front { Ordering::Less } else { Ordering::Greater };
+        let mut best: Option<usize> = None;
+        for (idx, head) in heads.iter().enumerate() {
+            let Some((key, _)) = head else { continue };
+            let better = match best.and_then(|b| heads[b].as_ref()) {
+                Some((best_key, _)) => K::compare(K::as_bytes(&key.value()).as_ref(), K::as_bytes(&best_key.value()).as_ref()) == wanted,
+                None => true,
+            };
+            if better {
+                best = Some(idx);
+            }
+        }
+        best.and_then(|idx| heads[idx].take()).map(Ok)
+    }
+}
+
+impl<K, T, I> Iterator for MergedRange<K, T, I>
+where
+    K: Key + 'static,
+    I: DoubleEndedIterator<Item = redb::Result<(AccessGuard<'static, K>, T)>>,
+{
+    type Item = redb::Result<(AccessGuard<'static, K>, T)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.take(true)
+    }
+}
+
+impl<K, T, I> DoubleEndedIterator for MergedRange<K, T, I>
+where
+    K: Key + 'static,
+    I: DoubleEndedIterator<Item = redb::Result<(AccessGuard<'static, K>, T)>>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.take(false)
+    }
+}
+
 #[cfg(all(test, not(feature = "integration")))]
 mod tests {
     use crate::storage::test_utils;
@@ -290,4 +500,24 @@ mod tests {
             assert_eq!(partition_key_redb::<TxHash>(n, &kb), manual_reduce_le(kb_bytes, n));
         }
     }
+
+    #[test]
+    fn range_partition_keeps_buckets_together_and_rotates() {
+        let p = RangePartitioner::new(3, 10);
+        assert_eq!(p.partition_root(0), 0);
+        assert_eq!(p.partition_root(9), 0);
+        assert_eq!(p.partition_root(10), 1);
+        assert_eq!(p.partition_root(25), 2);
+        assert_eq!(p.partition_root(30), 0);
+    }
+
+    #[test]
+    fn range_partition_roots_touch_only_overlapping_shards() {
+        let p = RangePartitioner::new(4, 10);
+        assert_eq!(p.partition_roots(3, 7), vec![0]);
+        assert_eq!(p.partition_roots(8, 12), vec![0, 1]);
+        assert_eq!(p.partition_roots(25, 41), vec![2, 3, 0]);
+        assert_eq!(p.partition_roots(0, 1000), vec![0, 1, 2, 3]);
+        assert_eq!(p.partition_roots(7, 3), Vec::<usize>::new());
+    }
 }
@@ -0,0 +1,443 @@
+use crate::storage::bloom::BloomFilters;
+use crate::storage::init::{shard_suffix, DbDef, DbDefWithCache, StorageOwner};
+use crate::storage::layout::{move_file, DbLayout};
+use crate::storage::table_writer_api::ColumnTables;
+use crate::{info, AppError};
+use redb::{Database, Key, MultimapTableDefinition, ReadTransaction, ReadableDatabase, ReadableMultimapTable, ReadableTable, TableDefinition, TableError, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Entries written into the target shards per write transaction, keeps memory flat while streaming huge columns.
+pub const RESHARD_BATCH_SIZE: usize = 100_000;
+const RESHARD_DIR: &str = ".reshard";
+const STATE_FILE: &str = "state.json";
+
+/// Moves all entries of `def` from `src` into `dst[route(k, v)]`, committing every `RESHARD_BATCH_SIZE` entries.
+/// Inserts are idempotent, so a batch that is replayed after a crash does not change the result.
+pub(crate) fn reshard_table<K: Key + 'static, V: Value + 'static>(
+    src: &ReadTransaction,
+    dst: &[Database],
+    def: TableDefinition<'static, K, V>,
+    route: impl Fn(&K::SelfType<'_>, &V::SelfType<'_>) -> Result<usize, AppError>,
+) -> Result<u64, AppError> {
+    let source = match src.open_table(def) {
+        Ok(t) => t,
+        Err(TableError::TableDoesNotExist(_)) => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut moved = 0u64;
+    let mut cursor: Option<Vec<u8>> = None;
+    loop {
+        let txs = dst.iter().map(|db| db.begin_write()).collect::<Result<Vec<_>, _>>()?;
+        let mut batch = 0usize;
+        let mut next_cursor = None;
+        {
+            let mut tables = txs.iter().map(|tx| tx.open_table(def)).collect::<Result<Vec<_>, _>>()?;
+            let entries = match &cursor {
+                Some(bytes) => source.range::<K::SelfType<'_>>((Bound::Excluded(K::from_bytes(bytes)), Bound::Unbounded))?,
+                None => source.iter()?,
+            };
+            for entry in entries {
+                let (k, v) = entry?;
+                let (key, value) = (k.value(), v.value());
+                tables[route(&key, &value)?].insert(&key, &value)?;
+                batch += 1;
+                if batch == RESHARD_BATCH_SIZE {
+                    next_cursor = Some(K::as_bytes(&key).as_ref().to_vec());
+                    break;
+                }
+            }
+        }
+        for tx in txs {
+            tx.commit()?;
+        }
+        moved += batch as u64;
+        match next_cursor {
+            Some(c) => cursor = Some(c),
+            None => return Ok(moved),
+        }
+    }
+}
+
+/// Multimap flavour of `reshard_table`, batches are cut only at key boundaries.
+pub(crate) fn reshard_multimap_table<K: Key + 'static, V: Key + 'static>(
+    src: &ReadTransaction,
+    dst: &[Database],
+    def: MultimapTableDefinition<'static, K, V>,
+    route: impl Fn(&K::SelfType<'_>, &V::SelfType<'_>) -> Result<usize, AppError>,
+) -> Result<u64, AppError> {
+    let source = match src.open_multimap_table(def) {
+        Ok(t) => t,
+        Err(TableError::TableDoesNotExist(_)) => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut moved = 0u64;
+    let mut cursor: Option<Vec<u8>> = None;
+    loop {
+        let txs = dst.iter().map(|db| db.begin_write()).collect::<Result<Vec<_>, _>>()?;
+        let mut batch = 0usize;
+        let mut next_cursor = None;
+        {
+            let mut tables = txs.iter().map(|tx| tx.open_multimap_table(def)).collect::<Result<Vec<_>, _>>()?;
+            let entries = match &cursor {
+                Some(bytes) => source.range::<K::SelfType<'_>>((Bound::Excluded(K::from_bytes(bytes)), Bound::Unbounded))?,
+                None => source.iter()?,
+            };
+            for entry in entries {
+                let (k, values) = entry?;
+                let key = k.value();
+                for v in values {
+                    let v = v?;
+                    let value = v.value();
+                    tables[route(&key, &value)?].insert(&key, &value)?;
+                    batch += 1;
+                }
+                if batch >= RESHARD_BATCH_SIZE {
+                    next_cursor = Some(K::as_bytes(&key).as_ref().to_vec());
+                    break;
+                }
+            }
+        }
+        for tx in txs {
+            tx.commit()?;
+        }
+        moved += batch as u64;
+        match next_cursor {
+            Some(c) => cursor = Some(c),
+            None => return Ok(moved),
+        }
+    }
+}
+
+/// Number of shards a column occupies in its mounted dirs, `None` if it has no files there yet.
+pub fn shards_on_disk(layout: &DbLayout, name: &str) -> Option<usize> {
+    if layout.file_path(name, None).exists() {
+        return Some(1);
+    }
+    let count = (0..).take_while(|i| layout.file_path(name, Some(*i)).exists()).count();
+    if count == 0 { None } else { Some(count) }
+}
+
+fn staging_dir(db_dir: &Path, name: &str) -> PathBuf {
+    db_dir.join(RESHARD_DIR).join(name)
+}
+
+/// Columns with an unfinished resharding in `db_dir`.
+pub fn pending_reshards(db_dir: &Path) -> Result<Vec<String>, AppError> {
+    let dir = db_dir.join(RESHARD_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.path().join(STATE_FILE).exists() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum ReshardPhase {
+    /// source shards already fully redistributed into the staging dir
+    Copying { done: Vec<usize> },
+    /// staging is complete, old shard files are being deleted
+    Copied,
+    /// old shard files are gone, staged files are being moved in
+    Swapping,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct ReshardState {
+    from: usize,
+    to: usize,
+    phase: ReshardPhase,
+}
+
+impl ReshardState {
+    fn load(dir: &Path) -> Result<Option<Self>, AppError> {
+        let path = dir.join(STATE_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&fs::read(path)?)?))
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), AppError> {
+        let tmp = dir.join(format!("{}.tmp", STATE_FILE));
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(tmp, dir.join(STATE_FILE))?;
+        Ok(())
+    }
+}
+
+/// Redistributes a column from `from` to `to` shards. Progress is persisted after every source shard
+/// and every phase, so calling it again after a crash resumes where it stopped.
+fn reshard_column(layout: &DbLayout, column: &dyn ColumnTables, from: usize, to: usize) -> Result<u64, AppError> {
+    let name = column.name();
+    let staging = staging_dir(&layout.root, &name);
+    fs::create_dir_all(&staging)?;
+    let mut state = match ReshardState::load(&staging)? {
+        Some(s) if s.from == from && s.to == to => s,
+        Some(s) => return Err(AppError::Custom(format!(
+            "column `{}`: unfinished resharding {} -> {} conflicts with requested {} -> {}", name, s.from, s.to, from, to
+        ))),
+        None => {
+            let s = ReshardState { from, to, phase: ReshardPhase::Copying { done: Vec::new() } };
+            s.save(&staging)?;
+            s
+        }
+    };
+
+    let mut moved = 0u64;
+    if let ReshardPhase::Copying { mut done } = state.phase.clone() {
+        let targets = (0..to)
+            .map(|idx| Database::create(StorageOwner::db_file_path(&staging, &name, shard_suffix(to, idx))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let remaining: Vec<usize> = (0..from).filter(|idx| !done.contains(idx)).collect();
+        for idx in remaining {
+            let source = Database::open(layout.file_path(&name, shard_suffix(from, idx)))?;
+            moved += column.reshard_tables(&source.begin_read()?, &targets)?;
+            done.push(idx);
+            state.phase = ReshardPhase::Copying { done: done.clone() };
+            state.save(&staging)?;
+            info!("Resharding {}: source shard {}/{} done", name, done.len(), from);
+        }
+        drop(targets);
+        state.phase = ReshardPhase::Copied;
+        state.save(&staging)?;
+    }
+
+    if state.phase == ReshardPhase::Copied {
+        for idx in 0..from {
+            let path = layout.file_path(&name, shard_suffix(from, idx));
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        BloomFilters::remove_files(layout, &name, from)?;
+        state.phase = ReshardPhase::Swapping;
+        state.save(&staging)?;
+    }
+
+    for idx in 0..to {
+        let staged = StorageOwner::db_file_path(&staging, &name, shard_suffix(to, idx));
+        if staged.exists() {
+            let target = layout.file_path(&name, shard_suffix(to, idx));
+            fs::create_dir_all(layout.shard_dir(&name, shard_suffix(to, idx)))?;
+            move_file(&staged, &target)?;
+        }
+    }
+    fs::remove_dir_all(&staging)?;
+    Ok(moved)
+}
+
+impl StorageOwner {
+    /// Reshards every column whose declared shard count differs from its files on disk, and finishes
+    /// any interrupted resharding. Must run before the dbs are opened, returns the resharded column names.
+    pub async fn reshard_if_needed(layout: &DbLayout, db_defs: &[DbDef], columns: Vec<Arc<dyn ColumnTables>>) -> Result<Vec<String>, AppError> {
+        let pending = pending_reshards(&layout.root)?;
+        let tables: HashMap<String, Arc<dyn ColumnTables>> = columns.into_iter().map(|c| (c.name(), c)).collect();
+        let mut tasks = Vec::new();
+        for def in db_defs {
+            DbDefWithCache::new(def.clone(), 0).validate()?;
+            let on_disk = match ReshardState::load(&staging_dir(&layout.root, &def.name))? {
+                Some(state) => Some(state.from),
+                None => shards_on_disk(layout, &def.name),
+            };
+            let from = match on_disk {
+                Some(from) if from != def.shards || pending.contains(&def.name) => from,
+                _ => continue,
+            };
+            let column = tables.get(&def.name).cloned().ok_or_else(|| {
+                AppError::Custom(format!("column `{}`: no table definitions to reshard it with", def.name))
+            })?;
+            let layout = layout.clone();
+            let to = def.shards;
+            info!("Resharding {} from {} to {} shards", def.name, from, to);
+            tasks.push(tokio::task::spawn_blocking(move || -> Result<String, AppError> {
+                let moved = reshard_column(&layout, column.as_ref(), from, to)?;
+                info!("Resharded {} from {} to {} shards, {} entries moved", column.name(), from, to, moved);
+                Ok(column.name())
+            }));
+        }
+        let mut names = Vec::with_capacity(tasks.len());
+        for res in futures_util::future::try_join_all(tasks).await? {
+            names.push(res?);
+        }
+        Ok(names)
+    }
+
+    /// Fails if any column is laid out on disk with a different shard count than declared, is half-resharded
+    /// or has shard files outside of their mounted dir.
+    pub fn validate_layout(layout: &DbLayout, db_defs: &[DbDef]) -> Result<(), AppError> {
+        let db_dir = &layout.root;
+        let mut problems: Vec<String> =
+            pending_reshards(db_dir)?.into_iter().map(|name| format!("column `{}` has an unfinished resharding", name)).collect();
+        for (found, expected) in layout.misplaced_files(db_defs) {
+            problems.push(format!("{:?} is mounted at {:?}, move it there", found, expected));
+        }
+        for def in db_defs {
+            match shards_on_disk(layout, &def.name) {
+                Some(on_disk) if on_disk != def.shards =>
+                    problems.push(format!("column `{}` has {} shards on disk but declares {}", def.name, on_disk, def.shards)),
+                _ => {}
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Custom(format!(
+                "db layout at {:?} does not match the schema, reshard it with StorageOwner::reshard_if_needed:\n{}", db_dir, problems.join("\n")
+            )))
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+    use crate::storage::layout::MountPoint;
+    use crate::storage::table_dict::DictFactory;
+    use crate::storage::table_index::IndexFactory;
+    use crate::storage::table_plain::PlainFactory;
+    use crate::storage::table_writer_api::{ReadTableLike, RedbitTableDefinition, ShardedTableReader, WriterLike};
+    use crate::storage::test_utils::{addr, tmp_dir, txh, Address, TxHash};
+    use crate::{BytesPartitioner, Partitioning, Xxh3Partitioner};
+    use redb::Durability;
+
+    type PlainDef = RedbitTableDefinition<u32, Address, BytesPartitioner, Xxh3Partitioner, PlainFactory<u32, Address>>;
+    type IndexDef = RedbitTableDefinition<u32, TxHash, BytesPartitioner, Xxh3Partitioner, IndexFactory<u32, TxHash>>;
+    type DictDef = RedbitTableDefinition<u32, TxHash, BytesPartitioner, Xxh3Partitioner, DictFactory<u32, TxHash>>;
+
+    fn db_def(name: &str, shards: usize) -> DbDef {
+        DbDef { name: name.to_string(), shards, db_cache_weight_or_zero: 0, lru_cache_size_or_zero: 0 }
+    }
+
+    fn plain_def(shards: usize) -> PlainDef {
+        RedbitTableDefinition::new(false, Partitioning::by_key(shards), PlainFactory::new("reshard_plain", TableDefinition::new("reshard_plain")))
+    }
+
+    fn index_def(shards: usize) -> IndexDef {
+        let factory = IndexFactory::new("reshard_index", 0, false, false, MultimapTableDefinition::new("reshard_pk_by_index"), TableDefinition::new("reshard_index_by_pk"));
+        RedbitTableDefinition::new(false, Partitioning::by_value(shards), factory)
+    }
+
+    fn dict_def(shards: usize) -> DictDef {
+        let factory = DictFactory::new(
+            "reshard_dict",
+            0,
+            MultimapTableDefinition::new("reshard_dict_pk_to_ids"),
+            TableDefinition::new("reshard_value_by_dict_pk"),
+            TableDefinition::new("reshard_value_to_dict_pk"),
+            TableDefinition::new("reshard_dict_pk_by_id"),
+        );
+        RedbitTableDefinition::new(false, Partitioning::by_value(shards), factory)
+    }
+
+    fn db_defs(shards: usize) -> Vec<DbDef> {
+        vec![db_def("reshard_plain", shards), db_def("reshard_index", shards), db_def("reshard_dict", shards)]
+    }
+
+    async fn populate(layout: &DbLayout, shards: usize) {
+        let (_, _owner, storage) = StorageOwner::init_with_layout(layout.clone(), db_defs(shards), 0, false).await.expect("init");
+        let plain = plain_def(shards).writer(&storage).expect("plain writer");
+        let index = index_def(shards).writer(&storage).expect("index writer");
+        let dict = dict_def(shards).writer(&storage).expect("dict writer");
+        plain.begin(Durability::Immediate).expect("begin");
+        index.begin(Durability::Immediate).expect("begin");
+        dict.begin(Durability::Immediate).expect("begin");
+        for k in 1u32..=60 {
+            plain.insert_on_flush(k, addr(&[k as u8])).expect("insert");
+            index.insert_on_flush(k, txh(&[(k % 7) as u8])).expect("insert");
+            dict.insert_now(k, txh(&[(k % 5) as u8])).expect("insert");
+        }
+        plain.flush().expect("flush");
+        index.flush().expect("flush");
+        dict.flush().expect("flush");
+        plain.shutdown().expect("shutdown");
+        index.shutdown().expect("shutdown");
+        dict.shutdown().expect("shutdown");
+    }
+
+    async fn assert_readable(layout: &DbLayout, shards: usize) {
+        let (_, _owner, storage) = StorageOwner::init_with_layout(layout.clone(), db_defs(shards), 0, false).await.expect("open");
+        let ShardedTableReader::Plain(plain) = plain_def(shards).reader(&storage).expect("reader") else { panic!("plain reader expected") };
+        let ShardedTableReader::Index(index) = index_def(shards).reader(&storage).expect("reader") else { panic!("index reader expected") };
+        let ShardedTableReader::Dict(dict) = dict_def(shards).reader(&storage).expect("reader") else { panic!("dict reader expected") };
+        for k in 1u32..=60 {
+            assert_eq!(plain.get_value(k).expect("get").expect("some").value().0, vec![k as u8]);
+            assert_eq!(index.get_value(k).expect("get").expect("some").value(), txh(&[(k % 7) as u8]));
+            assert_eq!(dict.get_value(k).expect("get").expect("some").value(), txh(&[(k % 5) as u8]));
+        }
+        for m in 0u8..7 {
+            let pks: Vec<u32> = index.index_keys(txh(&[m])).expect("keys").into_iter().flatten().map(|g| g.expect("pk").value()).collect();
+            assert_eq!(pks, (1u32..=60).filter(|k| (k % 7) as u8 == m).collect::<Vec<_>>());
+        }
+        for m in 0u8..5 {
+            let ids: Vec<u32> = dict.dict_keys(txh(&[m])).expect("keys").expect("some").map(|g| g.expect("id").value()).collect();
+            assert_eq!(ids, (1u32..=60).filter(|k| (k % 5) as u8 == m).collect::<Vec<_>>());
+        }
+    }
+
+    fn columns(shards: usize) -> Vec<Arc<dyn ColumnTables>> {
+        vec![Arc::new(plain_def(shards)), Arc::new(index_def(shards)), Arc::new(dict_def(shards))]
+    }
+
+    #[tokio::test]
+    async fn reshard_grows_and_shrinks_columns() {
+        let db_dir = tmp_dir("reshard_grow");
+        let layout = DbLayout::new(db_dir.clone());
+        populate(&layout, 2).await;
+        assert_eq!(shards_on_disk(&layout, "reshard_plain"), Some(2));
+
+        // the new shard of the plain column goes to its own drive
+        let mount = MountPoint { column: Some("reshard_plain".to_string()), shard: Some(2), dir: tmp_dir("reshard_grow_mount") };
+        let mounted = DbLayout::with_mounts(db_dir.clone(), vec![mount.clone()]);
+        let defs = db_defs(3);
+        assert!(StorageOwner::validate_layout(&mounted, &defs).is_err());
+        let mut resharded = StorageOwner::reshard_if_needed(&mounted, &defs, columns(3)).await.expect("reshard");
+        resharded.sort();
+        assert_eq!(resharded, vec!["reshard_dict".to_string(), "reshard_index".to_string(), "reshard_plain".to_string()]);
+        assert_eq!(shards_on_disk(&mounted, "reshard_plain"), Some(3));
+        assert!(mount.dir.join("reshard_plain-2.db").exists());
+        assert!(!db_dir.join("reshard_plain-2.db").exists());
+        assert!(pending_reshards(&db_dir).expect("pending").is_empty());
+        assert_readable(&mounted, 3).await;
+
+        StorageOwner::reshard_if_needed(&mounted, &db_defs(1), columns(1)).await.expect("reshard");
+        assert_eq!(shards_on_disk(&layout, "reshard_plain"), Some(1));
+        assert!(!mount.dir.join("reshard_plain-2.db").exists());
+        assert_readable(&layout, 1).await;
+    }
+
+    #[tokio::test]
+    async fn reshard_resumes_after_interruption() {
+        let db_dir = tmp_dir("reshard_resume");
+        let layout = DbLayout::new(db_dir.clone());
+        populate(&layout, 2).await;
+
+        // pretend the process died after redistributing the first source shard
+        let staging = staging_dir(&db_dir, "reshard_plain");
+        fs::create_dir_all(&staging).expect("staging");
+        let targets: Vec<Database> = (0..3)
+            .map(|i| Database::create(StorageOwner::db_file_path(&staging, "reshard_plain", Some(i))).expect("create"))
+            .collect();
+        let first = Database::open(StorageOwner::db_file_path(&db_dir, "reshard_plain", Some(0))).expect("open");
+        plain_def(3).reshard_tables(&first.begin_read().expect("read"), &targets).expect("reshard");
+        drop((targets, first));
+        ReshardState { from: 2, to: 3, phase: ReshardPhase::Copying { done: vec![0] } }.save(&staging).expect("state");
+
+        let defs = db_defs(3);
+        assert!(StorageOwner::validate_layout(&layout, &defs).expect_err("pending").to_string().contains("unfinished resharding"));
+        StorageOwner::reshard_if_needed(&layout, &defs, columns(3)).await.expect("resume");
+        StorageOwner::validate_layout(&layout, &defs).expect("layout");
+        assert_readable(&layout, 3).await;
+    }
+}
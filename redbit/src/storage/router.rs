@@ -1,11 +1,13 @@
 use crossbeam::channel::{bounded, Sender, TrySendError};
 use redb::{Key, Value};
 use std::borrow::Borrow;
+use std::ops::Bound;
 use std::sync::Arc;
 
 use crate::storage::async_boundary::{ValueBuf, ValueOwned};
+use crate::storage::backpressure::QueueBudget;
 use crate::storage::table_writer_api::WriterCommand;
-use crate::{AppError, DbKey, KeyPartitioner, Partitioning, DbVal, ValuePartitioner};
+use crate::{AppError, DbKey, KeyPartitioner, Partitioning, CacheKey, ValuePartitioner};
 
 #[inline]
 fn fast_send<K: DbKey + Send, V: Key + Send + 'static>(
@@ -38,17 +40,27 @@ pub trait Router<K: DbKey, V: Value>: Send + Sync {
 pub struct ShardedRouter<K: DbKey + Send, V: Key + Send + 'static, KP, VP> {
     part: Partitioning<KP, VP>,
     senders: Arc<[Sender<WriterCommand<K, V>>]>,
+    budgets: Arc<[Arc<QueueBudget>]>,
 }
 
 impl<K, V, KP, VP> ShardedRouter<K, V, KP, VP>
 where
     K: DbKey + Send,
-    V: DbVal + Send,
+    V: CacheKey + Send,
     KP: KeyPartitioner<K>,
     VP: ValuePartitioner<V>,
 {
-    pub fn new(part: Partitioning<KP, VP>, senders: Vec<Sender<WriterCommand<K, V>>>) -> Self {
-        Self { part, senders: senders.into() }
+    pub fn new(part: Partitioning<KP, VP>, senders: Vec<Sender<WriterCommand<K, V>>>, budgets: Vec<Arc<QueueBudget>>) -> Self {
+        Self { part, senders: senders.into(), budgets: budgets.into() }
+    }
+
+    /// Sends to shard `sid`, commands carrying data wait for room in the shard's queue budget first.
+    #[inline]
+    fn send(&self, sid: usize, msg: WriterCommand<K, V>) -> Result<(), AppError> {
+        if let Some(bytes) = msg.payload_bytes() {
+            self.budgets[sid].acquire(bytes)?;
+        }
+        fast_send(&self.senders[sid], msg)
     }
 
     #[inline]
@@ -88,7 +100,7 @@ where
 impl<K, V, KP, VP> Router<K, V> for ShardedRouter<K, V, KP, VP>
 where
     K: DbKey + Send,
-    V: DbVal + Send,
+    V: CacheKey + Send,
     KP: KeyPartitioner<K>,
     VP: ValuePartitioner<V>,
 {
@@ -99,11 +111,11 @@ where
     fn merge_unsorted_inserts(&self, pairs: Vec<(K, V)>, last_shards: Option<usize>) -> Result<(), AppError> {
         if !pairs.is_empty() {
             if self.senders.len() == 1 {
-                fast_send(&self.senders[0], WriterCommand::MergeUnsortedInserts(pairs))?;
+                self.send(0, WriterCommand::MergeUnsortedInserts(pairs))?;
             } else {
                 for (sid, bucket) in self.bucket(pairs).into_iter().enumerate() {
                     if bucket.is_empty() { continue; }
-                    fast_send(&self.senders[sid], WriterCommand::MergeUnsortedInserts(bucket))?;
+                    self.send(sid, WriterCommand::MergeUnsortedInserts(bucket))?;
                 }
             }
         }
@@ -114,8 +126,8 @@ where
     }
 
     fn ready_for_flush(&self, shards: usize) -> Result<(), AppError> {
-        for s in self.senders.iter() {
-            fast_send(s, WriterCommand::ReadyForFlush(shards))?;
+        for sid in 0..self.shards() {
+            self.send(sid, WriterCommand::ReadyForFlush(shards))?;
         }
         Ok(())
     }
@@ -124,11 +136,11 @@ where
         if pairs.is_empty() {
             Ok(())
         } else if self.senders.len() == 1 {
-            fast_send(&self.senders[0], WriterCommand::WriteSortedInsertsOnFlush(pairs))
+            self.send(0, WriterCommand::WriteSortedInsertsOnFlush(pairs))
         } else {
             for (sid, bucket) in self.bucket(pairs).into_iter().enumerate() {
                 if bucket.is_empty() { continue; }
-                fast_send(&self.senders[sid], WriterCommand::WriteSortedInsertsOnFlush(bucket))?;
+                self.send(sid, WriterCommand::WriteSortedInsertsOnFlush(bucket))?;
             }
             Ok(())
         }
@@ -136,30 +148,30 @@ where
 
     fn write_insert_now(&self, k: K, v: V) -> Result<(), AppError> {
         if self.senders.len() == 1 {
-            fast_send(&self.senders[0], WriterCommand::WriteInsertNow(k, v))
+            self.send(0, WriterCommand::WriteInsertNow(k, v))
         } else {
             let (sid, key, value) = self.bucket_one(k, v);
-            fast_send(&self.senders[sid], WriterCommand::WriteInsertNow(key, value))
+            self.send(sid, WriterCommand::WriteInsertNow(key, value))
         }
     }
 
     fn delete_kv(&self, key: K) -> Result<bool, AppError> {
         if self.senders.len() == 1 {
             let (ack_tx, ack_rx) = bounded::<Result<bool, AppError>>(1);
-            fast_send(&self.senders[0], WriterCommand::Remove(key, ack_tx))?;
+            self.send(0, WriterCommand::Remove(key, ack_tx))?;
             ack_rx.recv()?
         } else {
             match &self.part {
                 Partitioning::ByKey(kp) => {
                     let sid = kp.partition_key(key.borrow());
                     let (ack_tx, ack_rx) = bounded::<Result<bool, AppError>>(1);
-                    fast_send(&self.senders[sid], WriterCommand::Remove(key, ack_tx))?;
+                    self.send(sid, WriterCommand::Remove(key, ack_tx))?;
                     ack_rx.recv()?
                 },
                 Partitioning::ByValue(_) => {
-                    for s in self.senders.iter() {
+                    for sid in 0..self.shards() {
                         let (ack_tx, ack_rx) = bounded::<Result<bool, AppError>>(1);
-                        fast_send(s, WriterCommand::Remove(key, ack_tx))?;
+                        self.send(sid, WriterCommand::Remove(key, ack_tx))?;
                         if ack_rx.recv()?? {
                             return Ok(true);
                         }
@@ -173,10 +185,26 @@ where
     fn range(&self, from: K, until: K) -> Result<Vec<(ValueBuf<K>, ValueBuf<V>)>, AppError> {
         if self.senders.len() == 1 {
             let (ack_tx, ack_rx) = bounded::<Result<Vec<(ValueBuf<K>, ValueBuf<V>)>, AppError>>(1);
-            fast_send(&self.senders[0], WriterCommand::Range(from, until, ack_tx))?;
+            self.send(0, WriterCommand::Range(from, until, ack_tx))?;
             ack_rx.recv()?
         } else {
-            unimplemented!()
+            let sids = match &self.part {
+                Partitioning::ByKey(kp) => kp.partition_range(Bound::Included(from.borrow()), Bound::Excluded(until.borrow())),
+                Partitioning::ByValue(_) => None,
+            }.unwrap_or_else(|| (0..self.shards()).collect());
+            let mut acks = Vec::with_capacity(sids.len());
+            for sid in sids {
+                let (ack_tx, ack_rx) = bounded::<Result<Vec<(ValueBuf<K>, ValueBuf<V>)>, AppError>>(1);
+                self.send(sid, WriterCommand::Range(from, until, ack_tx))?;
+                acks.push(ack_rx);
+            }
+            let mut entries = Vec::new();
+            for ack in acks {
+                entries.extend(ack.recv()??);
+            }
+            // each shard's entries are sorted already, the stable sort merges the runs
+            entries.sort_by(|(a, _), (b, _)| K::compare(a.as_bytes(), b.as_bytes()));
+            Ok(entries)
         }
     }
 
@@ -188,7 +216,7 @@ where
     ) -> Result<(), AppError> {
         if self.senders.len() == 1 {
             let last_shards = if is_last { Some(1) } else { None };
-            fast_send(&self.senders[0], WriterCommand::QueryAndWrite { last_shards, values: values.into_iter().enumerate().collect(), sink })
+            self.send(0, WriterCommand::QueryAndWrite { last_shards, values: values.into_iter().enumerate().collect(), sink })
         } else {
             let vp = match &self.part {
                 Partitioning::ByValue(vp) => vp,
@@ -209,7 +237,7 @@ where
 
             for (sid, values) in buckets.into_iter().enumerate() {
                 if values.is_empty() && !is_last { continue; }
-                fast_send(&self.senders[sid], WriterCommand::QueryAndWrite { last_shards, values, sink: sink.clone() })?;
+                self.send(sid, WriterCommand::QueryAndWrite { last_shards, values, sink: sink.clone() })?;
             }
             Ok(())
         }
@@ -0,0 +1,345 @@
+use crate::storage::init::{DbDef, StorageOwner};
+use crate::{info, AppError, StructInfo};
+use redb::Value;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+pub const SCHEMA_VERSION: u32 = 1;
+pub const SCHEMA_FILE: &str = "schema.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnKind {
+    Plain,
+    Index,
+    Dict,
+}
+
+/// redb `TypeName` plus the fixed width, so that layout changes like a wider pointer index are caught too.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeSchema {
+    pub name: String,
+    pub width: Option<usize>,
+}
+
+impl TypeSchema {
+    pub fn of<T: Value>() -> Self {
+        TypeSchema { name: T::type_name().name().to_string(), width: T::fixed_width() }
+    }
+}
+
+impl fmt::Display for TypeSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.width {
+            Some(w) => write!(f, "{}({}b)", self.name, w),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnSchema {
+    pub entity: String,
+    pub column: String,
+    pub kind: ColumnKind,
+    pub shards: usize,
+    pub key: TypeSchema,
+    pub value: TypeSchema,
+    /// Non-default key partitioning like `range(1000)`, switching it moves keys to other shards.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+}
+
+impl ColumnSchema {
+    /// Shard count is deliberately not compared, it is handled by resharding.
+    fn differs(&self, other: &ColumnSchema) -> bool {
+        self.entity != other.entity || self.kind != other.kind || self.key != other.key || self.value != other.value || self.partition != other.partition
+    }
+}
+
+impl fmt::Display for ColumnSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{} {:?} {} -> {}", self.entity, self.column, self.kind, self.key, self.value)?;
+        match &self.partition {
+            Some(p) => write!(f, " by {}", p),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchemaChange {
+    Added(ColumnSchema),
+    Removed(ColumnSchema),
+    Changed { before: ColumnSchema, after: ColumnSchema },
+}
+
+impl SchemaChange {
+    pub fn column(&self) -> &str {
+        match self {
+            SchemaChange::Added(c) | SchemaChange::Removed(c) => &c.column,
+            SchemaChange::Changed { after, .. } => &after.column,
+        }
+    }
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaChange::Added(c) => write!(f, "column `{}` added: {}", c.column, c),
+            SchemaChange::Removed(c) => write!(f, "column `{}` removed: {}", c.column, c),
+            SchemaChange::Changed { before, after } => write!(f, "column `{}` changed: {}  =>  {}", after.column, before, after),
+        }
+    }
+}
+
+/// Migration step for a single column, registered with `inventory::submit!`. It runs before the dbs are opened
+/// and must leave the column files in the shape of the new schema.
+pub struct SchemaMigration {
+    pub column: &'static str,
+    pub migrate: fn(db_dir: &Path, change: &SchemaChange) -> Result<(), AppError>,
+}
+
+inventory::collect!(SchemaMigration);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaManifest {
+    pub version: u32,
+    pub columns: Vec<ColumnSchema>,
+}
+
+impl SchemaManifest {
+    pub fn new(mut columns: Vec<ColumnSchema>) -> Self {
+        columns.sort_by(|a, b| a.column.cmp(&b.column));
+        SchemaManifest { version: SCHEMA_VERSION, columns }
+    }
+
+    /// Schema of all entities registered in the inventory.
+    pub fn from_inventory() -> Result<Self, AppError> {
+        let mut columns = Vec::new();
+        for info in inventory::iter::<StructInfo> {
+            let shards: BTreeMap<String, usize> = (info.db_defs)().into_iter().map(|d| (d.name, d.shards)).collect();
+            for c in (info.column_tables)()? {
+                columns.push(ColumnSchema {
+                    entity: info.name.to_string(),
+                    column: c.name(),
+                    kind: c.kind(),
+                    shards: shards.get(&c.name()).copied().unwrap_or(1),
+                    key: c.key_type(),
+                    value: c.value_type(),
+                    partition: c.partition(),
+                });
+            }
+        }
+        Ok(Self::new(columns))
+    }
+
+    pub fn read(db_dir: &Path) -> Result<Option<Self>, AppError> {
+        let path = db_dir.join(SCHEMA_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&fs::read(path)?)?))
+    }
+
+    pub fn write(&self, db_dir: &Path) -> Result<(), AppError> {
+        let tmp = db_dir.join(format!("{}.tmp", SCHEMA_FILE));
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, db_dir.join(SCHEMA_FILE))?;
+        Ok(())
+    }
+
+    /// Changes needed to get from `self` (stored) to `expected` (compiled in).
+    pub fn diff(&self, expected: &SchemaManifest) -> Vec<SchemaChange> {
+        let before: BTreeMap<&str, &ColumnSchema> = self.columns.iter().map(|c| (c.column.as_str(), c)).collect();
+        let after: BTreeMap<&str, &ColumnSchema> = expected.columns.iter().map(|c| (c.column.as_str(), c)).collect();
+        let mut changes = Vec::new();
+        for (name, a) in &after {
+            match before.get(name) {
+                None => changes.push(SchemaChange::Added((*a).clone())),
+                Some(b) if b.differs(a) => changes.push(SchemaChange::Changed { before: (*b).clone(), after: (*a).clone() }),
+                Some(_) => {}
+            }
+        }
+        for (name, b) in &before {
+            if !after.contains_key(name) {
+                changes.push(SchemaChange::Removed((*b).clone()));
+            }
+        }
+        changes
+    }
+}
+
+impl StorageOwner {
+    /// Compares the schema stored in `db_dir` with the registered entities, see `check_schema_with`.
+    pub fn check_schema(db_dir: &Path) -> Result<Vec<SchemaChange>, AppError> {
+        let migrations: Vec<&SchemaMigration> = inventory::iter::<SchemaMigration>.into_iter().collect();
+        Self::check_schema_with(db_dir, &SchemaManifest::from_inventory()?, &migrations)
+    }
+
+    /// Diffs the stored schema against `expected`, runs a registered migration for every changed column and fails
+    /// with a report of all changes that have none, before any db is opened. The manifest is rewritten on success.
+    pub fn check_schema_with(db_dir: &Path, expected: &SchemaManifest, migrations: &[&SchemaMigration]) -> Result<Vec<SchemaChange>, AppError> {
+        let stored = match SchemaManifest::read(db_dir)? {
+            Some(stored) => stored,
+            None => {
+                info!("No schema manifest at {:?}, recording the current one", db_dir);
+                expected.write(db_dir)?;
+                return Ok(Vec::new());
+            }
+        };
+        if stored.version > SCHEMA_VERSION {
+            return Err(AppError::Custom(format!("schema manifest version {} is newer than supported {}", stored.version, SCHEMA_VERSION)));
+        }
+        let changes = stored.diff(expected);
+        let unmigrated: Vec<String> = changes.iter()
+            .filter(|ch| !migrations.iter().any(|m| m.column == ch.column()))
+            .map(|ch| ch.to_string())
+            .collect();
+        if !unmigrated.is_empty() {
+            return Err(AppError::Custom(format!(
+                "schema at {:?} does not match the entities and no migration is registered for:\n{}", db_dir, unmigrated.join("\n")
+            )));
+        }
+        for change in &changes {
+            for m in migrations.iter().filter(|m| m.column == change.column()) {
+                info!("Migrating {}", change);
+                (m.migrate)(db_dir, change)?;
+            }
+        }
+        if stored != *expected {
+            expected.write(db_dir)?;
+        }
+        Ok(changes)
+    }
+
+    /// Checks the entities against the dbs about to be created, for storage without a stored manifest to diff against
+    /// like the in-memory one. Every column needs a db of its own with the column's shard count.
+    pub fn check_schema_defs(expected: &SchemaManifest, db_defs: &[DbDef]) -> Result<(), AppError> {
+        let mut problems = Vec::new();
+        for pair in expected.columns.windows(2) {
+            if pair[0].column == pair[1].column {
+                problems.push(format!("column `{}` is declared by both {} and {}", pair[0].column, pair[0].entity, pair[1].entity));
+            }
+        }
+        for c in &expected.columns {
+            match db_defs.iter().find(|d| d.name == c.column) {
+                None => problems.push(format!("column `{}` of {} has no db", c.column, c.entity)),
+                Some(d) if d.shards != c.shards => problems.push(format!("column `{}` has {} shards but its db {}", c.column, c.shards, d.shards)),
+                Some(_) => {}
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Custom(format!("schema does not match the dbs:\n{}", problems.join("\n"))))
+        }
+    }
+
+    /// Fails on any difference between the stored schema and the registered entities, migrating is left to the indexer.
+    pub fn check_schema_read_only(db_dir: &Path) -> Result<(), AppError> {
+        let expected = SchemaManifest::from_inventory()?;
+        match SchemaManifest::read(db_dir)? {
+            None => Ok(()),
+            Some(stored) => {
+                let changes: Vec<String> = stored.diff(&expected).iter().map(|ch| ch.to_string()).collect();
+                if changes.is_empty() {
+                    Ok(())
+                } else {
+                    Err(AppError::Custom(format!(
+                        "schema at {:?} does not match the entities, let the indexer migrate it first:\n{}", db_dir, changes.join("\n")
+                    )))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+    use crate::storage::test_utils::mk_tmp_dir;
+
+    fn column(name: &str, kind: ColumnKind, value: &str) -> ColumnSchema {
+        ColumnSchema {
+            entity: "Utxo".to_string(),
+            column: name.to_string(),
+            kind,
+            shards: 1,
+            key: TypeSchema { name: "TxPointer".to_string(), width: Some(8) },
+            value: TypeSchema { name: value.to_string(), width: None },
+            partition: None,
+        }
+    }
+
+    #[test]
+    fn diff_reports_kind_type_and_membership_changes_but_not_shards() {
+        let stored = SchemaManifest::new(vec![
+            column("utxo_address", ColumnKind::Index, "Address"),
+            column("utxo_amount", ColumnKind::Plain, "u64"),
+            column("utxo_script", ColumnKind::Plain, "Script"),
+        ]);
+        let mut resharded = column("utxo_amount", ColumnKind::Plain, "u64");
+        resharded.shards = 4;
+        let expected = SchemaManifest::new(vec![
+            column("utxo_address", ColumnKind::Dict, "Address"),
+            resharded,
+            column("utxo_datum", ColumnKind::Plain, "Datum"),
+        ]);
+        let changes = stored.diff(&expected);
+        assert_eq!(changes.len(), 3, "{changes:?}");
+        assert!(matches!(&changes[0], SchemaChange::Changed { before, after } if before.kind == ColumnKind::Index && after.kind == ColumnKind::Dict));
+        assert!(matches!(&changes[1], SchemaChange::Added(c) if c.column == "utxo_datum"));
+        assert!(matches!(&changes[2], SchemaChange::Removed(c) if c.column == "utxo_script"));
+
+        let mut by_range = column("utxo_amount", ColumnKind::Plain, "u64");
+        by_range.partition = Some("range(1000)".to_string());
+        let repartitioned = SchemaManifest::new(vec![column("utxo_amount", ColumnKind::Plain, "u64")]).diff(&SchemaManifest::new(vec![by_range]));
+        assert!(matches!(repartitioned.as_slice(), [SchemaChange::Changed { .. }]), "{repartitioned:?}");
+    }
+
+    #[test]
+    fn check_schema_defs_requires_a_db_per_column() {
+        let db = |name: &str, shards: usize| DbDef { name: name.to_string(), shards, db_cache_weight_or_zero: 0, lru_cache_size_or_zero: 0 };
+        let mut sharded = column("utxo_amount", ColumnKind::Plain, "u64");
+        sharded.shards = 2;
+        let expected = SchemaManifest::new(vec![column("utxo_address", ColumnKind::Index, "Address"), sharded]);
+        assert!(StorageOwner::check_schema_defs(&expected, &[db("utxo_address", 1), db("utxo_amount", 2)]).is_ok());
+
+        let err = StorageOwner::check_schema_defs(&expected, &[db("utxo_amount", 1)]).expect_err("mismatch");
+        assert!(err.to_string().contains("column `utxo_address` of Utxo has no db"), "{err}");
+        assert!(err.to_string().contains("column `utxo_amount` has 2 shards but its db 1"), "{err}");
+
+        let mut twice = column("utxo_address", ColumnKind::Index, "Address");
+        twice.entity = "Asset".to_string();
+        let duplicated = SchemaManifest::new(vec![column("utxo_address", ColumnKind::Index, "Address"), twice]);
+        let err = StorageOwner::check_schema_defs(&duplicated, &[db("utxo_address", 1)]).expect_err("duplicate");
+        assert!(err.to_string().contains("is declared by both"), "{err}");
+    }
+
+    #[test]
+    fn check_schema_fails_without_migration_and_runs_registered_one() {
+        let dir = mk_tmp_dir("schema");
+        let v1 = SchemaManifest::new(vec![column("utxo_address", ColumnKind::Index, "Address")]);
+        assert!(StorageOwner::check_schema_with(&dir, &v1, &[]).expect("first start").is_empty());
+        assert_eq!(SchemaManifest::read(&dir).expect("read"), Some(v1.clone()));
+
+        let v2 = SchemaManifest::new(vec![column("utxo_address", ColumnKind::Dict, "Address")]);
+        let err = StorageOwner::check_schema_with(&dir, &v2, &[]).expect_err("kind change");
+        assert!(err.to_string().contains("column `utxo_address` changed"), "{err}");
+        assert_eq!(SchemaManifest::read(&dir).expect("read"), Some(v1), "failed check must keep the stored manifest");
+
+        fn drop_column(db_dir: &Path, change: &SchemaChange) -> Result<(), AppError> {
+            fs::write(db_dir.join(format!("{}.migrated", change.column())), b"")?;
+            Ok(())
+        }
+        let migration = SchemaMigration { column: "utxo_address", migrate: drop_column };
+        let changes = StorageOwner::check_schema_with(&dir, &v2, &[&migration]).expect("migrated");
+        assert_eq!(changes.len(), 1);
+        assert!(dir.join("utxo_address.migrated").exists());
+        assert_eq!(SchemaManifest::read(&dir).expect("read"), Some(v2));
+    }
+}
@@ -0,0 +1,453 @@
+use crate::storage::init::{inventory_columns, shard_suffix, DbDef, Storage, StorageOwner};
+use crate::storage::layout::DbLayout;
+use crate::storage::table_writer_api::ColumnTables;
+use crate::{info, AppError, StructInfo};
+use chrono::Utc;
+use redb::{Database, Key, MultimapTableDefinition, ReadTransaction, ReadableDatabase, ReadableMultimapTable, ReadableTable, TableDefinition, TableError, Value, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+const PIN_RETRY_DELAY: Duration = Duration::from_millis(10);
+/// Snapshots wait up to a minute for commits to pause.
+const PIN_MAX_ATTEMPTS: usize = 6_000;
+/// Reads wait half a second, they hold a read permit meanwhile and the client can retry.
+const PIN_READ_MAX_ATTEMPTS: usize = 50;
+
+/// Seqlock-like fence around two-phase commits. Each column commits its shards independently,
+/// so a snapshot is only consistent if every read transaction is opened while no commit is in
+/// flight and no commit completed in between, which is what `quiescent_epoch` + `is_unchanged_since` verify.
+#[derive(Debug, Default)]
+pub struct CommitFence {
+    in_flight: AtomicUsize,
+    epoch: AtomicU64,
+    last_commit: AtomicU64,
+}
+
+pub struct CommitGuard(Arc<CommitFence>);
+
+impl Drop for CommitGuard {
+    fn drop(&mut self) {
+        self.0.epoch.fetch_add(1, Ordering::SeqCst);
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl CommitFence {
+    pub fn enter(self: &Arc<Self>) -> CommitGuard {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        CommitGuard(Arc::clone(self))
+    }
+
+    pub fn quiescent_epoch(&self) -> Option<u64> {
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        if self.in_flight.load(Ordering::SeqCst) == 0 { Some(epoch) } else { None }
+    }
+
+    pub fn is_unchanged_since(&self, epoch: u64) -> bool {
+        self.in_flight.load(Ordering::SeqCst) == 0 && self.epoch.load(Ordering::SeqCst) == epoch
+    }
+
+    /// Epoch for the commit markers of a write context that is about to begin.
+    pub fn next_commit(&self) -> u64 {
+        self.last_commit.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Continues after the last commit found on disk, so that markers keep increasing across restarts.
+    pub fn resume_after(&self, epoch: u64) {
+        self.last_commit.fetch_max(epoch, Ordering::SeqCst);
+    }
+}
+
+/// Value read from transactions that all reflect the same commit, `height` is the last root key committed at that point
+/// if the storage holds a root entity.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PinnedRead<T> {
+    pub value: T,
+    pub height: Option<u128>,
+}
+
+impl<T> PinnedRead<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> PinnedRead<U> {
+        PinnedRead { value: f(self.value), height: self.height }
+    }
+
+    pub fn try_map<U>(self, f: impl FnOnce(T) -> Result<U, AppError>) -> Result<PinnedRead<U>, AppError> {
+        Ok(PinnedRead { value: f(self.value)?, height: self.height })
+    }
+}
+
+impl Storage {
+    /// Opens read transactions through `open` at a moment with no commit in flight and retries if a commit landed
+    /// meanwhile, the same fence `snapshot_with` pins its transactions with. Columns are separate dbs, so transactions
+    /// opened one by one could otherwise see a parent committed and its children not yet. Gives up with
+    /// `AppError::Unavailable` if commits do not pause within `PIN_READ_MAX_ATTEMPTS`.
+    pub fn pin_read<T>(self: &Arc<Self>, mut open: impl FnMut(&Arc<Storage>) -> Result<T, AppError>) -> Result<PinnedRead<T>, AppError> {
+        for _ in 0..PIN_READ_MAX_ATTEMPTS {
+            if let Some(epoch) = self.commit_fence.quiescent_epoch() {
+                let value = open(self)?;
+                let height = self.root_height()?;
+                if self.commit_fence.is_unchanged_since(epoch) {
+                    return Ok(PinnedRead { value, height });
+                }
+            }
+            std::thread::sleep(PIN_RETRY_DELAY);
+        }
+        Err(AppError::Unavailable(format!("unable to pin consistent read transactions after {} attempts, commits never paused", PIN_READ_MAX_ATTEMPTS)))
+    }
+
+    /// Last committed key of the root entities, the highest one if there are more of them.
+    pub fn root_height(self: &Arc<Self>) -> Result<Option<u128>, AppError> {
+        let mut height = None;
+        for info in inventory::iter::<StructInfo>.into_iter().filter(|info| info.root) {
+            match (info.root_height)(self) {
+                Ok(h) => height = height.max(h),
+                Err(AppError::RedbTable(TableError::TableDoesNotExist(_))) => {} // nothing committed yet
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(height)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotFile {
+    pub file: String,
+    pub entries: u64,
+    pub bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_key: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotColumn {
+    pub name: String,
+    pub shards: usize,
+    pub root: bool,
+    pub files: Vec<SnapshotFile>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub version: u32,
+    pub created_at: String,
+    pub columns: Vec<SnapshotColumn>,
+}
+
+impl SnapshotManifest {
+    pub fn read(snapshot_dir: &Path) -> Result<Self, AppError> {
+        let bytes = fs::read(snapshot_dir.join(MANIFEST_FILE))?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    fn write(&self, snapshot_dir: &Path) -> Result<(), AppError> {
+        let tmp = snapshot_dir.join(format!("{}.tmp", MANIFEST_FILE));
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, snapshot_dir.join(MANIFEST_FILE))?;
+        Ok(())
+    }
+
+    /// Checks the manifest against the expected column layout and the files on disk, all problems are reported at once.
+    pub fn validate(&self, snapshot_dir: &Path, db_defs: &[DbDef]) -> Result<(), AppError> {
+        let mut problems = Vec::new();
+        if self.version != SNAPSHOT_VERSION {
+            problems.push(format!("unsupported snapshot version {}, expected {}", self.version, SNAPSHOT_VERSION));
+        }
+        let expected: BTreeMap<&str, usize> = db_defs.iter().map(|d| (d.name.as_str(), d.shards)).collect();
+        let actual: BTreeMap<&str, &SnapshotColumn> = self.columns.iter().map(|c| (c.name.as_str(), c)).collect();
+        for (name, shards) in &expected {
+            match actual.get(name) {
+                None => problems.push(format!("column `{}` is missing in snapshot", name)),
+                Some(c) if c.shards != *shards => problems.push(format!("column `{}` has {} shards in snapshot, expected {}", name, c.shards, shards)),
+                Some(c) if c.files.len() != c.shards => problems.push(format!("column `{}` lists {} files for {} shards", name, c.files.len(), c.shards)),
+                Some(_) => {}
+            }
+        }
+        for name in actual.keys().filter(|n| !expected.contains_key(*n)) {
+            problems.push(format!("column `{}` in snapshot is unknown to the schema", name));
+        }
+        for f in self.columns.iter().flat_map(|c| c.files.iter()) {
+            match fs::metadata(snapshot_dir.join(&f.file)) {
+                Ok(m) if m.len() != f.bytes => problems.push(format!("file `{}` has {} bytes, manifest says {}", f.file, m.len(), f.bytes)),
+                Ok(_) => {}
+                Err(e) => problems.push(format!("file `{}`: {}", f.file, e)),
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Custom(format!("invalid snapshot at {:?}:\n{}", snapshot_dir, problems.join("\n"))))
+        }
+    }
+}
+
+pub(crate) fn copy_table<K: Key + 'static, V: Value + 'static>(
+    src: &ReadTransaction,
+    dst: &WriteTransaction,
+    def: TableDefinition<'static, K, V>,
+) -> Result<u64, AppError> {
+    let source = match src.open_table(def) {
+        Ok(t) => t,
+        Err(TableError::TableDoesNotExist(_)) => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut target = dst.open_table(def)?;
+    let mut count = 0u64;
+    for entry in source.iter()? {
+        let (k, v) = entry?;
+        target.insert(k.value(), v.value())?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub(crate) fn copy_multimap_table<K: Key + 'static, V: Key + 'static>(
+    src: &ReadTransaction,
+    dst: &WriteTransaction,
+    def: MultimapTableDefinition<'static, K, V>,
+) -> Result<u64, AppError> {
+    let source = match src.open_multimap_table(def) {
+        Ok(t) => t,
+        Err(TableError::TableDoesNotExist(_)) => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut target = dst.open_multimap_table(def)?;
+    let mut count = 0u64;
+    for entry in source.iter()? {
+        let (k, values) = entry?;
+        for v in values {
+            target.insert(k.value(), v?.value())?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+pub(crate) fn last_key<K: Key + 'static, V: Value + 'static>(tx: &ReadTransaction, def: TableDefinition<'static, K, V>) -> Result<Option<String>, AppError> {
+    let table = match tx.open_table(def) {
+        Ok(t) => t,
+        Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(table.last()?.map(|(k, _)| format!("{:?}", k.value())))
+}
+
+fn inventory_db_defs() -> Vec<DbDef> {
+    inventory::iter::<StructInfo>.into_iter().flat_map(|info| (info.db_defs)()).collect()
+}
+
+impl StorageOwner {
+    /// Snapshots every column of all registered entities into `target_dir`, see `snapshot_with`.
+    pub async fn snapshot(&self, target_dir: PathBuf) -> Result<SnapshotManifest, AppError> {
+        self.snapshot_with(target_dir, inventory_columns()?).await
+    }
+
+    /// Copies all shards of all columns into `target_dir` while the indexer keeps running.
+    /// Read transactions are opened on every shard at a moment with no commit in flight,
+    /// so all files agree on the same committed root height. The manifest is written last.
+    pub async fn snapshot_with(&self, target_dir: PathBuf, columns: Vec<Arc<dyn ColumnTables>>) -> Result<SnapshotManifest, AppError> {
+        if target_dir.exists() && fs::read_dir(&target_dir)?.next().is_some() {
+            return Err(AppError::Custom(format!("snapshot target {:?} is not empty", target_dir)));
+        }
+        fs::create_dir_all(&target_dir)?;
+
+        let tables: HashMap<String, Arc<dyn ColumnTables>> = columns.into_iter().map(|c| (c.name(), c)).collect();
+        let mut names: Vec<&String> = self.index_dbs.keys().collect();
+        names.sort();
+        for name in &names {
+            if !tables.contains_key(*name) {
+                return Err(AppError::Custom(format!("column `{}`: no table definitions to snapshot it with", name)));
+            }
+        }
+
+        let pinned = self.pin_read_txs(&names).await?;
+        info!("Snapshotting {} columns into {:?}", names.len(), target_dir);
+
+        let copy_tasks = pinned.into_iter().map(|(name, shards, idx, src)| {
+            let column = Arc::clone(&tables[&name]);
+            let path = StorageOwner::db_file_path(&target_dir, &name, shard_suffix(shards, idx));
+            tokio::task::spawn_blocking(move || -> Result<(String, usize, SnapshotFile), AppError> {
+                let entries = {
+                    let db = Database::create(&path)?;
+                    let dst = db.begin_write()?;
+                    let entries = column.copy_tables(&src, &dst)?;
+                    dst.commit()?;
+                    entries
+                };
+                let last_key = if column.is_root() { column.last_key(&src)? } else { None };
+                let file = path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+                let bytes = fs::metadata(&path)?.len();
+                Ok((name, idx, SnapshotFile { file, entries, bytes, last_key }))
+            })
+        });
+
+        let mut files: BTreeMap<String, Vec<(usize, SnapshotFile)>> = BTreeMap::new();
+        for res in futures_util::future::try_join_all(copy_tasks).await? {
+            let (name, idx, file) = res?;
+            files.entry(name).or_default().push((idx, file));
+        }
+
+        let columns = files.into_iter().map(|(name, mut shard_files)| {
+            shard_files.sort_by_key(|(idx, _)| *idx);
+            SnapshotColumn {
+                shards: shard_files.len(),
+                root: tables[&name].is_root(),
+                files: shard_files.into_iter().map(|(_, f)| f).collect(),
+                name,
+            }
+        }).collect();
+
+        let manifest = SnapshotManifest { version: SNAPSHOT_VERSION, created_at: Utc::now().to_rfc3339(), columns };
+        manifest.write(&target_dir)?;
+        Ok(manifest)
+    }
+
+    async fn pin_read_txs(&self, names: &[&String]) -> Result<Vec<(String, usize, usize, ReadTransaction)>, AppError> {
+        for _ in 0..PIN_MAX_ATTEMPTS {
+            if let Some(epoch) = self.commit_fence.quiescent_epoch() {
+                let mut pinned = Vec::new();
+                for name in names {
+                    let shards = self.index_dbs[*name].shards();
+                    for (idx, db) in shards.iter().enumerate() {
+                        pinned.push(((*name).clone(), shards.len(), idx, db.begin_read()?));
+                    }
+                }
+                if self.commit_fence.is_unchanged_since(epoch) {
+                    return Ok(pinned);
+                }
+            }
+            tokio::time::sleep(PIN_RETRY_DELAY).await;
+        }
+        Err(AppError::Custom(format!("unable to pin a consistent snapshot after {} attempts, commits never paused", PIN_MAX_ATTEMPTS)))
+    }
+
+    /// Restores a snapshot of all registered entities into `layout`, see `restore_with`.
+    pub async fn restore(snapshot_dir: PathBuf, layout: DbLayout, db_cache_size_gb: u8) -> Result<(StorageOwner, Arc<crate::Storage>), AppError> {
+        Self::restore_with(snapshot_dir, layout, inventory_db_defs(), db_cache_size_gb).await
+    }
+
+    /// Validates the snapshot manifest against `db_defs` before anything is copied into the layout root, which must
+    /// not exist yet. Shard files are copied straight into their mounted dirs.
+    pub async fn restore_with(snapshot_dir: PathBuf, layout: DbLayout, db_defs: Vec<DbDef>, db_cache_size_gb: u8) -> Result<(StorageOwner, Arc<crate::Storage>), AppError> {
+        if layout.root.exists() {
+            return Err(AppError::Custom(format!("restore target {:?} already exists", layout.root)));
+        }
+        let manifest = SnapshotManifest::read(&snapshot_dir)?;
+        manifest.validate(&snapshot_dir, &db_defs)?;
+        layout.validate(&db_defs)?;
+
+        layout.create_dirs(&db_defs)?;
+        for c in &manifest.columns {
+            for (idx, f) in c.files.iter().enumerate() {
+                fs::copy(snapshot_dir.join(&f.file), layout.file_path(&c.name, shard_suffix(c.shards, idx)))?;
+            }
+        }
+        info!("Restored snapshot from {} into {:?}", manifest.created_at, layout.root);
+        let (_, owner, view) = StorageOwner::init_with_layout(layout, db_defs, db_cache_size_gb, true).await?;
+        Ok((owner, view))
+    }
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+    use crate::storage::table_plain::PlainFactory;
+    use crate::storage::table_writer_api::{ReadTableLike, RedbitTableDefinition, ShardedTableReader, WriterLike};
+    use crate::storage::test_utils::{addr, tmp_dir, Address};
+    use crate::{BytesPartitioner, Partitioning, Xxh3Partitioner};
+    use redb::Durability;
+
+    type PlainDef = RedbitTableDefinition<u32, Address, BytesPartitioner, Xxh3Partitioner, PlainFactory<u32, Address>>;
+
+    fn plain_def(name: &'static str, shards: usize) -> (PlainDef, DbDef) {
+        let def = RedbitTableDefinition::new(true, Partitioning::by_key(shards), PlainFactory::new(name, TableDefinition::new(name)));
+        (def, DbDef { name: name.to_string(), shards, db_cache_weight_or_zero: 0, lru_cache_size_or_zero: 0 })
+    }
+
+    #[test]
+    fn commit_fence_tracks_in_flight_commits() {
+        let fence = Arc::new(CommitFence::default());
+        let epoch = fence.quiescent_epoch().expect("quiescent");
+        let guard = fence.enter();
+        assert!(fence.quiescent_epoch().is_none());
+        assert!(!fence.is_unchanged_since(epoch));
+        drop(guard);
+        assert_eq!(fence.quiescent_epoch(), Some(epoch + 1));
+        assert!(!fence.is_unchanged_since(epoch));
+    }
+
+    #[tokio::test]
+    async fn pinned_read_waits_for_commits_in_flight() {
+        let (_, db_def) = plain_def("pin_plain", 1);
+        let (_, _owner, storage) = StorageOwner::init(tmp_dir("pin_src"), vec![db_def], 0, false).await.expect("init");
+        let guard = storage.commit_fence.enter();
+        let reader = {
+            let storage = Arc::clone(&storage);
+            std::thread::spawn(move || storage.pin_read(|_| Ok(storage.commit_fence.quiescent_epoch())))
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!reader.is_finished(), "read must not be pinned while a commit is in flight");
+        drop(guard);
+        let pinned = reader.join().unwrap().expect("pinned");
+        assert_eq!(pinned.value, Some(1), "read is pinned after the commit");
+        assert_eq!(pinned.height, None, "no root entity is registered");
+
+        let _guard = storage.commit_fence.enter();
+        let busy = storage.pin_read(|_| Ok(()));
+        assert!(matches!(busy, Err(AppError::Unavailable(_))), "read gives up while commits never pause");
+    }
+
+    #[tokio::test]
+    async fn snapshot_and_restore_roundtrip() {
+        let (def, db_def) = plain_def("snap_plain", 2);
+        let (_, owner, storage) = StorageOwner::init(tmp_dir("snap_src"), vec![db_def.clone()], 0, false).await.expect("init");
+
+        let writer = def.writer(&storage).expect("writer");
+        writer.begin(Durability::Immediate).expect("begin");
+        for k in 1u32..=20 {
+            writer.insert_on_flush(k, addr(&[k as u8])).expect("insert");
+        }
+        writer.flush().expect("flush");
+        writer.shutdown().expect("shutdown");
+
+        let snapshot_dir = tmp_dir("snap_target");
+        let columns: Vec<Arc<dyn ColumnTables>> = vec![Arc::new(def)];
+        let manifest = owner.snapshot_with(snapshot_dir.clone(), columns).await.expect("snapshot");
+        assert_eq!(manifest.columns.len(), 1);
+        assert_eq!(manifest.columns[0].files.iter().map(|f| f.entries).sum::<u64>(), 20);
+        assert!(manifest.columns[0].files.iter().any(|f| f.last_key.is_some()));
+        assert_eq!(SnapshotManifest::read(&snapshot_dir).expect("manifest"), manifest);
+
+        let (def, db_def) = plain_def("snap_plain", 2);
+        let (_owner, restored) = StorageOwner::restore_with(snapshot_dir, DbLayout::new(tmp_dir("snap_restored")), vec![db_def], 0).await.expect("restore");
+        let ShardedTableReader::Plain(reader) = def.reader(&restored).expect("reader") else { panic!("plain reader expected") };
+        for k in 1u32..=20 {
+            let got = reader.get_value(&k).expect("get").expect("some");
+            assert_eq!(got.value().0, vec![k as u8]);
+        }
+    }
+
+    #[tokio::test]
+    async fn restore_rejects_mismatched_layout() {
+        let (def, db_def) = plain_def("snap_layout", 2);
+        let (_, owner, _storage) = StorageOwner::init(tmp_dir("snap_layout_src"), vec![db_def], 0, false).await.expect("init");
+        let snapshot_dir = tmp_dir("snap_layout_target");
+        owner.snapshot_with(snapshot_dir.clone(), vec![Arc::new(def)]).await.expect("snapshot");
+
+        let (_, resharded) = plain_def("snap_layout", 3);
+        let (_, unknown) = plain_def("snap_unknown", 1);
+        let db_dir = tmp_dir("snap_layout_restored");
+        let err = StorageOwner::restore_with(snapshot_dir, DbLayout::new(db_dir.clone()), vec![resharded, unknown], 0).await.err().expect("must fail");
+        let msg = err.to_string();
+        assert!(msg.contains("has 2 shards in snapshot, expected 3"), "{msg}");
+        assert!(msg.contains("`snap_unknown` is missing"), "{msg}");
+        assert!(!db_dir.exists(), "nothing may be copied before validation passes");
+    }
+}
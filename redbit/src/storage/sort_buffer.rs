@@ -1,18 +1,25 @@
-use crate::{DbKey, DbVal};
+use crate::storage::spill::{ExternalMerge, SpillConfig, SpilledRun};
+use crate::{AppError, DbKey, DbVal};
 use redb::Key;
 use std::borrow::Borrow;
 use std::cmp::{Ordering, Reverse};
 use std::collections::BinaryHeap;
+use std::sync::Arc;
 
 /// Keeps at most one sorted run at each level; on push it “carries” upward by merging.
 /// Total moves per element: O(log N). Flush typically deals with <= 13 runs for 25k items.
+/// With a [`SpillConfig`] the levels are merged into one run and written to disk whenever they
+/// outgrow the threshold, the spilled runs are then merged from disk at flush.
 #[derive(Clone)]
 pub struct MergeBuffer<K, V> {
     levels: Vec<Option<Vec<(K, V)>>>,
+    spill: Option<SpillConfig>,
+    spilled: Vec<Arc<SpilledRun>>,
+    buffered_bytes: usize,
 }
 
 impl<K, V> Default for MergeBuffer<K, V> {
-    fn default() -> Self { Self { levels: Vec::new() } }
+    fn default() -> Self { Self { levels: Vec::new(), spill: None, spilled: Vec::new(), buffered_bytes: 0 } }
 }
 
 impl<K, V> MergeBuffer<K, V>
@@ -20,27 +27,69 @@ where
     K: DbKey,
     V: DbVal
 {
-    pub fn new() -> Self { Self { levels: Vec::new() } }
+    pub fn new() -> Self { Self::default() }
+
+    pub fn with_spill(mut self, spill: Option<SpillConfig>) -> Self {
+        self.spill = spill;
+        self
+    }
 
     /// Drop all runs without merging.
     pub fn clear(&mut self) {
         self.levels.clear(); // drops inner Vecs; O(total_len)
+        self.spilled.clear(); // deletes the run files
+        self.buffered_bytes = 0;
     }
 
     /// Is the buffer currently empty?
     pub fn is_empty(&self) -> bool {
-        self.levels.iter().all(|s| s.is_none())
+        self.levels.iter().all(|s| s.is_none()) && self.spilled.is_empty()
     }
-    /// Construct from an **already sorted** run. No work needed on drain.
-    pub fn from_sorted(sorted: Vec<(K, V)>) -> Self {
-        if sorted.is_empty() {
-            return Self::new();
+
+    /// Counts the encoded size of an incoming run, only needed when the buffer may spill.
+    fn track(&mut self, run: &[(K, V)]) {
+        if self.spill.is_none() { return; }
+        let bytes = match (K::fixed_width(), V::fixed_width()) {
+            (Some(kw), Some(vw)) => run.len() * (kw + vw),
+            _ => run.iter().map(|(k, v)| K::as_bytes(k.borrow()).as_ref().len() + V::as_bytes(v.borrow()).as_ref().len()).sum(),
+        };
+        self.buffered_bytes += bytes;
+    }
+
+    /// Writes the in-memory levels to disk as one sorted run once they outgrow the spill threshold.
+    pub fn spill_if_needed(&mut self) -> Result<(), AppError> {
+        match &self.spill {
+            Some(cfg) if self.buffered_bytes > cfg.threshold_bytes => self.spill_levels(),
+            _ => Ok(()),
         }
-        MergeBuffer { levels: vec![Some(sorted)] }
+    }
+
+    fn spill_levels(&mut self) -> Result<(), AppError> {
+        let dir = match &self.spill {
+            Some(cfg) => cfg.dir.clone(),
+            None => return Ok(()),
+        };
+        let run = self.take_sorted();
+        self.buffered_bytes = 0;
+        if !run.is_empty() {
+            self.spilled.push(Arc::new(SpilledRun::write(&dir, &run)?));
+        }
+        Ok(())
+    }
+
+    /// When anything was spilled, spills the rest too and returns the merge of all runs in the order they were
+    /// spilled, so that pairs with equal keys keep their insertion order. `None` means use [`Self::take_sorted`].
+    pub fn take_spilled(&mut self) -> Result<Option<ExternalMerge<K, V>>, AppError> {
+        if self.spilled.is_empty() {
+            return Ok(None);
+        }
+        self.spill_levels()?;
+        ExternalMerge::new(std::mem::take(&mut self.spilled)).map(Some)
     }
 
     pub fn merge_unsorted(&mut self, mut run: Vec<(K, V)>) {
         if run.is_empty() { return; }
+        self.track(&run);
         run.sort_by(|(a,_),(b,_)| K::compare(K::as_bytes(a.borrow()).as_ref(), K::as_bytes(b.borrow()).as_ref()));
         self.merge_sorted(run);
     }
@@ -48,6 +97,7 @@ where
     pub fn append_sorted(&mut self, run: Vec<(K, V)>) {
         use std::cmp::Ordering;
         if run.is_empty() { return; }
+        self.track(&run);
 
         #[inline]
         fn last_key<K>(r: &[(K, impl Sized)]) -> &K { &r[r.len() - 1].0 }
@@ -261,9 +311,19 @@ where
         let (_, idx) = heap.pop().unwrap();
         pool[idx].take().unwrap_or_default()
     }
+}
+
+#[cfg(test)]
+impl<K: DbKey, V: DbVal> MergeBuffer<K, V> {
+    /// Construct from an **already sorted** run. No work needed on drain.
+    pub(crate) fn from_sorted(sorted: Vec<(K, V)>) -> Self {
+        if sorted.is_empty() {
+            return Self::new();
+        }
+        MergeBuffer { levels: vec![Some(sorted)], ..Self::default() }
+    }
 
-    #[allow(dead_code)]
-    pub fn runs(&self) -> usize {
+    pub(crate) fn runs(&self) -> usize {
         self.levels.iter().filter(|s| s.is_some()).count()
     }
 }
@@ -1065,7 +1125,7 @@ mod bench {
 
     /// Create a MergeBuffer with each provided run placed as a separate level slot.
     fn mk_buf_from_runs(runs: Vec<Vec<(TxHash, u32)>>) -> MergeBuffer<TxHash, u32> {
-        MergeBuffer { levels: runs.into_iter().map(Some).collect() }
+        MergeBuffer { levels: runs.into_iter().map(Some).collect(), ..MergeBuffer::default() }
     }
 
     // Build a sorted run whose keys are *contiguous counters*, starting at `start_ctr`.
@@ -0,0 +1,219 @@
+use crate::{AppError, DbKey, DbVal};
+use redb::{Key, Value};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::Arc;
+
+/// Directory under the storage root holding runs of writers that outgrew their memory threshold.
+pub const SPILL_DIR: &str = ".spill";
+/// Pairs handed to the table per write when a spilled batch is merged at flush.
+pub const SPILL_CHUNK_PAIRS: usize = 64 * 1024;
+
+static RUN_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Encoded key and value of one pair.
+pub type EncodedPair = (Vec<u8>, Vec<u8>);
+
+/// Where and past how many buffered bytes a writer spills its sorted runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpillConfig {
+    pub dir: PathBuf,
+    pub threshold_bytes: usize,
+}
+
+impl SpillConfig {
+    pub fn new(dir: PathBuf, threshold_bytes: usize) -> Self {
+        Self { dir, threshold_bytes }
+    }
+
+    /// Runs are deleted after their flush, the ones left behind belong to a killed process.
+    pub fn remove_stale_runs(&self) -> Result<(), AppError> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fixed-width values are written as they are, the others with a length prefix.
+fn write_field<T: Value>(w: &mut impl Write, bytes: &[u8]) -> std::io::Result<()> {
+    if T::fixed_width().is_none() {
+        w.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    }
+    w.write_all(bytes)
+}
+
+fn read_field<T: Value>(r: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let len = match T::fixed_width() {
+        Some(w) => w,
+        None => {
+            let mut len = [0u8; 4];
+            r.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+    };
+    let mut buf = vec![0u8; len];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Encoded `(K, V)` pairs sorted by key in a file that is deleted with the last reference to the run.
+pub struct SpilledRun {
+    path: PathBuf,
+    len: usize,
+}
+
+impl SpilledRun {
+    pub fn write<K: DbKey, V: DbVal>(dir: &Path, pairs: &[(K, V)]) -> Result<Self, AppError> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}-{}.run", std::process::id(), RUN_SEQ.fetch_add(1, AtomicOrdering::Relaxed)));
+        let mut w = BufWriter::new(File::create(&path)?);
+        for (k, v) in pairs {
+            write_field::<K>(&mut w, K::as_bytes(k.borrow()).as_ref())?;
+            write_field::<V>(&mut w, V::as_bytes(v.borrow()).as_ref())?;
+        }
+        w.flush()?;
+        Ok(SpilledRun { path, len: pairs.len() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Drop for SpilledRun {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+struct RunReader {
+    reader: BufReader<File>,
+    remaining: usize,
+}
+
+impl RunReader {
+    fn next<K: Key, V: Value>(&mut self) -> Result<Option<EncodedPair>, AppError> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        let k = read_field::<K>(&mut self.reader);
+        let v = k.and_then(|k| read_field::<V>(&mut self.reader).map(|v| (k, v)));
+        match v {
+            Ok(kv) => { self.remaining -= 1; Ok(Some(kv)) }
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Err(AppError::Custom("spilled run is truncated".to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Head of one run in the merge heap, ordered so that the smallest key and, among equal keys, the older run pops first.
+struct Head<K> {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    run: usize,
+    _pd: PhantomData<K>,
+}
+
+impl<K: Key> Ord for Head<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        K::compare(&other.key, &self.key).then_with(|| other.run.cmp(&self.run))
+    }
+}
+impl<K: Key> PartialOrd for Head<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+impl<K: Key> PartialEq for Head<K> {
+    fn eq(&self, other: &Self) -> bool { self.cmp(other) == Ordering::Equal }
+}
+impl<K: Key> Eq for Head<K> {}
+
+/// K-way merge of spilled runs, yields the pairs in key order in chunks so that only a chunk
+/// and one pair per run are in memory. Pairs with equal keys keep the order of their runs.
+/// The runs hold encoded pairs that cannot be decoded back into owned `(K, V)`, hence a heap of
+/// encoded heads rather than [`merge_sorted_by_key_gallop`](crate::storage::sort_buffer::merge_sorted_by_key_gallop) over whole runs.
+pub struct ExternalMerge<K, V> {
+    runs: Vec<Arc<SpilledRun>>,
+    readers: Vec<RunReader>,
+    heap: BinaryHeap<Head<K>>,
+    _pd: PhantomData<V>,
+}
+
+impl<K: Key, V: Value> ExternalMerge<K, V> {
+    pub fn new(runs: Vec<Arc<SpilledRun>>) -> Result<Self, AppError> {
+        let mut readers = Vec::with_capacity(runs.len());
+        for run in &runs {
+            readers.push(RunReader { reader: BufReader::new(File::open(&run.path)?), remaining: run.len });
+        }
+        let mut heap = BinaryHeap::with_capacity(runs.len());
+        for (run, reader) in readers.iter_mut().enumerate() {
+            if let Some((key, value)) = reader.next::<K, V>()? {
+                heap.push(Head { key, value, run, _pd: PhantomData });
+            }
+        }
+        Ok(ExternalMerge { runs, readers, heap, _pd: PhantomData })
+    }
+
+    pub fn len(&self) -> usize {
+        self.runs.iter().map(|run| run.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Next at most `max_pairs` encoded pairs in key order, `None` once all runs are drained.
+    pub fn next_chunk(&mut self, max_pairs: usize) -> Result<Option<Vec<EncodedPair>>, AppError> {
+        if self.heap.is_empty() {
+            return Ok(None);
+        }
+        let mut chunk = Vec::with_capacity(max_pairs.min(self.len()));
+        while chunk.len() < max_pairs && let Some(head) = self.heap.pop() {
+            if let Some((key, value)) = self.readers[head.run].next::<K, V>()? {
+                self.heap.push(Head { key, value, run: head.run, _pd: PhantomData });
+            }
+            chunk.push((head.key, head.value));
+        }
+        Ok(Some(chunk))
+    }
+}
+
+#[cfg(all(test, not(feature = "integration")))]
+mod tests {
+    use super::*;
+    use crate::storage::test_utils::{mk_tmp_dir, Address};
+
+    #[test]
+    fn external_merge_yields_key_order_in_chunks_and_keeps_run_order_for_equal_keys() {
+        let dir = mk_tmp_dir("spill_merge");
+        let r1: Vec<(u32, Address)> = vec![(1, Address(vec![1])), (4, Address(vec![4, 1])), (7, Address(vec![7]))];
+        let r2: Vec<(u32, Address)> = vec![(2, Address(vec![2])), (4, Address(vec![4, 2])), (9, Address(vec![]))];
+        let runs = vec![Arc::new(SpilledRun::write(&dir, &r1).unwrap()), Arc::new(SpilledRun::write(&dir, &r2).unwrap())];
+        let paths: Vec<PathBuf> = runs.iter().map(|r| r.path.clone()).collect();
+
+        let mut merge = ExternalMerge::<u32, Address>::new(runs).unwrap();
+        assert_eq!(merge.len(), 6);
+        let mut out = Vec::new();
+        while let Some(chunk) = merge.next_chunk(4).unwrap() {
+            assert!(chunk.len() <= 4);
+            out.extend(chunk.into_iter().map(|(k, v)| (u32::from_bytes(&k), v)));
+        }
+        let expected: Vec<(u32, Vec<u8>)> = vec![(1, vec![1]), (2, vec![2]), (4, vec![4, 1]), (4, vec![4, 2]), (7, vec![7]), (9, vec![])];
+        assert_eq!(out, expected);
+
+        drop(merge);
+        assert!(paths.iter().all(|p| !p.exists()), "runs are deleted with the merge");
+        SpillConfig::new(dir.clone(), 0).remove_stale_runs().unwrap();
+        assert!(!dir.exists());
+    }
+}
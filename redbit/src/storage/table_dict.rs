@@ -1,30 +1,33 @@
+use crate::storage::init::ShardDb;
+use crate::storage::schema::ColumnKind;
+use crate::storage::snapshot;
 use crate::storage::table_dict_read::ReadOnlyDictTable;
 use crate::storage::table_writer_api::TableFactory;
 use crate::{AppError, CacheKey, DbKey, DictTable};
-use lru::LruCache;
-use redb::{Database, Key, MultimapTableDefinition, TableDefinition, WriteTransaction};
+use crate::storage::cache::{LruStats, MeteredLru};
+use redb::{Key, MultimapTableDefinition, ReadTransaction, TableDefinition, WriteTransaction};
 use std::fmt::Debug;
 use std::num::NonZeroUsize;
 use std::sync::Weak;
 
 #[derive(Clone)]
-pub struct DictFactory<K: Key + 'static, V: Key + 'static> {
+pub struct DictFactory<K: Key + 'static, V: CacheKey> {
     pub name: String,
     pub dict_pk_to_ids_def: MultimapTableDefinition<'static, K, K>,
     pub value_by_dict_pk_def: TableDefinition<'static, K, V>,
-    pub value_to_dict_pk_def: TableDefinition<'static, V, K>,
+    pub value_to_dict_pk_def: TableDefinition<'static, V::Key, K>,
     pub dict_pk_by_id_def: TableDefinition<'static, K, K>,
     pub lru_capacity: Option<usize>,
 }
 
-impl<K: Key + 'static, V: Key + 'static> Debug for DictFactory<K, V> {
+impl<K: Key + 'static, V: CacheKey> Debug for DictFactory<K, V> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("DictFactory").field("name", &self.name).finish()
     }
 }
 
-impl<K: Key + 'static, V: Key + 'static> DictFactory<K, V> {
-    pub fn new(name: &str, lru_capacity: usize, dict_pk_to_ids_def: MultimapTableDefinition<'static, K, K>, value_by_dict_pk_def: TableDefinition<'static, K, V>, value_to_dict_pk_def: TableDefinition<'static, V, K>, dict_pk_by_id_def: TableDefinition<'static, K, K>) -> Self {
+impl<K: Key + 'static, V: CacheKey> DictFactory<K, V> {
+    pub fn new(name: &str, lru_capacity: usize, dict_pk_to_ids_def: MultimapTableDefinition<'static, K, K>, value_by_dict_pk_def: TableDefinition<'static, K, V>, value_to_dict_pk_def: TableDefinition<'static, V::Key, K>, dict_pk_by_id_def: TableDefinition<'static, K, K>) -> Self {
         let lru_cache_size_opt =
             if lru_capacity < 1 {
                 None
@@ -43,7 +46,7 @@ impl<K: Key + 'static, V: Key + 'static> DictFactory<K, V> {
 }
 
 impl<K: DbKey, V: CacheKey> TableFactory<K, V> for DictFactory<K, V> {
-    type CacheCtx = Option<LruCache<V::CK, K::Unit>>;
+    type CacheCtx = Option<MeteredLru<V::CK, K::Unit>>;
     type Table<'txn, 'c> = DictTable<'txn, 'c, K, V>;
     type ReadOnlyTable = ReadOnlyDictTable<K, V>;
 
@@ -51,8 +54,16 @@ impl<K: DbKey, V: CacheKey> TableFactory<K, V> for DictFactory<K, V> {
         self.name.clone()
     }
 
+    fn kind(&self) -> ColumnKind {
+        ColumnKind::Dict
+    }
+
     fn new_cache(&self) -> Self::CacheCtx {
-        self.lru_capacity.map(|cap| LruCache::new(NonZeroUsize::new(cap).expect("lru_capacity for dictionary must be > 0")))
+        self.lru_capacity.map(|cap| MeteredLru::new(NonZeroUsize::new(cap).expect("lru_capacity for dictionary must be > 0")))
+    }
+
+    fn cache_stats(cache: &Self::CacheCtx) -> Option<LruStats> {
+        cache.as_ref().map(|lru| lru.stats())
     }
 
     fn open_for_write<'txn, 'c>(
@@ -70,7 +81,7 @@ impl<K: DbKey, V: CacheKey> TableFactory<K, V> for DictFactory<K, V> {
         )
     }
 
-    fn open_for_read(&self, db_weak: &Weak<Database>) -> redb::Result<Self::ReadOnlyTable, AppError> {
+    fn open_for_read(&self, db_weak: &Weak<ShardDb>) -> redb::Result<Self::ReadOnlyTable, AppError> {
         ReadOnlyDictTable::new(
             db_weak,
             self.dict_pk_to_ids_def,
@@ -79,4 +90,12 @@ impl<K: DbKey, V: CacheKey> TableFactory<K, V> for DictFactory<K, V> {
             self.dict_pk_by_id_def,
         )
     }
+
+    fn copy_tables(&self, src: &ReadTransaction, dst: &WriteTransaction) -> Result<u64, AppError> {
+        let ids = snapshot::copy_multimap_table(src, dst, self.dict_pk_to_ids_def)?;
+        let values = snapshot::copy_table(src, dst, self.value_by_dict_pk_def)?;
+        let dict_pks = snapshot::copy_table(src, dst, self.value_to_dict_pk_def)?;
+        let by_id = snapshot::copy_table(src, dst, self.dict_pk_by_id_def)?;
+        Ok(ids + values + dict_pks + by_id)
+    }
 }
\ No newline at end of file
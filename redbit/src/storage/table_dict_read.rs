@@ -1,26 +1,30 @@
-use crate::storage::partitioning::ValuePartitioner;
+use crate::compress::{ValueBytes, VALUE_BYTES_SAMPLE};
+use crate::storage::init::ShardDb;
+use crate::storage::partitioning::{merge_value_ranges, KeyRange, ValuePartitioner, ValueRange};
+use crate::storage::fsck::{self, FsckIssue};
+use crate::storage::reshard;
 use crate::storage::table_dict::DictFactory;
 use crate::storage::table_writer_api::{ReadTableFactory, ShardedTableReader, TableFactory, TableInfo};
-use crate::{AppError, CacheKey, DbKey, KeyPartitioner, Partitioning, ReadTableLike, DbVal};
+use crate::{AppError, CacheKey, DbKey, KeyPartitioner, Partitioning, ReadTableLike};
 use redb::Key;
 use redb::*;
 use std::borrow::Borrow;
 use std::ops::RangeBounds;
 use std::sync::Weak;
 
-pub struct ReadOnlyDictTable<K: Key + 'static, V: Key + 'static> {
+pub struct ReadOnlyDictTable<K: Key + 'static, V: CacheKey> {
     dict_pk_to_ids: ReadOnlyMultimapTable<K, K>,
     value_by_dict_pk: ReadOnlyTable<K, V>,
-    value_to_dict_pk: ReadOnlyTable<V, K>,
+    value_to_dict_pk: ReadOnlyTable<V::Key, K>,
     dict_pk_by_id: ReadOnlyTable<K, K>,
 }
 
-impl<K: Key + 'static, V: Key + 'static> ReadOnlyDictTable<K, V> {
+impl<K: Key + 'static, V: CacheKey> ReadOnlyDictTable<K, V> {
     pub fn new(
-        db_weak: &Weak<Database>,
+        db_weak: &Weak<ShardDb>,
         dict_pk_to_ids_def: MultimapTableDefinition<K, K>,
         value_by_dict_pk_def: TableDefinition<K, V>,
-        value_to_dict_pk_def: TableDefinition<V, K>,
+        value_to_dict_pk_def: TableDefinition<V::Key, K>,
         dict_pk_by_id_def: TableDefinition<K, K>,
     ) -> Result<Self, AppError> {
         let db_arc = db_weak.upgrade().ok_or_else(|| AppError::Custom("database closed".to_string()))?;
@@ -34,13 +38,13 @@ impl<K: Key + 'static, V: Key + 'static> ReadOnlyDictTable<K, V> {
     }
 }
 
-pub struct ShardedReadOnlyDictTable<K: DbKey, V: DbVal, VP: ValuePartitioner<V>> {
+pub struct ShardedReadOnlyDictTable<K: DbKey, V: CacheKey, VP: ValuePartitioner<V>> {
     shards: Vec<ReadOnlyDictTable<K, V>>,
     value_partitioner: VP,
 }
 
 impl<K: DbKey, V: CacheKey, VP: ValuePartitioner<V>> ShardedReadOnlyDictTable<K, V, VP> {
-    pub fn new(value_partitioner: VP, dbs: Vec<Weak<Database>>, factory: &DictFactory<K, V>) -> Result<Self, AppError> {
+    pub fn new(value_partitioner: VP, dbs: Vec<Weak<ShardDb>>, factory: &DictFactory<K, V>) -> Result<Self, AppError> {
         let mut shards = Vec::with_capacity(dbs.len());
         for db_weak in &dbs {
             shards.push(factory.open_for_read(db_weak)?);
@@ -50,7 +54,7 @@ impl<K: DbKey, V: CacheKey, VP: ValuePartitioner<V>> ShardedReadOnlyDictTable<K,
 }
 
 impl<K: DbKey, V: CacheKey, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> ReadTableFactory<K, V, KP, VP> for DictFactory<K, V> {
-    fn build_sharded_reader(&self, dbs: Vec<Weak<Database>>, partitioning: &Partitioning<KP, VP>) -> std::result::Result<ShardedTableReader<K, V, KP, VP>, AppError> {
+    fn build_sharded_reader(&self, dbs: Vec<Weak<ShardDb>>, partitioning: &Partitioning<KP, VP>) -> std::result::Result<ShardedTableReader<K, V, KP, VP>, AppError> {
         match partitioning {
             Partitioning::ByKey(_) => {
                 Err(AppError::Custom("DictFactory does not support key partitioning".to_string()))
@@ -60,10 +64,41 @@ impl<K: DbKey, V: CacheKey, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> Read
             }
         }
     }
+
+    fn reshard_tables(&self, src: &ReadTransaction, dst: &[Database], partitioning: &Partitioning<KP, VP>) -> Result<u64, AppError> {
+        match partitioning {
+            Partitioning::ByKey(_) => {
+                Err(AppError::Custom("DictFactory does not support key partitioning".to_string()))
+            }
+            Partitioning::ByValue(vp) => {
+                // birth ids and dict pks do not carry the value, so they are routed by looking it up
+                let values = match src.open_table(self.value_by_dict_pk_def) {
+                    Ok(t) => t,
+                    Err(TableError::TableDoesNotExist(_)) => return Ok(0),
+                    Err(e) => return Err(e.into()),
+                };
+                let by_dict_pk = |dict_pk: &K::SelfType<'_>| -> Result<usize, AppError> {
+                    match values.get(dict_pk)? {
+                        Some(v) => Ok(vp.partition_value(v.value())),
+                        None => Err(AppError::Custom(format!("{}: dict pk {:?} has no value", self.name, dict_pk))),
+                    }
+                };
+                let ids = reshard::reshard_multimap_table(src, dst, self.dict_pk_to_ids_def, |dict_pk, _| by_dict_pk(dict_pk))?;
+                let by_id = reshard::reshard_table(src, dst, self.dict_pk_by_id_def, |_, dict_pk| by_dict_pk(dict_pk))?;
+                let values = reshard::reshard_table(src, dst, self.value_by_dict_pk_def, |_, v| Ok(vp.partition_value(v)))?;
+                let dict_pks = reshard::reshard_table(src, dst, self.value_to_dict_pk_def, |v, _| Ok(vp.partition_value(v)))?;
+                Ok(ids + by_id + values + dict_pks)
+            }
+        }
+    }
+
+    fn fsck_tables(&self, db: &ShardDb, repair: bool) -> Result<(u64, Vec<FsckIssue>), AppError> {
+        fsck::check_dict(db, &self.name, self.dict_pk_to_ids_def, self.value_by_dict_pk_def, self.value_to_dict_pk_def, self.dict_pk_by_id_def, repair)
+    }
 }
 
 
-impl<K: DbKey, V: DbVal, VP: ValuePartitioner<V>> ReadTableLike<K, V> for ShardedReadOnlyDictTable<K, V, VP> {
+impl<K: DbKey, V: CacheKey, VP: ValuePartitioner<V>> ReadTableLike<K, V> for ShardedReadOnlyDictTable<K, V, VP> {
 
     fn get_value<'k>(&self, key: impl Borrow<K::SelfType<'k>>) -> Result<Option<AccessGuard<'_, V>>, AppError> {
         for shard in &self.shards {
@@ -100,25 +135,51 @@ impl<K: DbKey, V: DbVal, VP: ValuePartitioner<V>> ReadTableLike<K, V> for Sharde
             total = total.saturating_add(s.value_by_dict_pk.len()?);
         }
         let rep_stats = self.shards[0].value_by_dict_pk.stats()?;
-        Ok(vec![TableInfo::from_stats("distinct_values", total, rep_stats)])
+        let mut sample = ValueBytes::default();
+        for s in &self.shards {
+            for entry in s.value_by_dict_pk.iter()?.take(VALUE_BYTES_SAMPLE - sample.values as usize) {
+                sample.add::<V>(&entry?.1.value());
+            }
+        }
+        Ok(vec![TableInfo::from_stats("distinct_values", total, rep_stats).with_value_bytes(sample)])
     }
 
-    fn index_keys<'v>(&self, _val: impl Borrow<V::SelfType<'v>>) -> Result<MultimapValue<'static, K>, AppError> {
+    fn index_keys<'v>(&self, _val: impl Borrow<V::SelfType<'v>>) -> Result<Option<MultimapValue<'static, K>>, AppError> {
         unimplemented!()
     }
 
-    fn iter_keys(&self) -> Result<Range<'_, K, V>, AppError> {
+    fn iter_keys(&self) -> Result<KeyRange<K, V>, AppError> {
         unimplemented!()
     }
 
-    fn range<'a, KR: Borrow<K::SelfType<'a>>>(&self, _range: impl RangeBounds<KR>) -> Result<Range<'static, K, V>, AppError> {
+    fn range<'a, KR: Borrow<K::SelfType<'a>>>(&self, _range: impl RangeBounds<KR>) -> Result<KeyRange<K, V>, AppError> {
         unimplemented!()
     }
 
-    fn index_range<'a, KR: Borrow<V::SelfType<'a>>>(&self, _range: impl RangeBounds<KR>) -> Result<MultimapRange<'static, V, K>, AppError> {
+    fn index_range<'a, KR: Borrow<V::SelfType<'a>>>(&self, _range: impl RangeBounds<KR>) -> Result<ValueRange<V::Key, K>, AppError> {
         unimplemented!()
     }
 
+    fn value_range_keys<'a, KR: Borrow<V::SelfType<'a>>>(&self, range: impl RangeBounds<KR>, limit: usize) -> Result<Vec<AccessGuard<'static, K>>, AppError> {
+        let mut ranges = Vec::with_capacity(self.shards.len());
+        for shard in &self.shards {
+            ranges.push(shard.value_to_dict_pk.range::<KR>((range.start_bound(), range.end_bound()))?);
+        }
+        let mut keys = Vec::new();
+        if limit > 0 {
+            merge_value_ranges(ranges, |shard, _, birth_id| {
+                for id in self.shards[shard].dict_pk_to_ids.get(birth_id.value())? {
+                    keys.push(id?);
+                    if keys.len() == limit {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            })?;
+        }
+        Ok(keys)
+    }
+
     fn last_key(&self) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>, AppError> {
         unimplemented!()
     }
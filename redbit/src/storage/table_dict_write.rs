@@ -1,27 +1,41 @@
 use crate::storage::async_boundary::{ValueBuf, ValueOwned};
 use crate::storage::table_writer_api::WriteTableLike;
 use crate::{AppError, CacheKey, DbKey};
-use lru::LruCache;
+use crate::storage::cache::MeteredLru;
 use redb::*;
 use redb::{Table, WriteTransaction};
 use std::borrow::Borrow;
+use std::collections::HashMap;
 use std::ops::RangeBounds;
 
+/// Writes of one batch that are not keyed by the input key, values are encoded once as keys for sorting.
+struct PendingDictWrites<'a, 'v, K: DbKey, V: CacheKey> {
+    pk_to_keys: Vec<(ValueOwned<K>, ValueOwned<K>)>,
+    value_to_pk: Vec<(Vec<u8>, &'a V::SelfType<'v>, ValueOwned<K>)>,
+    unlinks: Vec<(ValueOwned<K>, ValueOwned<K>)>,
+}
+
+impl<K: DbKey, V: CacheKey> Default for PendingDictWrites<'_, '_, K, V> {
+    fn default() -> Self {
+        Self { pk_to_keys: Vec::new(), value_to_pk: Vec::new(), unlinks: Vec::new() }
+    }
+}
+
 pub struct DictTable<'txn, 'c, K: DbKey, V: CacheKey> {
     pub(crate) dict_pk_to_keys: MultimapTable<'txn, K, K>,
     pub(crate) value_by_dict_pk: Table<'txn, K, V>,
-    pub(crate) value_to_dict_pk: Table<'txn, V, K>,
+    pub(crate) value_to_dict_pk: Table<'txn, V::Key, K>,
     pub(crate) dict_pk_by_key: Table<'txn, K, K>,
-    pub(crate) cache: Option<&'c mut LruCache<V::CK, K::Unit>>,
+    pub(crate) cache: Option<&'c mut MeteredLru<V::CK, K::Unit>>,
 }
 
 impl<'txn, 'c, K: DbKey, V: CacheKey> DictTable<'txn, 'c, K, V> {
     pub fn new(
         write_tx: &'txn WriteTransaction,
-        cache: Option<&'c mut LruCache<V::CK, K::Unit>>,
+        cache: Option<&'c mut MeteredLru<V::CK, K::Unit>>,
         dict_pk_to_ids_def: MultimapTableDefinition<K, K>,
         value_by_dict_pk_def: TableDefinition<K, V>,
-        value_to_dict_pk_def: TableDefinition<V, K>,
+        value_to_dict_pk_def: TableDefinition<V::Key, K>,
         dict_pk_by_id_def: TableDefinition<K, K>,
     ) -> Result<Self, AppError> {
         Ok(Self {
@@ -32,39 +46,136 @@ impl<'txn, 'c, K: DbKey, V: CacheKey> DictTable<'txn, 'c, K, V> {
             cache,
         })
     }
+
+}
+
+impl<'txn, 'c, K: DbKey, V: CacheKey> DictTable<'txn, 'c, K, V> {
+    fn same_key(a: &K::SelfType<'_>, b: &K::SelfType<'_>) -> bool {
+        K::compare(K::as_bytes(a).as_ref(), K::as_bytes(b).as_ref()).is_eq()
+    }
+
+    /// Birth id of a value that is already in the dictionary, seeds the cache on a table hit.
+    fn existing_birth(&mut self, val_ref: &V::SelfType<'_>, cache_key: &V::CK) -> Result<Option<ValueOwned<K>>, AppError> {
+        if let Some(&unit) = self.cache.as_mut().and_then(|c| c.get(cache_key)) {
+            return Ok(Some(Self::owned_from_unit(unit)));
+        }
+        match self.value_to_dict_pk.get(val_ref)? {
+            Some(birth_id_guard) => {
+                let birth_id = Self::owned_key_from_guard(birth_id_guard);
+                if let Some(c) = self.cache.as_mut() {
+                    c.put(cache_key.clone(), birth_id.into_unit());
+                }
+                Ok(Some(birth_id))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Stores a brand-new value under the key that introduces it. Returns the value the key was still the birth id of,
+    /// it must move to one of its remaining ids, see `rebirth`.
+    fn store_new_value(&mut self, key_ref: &K::SelfType<'_>, val_ref: &V::SelfType<'_>) -> Result<Option<ValueBuf<V>>, AppError> {
+        let previous = self.value_by_dict_pk.insert(key_ref, val_ref)?.map(|g| ValueBuf::<V>::new(V::as_bytes(&g.value()).as_ref().to_vec()));
+        Ok(previous.filter(|old| old.as_bytes() != V::as_bytes(val_ref).as_ref()))
+    }
+
+    /// Points `key` at `birth_id` and returns the birth id it was pointing at before, if it was another one.
+    fn point_key_at(&mut self, key_ref: &K::SelfType<'_>, birth_id: ValueOwned<K>) -> Result<Option<ValueOwned<K>>, AppError> {
+        let previous = self.dict_pk_by_key.insert(key_ref, birth_id.as_value())?.map(Self::owned_key_from_guard);
+        Ok(previous.filter(|prev| !Self::same_key(&prev.as_value(), &birth_id.as_value())))
+    }
+
+    /// Drops `key` from the ids of `birth_id`, the value is garbage collected together with its last id.
+    fn unlink(&mut self, birth_id: ValueOwned<K>, key_ref: &K::SelfType<'_>) -> Result<bool, AppError> {
+        let was_removed = self.dict_pk_to_keys.remove(birth_id.as_value(), key_ref)?;
+        if self.dict_pk_to_keys.get(birth_id.as_value())?.is_empty() {
+            let value = self.value_by_dict_pk.remove(birth_id.as_value())?.map(|g| ValueBuf::<V>::new(V::as_bytes(&g.value()).as_ref().to_vec()));
+            if let Some(value) = value {
+                self.drop_reverse(birth_id, &value)?;
+            }
+        }
+        Ok(was_removed)
+    }
+
+    /// The reverse entry of a value may belong to a younger birth of the same value written by older versions.
+    fn drop_reverse(&mut self, birth_id: ValueOwned<K>, value: &ValueBuf<V>) -> Result<(), AppError> {
+        let owned_by_birth = self.value_to_dict_pk.get(value.as_value())?.is_some_and(|b| Self::same_key(&b.value(), &birth_id.as_value()));
+        if owned_by_birth {
+            self.value_to_dict_pk.remove(value.as_value())?;
+            if let Some(c) = self.cache.as_mut() {
+                let _ = c.pop(&V::cache_key(&value.as_value()));
+            }
+        }
+        Ok(())
+    }
+
+    /// `birth_id` was deleted while other ids kept its value and now it introduces a new value. The old value moves
+    /// to the first of its remaining ids, or is garbage collected if `birth_id` was its last id.
+    fn rebirth(&mut self, birth_id: ValueOwned<K>, old_value: ValueBuf<V>) -> Result<(), AppError> {
+        let mut heirs = Vec::new();
+        for key in self.dict_pk_to_keys.remove_all(birth_id.as_value())? {
+            let key = Self::owned_key_from_guard(key?);
+            if !Self::same_key(&key.as_value(), &birth_id.as_value()) {
+                heirs.push(key);
+            }
+        }
+        let Some(&heir) = heirs.first() else {
+            return self.drop_reverse(birth_id, &old_value);
+        };
+        self.value_by_dict_pk.insert(heir.as_value(), old_value.as_value())?;
+        self.value_to_dict_pk.insert(old_value.as_value(), heir.as_value())?;
+        if let Some(c) = self.cache.as_mut() {
+            c.put(V::cache_key(&old_value.as_value()), heir.into_unit());
+        }
+        for key in heirs {
+            self.dict_pk_by_key.insert(key.as_value(), heir.as_value())?;
+            self.dict_pk_to_keys.insert(heir.as_value(), key.as_value())?;
+        }
+        Ok(())
+    }
+
+    fn flush_pending<'a, 'v>(&mut self, pending: &mut PendingDictWrites<'a, 'v, K, V>) -> Result<(), AppError> {
+        pending.pk_to_keys.sort_by(|(a, _), (b, _)| {
+            K::compare(K::as_bytes(&a.as_value()).as_ref(), K::as_bytes(&b.as_value()).as_ref())
+        });
+        for (birth_id, key) in pending.pk_to_keys.drain(..) {
+            self.dict_pk_to_keys.insert(birth_id.as_value(), key.as_value())?;
+        }
+        pending.value_to_pk.sort_by(|(a, _, _), (b, _, _)| V::Key::compare(a, b));
+        for (_, val_ref, birth_id) in pending.value_to_pk.drain(..) {
+            self.value_to_dict_pk.insert(val_ref, birth_id.as_value())?;
+        }
+        for (birth_id, key) in std::mem::take(&mut pending.unlinks) {
+            self.unlink(birth_id, &key.as_value())?;
+        }
+        Ok(())
+    }
 }
+
 impl<'txn, 'c, K: DbKey, V: CacheKey> WriteTableLike<K, V> for DictTable<'txn, 'c, K, V> {
     fn insert_kv<'k, 'v>(&mut self, key: impl Borrow<K::SelfType<'k>>, value: impl Borrow<V::SelfType<'v>>) -> Result<(), AppError>  {
         let key_ref: &K::SelfType<'k> = key.borrow();
         let val_ref: &V::SelfType<'v> = value.borrow();
         let cache_key: V::CK = V::cache_key(val_ref);
 
-        if let Some(&k) = self.cache.as_mut().and_then(|c| c.get(&cache_key)) {
-            let birth_id = Self::owned_from_unit(k);
-            self.dict_pk_by_key.insert(key_ref, birth_id.as_value())?;
-            self.dict_pk_to_keys.insert(birth_id.as_value(), key_ref)?;
-            Ok(())
-        } else {
-            if let Some(birth_id_guard) = self.value_to_dict_pk.get(val_ref)? {
-                let birth_id = Self::owned_key_from_guard(birth_id_guard);
-                if let Some(c) = self.cache.as_mut() {
-                    c.put(cache_key, birth_id.into_unit());
+        let birth_id = match self.existing_birth(val_ref, &cache_key)? {
+            Some(birth_id) => birth_id,
+            None => {
+                let birth_id = Self::owned_from_unit(Self::unit_from_key(key_ref));
+                if let Some(old_value) = self.store_new_value(key_ref, val_ref)? {
+                    self.rebirth(birth_id, old_value)?;
                 }
-
-                self.dict_pk_by_key.insert(key_ref, birth_id.as_value())?;
-                self.dict_pk_to_keys.insert(birth_id.as_value(), key_ref)?;
-            } else {
                 self.value_to_dict_pk.insert(val_ref, key_ref)?;
-                self.value_by_dict_pk.insert(key_ref, val_ref)?;
-                self.dict_pk_by_key.insert(key_ref, key_ref)?;
-                self.dict_pk_to_keys.insert(key_ref, key_ref)?;
-
                 if let Some(c) = self.cache.as_mut() {
-                    c.put(cache_key, Self::unit_from_key(key_ref));
+                    c.put(cache_key, birth_id.into_unit());
                 }
+                birth_id
             }
-            Ok(())
+        };
+        if let Some(previous) = self.point_key_at(key_ref, birth_id)? {
+            self.unlink(previous, key_ref)?;
         }
+        self.dict_pk_to_keys.insert(birth_id.as_value(), key_ref)?;
+        Ok(())
     }
 
     fn insert_many_sorted_by_key<'k, 'v, KR: Borrow<K::SelfType<'k>>, VR: Borrow<V::SelfType<'v>>>(
@@ -79,9 +190,11 @@ impl<'txn, 'c, K: DbKey, V: CacheKey> WriteTableLike<K, V> for DictTable<'txn, '
         // We defer writes that are *not* keyed by the input Key:
         // - dict_pk_to_keys: (birth_id -> key)  => sort by birth_id
         // - value_to_dict_pk: (value -> birth_id) => sort by value
+        // - ids that pointed at another value before => unlinked once the batch is written
         //
-        let mut pk_to_keys_batch = Vec::with_capacity(pairs.len());
-        let mut value_to_pk_batch = Vec::new();
+        let mut pending = PendingDictWrites::default();
+        // values born in this batch are not in value_to_dict_pk yet, without an LRU cache they would be born again
+        let mut born_in_batch: HashMap<V::CK, K::Unit> = HashMap::new();
 
         // --- Run 1: linear pass in Key order, update cache early ---
         for (k, v) in &pairs {
@@ -89,79 +202,46 @@ impl<'txn, 'c, K: DbKey, V: CacheKey> WriteTableLike<K, V> for DictTable<'txn, '
             let val_ref: &V::SelfType<'v> = v.borrow();
             let cache_key: V::CK = V::cache_key(val_ref);
 
-            if let Some(&unit) = self.cache.as_mut().and_then(|c| c.get(&cache_key)) {
-                // Existing value found via cache → we know birth_id.
-                let birth_id = Self::owned_from_unit(unit);
-                self.dict_pk_by_key.insert(key_ref, birth_id.as_value())?;
-
-                // Defer (birth_id -> key) write; we’ll sort by birth_id in Run 2.
-                let key_owned = Self::owned_from_unit(Self::unit_from_key(key_ref));
-                pk_to_keys_batch.push((birth_id, key_owned));
-            } else if let Some(birth_id_guard) = self.value_to_dict_pk.get(val_ref)? {
-                // Existing value found in table → materialize, seed cache, same as above.
-                let birth_id = Self::owned_key_from_guard(birth_id_guard);
-                if let Some(c) = self.cache.as_mut() {
-                    c.put(cache_key, birth_id.into_unit());
-                }
-
-                self.dict_pk_by_key.insert(key_ref, birth_id.as_value())?;
-
-                let key_owned = Self::owned_from_unit(Self::unit_from_key(key_ref));
-                pk_to_keys_batch.push((birth_id, key_owned));
-            } else {
-                // Brand-new value. The first key we encounter becomes the birth_id.
-                // Only write tables keyed by `key` now; defer the rest.
-                self.dict_pk_by_key.insert(key_ref, key_ref)?;
-                self.value_by_dict_pk.insert(key_ref, val_ref)?;
-
-                // Prepare deferred writes:
-                let birth_id_owned = Self::owned_from_unit(Self::unit_from_key(key_ref));
-                // (birth_id -> key)
-                pk_to_keys_batch.push((birth_id_owned.clone(), birth_id_owned.clone()));
-                // (value -> birth_id)
-                value_to_pk_batch.push((val_ref, birth_id_owned.clone()));
-
-                // Seed cache so subsequent keys with the same value in this batch hit fast path.
-                if let Some(c) = self.cache.as_mut() {
-                    c.put(cache_key, Self::unit_from_key(key_ref));
-                }
+            let birth_id =
+                if let Some(&unit) = born_in_batch.get(&cache_key) {
+                    Self::owned_from_unit(unit)
+                } else if let Some(birth_id) = self.existing_birth(val_ref, &cache_key)? {
+                    birth_id
+                } else {
+                    // Brand-new value. The first key we encounter becomes the birth_id.
+                    let birth_id = Self::owned_from_unit(Self::unit_from_key(key_ref));
+                    if let Some(old_value) = self.store_new_value(key_ref, val_ref)? {
+                        // rare, the key is taken over from a value whose ids may still be deferred, and unlinking
+                        // them may collect the key's value slot, so it is written again after the old value moved
+                        self.flush_pending(&mut pending)?;
+                        self.rebirth(birth_id, old_value)?;
+                        self.value_by_dict_pk.insert(key_ref, val_ref)?;
+                    }
+                    pending.value_to_pk.push((V::Key::as_bytes(val_ref).as_ref().to_vec(), val_ref, birth_id));
+                    born_in_batch.insert(cache_key.clone(), birth_id.into_unit());
+                    // Seed cache so subsequent keys with the same value hit fast path.
+                    if let Some(c) = self.cache.as_mut() {
+                        c.put(cache_key, birth_id.into_unit());
+                    }
+                    birth_id
+                };
+            let key_owned = Self::owned_from_unit(Self::unit_from_key(key_ref));
+            if let Some(previous) = self.point_key_at(key_ref, birth_id)? {
+                pending.unlinks.push((previous, key_owned));
             }
+            pending.pk_to_keys.push((birth_id, key_owned));
         }
 
-        // --- Run 2a: flush dict_pk_to_keys in birth_id order ---
-        pk_to_keys_batch.sort_by(|(a, _), (b, _)| {
-            K::compare(K::as_bytes(&a.as_value()).as_ref(), K::as_bytes(&b.as_value()).as_ref())
-        });
-        for (birth_id_owned, key_owned) in pk_to_keys_batch {
-            self.dict_pk_to_keys.insert(birth_id_owned.as_value(), key_owned.as_value())?;
-        }
-
-        // --- Run 2b: flush value_to_dict_pk in value order ---
-        value_to_pk_batch.sort_by(|a, b| {
-            V::compare(V::as_bytes(a.0).as_ref(), V::as_bytes(b.0).as_ref())
-        });
-        for (val_ref, birth_id_owned) in value_to_pk_batch {
-            self.value_to_dict_pk.insert(val_ref, birth_id_owned.as_value())?;
-        }
-
-        Ok(())
+        // --- Run 2: flush deferred writes in their key order, then collect values that lost their last id ---
+        self.flush_pending(&mut pending)
     }
 
     fn delete_kv<'k>(&mut self, key: impl Borrow<K::SelfType<'k>>) -> Result<bool, AppError>  {
         let key_ref: &K::SelfType<'k> = key.borrow();
-        if let Some(birth_guard) = self.dict_pk_by_key.remove(key_ref)? {
-            let birth_id = birth_guard.value();
-            let was_removed = self.dict_pk_to_keys.remove(&birth_id, key_ref)?;
-            if self.dict_pk_to_keys.get(&birth_id)?.is_empty() && let Some(value_guard) = self.value_by_dict_pk.remove(&birth_id)? {
-                let value = value_guard.value();
-                self.value_to_dict_pk.remove(&value)?;
-                if let Some(c) = self.cache.as_mut() {
-                    let _ = c.pop(&V::cache_key(&value));
-                }
-            }
-            Ok(was_removed)
-        } else {
-            Ok(false)
+        let birth_id = self.dict_pk_by_key.remove(key_ref)?.map(Self::owned_key_from_guard);
+        match birth_id {
+            Some(birth_id) => self.unlink(birth_id, key_ref),
+            None => Ok(false),
         }
     }
 
@@ -525,5 +605,86 @@ mod tests {
         }
     }
 
-}
+    fn has_value(dict: &DictTable<'_, '_, u32, Address>, v: &[u8]) -> bool {
+        dict.value_to_dict_pk.get(&addr(v)).expect("get").is_some()
+    }
 
+    #[tokio::test]
+    async fn dict_table_override_collects_value_without_ids() {
+        let (_db, tx, mut cache, t1, t2, t3, t4) = setup_dict_defs(1000);
+        let mut dict = mk_dict(&tx, &mut cache, t1, t2, t3, t4);
+        dict.insert_kv(1, addr(&[0xaa])).expect("insert");
+        dict.insert_kv(2, addr(&[0xbb])).expect("insert");
+
+        dict.insert_kv(1, addr(&[0xbb])).expect("override");
+
+        assert_eq!(birth_id_of(&dict, 1), 2);
+        assert!(dict.value_by_dict_pk.get(&1).expect("get").is_none(), "value A lost its last id");
+        assert!(!has_value(&dict, &[0xaa]));
+        assert!(dict.dict_pk_to_keys.get(&1).expect("get").is_empty());
+        assert_eq!(dict.dict_pk_to_keys.get(&2).expect("get").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn dict_table_delete_collects_value_with_its_last_id() {
+        let (_db, tx, mut cache, t1, t2, t3, t4) = setup_dict_defs(1000);
+        let mut dict = mk_dict(&tx, &mut cache, t1, t2, t3, t4);
+        for id in [1u32, 2, 3] {
+            dict.insert_kv(id, addr(&[0xaa])).expect("insert");
+        }
+
+        assert!(dict.delete_kv(1).expect("delete"));
+        assert_eq!(birth_id_of(&dict, 2), 1, "the birth id outlives its key while other ids use the value");
+        assert_eq!(value_of_birth(&dict, 1), vec![0xaa]);
+        assert_eq!(reverse_birth_of(&dict, &[0xaa]), 1);
+
+        assert!(dict.delete_kv(2).expect("delete"));
+        assert!(dict.delete_kv(3).expect("delete"));
+        assert!(!dict.delete_kv(3).expect("delete"));
+        assert_eq!(dict.value_by_dict_pk.len().expect("len"), 0);
+        assert_eq!(dict.value_to_dict_pk.len().expect("len"), 0);
+        assert_eq!(dict.dict_pk_to_keys.len().expect("len"), 0);
+    }
+
+    #[tokio::test]
+    async fn dict_table_reused_birth_key_hands_old_value_over() {
+        let (_db, tx, mut cache, t1, t2, t3, t4) = setup_dict_defs(1000);
+        let mut dict = mk_dict(&tx, &mut cache, t1, t2, t3, t4);
+        dict.insert_kv(1, addr(&[0xaa])).expect("insert");
+        dict.insert_kv(2, addr(&[0xaa])).expect("insert");
+        dict.delete_kv(1).expect("delete");
+
+        dict.insert_kv(1, addr(&[0xbb])).expect("insert");
+
+        assert_eq!(birth_id_of(&dict, 1), 1);
+        assert_eq!(value_of_birth(&dict, 1), vec![0xbb]);
+        assert_eq!(reverse_birth_of(&dict, &[0xbb]), 1);
+        assert_eq!(birth_id_of(&dict, 2), 2, "value A moved to its surviving id");
+        assert_eq!(value_of_birth(&dict, 2), vec![0xaa]);
+        assert_eq!(reverse_birth_of(&dict, &[0xaa]), 2);
+
+        dict.insert_kv(3, addr(&[0xaa])).expect("insert");
+        assert_eq!(birth_id_of(&dict, 3), 2);
+    }
+
+    #[tokio::test]
+    async fn dict_table_batch_without_cache_dedups_and_hands_old_value_over() {
+        let (_db, tx, _cache, t1, t2, t3, t4) = setup_dict_defs::<u32, Address>(1000);
+        let mut dict = DictTable::new(&tx, None, t1, t2, t3, t4).expect("dict");
+        let (val_a, val_b) = (addr(&[0xaa]), addr(&[0xbb]));
+        dict.insert_many_sorted_by_key(vec![(1u32, &val_a), (2u32, &val_a)]).expect("batch");
+        dict.delete_kv(1).expect("delete");
+
+        dict.insert_many_sorted_by_key(vec![(1u32, &val_b), (3u32, &val_b), (4u32, &val_a)]).expect("batch");
+
+        assert_eq!(birth_id_of(&dict, 3), 1, "a value new to the batch is born once");
+        assert_eq!(value_of_birth(&dict, 1), val_b.0);
+        assert_eq!(reverse_birth_of(&dict, &val_b.0), 1);
+        for id in [2u32, 4] {
+            assert_eq!(birth_id_of(&dict, id), 2);
+        }
+        assert_eq!(value_of_birth(&dict, 2), val_a.0);
+        assert_eq!(reverse_birth_of(&dict, &val_a.0), 2);
+        assert_eq!(dict.value_by_dict_pk.len().expect("len"), 2);
+    }
+}
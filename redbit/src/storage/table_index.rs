@@ -1,29 +1,38 @@
+use crate::storage::bloom::BloomFilter;
+use crate::storage::init::ShardDb;
+use crate::storage::schema::ColumnKind;
+use crate::storage::snapshot;
 use crate::storage::table_index_read::ReadOnlyIndexTable;
 use crate::storage::table_writer_api::TableFactory;
 use crate::{AppError, CacheKey, DbKey};
-use lru::LruCache;
-use redb::{Database, Key, MultimapTable, MultimapTableDefinition, Table, TableDefinition, WriteTransaction};
+use crate::storage::cache::{LruStats, MeteredLru};
+use redb::{Key, MultimapTable, MultimapTableDefinition, ReadTransaction, ReadableDatabase, ReadableMultimapTable, ReadableTableMetadata, Table, TableDefinition, TableError, Value, WriteTransaction};
 use std::fmt::Debug;
 use std::num::NonZeroUsize;
-use std::sync::Weak;
+use std::sync::{Arc, Weak};
 
 #[derive(Clone)]
-pub struct IndexFactory<K: Key + 'static, V: Key + 'static> {
+pub struct IndexFactory<K: Key + 'static, V: CacheKey> {
     pub(crate) name: String,
-    pub(crate) pk_by_index_def: MultimapTableDefinition<'static, V, K>,
+    pub(crate) pk_by_index_def: MultimapTableDefinition<'static, V::Key, K>,
     pub(crate) index_by_pk_def: TableDefinition<'static, K, V>,
     pub(crate) lru_capacity: Option<usize>,
+    pub(crate) bloom: bool,
+    /// `unique` fails the write transaction when a value is inserted for a second key
+    pub(crate) unique: bool,
+    /// bloom filters of the shards this factory serves, in shard order
+    pub(crate) bloom_filters: Vec<Arc<BloomFilter>>,
 }
 
 
-impl<K: Key + 'static, V: Key + 'static> Debug for IndexFactory<K, V> {
+impl<K: Key + 'static, V: CacheKey> Debug for IndexFactory<K, V> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("IndexFactory").field("name", &self.name).finish()
     }
 }
 
-impl<K: Key + 'static, V: Key + 'static> IndexFactory<K, V> {
-    pub fn new(name: &str, lru_capacity: usize, pk_by_index_def: MultimapTableDefinition<'static, V, K>, index_by_pk_def: TableDefinition<'static, K, V>) -> Self {
+impl<K: Key + 'static, V: CacheKey> IndexFactory<K, V> {
+    pub fn new(name: &str, lru_capacity: usize, bloom: bool, unique: bool, pk_by_index_def: MultimapTableDefinition<'static, V::Key, K>, index_by_pk_def: TableDefinition<'static, K, V>) -> Self {
         let lru_cache_size_opt =
             if lru_capacity < 1 {
                 None
@@ -34,29 +43,36 @@ impl<K: Key + 'static, V: Key + 'static> IndexFactory<K, V> {
             name: name.to_string(),
             pk_by_index_def,
             index_by_pk_def,
-            lru_capacity: lru_cache_size_opt
+            lru_capacity: lru_cache_size_opt,
+            bloom,
+            unique,
+            bloom_filters: Vec::new(),
         }
     }
 }
 
 pub struct IndexTable<'txn, 'c, K: DbKey, V: CacheKey> {
-    pub(crate) pk_by_index: MultimapTable<'txn, V, K>,
+    pub(crate) pk_by_index: MultimapTable<'txn, V::Key, K>,
     pub(crate) index_by_pk: Table<'txn, K, V>,
-    pub(crate) cache: Option<&'c mut LruCache<V::CK, K::Unit>>,
+    pub(crate) cache: Option<&'c mut MeteredLru<V::CK, K::Unit>>,
+    pub(crate) bloom: Option<Arc<BloomFilter>>,
+    pub(crate) unique: bool,
 }
 
 impl<'txn, 'c, K: DbKey, V: CacheKey> IndexTable<'txn, 'c, K, V> {
-    pub fn new(write_tx: &'txn WriteTransaction, cache: Option<&'c mut LruCache<V::CK, K::Unit>>, pk_by_index_def: MultimapTableDefinition<'static, V, K>, index_by_pk_def: TableDefinition<'static, K, V>) -> Result<Self, AppError> {
+    pub fn new(write_tx: &'txn WriteTransaction, cache: Option<&'c mut MeteredLru<V::CK, K::Unit>>, bloom: Option<Arc<BloomFilter>>, unique: bool, pk_by_index_def: MultimapTableDefinition<'static, V::Key, K>, index_by_pk_def: TableDefinition<'static, K, V>) -> Result<Self, AppError> {
         Ok(Self {
             pk_by_index: write_tx.open_multimap_table(pk_by_index_def)?,
             index_by_pk: write_tx.open_table(index_by_pk_def)?,
-            cache
+            cache,
+            bloom,
+            unique,
         })
     }
 }
 
 impl<K: DbKey, V: CacheKey> TableFactory<K, V> for IndexFactory<K, V> {
-    type CacheCtx = Option<LruCache<V::CK, K::Unit>>;
+    type CacheCtx = Option<MeteredLru<V::CK, K::Unit>>;
     type Table<'txn, 'c> = IndexTable<'txn, 'c, K, V>;
     type ReadOnlyTable = ReadOnlyIndexTable<K, V>;
 
@@ -64,8 +80,16 @@ impl<K: DbKey, V: CacheKey> TableFactory<K, V> for IndexFactory<K, V> {
         self.name.clone()
     }
 
+    fn kind(&self) -> ColumnKind {
+        ColumnKind::Index
+    }
+
     fn new_cache(&self) -> Self::CacheCtx {
-        self.lru_capacity.map(|cap| LruCache::new(NonZeroUsize::new(cap).expect("lru_capacity for index must be > 0")))
+        self.lru_capacity.map(|cap| MeteredLru::new(NonZeroUsize::new(cap).expect("lru_capacity for index must be > 0")))
+    }
+
+    fn cache_stats(cache: &Self::CacheCtx) -> Option<LruStats> {
+        cache.as_ref().map(|lru| lru.stats())
     }
 
     fn open_for_write<'txn, 'c>(
@@ -76,16 +100,60 @@ impl<K: DbKey, V: CacheKey> TableFactory<K, V> for IndexFactory<K, V> {
         IndexTable::new(
             tx,
             cache.as_mut(),
+            self.bloom_filters.first().cloned(),
+            self.unique,
             self.pk_by_index_def,
             self.index_by_pk_def,
         )
     }
 
-    fn open_for_read(&self, db_weak: &Weak<Database>) -> redb::Result<Self::ReadOnlyTable, AppError> {
+    fn open_for_read(&self, db_weak: &Weak<ShardDb>) -> redb::Result<Self::ReadOnlyTable, AppError> {
         ReadOnlyIndexTable::new(
             db_weak,
             self.pk_by_index_def,
             self.index_by_pk_def,
         )
     }
+
+    fn copy_tables(&self, src: &ReadTransaction, dst: &WriteTransaction) -> Result<u64, AppError> {
+        let pks = snapshot::copy_multimap_table(src, dst, self.pk_by_index_def)?;
+        let index = snapshot::copy_table(src, dst, self.index_by_pk_def)?;
+        Ok(pks + index)
+    }
+
+    fn bloom(&self) -> bool {
+        self.bloom
+    }
+
+    fn build_bloom(&self, db: &ShardDb) -> Result<BloomFilter, AppError> {
+        let tx = db.begin_read()?;
+        let pk_by_index = match tx.open_multimap_table(self.pk_by_index_def) {
+            Ok(t) => t,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(BloomFilter::with_capacity(0)),
+            Err(e) => return Err(e.into()),
+        };
+        // room to double before the filter has to grow
+        let filter = BloomFilter::with_capacity(pk_by_index.len()?.saturating_mul(2));
+        for entry in pk_by_index.iter()? {
+            let (value, _) = entry?;
+            filter.insert(V::Key::as_bytes(&value.value()).as_ref());
+        }
+        Ok(filter)
+    }
+
+    fn unique(&self) -> bool {
+        self.unique
+    }
+
+    fn with_bloom_filters(&self, filters: Vec<Arc<BloomFilter>>) -> Self {
+        Self {
+            name: self.name.clone(),
+            pk_by_index_def: self.pk_by_index_def,
+            index_by_pk_def: self.index_by_pk_def,
+            lru_capacity: self.lru_capacity,
+            bloom: self.bloom,
+            unique: self.unique,
+            bloom_filters: filters,
+        }
+    }
 }
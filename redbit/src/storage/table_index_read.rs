@@ -1,18 +1,24 @@
+use crate::compress::{ValueBytes, VALUE_BYTES_SAMPLE};
+use crate::storage::bloom::BloomFilter;
+use crate::storage::init::ShardDb;
+use crate::storage::fsck::{self, FsckIssue};
+use crate::storage::partitioning::{merge_value_ranges, KeyRange, MergedRange, ValueRange};
+use crate::storage::reshard;
 use crate::storage::table_index::IndexFactory;
 use crate::storage::table_writer_api::{ReadTableFactory, ReadTableLike, ShardedTableReader, TableFactory, TableInfo};
-use crate::{AppError, CacheKey, DbKey, KeyPartitioner, Partitioning, DbVal, ValuePartitioner};
-use redb::{AccessGuard, Database, Key, MultimapTableDefinition, MultimapValue, Range, ReadOnlyMultimapTable, ReadOnlyTable, ReadableDatabase, ReadableTableMetadata, TableDefinition};
+use crate::{AppError, CacheKey, DbKey, KeyPartitioner, Partitioning, ValuePartitioner};
+use redb::{AccessGuard, Database, Key, MultimapTableDefinition, MultimapValue, ReadOnlyMultimapTable, ReadOnlyTable, ReadTransaction, ReadableDatabase, ReadableMultimapTable, ReadableTableMetadata, TableDefinition, Value};
 use std::borrow::Borrow;
 use std::ops::RangeBounds;
-use std::sync::Weak;
+use std::sync::{Arc, Weak};
 
-pub struct ReadOnlyIndexTable<K: Key + 'static, V: Key + 'static> {
-    pk_by_index: ReadOnlyMultimapTable<V, K>,
+pub struct ReadOnlyIndexTable<K: Key + 'static, V: CacheKey> {
+    pk_by_index: ReadOnlyMultimapTable<V::Key, K>,
     index_by_pk: ReadOnlyTable<K, V>,
 }
 
-impl<K: Key + 'static, V: Key + 'static> ReadOnlyIndexTable<K, V> {
-    pub fn new(db_weak: &Weak<Database>, pk_by_index_def: MultimapTableDefinition<V, K>, index_by_pk_def: TableDefinition<K, V>) -> Result<Self, AppError> {
+impl<K: Key + 'static, V: CacheKey> ReadOnlyIndexTable<K, V> {
+    pub fn new(db_weak: &Weak<ShardDb>, pk_by_index_def: MultimapTableDefinition<V::Key, K>, index_by_pk_def: TableDefinition<K, V>) -> Result<Self, AppError> {
         let db_arc = db_weak.upgrade().ok_or_else(|| AppError::Custom("database closed".to_string()))?;
         let tx = db_arc.begin_read()?;
         Ok(Self {
@@ -22,23 +28,24 @@ impl<K: Key + 'static, V: Key + 'static> ReadOnlyIndexTable<K, V> {
     }
 }
 
-pub struct ShardedReadOnlyIndexTable<K: DbKey, V: DbVal, VP: ValuePartitioner<V>> {
+pub struct ShardedReadOnlyIndexTable<K: DbKey, V: CacheKey, VP: ValuePartitioner<V>> {
     shards: Vec<ReadOnlyIndexTable<K, V>>,
+    bloom_filters: Vec<Arc<BloomFilter>>,
     value_partitioner: VP,
 }
 
 impl<K: DbKey, V: CacheKey, VP: ValuePartitioner<V>> ShardedReadOnlyIndexTable<K, V, VP> {
-    pub fn new(value_partitioner: VP, dbs: Vec<Weak<Database>>, factory: &IndexFactory<K, V>) -> Result<Self, AppError> {
+    pub fn new(value_partitioner: VP, dbs: Vec<Weak<ShardDb>>, factory: &IndexFactory<K, V>) -> Result<Self, AppError> {
         let mut shards = Vec::with_capacity(dbs.len());
         for db_weak in &dbs {
             shards.push(factory.open_for_read(db_weak)?);
         }
-        Ok(Self { shards, value_partitioner })
+        Ok(Self { shards, bloom_filters: factory.bloom_filters.clone(), value_partitioner })
     }
 }
 
 impl<K: DbKey, V: CacheKey, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> ReadTableFactory<K, V, KP, VP> for IndexFactory<K, V> {
-    fn build_sharded_reader(&self, dbs: Vec<Weak<Database>>, partitioning: &Partitioning<KP, VP>) -> Result<ShardedTableReader<K, V, KP, VP>, AppError> {
+    fn build_sharded_reader(&self, dbs: Vec<Weak<ShardDb>>, partitioning: &Partitioning<KP, VP>) -> Result<ShardedTableReader<K, V, KP, VP>, AppError> {
         match partitioning {
             Partitioning::ByKey(_) => {
                 Err(AppError::Custom("IndexFactory does not support key partitioning".to_string()))
@@ -49,9 +56,26 @@ impl<K: DbKey, V: CacheKey, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> Read
             }
         }
     }
+
+    fn reshard_tables(&self, src: &ReadTransaction, dst: &[Database], partitioning: &Partitioning<KP, VP>) -> Result<u64, AppError> {
+        match partitioning {
+            Partitioning::ByKey(_) => {
+                Err(AppError::Custom("IndexFactory does not support key partitioning".to_string()))
+            }
+            Partitioning::ByValue(vp) => {
+                let pks = reshard::reshard_multimap_table(src, dst, self.pk_by_index_def, |v, _| Ok(vp.partition_value(v)))?;
+                let index = reshard::reshard_table(src, dst, self.index_by_pk_def, |_, v| Ok(vp.partition_value(v)))?;
+                Ok(pks + index)
+            }
+        }
+    }
+
+    fn fsck_tables(&self, db: &ShardDb, repair: bool) -> Result<(u64, Vec<FsckIssue>), AppError> {
+        fsck::check_index(db, &self.name, self.pk_by_index_def, self.index_by_pk_def, repair)
+    }
 }
 
-impl<K: DbKey, V: DbVal, VP: ValuePartitioner<V>> ReadTableLike<K, V> for ShardedReadOnlyIndexTable<K, V, VP> {
+impl<K: DbKey, V: CacheKey, VP: ValuePartitioner<V>> ReadTableLike<K, V> for ShardedReadOnlyIndexTable<K, V, VP> {
 
     fn get_value<'k>(&self, key: impl Borrow<K::SelfType<'k>>) -> Result<Option<AccessGuard<'_, V>>, AppError> {
         for shard in &self.shards {
@@ -62,24 +86,45 @@ impl<K: DbKey, V: DbVal, VP: ValuePartitioner<V>> ReadTableLike<K, V> for Sharde
         Ok(None)
     }
 
-    fn index_keys<'v>(&self, val: impl Borrow<V::SelfType<'v>>) -> Result<MultimapValue<'static, K>, AppError> {
-        let shard =
+    fn index_keys<'v>(&self, val: impl Borrow<V::SelfType<'v>>) -> Result<Option<MultimapValue<'static, K>>, AppError> {
+        let shard_idx =
             if self.shards.len() == 1 {
-                &self.shards[0]
+                0
             } else {
-                &self.shards[self.value_partitioner.partition_value(val.borrow())]
+                self.value_partitioner.partition_value(val.borrow())
             };
-        Ok(shard.pk_by_index.get(val.borrow())?)
+        if let Some(b) = self.bloom_filters.get(shard_idx) && !b.may_contain(V::Key::as_bytes(val.borrow()).as_ref()) {
+            return Ok(None);
+        }
+        Ok(Some(self.shards[shard_idx].pk_by_index.get(val.borrow())?))
     }
 
-    fn index_range<'a, KR: Borrow<V::SelfType<'a>>>(&self, range: impl RangeBounds<KR>) -> Result<redb::MultimapRange<'static, V, K>, AppError> {
-        let shard =
-            if self.shards.len() == 1 {
-                &self.shards[0]
-            } else {
-                unimplemented!()
-            };
-        Ok(shard.pk_by_index.range(range)?)
+    fn index_range<'a, KR: Borrow<V::SelfType<'a>>>(&self, range: impl RangeBounds<KR>) -> Result<ValueRange<V::Key, K>, AppError> {
+        let mut ranges = Vec::with_capacity(self.shards.len());
+        for shard in &self.shards {
+            ranges.push(shard.pk_by_index.range::<KR>((range.start_bound(), range.end_bound()))?);
+        }
+        Ok(MergedRange::new(ranges))
+    }
+
+    fn value_range_keys<'a, KR: Borrow<V::SelfType<'a>>>(&self, range: impl RangeBounds<KR>, limit: usize) -> Result<Vec<AccessGuard<'static, K>>, AppError> {
+        let mut ranges = Vec::with_capacity(self.shards.len());
+        for shard in &self.shards {
+            ranges.push(shard.pk_by_index.range::<KR>((range.start_bound(), range.end_bound()))?);
+        }
+        let mut keys = Vec::new();
+        if limit > 0 {
+            merge_value_ranges(ranges, |_, _, pks| {
+                for pk in pks {
+                    keys.push(pk?);
+                    if keys.len() == limit {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            })?;
+        }
+        Ok(keys)
     }
 
     /// Aggregated stats: sum len() across shards for pk_by_index, use the first shard's pk_by_index.stats() as representative.
@@ -90,18 +135,24 @@ impl<K: DbKey, V: DbVal, VP: ValuePartitioner<V>> ReadTableLike<K, V> for Sharde
             total = total.saturating_add(s.pk_by_index.len()?);
         }
         let rep_stats = self.shards[0].pk_by_index.stats()?;
+        let mut sample = ValueBytes::default();
+        for s in &self.shards {
+            for entry in s.pk_by_index.iter()?.take(VALUE_BYTES_SAMPLE - sample.values as usize) {
+                sample.add::<V::Key>(&entry?.0.value());
+            }
+        }
         Ok(vec![TableInfo::from_stats(
             "pk_by_index",
             total,
             rep_stats,
-        )])
+        ).with_value_bytes(sample)])
     }
 
-    fn iter_keys(&self) -> Result<Range<'_, K, V>, AppError> {
+    fn iter_keys(&self) -> Result<KeyRange<K, V>, AppError> {
         unimplemented!()
     }
 
-    fn range<'a, KR: Borrow<K::SelfType<'a>>>(&self, _range: impl RangeBounds<KR>) -> Result<Range<'static, K, V>, AppError> {
+    fn range<'a, KR: Borrow<K::SelfType<'a>>>(&self, _range: impl RangeBounds<KR>) -> Result<KeyRange<K, V>, AppError> {
         unimplemented!()
     }
 
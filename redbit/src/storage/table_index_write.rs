@@ -2,16 +2,41 @@ use crate::storage::async_boundary::{ValueBuf, ValueOwned};
 use crate::storage::table_index::IndexTable;
 use crate::storage::table_writer_api::WriteTableLike;
 use crate::{AppError, CacheKey, DbKey};
-use redb::ReadableMultimapTable;
+use redb::{Key, ReadableMultimapTable, Value};
 use std::borrow::Borrow;
 use std::ops::RangeBounds;
 
+impl<'txn, 'c, K: DbKey, V: CacheKey> IndexTable<'txn, 'c, K, V> {
+    /// Values of unique indexes are partitioned like any other, so the shard holding a value sees all of its keys,
+    /// including the ones inserted earlier in the same batch.
+    fn check_unique<'k, 'v>(&self, key: &K::SelfType<'k>, value: &V::SelfType<'v>) -> Result<(), AppError> {
+        if !self.unique {
+            return Ok(());
+        }
+        if let Some(b) = &self.bloom && !b.may_contain(V::Key::as_bytes(value).as_ref()) {
+            return Ok(());
+        }
+        for guard in self.pk_by_index.get(value)? {
+            let guard = guard?;
+            let other = guard.value();
+            if K::as_bytes(&other).as_ref() != K::as_bytes(key).as_ref() {
+                return Err(AppError::UniqueViolation(format!("{:?} is already indexed by {:?}, cannot index it by {:?}", value, other, key)));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl<'txn, 'c, K: DbKey, V: CacheKey> WriteTableLike<K, V> for IndexTable<'txn, 'c, K, V> {
     fn insert_kv<'k, 'v>(&mut self, key: impl Borrow<K::SelfType<'k>>, value: impl Borrow<V::SelfType<'v>>) -> Result<(), AppError>  {
         let key_ref: &K::SelfType<'k> = key.borrow();
         let val_ref: &V::SelfType<'v> = value.borrow();
+        self.check_unique(key_ref, val_ref)?;
         self.index_by_pk.insert(key_ref, val_ref)?;
         self.pk_by_index.insert(val_ref, key_ref)?;
+        if let Some(b) = &self.bloom {
+            b.insert(V::Key::as_bytes(val_ref).as_ref());
+        }
 
         if let Some(c) = self.cache.as_mut() {
             c.put(V::cache_key(val_ref), Self::unit_from_key(key_ref));
@@ -36,13 +61,17 @@ impl<'txn, 'c, K: DbKey, V: CacheKey> WriteTableLike<K, V> for IndexTable<'txn,
 
         // --- Run 2: sort by Value ---
         pairs.sort_by(|(_, a), (_, b)| {
-            V::compare(V::as_bytes(a.borrow()).as_ref(), V::as_bytes(b.borrow()).as_ref())
+            V::Key::compare(V::Key::as_bytes(a.borrow()).as_ref(), V::Key::as_bytes(b.borrow()).as_ref())
         });
 
         for (k, v) in &pairs {
             let key_ref: &K::SelfType<'k> = k.borrow();
             let val_ref: &V::SelfType<'v> = v.borrow();
+            self.check_unique(key_ref, val_ref)?;
             self.pk_by_index.insert(val_ref, key_ref)?;
+            if let Some(b) = &self.bloom {
+                b.insert(V::Key::as_bytes(val_ref).as_ref());
+            }
         }
 
         Ok(())
@@ -68,6 +97,9 @@ impl<'txn, 'c, K: DbKey, V: CacheKey> WriteTableLike<K, V> for IndexTable<'txn,
                 return Ok(Some(Self::owned_from_unit(k)));
             }
         }
+        if let Some(b) = &self.bloom && !b.may_contain(V::Key::as_bytes(value.borrow()).as_ref()) {
+            return Ok(None);
+        }
 
         let mut it = self.pk_by_index.get(value)?;
         if let Some(g) = it.next() {
@@ -84,7 +116,9 @@ impl<'txn, 'c, K: DbKey, V: CacheKey> WriteTableLike<K, V> for IndexTable<'txn,
 #[cfg(all(test, not(feature = "integration")))]
 mod tests {
     use super::*;
+    use crate::storage::bloom::BloomFilter;
     use crate::storage::index_test_utils::{mk_index, setup_index_defs};
+    use std::sync::Arc;
     use crate::storage::test_utils;
     use crate::storage::test_utils::TxHash;
 
@@ -178,4 +212,60 @@ mod tests {
         // Sanity: the tables still don't have this mapping; deleting should return false.
         assert!(!tbl.delete_kv(&k).expect("delete_kv on non-existent table row should be false"));
     }
+
+    // Bloom filter: values it has not seen are answered as missing without reading pk_by_index.
+    #[test]
+    fn bloom_filter_answers_missing_values_without_table_lookup() {
+        let name = "bloom_filter_answers_missing_values_without_table_lookup";
+        let (_owner_db, _, mut cache, pk_by_index_def, index_by_pk_def) = setup_index_defs::<u32, TxHash>(name, 1);
+
+        let tx = _owner_db.begin_write().expect("begin write");
+        let mut tbl: IndexTable<'_, '_, u32, TxHash> = mk_index(&tx, &mut cache, pk_by_index_def, index_by_pk_def);
+        tbl.cache = None;
+        tbl.bloom = Some(Arc::new(BloomFilter::with_capacity(0)));
+
+        let present = test_utils::txh(&[1, 1, 1]);
+        tbl.insert_kv(&5u32, &present).expect("insert 5");
+        assert_eq!(tbl.get_any_for_index(&present).unwrap().unwrap().as_value(), 5);
+
+        // written behind the filter's back, so only a table lookup could find it
+        let hidden = test_utils::txh(&[2, 2, 2]);
+        tbl.pk_by_index.insert(&hidden, &6u32).expect("raw insert");
+        assert!(tbl.get_any_for_index(&hidden).unwrap().is_none(), "filter must short-circuit the lookup");
+    }
+
+    // Unique index: the same key may be written again, a second key for the value is rejected.
+    #[test]
+    fn unique_index_rejects_second_key_for_value() {
+        let name = "unique_index_rejects_second_key_for_value";
+        let (_owner_db, _, mut cache, pk_by_index_def, index_by_pk_def) = setup_index_defs::<u32, TxHash>(name, 1_000);
+
+        let tx = _owner_db.begin_write().expect("begin write");
+        let mut tbl: IndexTable<'_, '_, u32, TxHash> = mk_index(&tx, &mut cache, pk_by_index_def, index_by_pk_def);
+        tbl.unique = true;
+
+        let h = test_utils::txh(&[4, 4, 4]);
+        tbl.insert_kv(&1u32, &h).expect("insert 1");
+        tbl.insert_kv(&1u32, &h).expect("re-insert of the same key");
+        let err = tbl.insert_kv(&2u32, &h).expect_err("second key must be rejected");
+        assert!(matches!(err, AppError::UniqueViolation(_)), "unexpected error {err}");
+    }
+
+    // Duplicates within one sorted batch are caught as well as those against the table.
+    #[test]
+    fn unique_index_rejects_duplicates_within_batch() {
+        let name = "unique_index_rejects_duplicates_within_batch";
+        let (_owner_db, _, mut cache, pk_by_index_def, index_by_pk_def) = setup_index_defs::<u32, TxHash>(name, 1_000);
+
+        let tx = _owner_db.begin_write().expect("begin write");
+        let mut tbl: IndexTable<'_, '_, u32, TxHash> = mk_index(&tx, &mut cache, pk_by_index_def, index_by_pk_def);
+        tbl.unique = true;
+
+        let (a, b) = (test_utils::txh(&[1]), test_utils::txh(&[2]));
+        tbl.insert_many_sorted_by_key(vec![(1u32, a), (2u32, b)]).expect("distinct values");
+        let err = tbl.insert_many_sorted_by_key(vec![(3u32, test_utils::txh(&[3])), (4u32, test_utils::txh(&[3]))]).expect_err("batch duplicate");
+        assert!(matches!(err, AppError::UniqueViolation(_)));
+        let err = tbl.insert_many_sorted_by_key(vec![(5u32, test_utils::txh(&[1]))]).expect_err("duplicate of a stored value");
+        assert!(matches!(err, AppError::UniqueViolation(_)));
+    }
 }
@@ -1,7 +1,10 @@
+use crate::storage::init::ShardDb;
 use std::fmt::Debug;
 use std::sync::Weak;
-use redb::{Database, Key, Table, TableDefinition, WriteTransaction};
+use redb::{Key, ReadTransaction, Table, TableDefinition, WriteTransaction};
 use crate::{AppError, DbKey, DbVal};
+use crate::storage::schema::ColumnKind;
+use crate::storage::snapshot;
 use crate::storage::table_plain_read::ReadOnlyPlainTable;
 use crate::storage::table_writer_api::TableFactory;
 
@@ -47,13 +50,25 @@ impl<K: DbKey, V: DbVal + 'static> TableFactory<K, V> for PlainFactory<K, V> {
         self.name.clone()
     }
 
+    fn kind(&self) -> ColumnKind {
+        ColumnKind::Plain
+    }
+
     fn new_cache(&self) -> Self::CacheCtx { }
 
     fn open_for_write<'txn, 'c>(&self, tx: &'txn WriteTransaction, _cache: &'c mut Self::CacheCtx) -> redb::Result<Self::Table<'txn, 'c>, AppError> {
         PlainTable::new(tx, self.table_def)
     }
 
-    fn open_for_read(&self, db_weak: &Weak<Database>) -> redb::Result<Self::ReadOnlyTable, AppError> {
+    fn open_for_read(&self, db_weak: &Weak<ShardDb>) -> redb::Result<Self::ReadOnlyTable, AppError> {
         ReadOnlyPlainTable::new(db_weak, self.table_def)
     }
+
+    fn copy_tables(&self, src: &ReadTransaction, dst: &WriteTransaction) -> Result<u64, AppError> {
+        snapshot::copy_table(src, dst, self.table_def)
+    }
+
+    fn last_key(&self, tx: &ReadTransaction) -> Result<Option<String>, AppError> {
+        snapshot::last_key(tx, self.table_def)
+    }
 }
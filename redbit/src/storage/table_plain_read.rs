@@ -1,8 +1,14 @@
+use crate::compress::{ValueBytes, VALUE_BYTES_SAMPLE};
+use crate::storage::init::ShardDb;
+use crate::storage::partitioning::{KeyRange, MergedRange, ValueRange};
+use crate::storage::fsck::{self, FsckIssue};
+use crate::storage::reshard;
 use crate::storage::table_plain::PlainFactory;
 use crate::storage::table_writer_api::{ReadTableFactory, ReadTableLike, ShardedTableReader, TableFactory, TableInfo};
-use crate::{AppError, DbKey, KeyPartitioner, Partitioning, DbVal, ValuePartitioner};
-use redb::{AccessGuard, Database, Key, MultimapRange, MultimapValue, ReadOnlyTable, ReadableDatabase, ReadableTable, ReadableTableMetadata, TableDefinition};
+use crate::{AppError, DbKey, KeyPartitioner, Partitioning, CacheKey, ValuePartitioner};
+use redb::{AccessGuard, Database, Key, MultimapValue, ReadOnlyTable, ReadTransaction, ReadableDatabase, ReadableTable, ReadableTableMetadata, TableDefinition};
 use std::borrow::Borrow;
+use std::cmp::Ordering;
 use std::ops::RangeBounds;
 use std::sync::Weak;
 
@@ -11,7 +17,7 @@ pub struct ReadOnlyPlainTable<K: Key + 'static, V: Key + 'static> {
 }
 
 impl<K: Key + 'static, V: Key + 'static> ReadOnlyPlainTable<K, V> {
-    pub fn new(db_weak: &Weak<Database>, underlying_def: TableDefinition<K, V>) -> Result<Self, AppError> {
+    pub fn new(db_weak: &Weak<ShardDb>, underlying_def: TableDefinition<K, V>) -> Result<Self, AppError> {
         let db_arc = db_weak.upgrade().ok_or_else(|| AppError::Custom("database closed".to_string()))?;
         let tx = db_arc.begin_read()?;
         Ok(Self {
@@ -20,24 +26,61 @@ impl<K: Key + 'static, V: Key + 'static> ReadOnlyPlainTable<K, V> {
     }
 }
 
-pub struct ShardedReadOnlyPlainTable<K: DbKey, V: DbVal, KP: KeyPartitioner<K>> {
+pub struct ShardedReadOnlyPlainTable<K: DbKey, V: CacheKey, KP: KeyPartitioner<K>> {
     shards: Vec<ReadOnlyPlainTable<K, V>>,
     pk_partitioner: KP,
 }
 
-impl<K: DbKey, V: DbVal, KP: KeyPartitioner<K>> ShardedReadOnlyPlainTable<K, V, KP> {
+impl<K: DbKey, V: CacheKey, KP: KeyPartitioner<K>> ShardedReadOnlyPlainTable<K, V, KP> {
     /// Build a sharded reader. Requires at least 2 DBs.
-    pub fn new(pk_partitioner: KP, dbs: Vec<Weak<Database>>, factory: &PlainFactory<K, V>) -> Result<Self, AppError> {
+    pub fn new(pk_partitioner: KP, dbs: Vec<Weak<ShardDb>>, factory: &PlainFactory<K, V>) -> Result<Self, AppError> {
         let mut shards = Vec::with_capacity(dbs.len());
         for db_weak in &dbs {
             shards.push(factory.open_for_read(db_weak)?);
         }
         Ok(Self { shards, pk_partitioner })
     }
+
+    /// Shards the range can have keys in, ordered the way the partitioner visits them.
+    pub fn range_shards<'a, KR: Borrow<K::SelfType<'a>>>(&self, range: &impl RangeBounds<KR>) -> Vec<usize> {
+        if self.shards.len() == 1 {
+            return vec![0];
+        }
+        let from = range.start_bound().map(|k| k.borrow());
+        let until = range.end_bound().map(|k| k.borrow());
+        self.pk_partitioner.partition_range(from, until).unwrap_or_else(|| (0..self.shards.len()).collect())
+    }
+
+    /// Ranges of the shards a key range overlaps, each sorted but not merged with the others.
+    fn ranges<'a, KR: Borrow<K::SelfType<'a>>>(&self, range: impl RangeBounds<KR>) -> Result<Vec<redb::Range<'static, K, V>>, AppError> {
+        let bounds = (range.start_bound(), range.end_bound());
+        self.range_shards(&range).into_iter().map(|idx| Ok(self.shards[idx].underlying.range::<KR>(bounds)?)).collect()
+    }
+
+    /// First or last entry over all shards, each shard is asked for its own edge only.
+    fn edge_entry(
+        &self,
+        edge: impl Fn(&ReadOnlyPlainTable<K, V>) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>, AppError>,
+        wanted: Ordering,
+    ) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>, AppError> {
+        let mut best: Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)> = None;
+        for shard in &self.shards {
+            if let Some(entry) = edge(shard)? {
+                let better = match &best {
+                    None => true,
+                    Some((k, _)) => K::compare(K::as_bytes(&entry.0.value()).as_ref(), K::as_bytes(&k.value()).as_ref()) == wanted,
+                };
+                if better {
+                    best = Some(entry);
+                }
+            }
+        }
+        Ok(best)
+    }
 }
 
-impl<K: DbKey, V: DbVal, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> ReadTableFactory<K, V, KP, VP> for PlainFactory<K, V> {
-    fn build_sharded_reader(&self, dbs: Vec<Weak<Database>>, partitioning: &Partitioning<KP, VP>) -> Result<ShardedTableReader<K, V, KP, VP>, AppError> {
+impl<K: DbKey, V: CacheKey, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> ReadTableFactory<K, V, KP, VP> for PlainFactory<K, V> {
+    fn build_sharded_reader(&self, dbs: Vec<Weak<ShardDb>>, partitioning: &Partitioning<KP, VP>) -> Result<ShardedTableReader<K, V, KP, VP>, AppError> {
         match partitioning {
             Partitioning::ByKey(kp) => {
                 let table = ShardedReadOnlyPlainTable::new(kp.clone(), dbs, self)?;
@@ -48,9 +91,24 @@ impl<K: DbKey, V: DbVal, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> ReadTab
             }
         }
     }
+
+    fn reshard_tables(&self, src: &ReadTransaction, dst: &[Database], partitioning: &Partitioning<KP, VP>) -> Result<u64, AppError> {
+        match partitioning {
+            Partitioning::ByKey(kp) => {
+                reshard::reshard_table(src, dst, self.table_def, |k, _| Ok(kp.partition_key(k)))
+            }
+            Partitioning::ByValue(_) => {
+                Err(AppError::Custom("PlainFactory does not support value partitioning".to_string()))
+            }
+        }
+    }
+
+    fn fsck_tables(&self, db: &ShardDb, _repair: bool) -> Result<(u64, Vec<FsckIssue>), AppError> {
+        fsck::check_plain(db, &self.name, self.table_def)
+    }
 }
 
-impl<K: DbKey, V: DbVal, KP: KeyPartitioner<K>> ReadTableLike<K, V> for ShardedReadOnlyPlainTable<K, V, KP> {
+impl<K: DbKey, V: CacheKey, KP: KeyPartitioner<K>> ReadTableLike<K, V> for ShardedReadOnlyPlainTable<K, V, KP> {
     fn get_value<'k>(&self, key: impl Borrow<K::SelfType<'k>>) -> Result<Option<AccessGuard<'_, V>>, AppError> {
         let shard =
             if self.shards.len() == 1 {
@@ -62,43 +120,27 @@ impl<K: DbKey, V: DbVal, KP: KeyPartitioner<K>> ReadTableLike<K, V> for ShardedR
     }
 
     fn first_key(&self) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>, AppError> {
-        let shard =
-            if self.shards.len() == 1 {
-                &self.shards[0]
-            } else {
-                unimplemented!()
-            };
-        Ok(shard.underlying.first()?)
+        if self.shards.len() == 1 {
+            Ok(self.shards[0].underlying.first()?)
+        } else {
+            self.edge_entry(|s| Ok(s.underlying.first()?), Ordering::Less)
+        }
     }
 
     fn last_key(&self) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>, AppError> {
-        let shard =
-            if self.shards.len() == 1 {
-                &self.shards[0]
-            } else {
-                unimplemented!()
-            };
-        Ok(shard.underlying.last()?)
+        if self.shards.len() == 1 {
+            Ok(self.shards[0].underlying.last()?)
+        } else {
+            self.edge_entry(|s| Ok(s.underlying.last()?), Ordering::Greater)
+        }
     }
 
-    fn range<'a, KR: Borrow<K::SelfType<'a>>>(&self, range: impl RangeBounds<KR>) -> Result<redb::Range<'static, K, V>, AppError> {
-        let shard =
-            if self.shards.len() == 1 {
-                &self.shards[0]
-            } else {
-                unimplemented!()
-            };
-        Ok(shard.underlying.range(range)?)
+    fn range<'a, KR: Borrow<K::SelfType<'a>>>(&self, range: impl RangeBounds<KR>) -> Result<KeyRange<K, V>, AppError> {
+        Ok(MergedRange::new(self.ranges(range)?))
     }
 
-    fn iter_keys(&self) -> Result<redb::Range<'_, K, V>, AppError> {
-        let shard =
-            if self.shards.len() == 1 {
-                &self.shards[0]
-            } else {
-                unimplemented!()
-            };
-        Ok(shard.underlying.iter()?)
+    fn iter_keys(&self) -> Result<KeyRange<K, V>, AppError> {
+        self.range::<K::SelfType<'_>>(..)
     }
 
     fn stats(&self) -> Result<Vec<TableInfo>, AppError> {
@@ -107,17 +149,27 @@ impl<K: DbKey, V: DbVal, KP: KeyPartitioner<K>> ReadTableLike<K, V> for ShardedR
             total = total.saturating_add(s.underlying.len()?);
         }
         let rep_stats = self.shards[0].underlying.stats()?;
-        Ok(vec![TableInfo::from_stats("underlying", total, rep_stats)])
+        let mut sample = ValueBytes::default();
+        for s in &self.shards {
+            for entry in s.underlying.iter()?.take(VALUE_BYTES_SAMPLE - sample.values as usize) {
+                sample.add::<V>(&entry?.1.value());
+            }
+        }
+        Ok(vec![TableInfo::from_stats("underlying", total, rep_stats).with_value_bytes(sample)])
     }
 
-    fn index_keys<'v>(&self, _val: impl Borrow<V::SelfType<'v>>) -> Result<MultimapValue<'static, K>, AppError> {
+    fn index_keys<'v>(&self, _val: impl Borrow<V::SelfType<'v>>) -> Result<Option<MultimapValue<'static, K>>, AppError> {
         unimplemented!()
     }
 
-    fn index_range<'a, KR: Borrow<V::SelfType<'a>>>(&self, _range: impl RangeBounds<KR>) -> Result<MultimapRange<'static, V, K>, AppError> {
+    fn index_range<'a, KR: Borrow<V::SelfType<'a>>>(&self, _range: impl RangeBounds<KR>) -> Result<ValueRange<V::Key, K>, AppError> {
         unimplemented!()
     }
 
+    fn value_range_keys<'a, KR: Borrow<V::SelfType<'a>>>(&self, _range: impl RangeBounds<KR>, _limit: usize) -> Result<Vec<AccessGuard<'static, K>>, AppError> {
+        Err(AppError::Custom("value_range_keys unsupported for plain tables, they are not ordered by value".into()))
+    }
+
     fn dict_keys<'v>(&self, _val: impl Borrow<V::SelfType<'v>>) -> redb::Result<Option<MultimapValue<'static, K>>, AppError> {
         unimplemented!()
     }
@@ -1,8 +1,10 @@
 use crate::storage::async_boundary::{ValueBuf, ValueOwned};
 use crate::storage::partitioning::{KeyPartitioner, ValuePartitioner};
 use crate::storage::router::Router;
+use crate::storage::commit::{shard_file_name, CommitMarker};
+use crate::storage::snapshot::CommitFence;
 use crate::storage::table_writer_api::*;
-use crate::{AppError, DbKey, DbVal, TxFSM};
+use crate::{AppError, DbKey, CacheKey, TxFSM};
 use crossbeam::channel::bounded;
 use redb::Durability;
 use std::cell::RefCell;
@@ -12,7 +14,7 @@ use std::sync::Arc;
 
 pub struct ShardedTableWriter<
     K: DbKey + Send,
-    V: DbVal + Send,
+    V: CacheKey + Send,
     KP: KeyPartitioner<K>,
     VP: ValuePartitioner<V>,
     F: TableFactory<K, V>,
@@ -21,31 +23,101 @@ pub struct ShardedTableWriter<
     shards: Vec<TxFSM<K, V, F>>,
     router: Arc<dyn Router<K, V>>,
     deferred: AtomicBool,
+    prepare: bool,
     sync_buf: RefCell<Vec<(K, V)>>,
+    commit_fence: Option<Arc<CommitFence>>,
     _pd: PhantomData<(KP,VP)>,
 }
 
-impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>,F> ShardedTableWriter<K,V,KP,VP,F>
+impl<K: DbKey + Send, V: CacheKey + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>,F> ShardedTableWriter<K,V,KP,VP,F>
     where F: TableFactory<K, V>,
 {
     pub fn new(root_pk: bool, shards: Vec<TxFSM<K, V, F>>, router: Arc<dyn Router<K, V>>, deferred: AtomicBool) -> Result<Self, AppError> {
-        Ok(Self { root_pk, router, deferred, shards, sync_buf: RefCell::new(Vec::new()), _pd: PhantomData })
+        Ok(Self { root_pk, router, deferred, prepare: false, shards, sync_buf: RefCell::new(Vec::new()), commit_fence: None, _pd: PhantomData })
+    }
+
+    pub fn with_commit_fence(mut self, fence: Arc<CommitFence>) -> Self {
+        self.commit_fence = Some(fence);
+        self
+    }
+
+    /// Prepared writers write their inserts ahead of the commit, see `WriterCommand::Prepare`.
+    pub fn with_prepare(mut self, prepare: bool) -> Self {
+        self.prepare = prepare;
+        self
+    }
+
+    fn route_sync_buf(&self) -> Result<(), AppError> {
+        if !self.sync_buf.borrow().is_empty() {
+            self.router.write_sorted_inserts_on_flush(std::mem::take(&mut *self.sync_buf.borrow_mut()))?;
+        }
+        Ok(())
+    }
+
+    /// Writers used on their own form a commit of their shards, within a write context the context allocates the marker.
+    fn own_marker(&self) -> Option<CommitMarker> {
+        self.commit_fence.as_ref().map(|f| CommitMarker::new(f.next_commit(), self.shard_names()))
+    }
+
+    fn shard_names(&self) -> Vec<String> {
+        self.shards.iter().enumerate().map(|(idx, w)| shard_file_name(&w.name, self.shards.len(), idx)).collect()
+    }
+
+    fn begin_marked_async(&self, durability: Durability, marker: Option<CommitMarker>) -> Result<Vec<StartFuture>, AppError> {
+        let mut v = Vec::with_capacity(self.shards.len());
+        for w in &self.shards {
+            let (ack_tx, ack_rx) = bounded::<Result<(), AppError>>(1);
+            w.topic.send(WriterCommand::Begin(ack_tx, durability, marker.clone()))?;
+            v.push(StartFuture(ack_rx));
+        }
+        Ok(v)
     }
 }
 
-impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>,F> WriteComponentRef for ShardedTableWriter<K,V,KP,VP,F>
+impl<K: DbKey + Send, V: CacheKey + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>,F> WriteComponentRef for ShardedTableWriter<K,V,KP,VP,F>
     where F: TableFactory<K, V> + Send + 'static,
 {
-    fn begin_async_ref(&self, d: Durability) -> redb::Result<Vec<StartFuture>, AppError> {
-        self.begin_async(d)
+    fn begin_async_ref(&self, d: Durability, marker: Option<CommitMarker>) -> redb::Result<Vec<StartFuture>, AppError> {
+        self.begin_marked_async(d, marker)
+    }
+    fn participants(&self) -> Vec<String> {
+        self.shard_names()
     }
     fn commit_with_ref(&self) -> Result<Vec<FlushFuture>, AppError> {
         self.flush_async()
     }
+    fn prepare_with_ref(&self) -> Result<Vec<StartFuture>, AppError> {
+        if !self.prepare {
+            return Ok(Vec::new());
+        }
+        self.route_sync_buf()?;
+        // deferred writers keep receiving inserts from other writers, they write ahead once all of them are ready
+        let deferred = self.deferred.load(Ordering::SeqCst);
+        let mut v = Vec::with_capacity(self.shards.len());
+        for w in &self.shards {
+            let (ack_tx, ack_rx) = bounded::<Result<(), AppError>>(1);
+            if deferred {
+                w.topic.send(WriterCommand::PrepareWhenReady(ack_tx))?;
+            } else {
+                w.topic.send(WriterCommand::Prepare(ack_tx))?;
+            }
+            v.push(StartFuture(ack_rx));
+        }
+        Ok(v)
+    }
+    fn abort_with_ref(&self) -> Result<(), AppError> {
+        for w in &self.shards {
+            w.topic.send(WriterCommand::Abort)?;
+        }
+        Ok(())
+    }
+    fn commit_fence(&self) -> Option<Arc<CommitFence>> {
+        self.commit_fence.clone()
+    }
 }
 
 
-impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>,F> WriterLike<K, V> for ShardedTableWriter<K,V,KP,VP,F>
+impl<K: DbKey + Send, V: CacheKey + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>,F> WriterLike<K, V> for ShardedTableWriter<K,V,KP,VP,F>
     where F: TableFactory<K, V> + Send + 'static,
 {
     fn acquire_router(&self) -> Arc<dyn Router<K, V>> {
@@ -54,22 +126,17 @@ impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartition
     }
 
     fn begin(&self, durability: Durability) -> Result<(), AppError> {
+        let marker = self.own_marker();
         for w in &self.shards {
             let (ack_tx, ack_rx) = bounded::<Result<(), AppError>>(1);
-            w.topic.send(WriterCommand::Begin(ack_tx, durability))?;
+            w.topic.send(WriterCommand::Begin(ack_tx, durability, marker.clone()))?;
             let _ = ack_rx.recv()?;
         }
         Ok(())
     }
 
     fn begin_async(&self, durability: Durability) -> Result<Vec<StartFuture>, AppError> {
-        let mut v = Vec::with_capacity(self.shards.len());
-        for w in &self.shards {
-            let (ack_tx, ack_rx) = bounded::<Result<(), AppError>>(1);
-            w.topic.send(WriterCommand::Begin(ack_tx, durability))?;
-            v.push(StartFuture(ack_rx));
-        }
-        Ok(v)
+        self.begin_marked_async(durability, self.own_marker())
     }
 
     fn delete_kv(&self, key: K) -> Result<bool, AppError> {
@@ -99,9 +166,7 @@ impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartition
 
     fn flush(&self) -> redb::Result<TaskResult, AppError> {
         let mut acks = Vec::with_capacity(self.shards.len());
-        if !self.sync_buf.borrow().is_empty() {
-            self.router.write_sorted_inserts_on_flush(std::mem::take(&mut *self.sync_buf.borrow_mut()))?;
-        }
+        self.route_sync_buf()?;
         for w in &self.shards {
             let (ack_tx, ack_rx) = bounded::<Result<TaskResult, AppError>>(1);
             let deferred = self.deferred.load(Ordering::SeqCst);
@@ -112,17 +177,12 @@ impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartition
             }
             acks.push(FlushFuture::eager(ack_rx));
         }
-        let mut tasks = Vec::with_capacity(acks.len());
-        for fut in acks {
-            tasks.push(fut.wait()?);
-        }
-        Ok(tasks.into_iter().max_by_key(|t| t.stats.sum()).unwrap())
+        // shards share the column name, they merge into the slowest one
+        Ok(FlushFuture::dedup_tasks_keep_slowest(acks)?.into_values().next().unwrap())
     }
 
     fn flush_async(&self) -> Result<Vec<FlushFuture>, AppError> {
-        if !self.sync_buf.borrow().is_empty() {
-            self.router.write_sorted_inserts_on_flush(std::mem::take(&mut *self.sync_buf.borrow_mut()))?;
-        }
+        self.route_sync_buf()?;
         let mut v: Vec<FlushFuture> = Vec::with_capacity(self.shards.len());
         for w in &self.shards {
             if self.root_pk {
@@ -163,10 +223,11 @@ impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartition
 
 #[cfg(all(test, not(feature = "integration")))]
 mod plain_sharded {
-    use crate::DbKey;
+    use crate::{AppError, DbKey, SpillConfig, WriterConfig};
     use crate::impl_copy_owned_value_identity;
+    use crate::storage::partitioning::KeyRange;
     use crate::storage::table_writer_api::{ReadTableLike, WriterLike};
-    use crate::storage::test_utils::addr;
+    use crate::storage::test_utils::{addr, Address};
     use crate::storage::{plain_test_utils, test_utils};
     use redb::Durability;
 
@@ -184,7 +245,8 @@ mod plain_sharded {
             let v = addr(&[k as u8, (k + 1) as u8, (k * 3) as u8]);
             writer.insert_on_flush(k, v).expect("insert");
         }
-        writer.flush().expect("flush");
+        let task = writer.flush().expect("flush");
+        assert_eq!(task.stats.queue.len(), n, "queue stats of every shard");
 
         let reader = plain_test_utils::mk_sharded_reader(name, n, weak_dbs, plain_def);
         for k in 1u32..=24 {
@@ -226,19 +288,136 @@ mod plain_sharded {
 
         writer.shutdown().expect("shutdown");
     }
+
+    // keys are spread over all shards, a range reads them merged in key order from either end
+    #[test]
+    fn sharded_plain_range_merges_shards() {
+        let n = 3usize;
+        let name = "plain_sharded_range";
+        let (_owned, weak_dbs) = test_utils::mk_shard_dbs(n, name);
+        let (writer, plain_def) = plain_test_utils::mk_sharded_writer(name, n, weak_dbs.clone());
+
+        writer.begin(Durability::None).expect("begin");
+        for k in 1u32..=30 {
+            writer.insert_on_flush(k, addr(&[k as u8])).expect("insert");
+        }
+        writer.flush().expect("flush");
+
+        let reader = plain_test_utils::mk_sharded_reader(name, n, weak_dbs, plain_def);
+        let keys = |range: KeyRange<u32, Address>| range.map(|e| e.expect("entry").0.value()).collect::<Vec<u32>>();
+        assert_eq!(keys(reader.range(5u32..25u32).expect("range")), (5u32..25).collect::<Vec<_>>());
+        assert_eq!(keys(reader.range::<u32>(..).expect("range")), (1u32..=30).collect::<Vec<_>>());
+        let desc: Vec<u32> = reader.range(5u32..=25u32).expect("range").rev().map(|e| e.expect("entry").0.value()).collect();
+        assert_eq!(desc, (5u32..=25).rev().collect::<Vec<_>>());
+
+        let mut both = reader.range(10u32..16u32).expect("range");
+        let mut met = Vec::new();
+        while let Some(front) = both.next() {
+            met.push(front.expect("entry").0.value());
+            if let Some(back) = both.next_back() {
+                met.push(back.expect("entry").0.value());
+            }
+        }
+        assert_eq!(met, vec![10, 15, 11, 14, 12, 13], "each key is read once when both ends meet");
+        assert_eq!(reader.range(31u32..40u32).expect("range").count(), 0);
+
+        writer.shutdown().expect("shutdown");
+    }
+
+    // iterating and ranging the writer's uncommitted state both see all shards in key order
+    #[test]
+    fn sharded_plain_iter_keys_and_writer_range_merge_shards() {
+        let n = 3usize;
+        let name = "plain_sharded_iter";
+        let (_owned, weak_dbs) = test_utils::mk_shard_dbs(n, name);
+        let (writer, plain_def) = plain_test_utils::mk_sharded_writer(name, n, weak_dbs.clone());
+
+        writer.begin(Durability::None).expect("begin");
+        for k in 1u32..=30 {
+            writer.insert_now(k, addr(&[k as u8])).expect("insert");
+        }
+        let in_tx: Vec<u32> = writer.range(5, 25).expect("range").iter().map(|(k, _)| k.as_value()).collect();
+        assert_eq!(in_tx, (5u32..25).collect::<Vec<_>>());
+        writer.flush().expect("flush");
+
+        let reader = plain_test_utils::mk_sharded_reader(name, n, weak_dbs, plain_def);
+        let keys: Vec<u32> = reader.iter_keys().expect("iter").map(|e| e.expect("entry").0.value()).collect();
+        assert_eq!(keys, (1u32..=30).collect::<Vec<_>>());
+        assert!(matches!(reader.value_range_keys(addr(&[1])..addr(&[9]), 10), Err(AppError::Custom(_))));
+
+        writer.shutdown().expect("shutdown");
+    }
+
+    // every batch outgrows the threshold, the flush merges the runs back from disk
+    #[test]
+    fn sharded_plain_spilled_batches_merge_at_flush() {
+        let n = 2usize;
+        let name = "plain_sharded_spill";
+        let (_owned, weak_dbs) = test_utils::mk_shard_dbs(n, name);
+        let spill_dir = test_utils::tmp_dir("spill");
+        let config = WriterConfig { spill: Some(SpillConfig::new(spill_dir.clone(), 1)), ..WriterConfig::default() };
+        let (writer, plain_def) = plain_test_utils::mk_sharded_writer_with(name, n, weak_dbs.clone(), config);
+
+        writer.begin(Durability::None).expect("begin");
+        let router = writer.acquire_router();
+        for batch in 0u32..4 {
+            // interleaved keys so that the runs overlap
+            let pairs = (0u32..25).rev().map(|i| (i * 4 + batch, addr(&[batch as u8, i as u8]))).collect();
+            router.merge_unsorted_inserts(pairs, if batch == 3 { Some(1) } else { None }).expect("merge");
+        }
+        writer.flush().expect("flush");
+
+        let reader = plain_test_utils::mk_sharded_reader(name, n, weak_dbs, plain_def);
+        for k in 0u32..100 {
+            let got = reader.get_value(&k).expect("get").expect("some");
+            assert_eq!(got.value().0, vec![(k % 4) as u8, (k / 4) as u8]);
+        }
+        assert_eq!(std::fs::read_dir(&spill_dir).expect("spill dir").count(), 0, "runs are deleted after flush");
+
+        writer.shutdown().expect("shutdown");
+    }
 }
 
 #[cfg(all(test, not(feature = "integration")))]
 mod index_sharded {
     use crate::storage::async_boundary::ValueOwned;
-    use crate::storage::table_writer_api::{ReadTableLike, WriterLike};
+    use crate::storage::table_index::IndexFactory;
+    use crate::storage::table_writer_api::{ReadTableLike, RedbitTableDefinition, WriteComponentRef, WriterLike};
     use crate::storage::test_utils::{addr, Address};
     use crate::storage::{index_test_utils, test_utils};
+    use crate::{AppError, Partitioning, WriterConfig};
     use crossbeam::channel;
-    use redb::Durability;
+    use redb::{Durability, MultimapTableDefinition, TableDefinition};
     use std::sync::Arc;
     use std::time::Duration;
 
+    // a deferred writer is prepared once its producers are ready, so its unique violations surface before the commit
+    #[test]
+    fn deferred_unique_index_fails_at_prepare() {
+        let n = 2usize;
+        let name = "index_sharded_deferred_unique";
+        let (_owned, weak_dbs) = test_utils::mk_shard_dbs(n, name);
+        let def = RedbitTableDefinition::new(
+            false,
+            Partitioning::by_value(n),
+            IndexFactory::new(name, 0, false, true, MultimapTableDefinition::<Address, u32>::new("pk_by_index"), TableDefinition::<u32, Address>::new("index_by_pk")),
+        );
+        let writer = def.writer_from_dbs(weak_dbs, WriterConfig::default()).expect("writer");
+
+        writer.begin(Durability::None).expect("begin");
+        let router = writer.acquire_router();
+        router.merge_unsorted_inserts(vec![(1u32, addr(b"a")), (2u32, addr(b"a"))], None).expect("merge");
+        let prepared = writer.prepare_with_ref().expect("prepare");
+        assert_eq!(prepared.len(), n);
+        router.merge_unsorted_inserts(vec![], Some(1)).expect("ready");
+        let results: Vec<Result<(), AppError>> = prepared.into_iter().map(|f| f.wait()).collect();
+        assert!(results.iter().any(|r| matches!(r, Err(AppError::UniqueViolation(_)))), "unexpected results {results:?}");
+
+        writer.abort_with_ref().expect("abort");
+        let _ = writer.flush();
+        writer.shutdown().expect("shutdown");
+    }
+
     #[test]
     fn inserting_in_wrong_order_should_fail() {
         let n = 3usize;
@@ -327,7 +506,7 @@ mod index_sharded {
 
         let reader = index_test_utils::mk_sharded_reader::<Address>(name, n, 0, weak_dbs, pk_by_index_def, index_by_pk_def);
         let keys_iter = reader.index_keys(&a3).expect("get_keys a3");
-        let mut keys: Vec<u32> = keys_iter.into_iter().map(|g| g.unwrap().value()).collect();
+        let mut keys: Vec<u32> = keys_iter.into_iter().flatten().map(|g| g.unwrap().value()).collect();
         keys.sort();
         assert_eq!(keys, vec![80, 100]);
 
@@ -348,6 +527,37 @@ mod index_sharded {
         writer.shutdown().expect("shutdown");
     }
 
+    #[test]
+    fn sharded_index_value_range_merges_shards_in_value_order() {
+        let n = 3usize;
+        let name = "index_sharded_value_range";
+        let (_owned, weak_dbs) = test_utils::mk_shard_dbs(n, name);
+        let (writer, pk_by_index_def, index_by_pk_def) = index_test_utils::mk_sharded_writer::<Address>(name, n, 0, weak_dbs.clone());
+
+        writer.begin(Durability::None).expect("begin");
+        for (pk, value) in [(1u32, 0xab05u16), (2, 0xab01), (3, 0xac00), (4, 0xab03), (5, 0xaa09), (6, 0xab01), (7, 0xabff)] {
+            writer.insert_on_flush(pk, addr(&value.to_be_bytes())).expect("insert");
+        }
+        writer.flush().expect("flush");
+
+        let reader = index_test_utils::mk_sharded_reader::<Address>(name, n, 0, weak_dbs, pk_by_index_def, index_by_pk_def);
+        let range = addr(&[0xab])..addr(&[0xac]);
+        let pks = |limit| reader.value_range_keys(range.clone(), limit).expect("range").into_iter().map(|g| g.value()).collect::<Vec<u32>>();
+        assert_eq!(pks(100), vec![2, 6, 4, 1, 7]);
+        assert_eq!(pks(3), vec![2, 6, 4]);
+        assert!(pks(0).is_empty());
+
+        let values = reader.index_range(range.clone()).expect("index range").map(|e| {
+            let (value, pks) = e.expect("entry");
+            (value.value().0[1], pks.map(|pk| pk.expect("pk").value()).collect::<Vec<u32>>())
+        });
+        assert_eq!(values.collect::<Vec<_>>(), vec![(0x01, vec![2, 6]), (0x03, vec![4]), (0x05, vec![1]), (0xff, vec![7])]);
+        let last = reader.index_range(range).expect("index range").next_back().expect("last").expect("entry");
+        assert_eq!(last.0.value().0[1], 0xff);
+
+        writer.shutdown().expect("shutdown");
+    }
+
 }
 
 #[cfg(all(test, not(feature = "integration")))]
@@ -420,4 +630,25 @@ mod dict_sharded {
 
         writer.shutdown().expect("shutdown");
     }
-}
\ No newline at end of file
+    #[test]
+    fn sharded_dict_value_range_merges_shards_in_value_order() {
+        let n = 4usize;
+        let name = "dict_sharded_value_range";
+        let (_owned, weak_dbs) = test_utils::mk_shard_dbs(n, name);
+        let (writer, dict_pk_to_ids, value_by_dict_pk, value_to_dict_pk, dict_pk_by_id) = dict_test_utils::mk_sharded_writer(name, n, weak_dbs.clone());
+
+        writer.begin(Durability::None).expect("begin");
+        for (id, value) in [(1u32, b"bc1qz".as_slice()), (2, b"bc1pa"), (3, b"bc1qa"), (4, b"bc1qz"), (5, b"1abc"), (6, b"bc1qm")] {
+            writer.insert_on_flush(id, addr(value)).expect("insert");
+        }
+        writer.flush().expect("flush");
+
+        let reader = dict_test_utils::mk_sharder_reader(name, n, weak_dbs, dict_pk_to_ids, value_by_dict_pk, value_to_dict_pk, dict_pk_by_id);
+        let ids = reader.value_range_keys(addr(b"bc1q").., 10).expect("range").into_iter().map(|g| g.value()).collect::<Vec<u32>>();
+        assert_eq!(ids, vec![3, 6, 1, 4]);
+        let ids = reader.value_range_keys(addr(b"bc1q")..addr(b"bc1r"), 2).expect("range").into_iter().map(|g| g.value()).collect::<Vec<u32>>();
+        assert_eq!(ids, vec![3, 6]);
+
+        writer.shutdown().expect("shutdown");
+    }
+}
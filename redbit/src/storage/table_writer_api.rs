@@ -1,9 +1,20 @@
+use crate::compress::ValueBytes;
+use crate::storage::bloom::BloomFilter;
+use crate::storage::backpressure::QueueStats;
+use crate::storage::cache::LruStats;
+use crate::storage::init::ShardDb;
+use crate::storage::partitioning::{KeyRange, ValueRange};
 use crate::storage::async_boundary::{ValueBuf, ValueOwned};
+use crate::storage::commit::CommitMarker;
 use crate::storage::context::{ToReadField, ToWriteField};
+use crate::storage::fsck::FsckIssue;
 use crate::storage::router::{Router, ShardedRouter};
-use crate::{AppError, Deserialize, KeyPartitioner, Partitioning, DbVal, Serialize, ShardedReadOnlyDictTable, ShardedReadOnlyIndexTable, ShardedReadOnlyPlainTable, ShardedTableWriter, Storage, ToSchema, TxFSM, ValuePartitioner, DbKey};
+use crate::storage::schema::{ColumnKind, TypeSchema};
+use crate::storage::snapshot::CommitFence;
+use crate::storage::tx_fsm::WriterConfig;
+use crate::{AppError, Deserialize, KeyPartitioner, Partitioning, CacheKey, DbVal, Serialize, ShardedReadOnlyDictTable, ShardedReadOnlyIndexTable, ShardedReadOnlyPlainTable, ShardedTableWriter, Storage, ToSchema, TxFSM, ValuePartitioner, DbKey};
 use crossbeam::channel::{bounded, Receiver, Sender};
-use redb::{AccessGuard, Database, Durability, Key, MultimapValue, TableStats, Value, WriteTransaction};
+use redb::{AccessGuard, Database, Durability, Key, MultimapValue, ReadTransaction, TableStats, Value, WriteTransaction};
 use std::borrow::Borrow;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
@@ -20,10 +31,22 @@ pub struct TaskStats {
     pub sort_took: u128,
     pub write_took: u128,
     pub flush_took: u128,
+    /// Counters of the column's LRU cache, `None` for columns without `lru_cache`.
+    pub lru: Option<LruStats>,
+    /// Peaks of the writers' command queues, one per shard in shard order, empty for tasks not run by a writer.
+    pub queue: Vec<QueueStats>,
 }
 impl TaskStats {
     pub fn new(collect_took: u128, sort_took: u128, write_took: u128, flush_took: u128) -> Self {
-        Self { collect_took, sort_took, write_took, flush_took }
+        Self { collect_took, sort_took, write_took, flush_took, lru: None, queue: Vec::new() }
+    }
+    pub fn with_lru(mut self, lru: Option<LruStats>) -> Self {
+        self.lru = lru;
+        self
+    }
+    pub fn with_queue(mut self, queue: QueueStats) -> Self {
+        self.queue.push(queue);
+        self
     }
     pub fn sum(&self) -> u128 {
         self.collect_took + self.sort_took + self.write_took + self.flush_took
@@ -38,7 +61,14 @@ pub struct TaskResult {
 
 impl fmt::Display for TaskResult {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} : c/s/w/c : {}/{}/{}/{} ms", self.name, self.stats.collect_took, self.stats.sort_took, self.stats.write_took, self.stats.flush_took)
+        write!(f, "{} : c/s/w/c : {}/{}/{}/{} ms", self.name, self.stats.collect_took, self.stats.sort_took, self.stats.write_took, self.stats.flush_took)?;
+        if let Some(lru) = self.stats.lru {
+            write!(f, " : lru h/m/i/e : {}/{}/{}/{}", lru.hits, lru.misses, lru.inserts, lru.evictions)?;
+        }
+        for (shard, queue) in self.stats.queue.iter().enumerate() {
+            write!(f, " : queue[{}] d/B/s/b : {}/{}/{}/{} ms", shard, queue.peak_depth, queue.peak_bytes, queue.blocked_sends, queue.blocked_ms)?;
+        }
+        Ok(())
     }
 }
 
@@ -117,12 +147,25 @@ impl FlushFuture {
     pub fn dedup_tasks_keep_slowest(futs: Vec<FlushFuture>) -> Result<HashMap<String, TaskResult>, AppError> {
         let mut by_name: HashMap<String, TaskResult> = HashMap::with_capacity(futs.len());
         for f in futs {
-            let res = f.wait()?;
+            let mut res = f.wait()?;
             match by_name.entry(res.name.clone()) {
                 Entry::Vacant(e) => { e.insert(res); }
                 Entry::Occupied(mut e) => {
+                    // shards of one column share the name, their lru counters add up
+                    let lru = match (e.get().stats.lru, res.stats.lru) {
+                        (Some(mut acc), Some(other)) => { acc.merge(&other); Some(acc) }
+                        (acc, other) => acc.or(other),
+                    };
+                    // futures of a column come in shard order, its queues are kept per shard
+                    let mut queue = std::mem::take(&mut e.get_mut().stats.queue);
+                    queue.append(&mut res.stats.queue);
                     if res.stats.sum() > e.get().stats.sum() {
+                        res.stats.lru = lru;
+                        res.stats.queue = queue;
                         e.insert(res); // keep the slowest per name
+                    } else {
+                        e.get_mut().stats.lru = lru;
+                        e.get_mut().stats.queue = queue;
                     }
                 }
             }
@@ -142,6 +185,10 @@ pub struct TableInfo {
     pub stored_leaf_bytes: u64,
     pub metadata_bytes: u64,
     pub fragmented_bytes: u64,
+    /// Bytes of the column values as stored and as decoded, they differ for `#[column(compress = "zstd")]` types.
+    /// Both are extrapolated from the first `VALUE_BYTES_SAMPLE` values found in the shards.
+    pub stored_value_bytes: u64,
+    pub raw_value_bytes: u64,
 }
 
 impl TableInfo {
@@ -155,17 +202,29 @@ impl TableInfo {
             stored_leaf_bytes: stats.stored_bytes(),
             metadata_bytes: stats.metadata_bytes(),
             fragmented_bytes: stats.fragmented_bytes(),
+            stored_value_bytes: 0,
+            raw_value_bytes: 0,
         }
     }
+
+    pub fn with_value_bytes(mut self, sample: ValueBytes) -> Self {
+        (self.stored_value_bytes, self.raw_value_bytes) = sample.extrapolate(self.table_entries);
+        self
+    }
 }
 
-pub trait ReadTableLike<K: Key + 'static, V: Key + 'static> {
-    fn index_keys<'v>(&self, val: impl Borrow<V::SelfType<'v>>) -> Result<MultimapValue<'static, K>, AppError>;
+pub trait ReadTableLike<K: Key + 'static, V: CacheKey> {
+    fn index_keys<'v>(&self, val: impl Borrow<V::SelfType<'v>>) -> Result<Option<MultimapValue<'static, K>>, AppError>;
     fn dict_keys<'v>(&self, val: impl Borrow<V::SelfType<'v>>) -> redb::Result<Option<MultimapValue<'static, K>>, AppError>;
     fn get_value<'k>(&self, key: impl Borrow<K::SelfType<'k>>) -> Result<Option<AccessGuard<'_, V>>, AppError>;
-    fn iter_keys(&self) -> Result<redb::Range<'_, K, V>, AppError>;
-    fn range<'a, KR: Borrow<K::SelfType<'a>>>(&self, range: impl RangeBounds<KR>) -> Result<redb::Range<'static, K, V>, AppError>;
-    fn index_range<'a, KR: Borrow<V::SelfType<'a>>>(&self, range: impl RangeBounds<KR>) -> Result<redb::MultimapRange<'static, V, K>, AppError>;
+    /// Entries of all shards in key order.
+    fn iter_keys(&self) -> Result<KeyRange<K, V>, AppError>;
+    /// Entries of `range` of all shards in key order, read from either end.
+    fn range<'a, KR: Borrow<K::SelfType<'a>>>(&self, range: impl RangeBounds<KR>) -> Result<KeyRange<K, V>, AppError>;
+    /// Values of `range` of all shards in value order with their ids, read lazily from either end.
+    fn index_range<'a, KR: Borrow<V::SelfType<'a>>>(&self, range: impl RangeBounds<KR>) -> Result<ValueRange<V::Key, K>, AppError>;
+    /// Ids of the values in `range` of all shards in value order, at most `limit` of them.
+    fn value_range_keys<'a, KR: Borrow<V::SelfType<'a>>>(&self, range: impl RangeBounds<KR>, limit: usize) -> Result<Vec<AccessGuard<'static, K>>, AppError>;
     fn last_key(&self) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>, AppError>;
     fn first_key(&self) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>, AppError>;
     fn stats(&self) -> Result<Vec<TableInfo>, AppError>;
@@ -222,27 +281,61 @@ pub trait TableFactory<K: DbKey, V: Key + 'static> {
     type Table<'txn, 'c>: WriteTableLike<K, V>;
     type ReadOnlyTable;
     fn name(&self) -> String;
+    fn kind(&self) -> ColumnKind;
     fn new_cache(&self) -> Self::CacheCtx;
     fn open_for_write<'txn, 'c>(&self, tx: &'txn WriteTransaction, cache: &'c mut Self::CacheCtx) -> Result<Self::Table<'txn, 'c>, AppError>;
-    fn open_for_read(&self, db_weak: &Weak<Database>) -> redb::Result<Self::ReadOnlyTable, AppError>;
+    fn open_for_read(&self, db_weak: &Weak<ShardDb>) -> redb::Result<Self::ReadOnlyTable, AppError>;
+    /// Copies every underlying table visible in `src` into `dst`, returns the number of copied entries.
+    fn copy_tables(&self, src: &ReadTransaction, dst: &WriteTransaction) -> Result<u64, AppError>;
+    /// Debug rendering of the last key, only meaningful for plain tables holding root pks.
+    fn last_key(&self, _tx: &ReadTransaction) -> Result<Option<String>, AppError> {
+        Ok(None)
+    }
+    /// Whether the column keeps a bloom filter per shard, see `BloomFilters`.
+    fn bloom(&self) -> bool {
+        false
+    }
+    /// Builds the bloom filter of one shard from its tables.
+    fn build_bloom(&self, _db: &ShardDb) -> Result<BloomFilter, AppError> {
+        Err(AppError::Custom(format!("column `{}` keeps no bloom filter", self.name())))
+    }
+    /// Writers of unique tables are prepared before the commit.
+    fn unique(&self) -> bool {
+        false
+    }
+    /// The same factory serving shards with the given bloom filters, in shard order.
+    fn with_bloom_filters(&self, _filters: Vec<Arc<BloomFilter>>) -> Self where Self: Clone {
+        self.clone()
+    }
+    /// Counters of the transaction's cache, reported along with the task timings.
+    fn cache_stats(_cache: &Self::CacheCtx) -> Option<LruStats> {
+        None
+    }
 }
 
-pub trait ReadTableFactory<K: DbKey, V: DbVal, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>>: TableFactory<K, V> {
+pub trait ReadTableFactory<K: DbKey, V: CacheKey, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>>: TableFactory<K, V> {
     fn build_sharded_reader(
         &self,
-        dbs: Vec<Weak<Database>>,
+        dbs: Vec<Weak<ShardDb>>,
         part: &Partitioning<KP, VP>,
     ) -> Result<ShardedTableReader<K, V, KP, VP>, AppError>;
+
+    /// Redistributes all entries of one source shard into `dst` shards routed by `part`, returns the number of moved entries.
+    fn reshard_tables(&self, src: &ReadTransaction, dst: &[Database], part: &Partitioning<KP, VP>) -> Result<u64, AppError>;
+
+    /// Verifies that the tables of one shard agree with each other, returns the number of checked entries.
+    fn fsck_tables(&self, db: &ShardDb, repair: bool) -> Result<(u64, Vec<FsckIssue>), AppError>;
 }
 
 pub struct FlushState {
     pub sender: Option<Sender<Result<TaskResult, AppError>>>,
+    pub prepare: Option<Sender<Result<(), AppError>>>,
     pub sum: usize,
     pub shards: Option<usize>
 }
 
 pub enum WriterCommand<K: DbKey, V: Key> {
-    Begin(Sender<Result<(), AppError>>, Durability),
+    Begin(Sender<Result<(), AppError>>, Durability, Option<CommitMarker>),
     WriteSortedInsertsOnFlush(Vec<(K, V)>),
     WriteInsertNow(K, V),
     AppendSortedInserts(Vec<(K, V)>),
@@ -256,9 +349,36 @@ pub enum WriterCommand<K: DbKey, V: Key> {
     Range(K, K, Sender<Result<Vec<(ValueBuf<K>, ValueBuf<V>)>, AppError>>),
     Flush(Sender<Result<TaskResult, AppError>>),
     FlushWhenReady(Sender<Result<TaskResult, AppError>>),
+    /// Writes the buffered inserts ahead of the flush, so that a failing write (unique violation) is reported before any shard commits.
+    Prepare(Sender<Result<(), AppError>>),
+    /// `Prepare` of a writer fed by other writers, it waits until all of them are `ReadyForFlush`.
+    PrepareWhenReady(Sender<Result<(), AppError>>),
+    /// The following flush drops the transaction instead of committing it.
+    Abort,
     ReadyForFlush(usize),
     Shutdown(Sender<Result<(), AppError>>),
 }
+
+impl<K: DbKey, V: DbVal> WriterCommand<K, V> {
+    /// Encoded size of the carried data, `None` for commands that are not accounted in the writer's queue budget.
+    pub fn payload_bytes(&self) -> Option<usize> {
+        fn key_len<K: DbKey>(k: &K) -> usize {
+            K::fixed_width().unwrap_or_else(|| K::as_bytes(k.borrow()).as_ref().len())
+        }
+        fn value_len<V: DbVal>(v: &V) -> usize {
+            V::fixed_width().unwrap_or_else(|| V::as_bytes(v.borrow()).as_ref().len())
+        }
+        match self {
+            WriterCommand::WriteSortedInsertsOnFlush(kvs)
+            | WriterCommand::AppendSortedInserts(kvs)
+            | WriterCommand::MergeUnsortedInserts(kvs) => Some(kvs.iter().map(|(k, v)| key_len(k) + value_len(v)).sum()),
+            WriterCommand::WriteInsertNow(k, v) => Some(key_len(k) + value_len(v)),
+            WriterCommand::QueryAndWrite { values, .. } => Some(values.iter().map(|(_, v)| value_len(v)).sum()),
+            _ => None,
+        }
+    }
+}
+
 pub struct WriteResult {
     pub collect_took: u128,
     pub sort_took: u128,
@@ -282,8 +402,17 @@ pub enum Control {
 }
 
 pub trait WriteComponentRef {
-    fn begin_async_ref(&self, d: Durability) -> redb::Result<Vec<StartFuture>, AppError>;
+    fn begin_async_ref(&self, d: Durability, marker: Option<CommitMarker>) -> redb::Result<Vec<StartFuture>, AppError>;
+    /// Dbs (shard file names) that commit when this component does.
+    fn participants(&self) -> Vec<String>;
     fn commit_with_ref(&self) -> Result<Vec<FlushFuture>, AppError>;
+    /// Writes whose failure must prevent the whole commit, see `WriterCommand::Prepare`.
+    fn prepare_with_ref(&self) -> Result<Vec<StartFuture>, AppError>;
+    fn abort_with_ref(&self) -> Result<(), AppError>;
+    /// Fence that snapshots use to observe a state with no commit in flight.
+    fn commit_fence(&self) -> Option<Arc<CommitFence>> {
+        None
+    }
 }
 pub trait WriterLike<K: DbKey, V: Value> {
     fn acquire_router(&self) -> Arc<dyn Router<K, V>>;
@@ -306,7 +435,7 @@ pub trait WriterLike<K: DbKey, V: Value> {
 }
 
 #[derive(Debug)]
-pub struct RedbitTableDefinition<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>, F>
+pub struct RedbitTableDefinition<K: DbKey + Send, V: CacheKey + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>, F>
     where F: ReadTableFactory<K, V, KP, VP> + Send + Clone,
 {
     root_pk: bool,
@@ -316,7 +445,7 @@ pub struct RedbitTableDefinition<K: DbKey + Send, V: DbVal + Send, KP: KeyPartit
     v_phantom: PhantomData<V>,
 }
 
-impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>, F> ToReadField for RedbitTableDefinition<K, V, KP, VP, F>
+impl<K: DbKey + Send, V: CacheKey + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>, F> ToReadField for RedbitTableDefinition<K, V, KP, VP, F>
     where F: ReadTableFactory<K, V, KP, VP> + Send + Clone + 'static,
 {
     type ReadField = ShardedTableReader<K, V, KP, VP>;
@@ -325,7 +454,7 @@ impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartition
         self.reader(storage)
     }
 }
-impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>, F> ToWriteField for RedbitTableDefinition<K, V, KP, VP, F>
+impl<K: DbKey + Send, V: CacheKey + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>, F> ToWriteField for RedbitTableDefinition<K, V, KP, VP, F>
     where F: ReadTableFactory<K, V, KP, VP> + Send + Clone + 'static,
 {
     type WriteField = ShardedTableWriter<K, V, KP, VP, F>;
@@ -335,7 +464,7 @@ impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartition
     }
 }
 
-impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>, F> RedbitTableDefinition<K, V, KP, VP, F>
+impl<K: DbKey + Send, V: CacheKey + Send, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>, F> RedbitTableDefinition<K, V, KP, VP, F>
     where F: ReadTableFactory<K, V, KP, VP> + Send + Clone + 'static,
 {
     pub fn new(root_pk: bool, partitioning: Partitioning<KP, VP>, factory: F) -> Self {
@@ -348,23 +477,36 @@ impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartition
         }
     }
 
-    pub fn writer_from_dbs(&self, dbs: Vec<Weak<Database>>) -> Result<ShardedTableWriter<K,V,KP,VP,F>, AppError> {
+    pub fn writer_from_dbs(&self, dbs: Vec<Weak<ShardDb>>, config: WriterConfig) -> Result<ShardedTableWriter<K,V,KP,VP,F>, AppError> {
+        self.writer_from_shards(dbs.into_iter().map(|db| (db, self.factory.clone())).collect(), config)
+    }
+
+    fn writer_from_shards(&self, dbs: Vec<(Weak<ShardDb>, F)>, config: WriterConfig) -> Result<ShardedTableWriter<K,V,KP,VP,F>, AppError> {
         let mut shards = Vec::with_capacity(dbs.len());
-        for db_weak in dbs.into_iter() {
-            shards.push(TxFSM::<K,V,F>::new(db_weak, self.factory.clone())?);
+        for (db_weak, factory) in dbs.into_iter() {
+            shards.push(TxFSM::<K,V,F>::new(db_weak, factory, config.clone())?);
         }
         let senders: Vec<_> = shards.iter().map(|w| w.sender()).collect();
-        let router = Arc::new(ShardedRouter::new(self.partitioning.clone(), senders));
+        let budgets: Vec<_> = shards.iter().map(|w| Arc::clone(&w.budget)).collect();
+        let router = Arc::new(ShardedRouter::new(self.partitioning.clone(), senders, budgets));
         let deferred = AtomicBool::new(false);
-        ShardedTableWriter::new(self.root_pk, shards, router, deferred)
+        Ok(ShardedTableWriter::new(self.root_pk, shards, router, deferred)?.with_prepare(self.factory.unique()))
     }
 
     pub fn writer(&self, storage: &Arc<Storage>) -> Result<ShardedTableWriter<K,V,KP,VP,F>, AppError> {
+        storage.check_writable(self.factory.name().as_str())?;
         let dbs = storage.fetch_dbs(self.factory.name().as_str())?;
-        self.writer_from_dbs(dbs)
+        let filters = if self.factory.bloom() { storage.bloom_filters.shard_filters(&self.factory.name())? } else { None };
+        let writer =
+            if let Some(filters) = filters {
+                self.writer_from_shards(dbs.into_iter().zip(filters).map(|(db, f)| (db, self.factory.with_bloom_filters(vec![f]))).collect(), storage.writer_config.clone())?
+            } else {
+                self.writer_from_shards(dbs.into_iter().map(|db| (db, self.factory.clone())).collect(), storage.writer_config.clone())?
+            };
+        Ok(writer.with_commit_fence(Arc::clone(&storage.commit_fence)))
     }
 
-    pub fn reader_from_dbs(&self, dbs: Vec<Weak<Database>>) -> Result<ShardedTableReader<K, V, KP, VP>, AppError> {
+    pub fn reader_from_dbs(&self, dbs: Vec<Weak<ShardDb>>) -> Result<ShardedTableReader<K, V, KP, VP>, AppError> {
         if dbs.len() < 1 {
             return Err(AppError::Custom(format!(
                 "ShardedReadOnlyTable expected at least one database, got {}",
@@ -376,18 +518,83 @@ impl<K: DbKey + Send, V: DbVal + Send, KP: KeyPartitioner<K>, VP: ValuePartition
 
     pub fn reader(&self, storage: &Arc<Storage>) -> Result<ShardedTableReader<K, V, KP, VP>, AppError> {
         let dbs = storage.fetch_dbs(self.factory.name().as_str())?;
-        self.reader_from_dbs(dbs)
+        let filters = if self.factory.bloom() { storage.bloom_filters.shard_filters(&self.factory.name())? } else { None };
+        match filters {
+            Some(filters) => self.factory.with_bloom_filters(filters).build_sharded_reader(dbs, &self.partitioning),
+            None => self.reader_from_dbs(dbs),
+        }
     }
 }
 
-pub enum ShardedTableReader<K: DbKey, V: DbVal, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> {
+/// Type-erased view of a column definition, used by storage-wide operations like snapshots
+/// that need to reach the typed tables of every column without knowing its key/value types.
+pub trait ColumnTables: Send + Sync {
+    fn name(&self) -> String;
+    fn is_root(&self) -> bool;
+    fn kind(&self) -> ColumnKind;
+    fn key_type(&self) -> TypeSchema;
+    fn value_type(&self) -> TypeSchema;
+    fn partition(&self) -> Option<String>;
+    fn copy_tables(&self, src: &ReadTransaction, dst: &WriteTransaction) -> Result<u64, AppError>;
+    fn last_key(&self, tx: &ReadTransaction) -> Result<Option<String>, AppError>;
+    fn reshard_tables(&self, src: &ReadTransaction, dst: &[Database]) -> Result<u64, AppError>;
+    fn fsck_tables(&self, db: &ShardDb, repair: bool) -> Result<(u64, Vec<FsckIssue>), AppError>;
+    fn bloom(&self) -> bool;
+    fn build_bloom(&self, db: &ShardDb) -> Result<BloomFilter, AppError>;
+}
+
+impl<K: DbKey + Send + Sync, V: CacheKey + Send + Sync, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>, F> ColumnTables for RedbitTableDefinition<K, V, KP, VP, F>
+    where F: ReadTableFactory<K, V, KP, VP> + Send + Sync + Clone + 'static,
+{
+    fn name(&self) -> String {
+        self.factory.name()
+    }
+    fn is_root(&self) -> bool {
+        self.root_pk
+    }
+    fn kind(&self) -> ColumnKind {
+        self.factory.kind()
+    }
+    fn key_type(&self) -> TypeSchema {
+        TypeSchema::of::<K>()
+    }
+    fn value_type(&self) -> TypeSchema {
+        TypeSchema::of::<V>()
+    }
+    fn partition(&self) -> Option<String> {
+        match &self.partitioning {
+            Partitioning::ByKey(kp) => kp.layout(),
+            Partitioning::ByValue(_) => None,
+        }
+    }
+    fn copy_tables(&self, src: &ReadTransaction, dst: &WriteTransaction) -> Result<u64, AppError> {
+        self.factory.copy_tables(src, dst)
+    }
+    fn last_key(&self, tx: &ReadTransaction) -> Result<Option<String>, AppError> {
+        self.factory.last_key(tx)
+    }
+    fn reshard_tables(&self, src: &ReadTransaction, dst: &[Database]) -> Result<u64, AppError> {
+        self.factory.reshard_tables(src, dst, &self.partitioning)
+    }
+    fn fsck_tables(&self, db: &ShardDb, repair: bool) -> Result<(u64, Vec<FsckIssue>), AppError> {
+        self.factory.fsck_tables(db, repair)
+    }
+    fn bloom(&self) -> bool {
+        self.factory.bloom()
+    }
+    fn build_bloom(&self, db: &ShardDb) -> Result<BloomFilter, AppError> {
+        self.factory.build_bloom(db)
+    }
+}
+
+pub enum ShardedTableReader<K: DbKey, V: CacheKey, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> {
     Plain(ShardedReadOnlyPlainTable<K, V, KP>),
     Index(ShardedReadOnlyIndexTable<K, V, VP>),
     Dict(ShardedReadOnlyDictTable<K, V, VP>),
 }
 
-impl<K: DbKey, V: DbVal, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> ReadTableLike<K, V> for ShardedTableReader<K, V, KP, VP> {
-    fn index_keys<'v>(&self, val: impl Borrow<V::SelfType<'v>>) -> Result<MultimapValue<'static, K>, AppError> {
+impl<K: DbKey, V: CacheKey, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> ReadTableLike<K, V> for ShardedTableReader<K, V, KP, VP> {
+    fn index_keys<'v>(&self, val: impl Borrow<V::SelfType<'v>>) -> Result<Option<MultimapValue<'static, K>>, AppError> {
         match self {
             ShardedTableReader::Index(t) => t.index_keys(val),
             _ => Err(AppError::Custom("index_keys unsupported for this table kind".into())),
@@ -409,7 +616,7 @@ impl<K: DbKey, V: DbVal, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> ReadTab
         }
     }
 
-    fn iter_keys(&self) -> Result<redb::Range<'_, K, V>, AppError> {
+    fn iter_keys(&self) -> Result<KeyRange<K, V>, AppError> {
         match self {
             ShardedTableReader::Plain(t) => t.iter_keys(),
             ShardedTableReader::Index(t) => t.iter_keys(),
@@ -417,7 +624,7 @@ impl<K: DbKey, V: DbVal, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> ReadTab
         }
     }
 
-    fn range<'a, KR: Borrow<K::SelfType<'a>>>(&self, r: impl RangeBounds<KR>) -> Result<redb::Range<'static, K, V>, AppError> {
+    fn range<'a, KR: Borrow<K::SelfType<'a>>>(&self, r: impl RangeBounds<KR>) -> Result<KeyRange<K, V>, AppError> {
         match self {
             ShardedTableReader::Plain(t) => t.range(r),
             ShardedTableReader::Index(t) => t.range(r),
@@ -425,13 +632,21 @@ impl<K: DbKey, V: DbVal, KP: KeyPartitioner<K>, VP: ValuePartitioner<V>> ReadTab
         }
     }
 
-    fn index_range<'a, KR: Borrow<V::SelfType<'a>>>(&self, r: impl RangeBounds<KR>) -> Result<redb::MultimapRange<'static, V, K>, AppError> {
+    fn index_range<'a, KR: Borrow<V::SelfType<'a>>>(&self, r: impl RangeBounds<KR>) -> Result<ValueRange<V::Key, K>, AppError> {
         match self {
             ShardedTableReader::Index(t) => t.index_range(r),
             _ => Err(AppError::Custom("index_range unsupported for this table kind".into())),
         }
     }
 
+    fn value_range_keys<'a, KR: Borrow<V::SelfType<'a>>>(&self, r: impl RangeBounds<KR>, limit: usize) -> Result<Vec<AccessGuard<'static, K>>, AppError> {
+        match self {
+            ShardedTableReader::Index(t) => t.value_range_keys(r, limit),
+            ShardedTableReader::Dict(t) => t.value_range_keys(r, limit),
+            _ => Err(AppError::Custom("value_range_keys unsupported for this table kind".into())),
+        }
+    }
+
     fn first_key(&self) -> Result<Option<(AccessGuard<'_, K>, AccessGuard<'_, V>)>, AppError> {
         match self {
             ShardedTableReader::Plain(t) => t.first_key(),
@@ -1,20 +1,41 @@
+use crate::storage::backpressure::{QueueBudget, DEFAULT_WRITER_QUEUE_BUDGET};
+use crate::storage::init::ShardDb;
+use crate::storage::commit;
 use crate::storage::sort_buffer::MergeBuffer;
+use crate::storage::spill::{ExternalMerge, SpillConfig, SPILL_CHUNK_PAIRS};
 use crate::storage::table_writer_api::*;
 use crate::{error, AppError, DbKey, DbVal};
 use crossbeam::channel::{unbounded, Receiver, Sender};
-use redb::{Database, Key};
+use redb::{Durability, Key};
 use std::cell::RefCell;
 use std::marker::PhantomData;
-use std::sync::Weak;
+use std::sync::{Arc, Weak};
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::Instant;
 
+/// How column writers buffer their input, shared by all writers of a storage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriterConfig {
+    /// Bytes a writer may lag behind its producers before they block.
+    pub queue_budget: usize,
+    /// Spilling of oversized batches to disk, `None` keeps whole batches in memory.
+    pub spill: Option<SpillConfig>,
+}
+
+impl Default for WriterConfig {
+    fn default() -> Self {
+        Self { queue_budget: DEFAULT_WRITER_QUEUE_BUDGET, spill: None }
+    }
+}
+
 struct TxState<'txn, 'c, K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V>> {
     table: F::Table<'txn, 'c>,
     async_merge_buf: RefCell<MergeBuffer<K, V>>,
     deferred: Option<FlushState>,
     write_error: Option<AppError>,
+    /// Inserts written by `Prepare`, the flush only commits them
+    prepared: Option<WriteResult>,
     collecting_start: Instant,
 }
 
@@ -24,26 +45,69 @@ impl<'txn, 'c, K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V>> TxState<
             return Ok(Control::Error(sender, error));
         }
 
+        let written = match self.prepared.take() {
+            Some(prepared) if self.async_merge_buf.borrow().is_empty() => Ok(prepared),
+            _ => self.write_buffered(),
+        };
+        Ok(Control::Commit(sender, written))
+    }
+
+    fn prepare(&mut self, ack: Sender<Result<(), AppError>>) -> Result<Control, AppError> {
+        match self.write_buffered() {
+            Ok(written) => {
+                self.prepared = Some(written);
+                ack.send(Ok(()))?;
+            }
+            Err(err) => ack.send(Err(err))?,
+        }
+        Ok(Control::Continue)
+    }
+
+    /// Once every producer is ready no more inserts arrive, so the pending `PrepareWhenReady` can write them all.
+    fn prepare_when_ready(&mut self) -> Result<Control, AppError> {
+        let ready = matches!(&self.deferred, Some(FlushState { sum, shards: Some(total), .. }) if sum == total);
+        match self.deferred.as_mut().filter(|_| ready).and_then(|state| state.prepare.take()) {
+            Some(ack) => self.prepare(ack),
+            None => Ok(Control::Continue),
+        }
+    }
+
+    fn write_buffered(&mut self) -> Result<WriteResult, AppError> {
         let collect_took = self.collecting_start.elapsed().as_millis();
 
         let mut buf = self.async_merge_buf.borrow_mut();
         let sort_start = Instant::now();
-        let kvs = buf.take_sorted();
+        let merge = match buf.take_spilled() {
+            Ok(merge) => merge,
+            Err(err) => {
+                buf.clear();
+                return Err(err);
+            }
+        };
+        let kvs = if merge.is_none() { buf.take_sorted() } else { Vec::new() };
         let sort_took = sort_start.elapsed().as_millis();
 
         let write_start = Instant::now();
-        if !kvs.is_empty() {
-            if let Err(err) = self.table.insert_many_sorted_by_key(kvs) {
-                buf.clear();
-                return Ok(Control::Commit(sender, Err(err)));
-            }
-        }
-        let write_took = write_start.elapsed().as_millis();
+        let written = match merge {
+            Some(mut merge) => Self::write_merged(&mut self.table, &mut merge),
+            None if !kvs.is_empty() => self.table.insert_many_sorted_by_key(kvs),
+            None => Ok(()),
+        };
         buf.clear();
-        drop(buf);
+        written?;
+        let write_took = write_start.elapsed().as_millis();
 
-        Ok(Control::Commit(sender, Ok(WriteResult::new(collect_took, sort_took, write_took))))
+        Ok(WriteResult::new(collect_took, sort_took, write_took))
     }
+    /// Writes spilled runs chunk by chunk, each chunk continues in key order where the previous one ended.
+    fn write_merged(table: &mut F::Table<'txn, 'c>, merge: &mut ExternalMerge<K, V>) -> Result<(), AppError> {
+        while let Some(chunk) = merge.next_chunk(SPILL_CHUNK_PAIRS)? {
+            let pairs: Vec<(K::SelfType<'_>, V::SelfType<'_>)> = chunk.iter().map(|(k, v)| (K::from_bytes(k), V::from_bytes(v))).collect();
+            table.insert_many_sorted_by_key(pairs)?;
+        }
+        Ok(())
+    }
+
     fn step(&mut self, cmd: WriterCommand<K, V>) -> Result<Control, AppError> {
         match cmd {
             WriterCommand::WriteInsertNow(k, v) => {
@@ -51,18 +115,24 @@ impl<'txn, 'c, K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V>> TxState<
                 Ok(Control::Continue)
             }
             WriterCommand::MergeUnsortedInserts(kvs) => {
-                self.async_merge_buf.borrow_mut().merge_unsorted(kvs);
+                let mut buf = self.async_merge_buf.borrow_mut();
+                buf.merge_unsorted(kvs);
+                buf.spill_if_needed()?;
                 Ok(Control::Continue)
             }
             WriterCommand::AppendSortedInserts(kvs) => {
-                self.async_merge_buf.borrow_mut().append_sorted(kvs);
+                let mut buf = self.async_merge_buf.borrow_mut();
+                buf.append_sorted(kvs);
+                buf.spill_if_needed()?;
                 Ok(Control::Continue)
             }
             WriterCommand::WriteSortedInsertsOnFlush(kvs) => {
-                if !self.async_merge_buf.borrow().is_empty() {
+                let mut buf = self.async_merge_buf.borrow_mut();
+                if !buf.is_empty() {
                     Err(AppError::Custom("WriteSortedInserts cannot be mixed with SortInserts now".to_string()))
                 } else {
-                    self.async_merge_buf = RefCell::new(MergeBuffer::from_sorted(kvs));
+                    buf.append_sorted(kvs); // lands as a single run in the empty buffer
+                    buf.spill_if_needed()?;
                     Ok(Control::Continue)
                 }
             }
@@ -94,9 +164,9 @@ impl<'txn, 'c, K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V>> TxState<
                         }
                         *sender = Some(ack.clone())
                     },
-                    None => self.deferred = Some(FlushState { sender: Some(ack.clone()), sum: 0, shards: None }),
+                    None => self.deferred = Some(FlushState { sender: Some(ack.clone()), prepare: None, sum: 0, shards: None }),
                 }
-                if let Some(FlushState { sender: Some(_), sum, shards: Some(total) }) = &self.deferred {
+                if let Some(FlushState { sender: Some(_), sum, shards: Some(total), .. }) = &self.deferred {
                     if *sum == *total {
                         return self.step(WriterCommand::Flush(ack));
                     }
@@ -106,9 +176,10 @@ impl<'txn, 'c, K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V>> TxState<
             WriterCommand::ReadyForFlush(total) => {
                 match &mut self.deferred {
                     Some(FlushState { sum, shards, .. }) => { *sum += 1; *shards = Some(total); }
-                    None => self.deferred = Some(FlushState { sender: None, sum: 1, shards: Some(total) }),
+                    None => self.deferred = Some(FlushState { sender: None, prepare: None, sum: 1, shards: Some(total) }),
                 }
-                if let Some(FlushState { sender: Some(ack), sum, shards: Some(t) }) = &self.deferred {
+                self.prepare_when_ready()?;
+                if let Some(FlushState { sender: Some(ack), sum, shards: Some(t), .. }) = &self.deferred {
                     if *sum == *t {
                         return self.step(WriterCommand::Flush(ack.clone()));
                     }
@@ -118,16 +189,40 @@ impl<'txn, 'c, K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V>> TxState<
             WriterCommand::Flush(sender) => {
                 self.flush(sender)
             }
+            WriterCommand::Prepare(ack) => self.prepare(ack),
+            WriterCommand::PrepareWhenReady(ack) => {
+                match &mut self.deferred {
+                    Some(FlushState { prepare, .. }) => *prepare = Some(ack),
+                    None => self.deferred = Some(FlushState { sender: None, prepare: Some(ack), sum: 0, shards: None }),
+                }
+                self.prepare_when_ready()
+            }
+            WriterCommand::Abort => {
+                if self.write_error.is_none() {
+                    self.write_error = Some(AppError::Custom("write transaction aborted".to_string()));
+                }
+                Ok(Control::Continue)
+            }
             WriterCommand::Shutdown(ack) => Ok(Control::Shutdown(ack)),
-            WriterCommand::Begin(_, _) => unreachable!("Begin handled outside"),
+            WriterCommand::Begin(_, _, _) => unreachable!("Begin handled outside"),
         }
     }
 
-    fn drain_batch(&mut self, rx: &Receiver<WriterCommand<K, V>>) -> Result<Control, AppError> {
-        let mut ctrl = self.step(rx.recv()?)?;
+    /// Steps the command and gives its bytes back to producers even if it fails.
+    fn step_released(&mut self, cmd: WriterCommand<K, V>, budget: &QueueBudget) -> Result<Control, AppError> {
+        let bytes = cmd.payload_bytes();
+        let ctrl = self.step(cmd);
+        if let Some(bytes) = bytes {
+            budget.release(bytes);
+        }
+        ctrl
+    }
+
+    fn drain_batch(&mut self, rx: &Receiver<WriterCommand<K, V>>, budget: &QueueBudget) -> Result<Control, AppError> {
+        let mut ctrl = self.step_released(rx.recv()?, budget)?;
         if !matches!(ctrl, Control::Continue) { return Ok(ctrl); }
         for cmd in rx.try_iter() {
-            ctrl = self.step(cmd)?;
+            ctrl = self.step_released(cmd, budget)?;
             if !matches!(ctrl, Control::Continue) { break; }
         }
         Ok(ctrl)
@@ -137,14 +232,24 @@ impl<'txn, 'c, K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V>> TxState<
 // ========================= TableWriter (outer loop; drop st before moving tx) =========================
 pub struct TxFSM<K: DbKey + Send, V: Key + Send + 'static, F> {
     pub(crate) topic: Sender<WriterCommand<K, V>>,
+    pub(crate) budget: Arc<QueueBudget>,
     pub(crate) handle: JoinHandle<()>,
+    pub(crate) name: String,
     _marker: PhantomData<F>,
 }
 
 impl<K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V> + Send + 'static> TxFSM<K, V, F> {
-    pub fn new(db_weak: Weak<Database>, factory: F) -> Result<Self, AppError> {
+    /// Producers sending data through the router block while the writer is behind by more than `config.queue_budget` bytes.
+    pub fn new(db_weak: Weak<ShardDb>, factory: F, config: WriterConfig) -> Result<Self, AppError> {
         let (topic, receiver): (Sender<WriterCommand<K, V>>, Receiver<WriterCommand<K, V>>) = unbounded();
+        let budget = Arc::new(QueueBudget::new(config.queue_budget));
+        let writer_budget = Arc::clone(&budget);
+        let name = factory.name();
         let handle = thread::spawn(move || {
+            let budget = writer_budget;
+            let _closer = budget.close_on_drop();
+            // the on-disk state is durable at start, so the first marked transaction takes the savepoint
+            let mut savepoint_due = true;
             'outer: loop {
                 let cmd = match receiver.recv() {
                     Ok(c) => c,
@@ -152,20 +257,17 @@ impl<K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V> + Send + 'static> T
                 };
 
                 match cmd {
-                    WriterCommand::Begin(ack, durability) => {
+                    WriterCommand::Begin(ack, durability, marker) => {
                         let db_arc = match db_weak.upgrade() {
                             Some(db) => db,
                             None => { let _ = ack.send(Err(AppError::Custom("database closed".to_string()))); break 'outer; }
                         };
 
-                        let mut tx = match db_arc.begin_write() {
+                        let takes_savepoint = savepoint_due && marker.is_some();
+                        let tx = match commit::begin_write(&db_arc, durability, takes_savepoint) {
                             Ok(tx) => tx,
-                            Err(e) => { let _ = ack.send(Err(AppError::from(e))); continue 'outer; }
+                            Err(e) => { let _ = ack.send(Err(e)); continue 'outer; }
                         };
-                        match tx.set_durability(durability) {
-                            Ok(()) => {}
-                            Err(e) => { let _ = ack.send(Err(AppError::Custom(e.to_string()))); continue 'outer; }
-                        }
                         drop(db_arc);
 
                         let mut cache_local = factory.new_cache();
@@ -176,14 +278,15 @@ impl<K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V> + Send + 'static> T
 
                         let mut st = TxState::<K, V, F> {
                             table,
-                            async_merge_buf: RefCell::new(MergeBuffer::new()),
+                            async_merge_buf: RefCell::new(MergeBuffer::new().with_spill(config.spill.clone())),
                             deferred: None,
                             write_error: None,
+                            prepared: None,
                             collecting_start: Instant::now(),
                         };
 
                         'in_tx: loop {
-                            match st.drain_batch(&receiver) {
+                            match st.drain_batch(&receiver, &budget) {
                                 Ok(Control::Continue) => continue,
                                 Ok(Control::Error(sender, err)) => {
                                     let _ = sender.send(Err(err));
@@ -198,10 +301,17 @@ impl<K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V> + Send + 'static> T
                                         Ok(wr) => {
                                             drop(st); // ends &tx borrow
                                             let flush_start = Instant::now();
+                                            if let Some(Err(e)) = marker.as_ref().map(|m| m.write(&tx)) {
+                                                let _ = sender.send(Err(e));
+                                                break 'in_tx;
+                                            }
                                             match tx.commit() {
                                                 Ok(()) => {
+                                                    savepoint_due = matches!(durability, Durability::Immediate) || (savepoint_due && !takes_savepoint);
                                                     let flush_took = flush_start.elapsed().as_millis();
-                                                    let stats = TaskStats::new(wr.collect_took, wr.sort_took, wr.write_took, flush_took);
+                                                    let stats = TaskStats::new(wr.collect_took, wr.sort_took, wr.write_took, flush_took)
+                                                        .with_lru(F::cache_stats(&cache_local))
+                                                        .with_queue(budget.take_stats());
                                                     let _ = sender.send(Ok(TaskResult::new(&factory.name(), stats)));
                                                 }
                                                 Err(e) => {
@@ -213,8 +323,13 @@ impl<K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V> + Send + 'static> T
                                     }
                                 }
                                 Ok(Control::Shutdown(ack)) => {
-                                    if let Some(FlushState { sender: Some(pending), .. }) = st.deferred.take() {
-                                        let _ = pending.send(Err(AppError::Custom("aborted".to_string())));
+                                    if let Some(FlushState { sender, prepare, .. }) = st.deferred.take() {
+                                        if let Some(pending) = sender {
+                                            let _ = pending.send(Err(AppError::Custom("aborted".to_string())));
+                                        }
+                                        if let Some(pending) = prepare {
+                                            let _ = pending.send(Err(AppError::Custom("aborted".to_string())));
+                                        }
                                     }
                                     drop(st); // drop table borrow first
                                     drop(tx); // abort tx
@@ -245,7 +360,7 @@ impl<K: DbKey + Send, V: DbVal + Send, F: TableFactory<K, V> + Send + 'static> T
             }
         });
 
-        Ok(Self { topic, handle, _marker: PhantomData })
+        Ok(Self { topic, budget, handle, name, _marker: PhantomData })
     }
 
     pub fn sender(&self) -> Sender<WriterCommand<K, V>> { self.topic.clone() }